@@ -7,4 +7,18 @@ layout(location = 0) out vec4 outColor;
 
 layout(binding = 0, set = 0) uniform sampler2D font_texture;
 
-void main() { outColor = inColor * texture(font_texture, inUV); }
+// True if the color attachment is sRGB-encoded, in which case the device encodes the linear values written to it.
+layout(constant_id = 0) const bool LINEAR_OUTPUT = false;
+
+vec3 linear_from_gamma(vec3 gamma) {
+    bvec3 cutoff = lessThan(gamma, vec3(0.04045));
+    vec3 lower = gamma / vec3(12.92);
+    vec3 higher = pow((gamma + vec3(0.055)) / vec3(1.055), vec3(2.4));
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+  // egui blends in gamma space: both the vertex color and the texels (uploaded as UNORM) are sRGB-encoded.
+  vec4 color = inColor * texture(font_texture, inUV);
+  outColor = LINEAR_OUTPUT ? vec4(linear_from_gamma(color.rgb), color.a) : color;
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform sampler2D diffuse;
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoords;
+
+layout(location = 0) out vec4 outColor;
+
+const vec3 lightDirection = normalize(vec3(0.5, 1.0, 0.3));
+
+void main() {
+    vec4 color = texture(diffuse, fragTexCoords);
+    // Alpha-tested materials, such as foliage.
+    if (color.a < 0.5)
+        discard;
+
+    float light = 0.4 + 0.6 * max(dot(normalize(fragNormal), lightDirection), 0.0);
+    outColor = vec4(color.rgb * light, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 viewProjection;
+} camera;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoords;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoords;
+
+void main() {
+    gl_Position = camera.viewProjection * vec4(inPosition, 1.0);
+    fragNormal = inNormal;
+    fragTexCoords = inTexCoords;
+}
@@ -1,11 +1,16 @@
 #version 450
 
-layout(location = 0) in vec2 inPosition;
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 viewProjection;
+} camera;
+
+layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inOffset;
 
 layout(location = 0) out vec3 fragColor;
 
 void main() {
-    gl_Position = vec4(inPosition, 0.0, 1.0);
+    gl_Position = camera.viewProjection * vec4(inPosition + inOffset, 1.0);
     fragColor = inColor;
-}
\ No newline at end of file
+}
@@ -1,19 +1,33 @@
-use std::{ffi::{CStr, CString}, sync::Arc, time::SystemTime};
+use std::{ffi::{CStr, CString}, mem::ManuallyDrop, path::PathBuf, sync::Arc, time::{Duration, Instant, SystemTime}};
 
-use egui_winit::winit::{event::{Event, WindowEvent}, event_loop::{ControlFlow, EventLoop}, keyboard::ModifiersState};
+use ash::prelude::VkResult;
+use ash::vk;
+use egui_winit::winit::{event::{Event, WindowEvent}, event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget}, keyboard::ModifiersState, window::WindowBuilder};
 
-use crate::orchestration::rendering::{Orchestrator, RendererOrchestrator};
+use crate::orchestration::rendering::{Orchestrator, RendererFn, RendererOrchestrator};
 use crate::vk::{context::Context, renderer::RendererOptions};
 use crate::window::Window;
 
+use self::frame_limiter::FrameLimiter;
+
+pub mod frame_limiter;
+
 type OrchestratorFn = fn(Arc<Context>) -> Orchestrator;
 
 pub struct ApplicationOptions {
     pub title : String,
     pub renderer_options : RendererOptions,
-    pub device_extensions : Vec<CString>,
     pub instance_extensions : Vec<CString>,
+    pub optional_instance_extensions : Vec<CString>,
     pub orchestrator : OrchestratorFn,
+    pub max_fps : Option<u32>,
+    pub redraw_mode : RedrawMode,
+
+    pub maximized : bool,
+    pub min_resolution : Option<[u32; 2]>,
+    pub resizable : bool,
+    pub decorations : bool,
+    pub drag_and_drop : bool,
 }
 
 impl Default for ApplicationOptions {
@@ -24,8 +38,16 @@ impl Default for ApplicationOptions {
             orchestrator : Orchestrator::new,
 
             renderer_options: Default::default(),
-            device_extensions : vec![],
             instance_extensions : vec![],
+            optional_instance_extensions : vec![],
+            max_fps : None,
+            redraw_mode : RedrawMode::default(),
+
+            maximized : false,
+            min_resolution : None,
+            resizable : true,
+            decorations : true,
+            drag_and_drop : true,
         }
     }
 }
@@ -36,23 +58,83 @@ impl ApplicationOptions {
         self
     }
 
+    /// Requires an instance extension. Creating the application panics if the extension is not available.
     #[inline] pub fn instance_extension(mut self, value : CString) -> Self {
         self.instance_extensions.push(value);
         self
     }
 
-    #[inline] pub fn device_extension(mut self, value : CString) -> Self {
-        self.device_extensions.push(value);
+    /// Enables an instance extension if it is available. Use
+    /// [`Context::is_extension_enabled`](crate::vk::context::Context::is_extension_enabled) to find out if it is.
+    #[inline] pub fn optional_instance_extension(mut self, value : CString) -> Self {
+        self.optional_instance_extensions.push(value);
+        self
+    }
+
+    /// Limits the amount of frames rendered per second. Zero removes the limit.
+    ///
+    /// # Description
+    ///
+    /// By default, frames are not limited if the swapchain presents with [`vk::PresentModeKHR::FIFO`], since
+    /// presentation is then synchronized with the display. With other presentation modes such as
+    /// [`vk::PresentModeKHR::MAILBOX`], frames are limited to the refresh rate of the display the window is on.
+    #[inline] pub fn max_fps(mut self, max_fps : u32) -> Self {
+        self.max_fps = Some(max_fps);
         self
     }
 
+    /// Prevents the window from being resized below the given size, in logical pixels. It must not be larger than the
+    /// resolution of the window (see [`RendererOptions::resolution`]).
+    #[inline] pub fn min_resolution(mut self, min_resolution : [u32; 2]) -> Self {
+        self.min_resolution = Some(min_resolution);
+        self
+    }
+
+    value_builder! {
+        /// Selects when frames are drawn; see [`RedrawMode`].
+        redraw_mode, RedrawMode
+    }
+    value_builder! {
+        /// Opens the window maximized.
+        maximized, bool
+    }
+    value_builder! { resizable, bool }
+    value_builder! {
+        /// Draws the title bar and borders of the window.
+        decorations, bool
+    }
+    value_builder! {
+        /// Allows files to be dropped onto the window (see [`ApplicationBuilder::file_drop`]). On Windows, drag and drop
+        /// requires COM to be initialized in single-threaded mode, and must be disabled if other code expects otherwise.
+        drag_and_drop, bool
+    }
     value_builder! { renderer, renderer_options, RendererOptions }
     value_builder! { orchestrator, orchestrator, OrchestratorFn }
 }
 
+/// Determines when the application draws frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Frames are drawn continuously, as fast as [`ApplicationOptions::max_fps`] allows.
+    #[default]
+    Continuous,
+    /// Frames are only drawn in response to window events, when a renderer asks to be repainted (see
+    /// [`Renderer::repaint_after`](crate::orchestration::rendering::Renderer::repaint_after)), or when
+    /// [`Application::request_redraw`] is called. The event loop sleeps in between, which saves power in applications
+    /// that are mostly idle, such as editors.
+    Reactive,
+}
+
 #[derive(Debug)]
 pub enum RendererError {
+    /// The swapchain no longer matches the window, and is still invalid after being recreated. It is recreated again
+    /// before the next frame.
     InvalidSwapchain,
+    /// The GPU did not make a swapchain image available in time; the frame was skipped.
+    Timeout,
+    /// The logical device was lost, typically because the driver reset the GPU. See
+    /// [`RendererOrchestrator::recover_device_lost`].
+    DeviceLost,
 }
 
 pub type PrepareFn = fn() -> ApplicationOptions;
@@ -61,6 +143,30 @@ pub type UpdateFn<T> = fn(&mut Application, &mut T);
 pub type RenderFn<T> = fn(&mut Application, &mut T) -> Result<(), RendererError>;
 pub type WindowEventFn<T> = fn(&mut Application, &mut T, event : &WindowEvent);
 pub type InterfaceFn<T> = fn(&mut T, ctx : &mut egui::Context);
+pub type FileDropFn<T> = fn(&mut Application, &mut T, event : FileDropEvent);
+
+/// A file dragged over, or dropped onto, the primary window. One event is sent per file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileDropEvent {
+    /// A file is being dragged over the window.
+    Hovered(PathBuf),
+    /// A file was dropped onto the window.
+    Dropped(PathBuf),
+    /// The files dragged over the window left it, or the drag was cancelled.
+    Cancelled,
+}
+
+impl FileDropEvent {
+    /// Returns the file drop event corresponding to a window event, if any.
+    pub fn from_window_event(event : &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::HoveredFile(path) => Some(Self::Hovered(path.clone())),
+            WindowEvent::DroppedFile(path) => Some(Self::Dropped(path.clone())),
+            WindowEvent::HoveredFileCancelled => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+}
 
 pub struct ApplicationBuilder<State : 'static> {
     pub prepare : Option<PrepareFn>,
@@ -68,6 +174,7 @@ pub struct ApplicationBuilder<State : 'static> {
     pub update : Option<UpdateFn<State>>,
     pub event : Option<WindowEventFn<State>>,
     pub render : Option<RenderFn<State>>,
+    pub file_drop : Option<FileDropFn<State>>,
 }
 
 pub struct ApplicationCallbacks<State : 'static> {
@@ -76,6 +183,7 @@ pub struct ApplicationCallbacks<State : 'static> {
     pub update : UpdateFn<State>,
     pub event : WindowEventFn<State>,
     pub render : RenderFn<State>,
+    pub file_drop : FileDropFn<State>,
 }
 
 impl<T> ApplicationBuilder<T> {
@@ -99,6 +207,13 @@ impl<T> ApplicationBuilder<T> {
         self
     }
 
+    /// Sets the function called when files are dragged over, or dropped onto, the primary window. This requires
+    /// [`ApplicationOptions::drag_and_drop`] to be enabled.
+    pub fn file_drop(mut self, file_drop : FileDropFn<T>) -> Self {
+        self.file_drop = Some(file_drop);
+        self
+    }
+
     pub fn run(self) {
         main_loop(self);
     }
@@ -114,19 +229,42 @@ fn main_loop<T : 'static>(builder: ApplicationBuilder<T>) {
         update: builder.update.unwrap_or(|_, _| { }),
         event: builder.event.unwrap_or(|_, _, _| { }),
         render: builder.render.unwrap_or(|_, _| Ok(())),
+        file_drop: builder.file_drop.unwrap_or(|_, _, _| { }),
     };
 
     let mut settings = (builder.prepare)();
 
-    let mut app = Application::new(settings, &event_loop);
-    let mut app_data = (builder.setup)(&mut app);
+    let mut app = match Application::try_new(settings, &event_loop) {
+        Ok(app) => app,
+        Err(error) => {
+            println!("Failed to initialize Vulkan: {:?}", error);
+            return;
+        }
+    };
+    // Application data is dropped explicitly when the event loop exits; see Event::LoopExiting below.
+    let mut app_data = ManuallyDrop::new((builder.setup)(&mut app));
     let mut dirty_swapchain = false;
 
     let now = SystemTime::now();
     let mut modifiers = ModifiersState::default();
 
     event_loop.run(move |event, target| {
-        target.set_control_flow(ControlFlow::Poll);
+        if let Event::LoopExiting = event {
+            // Application data may own objects that are still in use by the GPU. It is dropped before the
+            // application, which tears down the renderers and the device once the closure is dropped.
+            app.wait_idle();
+            unsafe {
+                ManuallyDrop::drop(&mut app_data);
+            }
+            return;
+        }
+
+        match app.redraw_mode {
+            RedrawMode::Continuous => target.set_control_flow(ControlFlow::Poll),
+            // Nothing is drawn until the window is restored, which resizes it.
+            RedrawMode::Reactive if app.orchestrator.context.window.is_minimized() => target.set_control_flow(ControlFlow::Wait),
+            RedrawMode::Reactive => (),
+        }
 
         if !app.orchestrator.context.window.is_minimized() {
             if dirty_swapchain {
@@ -134,17 +272,47 @@ fn main_loop<T : 'static>(builder: ApplicationBuilder<T>) {
                 dirty_swapchain = false;
             }
 
+            // Any window event, including the redraw requests of the system, can change what is displayed.
+            if let Event::WindowEvent { .. } = event {
+                app.request_redraw();
+            }
+
             match event {
+                Event::WindowEvent { event, window_id } if app.orchestrator.has_viewport(window_id) => {
+                    match event {
+                        WindowEvent::CloseRequested => app.orchestrator.remove_viewport(window_id),
+                        WindowEvent::Resized(_) => dirty_swapchain = true,
+                        WindowEvent::ScaleFactorChanged { .. } => {
+                            // The interface needs to know about the new scale factor, too.
+                            dirty_swapchain = true;
+                            app.orchestrator.handle_viewport_event(window_id, &event);
+                        },
+                        _ => app.orchestrator.handle_viewport_event(window_id, &event),
+                    }
+                }
                 Event::WindowEvent { event, .. } => {
                     match event {
                         WindowEvent::CloseRequested => target.exit(),
                         WindowEvent::ModifiersChanged(m) => modifiers = m.state(),
+                        WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => dirty_swapchain = true,
                         _ => (),
                     }
+                    if let Some(file_drop) = FileDropEvent::from_window_event(&event) {
+                        (builder.file_drop)(&mut app, &mut app_data, file_drop);
+                    }
                     (builder.event)(&mut app, &mut app_data, &event);
                 }
                 Event::AboutToWait => {
+                    if !app.should_redraw(Instant::now()) {
+                        target.set_control_flow(reactive_control_flow(false, app.next_redraw));
+                        return;
+                    }
+
+                    app.frame_limiter.wait();
+
                     puffin::GlobalProfiler::lock().new_frame();
+
+                    app.create_pending_windows(target);
             
                     let now = now.elapsed().unwrap();
 
@@ -153,11 +321,26 @@ fn main_loop<T : 'static>(builder: ApplicationBuilder<T>) {
                     dirty_swapchain = match (builder.render)(&mut app, &mut app_data) {
                         Ok(_) => false,
                         Err(RendererError::InvalidSwapchain) => true,
+                        Err(RendererError::Timeout) => false,
+                        Err(RendererError::DeviceLost) => {
+                            println!("The device was lost; recreating it.");
+                            if app.recover_device_lost().is_err() {
+                                println!("The rendering context is still in use and the device can't be recreated; exiting.");
+                                target.exit();
+                            }
+                            false
+                        },
                     };
+
+                    if app.redraw_mode == RedrawMode::Reactive {
+                        // Keep drawing until the swapchain is valid again.
+                        app.redraw_requested = dirty_swapchain;
+                        app.next_redraw = next_redraw(Instant::now(), app.orchestrator.repaint_after());
+                        target.set_control_flow(reactive_control_flow(app.redraw_requested, app.next_redraw));
+                    }
                 }
                 Event::Suspended => println!("Suspended."),
                 Event::Resumed => println!("Resumed."),
-                Event::LoopExiting => app.orchestrator.context.device.wait_idle(),
                 _ => { }
             }
         }
@@ -167,6 +350,15 @@ fn main_loop<T : 'static>(builder: ApplicationBuilder<T>) {
 
 pub struct Application {
     pub orchestrator : RendererOrchestrator,
+
+    frame_limiter : FrameLimiter,
+    pending_windows : Vec<(WindowBuilder, Vec<RendererFn>)>,
+
+    redraw_mode : RedrawMode,
+    // Whether a frame must be drawn at the next iteration of the event loop; only used in reactive mode.
+    redraw_requested : bool,
+    // The time at which renderers asked for the next frame to be drawn, if any; only used in reactive mode.
+    next_redraw : Option<Instant>,
 }
 
 impl Application {
@@ -177,30 +369,202 @@ impl Application {
             update : None,
             event : None,
             render : None,
+            file_drop : None,
         }
     }
 
+    /// Creates a new application.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if a presentation surface can't be created for the window. See [`Application::try_new`].
     pub fn new(options : ApplicationOptions, event_loop : &EventLoop<()>) -> Self {
+        Self::try_new(options, event_loop)
+            .expect("Failed to create a presentation surface")
+    }
+
+    /// Creates a new application.
+    ///
+    /// # Returns
+    ///
+    /// The error returned by the driver if a presentation surface can't be created for the window, which typically
+    /// means that the driver does not support the windowing system in use.
+    pub fn try_new(options : ApplicationOptions, event_loop : &EventLoop<()>) -> VkResult<Self> {
         let mut window = Window::new(&options, event_loop);
 
         let context = Arc::new(unsafe {
-            let mut all_extensions = options.instance_extensions.clone();
-            all_extensions.extend(window.surface_extensions().iter().map(|&extension| CStr::from_ptr(extension).to_owned()));
-            all_extensions.push(ash::ext::debug_utils::NAME.into());
-            all_extensions.dedup();
+            let mut required_extensions = options.instance_extensions.clone();
+            required_extensions.extend(window.surface_extensions().iter().map(|&extension| CStr::from_ptr(extension).to_owned()));
 
-            Context::new(CString::new("send-help").unwrap_unchecked(), all_extensions)
+            // Debug utilities are only used for validation messages and debug names.
+            let mut optional_extensions = options.optional_instance_extensions.clone();
+            optional_extensions.push(ash::ext::debug_utils::NAME.into());
+
+            Context::new(CString::new("send-help").unwrap_unchecked(), required_extensions, optional_extensions,
+                options.renderer_options.validation, options.renderer_options.debug_messenger)
         });
-        window.create_surface(&context);
+        window.create_surface(&context)?;
 
-        let orchestrator = (options.orchestrator)(context).build(options.renderer_options, window, options.device_extensions);
+        let orchestrator = (options.orchestrator)(context).build(options.renderer_options, window);
+        let max_fps = options.max_fps.or_else(|| default_max_fps(&orchestrator));
 
-        Self {
+        Ok(Self {
             orchestrator,
+            frame_limiter : FrameLimiter::new(max_fps),
+            pending_windows : vec![],
+
+            redraw_mode : options.redraw_mode,
+            redraw_requested : true,
+            next_redraw : None,
+        })
+    }
+
+    /// Limits the amount of frames rendered per second. `None` (or zero) removes the limit.
+    pub fn set_max_fps(&mut self, max_fps : Option<u32>) {
+        self.frame_limiter.set_max_fps(max_fps);
+    }
+
+    /// Blocks until the device has finished executing all submitted work.
+    ///
+    /// # Description
+    ///
+    /// [`ApplicationBuilder::run`] calls this when the event loop exits, before dropping the application data. Code
+    /// that drives its own event loop must do the same before dropping any object created from the rendering context,
+    /// since the GPU may still be using it; renderers are taken care of by the orchestrator when it is dropped.
+    pub fn wait_idle(&self) {
+        self.orchestrator.context.device.wait_idle();
+    }
+
+    /// Returns the mode that determines when frames are drawn.
+    #[inline] pub fn redraw_mode(&self) -> RedrawMode { self.redraw_mode }
+
+    /// Changes when frames are drawn. See [`RedrawMode`].
+    pub fn set_redraw_mode(&mut self, redraw_mode : RedrawMode) {
+        self.redraw_mode = redraw_mode;
+        self.redraw_requested = true;
+    }
+
+    /// Requests a frame to be drawn at the next iteration of the event loop. This is only needed with
+    /// [`RedrawMode::Reactive`], when something other than a window event changes what is displayed.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    /// Returns true if a frame should be drawn at the given time.
+    fn should_redraw(&self, now : Instant) -> bool {
+        self.redraw_mode == RedrawMode::Continuous
+            || self.redraw_requested
+            || self.next_redraw.is_some_and(|next_redraw| next_redraw <= now)
+    }
+
+    /// Requests the creation of a secondary window, rendered to by the given renderers.
+    /// 
+    /// The window is created at the next iteration of the event loop. See
+    /// [`RendererOrchestrator::add_viewport`] for details.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `builder` - Describes the window to create.
+    /// * `renderers` - Functions creating the renderers that will draw to this window.
+    pub fn open_window(&mut self, builder : WindowBuilder, renderers : Vec<RendererFn>) {
+        self.pending_windows.push((builder, renderers));
+    }
+
+    fn create_pending_windows(&mut self, target : &EventLoopWindowTarget<()>) {
+        for (builder, renderers) in self.pending_windows.drain(..) {
+            let window = Window::from_builder(builder, target);
+            if let Err(error) = self.orchestrator.add_viewport(window, &renderers) {
+                println!("Failed to create a presentation surface for a new window: {:?}", error);
+            }
         }
     }
 
     pub fn recreate_swapchain(&mut self) {
         self.orchestrator.recreate_swapchain();
     }
-}
\ No newline at end of file
+
+    /// Recreates the logical device and all rendering resources after the device was lost. See
+    /// [`RendererOrchestrator::recover_device_lost`].
+    pub fn recover_device_lost(&mut self) -> Result<(), RendererError> {
+        self.orchestrator.recover_device_lost()
+    }
+}
+
+/// Returns the frame rate limit used when the application does not provide one; see [`ApplicationOptions::max_fps`].
+fn default_max_fps(orchestrator : &RendererOrchestrator) -> Option<u32> {
+    match orchestrator.swapchain().present_mode {
+        vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED => None,
+        _ => orchestrator.context.window.handle().current_monitor()
+            .and_then(|monitor| monitor.refresh_rate_millihertz())
+            .map(|refresh_rate| refresh_rate.div_ceil(1000)),
+    }
+}
+
+/// Returns the time at which the next frame should be drawn, given the delay requested by the renderers.
+fn next_redraw(now : Instant, repaint_after : Option<Duration>) -> Option<Instant> {
+    // Delays too large to be represented are as good as no delay at all.
+    repaint_after.and_then(|delay| now.checked_add(delay))
+}
+
+/// Returns the control flow of the event loop in reactive mode.
+///
+/// # Arguments
+///
+/// * `redraw_requested` - Whether a frame must be drawn as soon as possible.
+/// * `next_redraw` - The time at which the next frame should be drawn, if any.
+fn reactive_control_flow(redraw_requested : bool, next_redraw : Option<Instant>) -> ControlFlow {
+    match next_redraw {
+        _ if redraw_requested => ControlFlow::Poll,
+        Some(next_redraw) => ControlFlow::WaitUntil(next_redraw),
+        None => ControlFlow::Wait,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use egui_winit::winit::event::WindowEvent;
+    use egui_winit::winit::event_loop::ControlFlow;
+
+    use super::{next_redraw, reactive_control_flow, FileDropEvent};
+
+    #[test]
+    pub fn file_drop_events() {
+        let path = PathBuf::from("World of Warcraft");
+
+        assert_eq!(FileDropEvent::from_window_event(&WindowEvent::HoveredFile(path.clone())), Some(FileDropEvent::Hovered(path.clone())));
+        assert_eq!(FileDropEvent::from_window_event(&WindowEvent::DroppedFile(path.clone())), Some(FileDropEvent::Dropped(path)));
+        assert_eq!(FileDropEvent::from_window_event(&WindowEvent::HoveredFileCancelled), Some(FileDropEvent::Cancelled));
+        assert_eq!(FileDropEvent::from_window_event(&WindowEvent::Focused(true)), None);
+    }
+
+    #[test]
+    pub fn reactive_redraws() {
+        let now = Instant::now();
+
+        assert_eq!(next_redraw(now, None), None);
+        assert_eq!(next_redraw(now, Some(Duration::from_millis(250))), Some(now + Duration::from_millis(250)));
+        assert_eq!(next_redraw(now, Some(Duration::MAX)), None);
+
+        assert_eq!(reactive_control_flow(false, None), ControlFlow::Wait);
+        assert_eq!(reactive_control_flow(false, Some(now)), ControlFlow::WaitUntil(now));
+        assert_eq!(reactive_control_flow(true, Some(now)), ControlFlow::Poll);
+        assert_eq!(reactive_control_flow(true, None), ControlFlow::Poll);
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn clean_shutdown() {
+        let (_event_loop, app) = crate::testing::test_application();
+        let context = Arc::downgrade(&app.orchestrator.context);
+
+        // Tearing down the orchestrator must release every object created from the rendering context, which is then
+        // destroyed along with the device.
+        drop(app);
+        assert!(context.upgrade().is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long before the start of the next frame the limiter stops sleeping and starts spinning. Operating systems
+/// usually wake threads up a bit late, so sleeping for the entire remaining time would overshoot the frame budget.
+const SPIN_DURATION : Duration = Duration::from_millis(1);
+
+/// Limits how often frames are rendered.
+pub struct FrameLimiter {
+    frame_time : Option<Duration>,
+    next_frame : Instant,
+}
+
+impl FrameLimiter {
+    /// Creates a new frame limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_fps` - The maximum amount of frames per second, or `None` to render as fast as possible.
+    pub fn new(max_fps : Option<u32>) -> Self {
+        Self {
+            frame_time : max_fps.and_then(frame_time),
+            next_frame : Instant::now(),
+        }
+    }
+
+    /// Sets the maximum amount of frames per second. `None` (or zero) removes the limit.
+    pub fn set_max_fps(&mut self, max_fps : Option<u32>) {
+        self.frame_time = max_fps.and_then(frame_time);
+        self.next_frame = Instant::now();
+    }
+
+    /// Returns the minimum amount of time between the start of two frames, if frames are limited.
+    #[inline] pub fn frame_time(&self) -> Option<Duration> { self.frame_time }
+
+    /// Blocks until the next frame should start. This should be called once per frame, before rendering it.
+    ///
+    /// # Description
+    ///
+    /// Frames are scheduled relatively to the start of the previous frame, so the time spent rendering is accounted
+    /// for. If a frame took longer than the frame budget, the next frame starts immediately and the schedule starts
+    /// over from there, rather than rendering several frames back to back to catch up.
+    pub fn wait(&mut self) {
+        let Some(frame_time) = self.frame_time else {
+            return;
+        };
+
+        let remaining = self.next_frame.saturating_duration_since(Instant::now());
+        if remaining > SPIN_DURATION {
+            thread::sleep(remaining - SPIN_DURATION);
+        }
+
+        while Instant::now() < self.next_frame {
+            hint::spin_loop();
+        }
+
+        self.next_frame = next_deadline(self.next_frame, Instant::now(), frame_time);
+    }
+}
+
+/// Returns the minimum time between two frames for the given frame rate, or `None` if the frame rate is zero.
+fn frame_time(fps : u32) -> Option<Duration> {
+    (fps != 0).then(|| Duration::from_secs(1) / fps)
+}
+
+/// Returns the time at which the frame after the current one should start.
+///
+/// # Arguments
+///
+/// * `deadline` - The time at which the current frame was supposed to start.
+/// * `now` - The time at which the current frame actually starts.
+/// * `frame_time` - The minimum time between two frames.
+fn next_deadline(deadline : Instant, now : Instant, frame_time : Duration) -> Instant {
+    deadline.max(now) + frame_time
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{frame_time, next_deadline};
+
+    #[test]
+    pub fn frame_times() {
+        assert_eq!(frame_time(0), None);
+        assert_eq!(frame_time(1), Some(Duration::from_secs(1)));
+        assert_eq!(frame_time(60), Some(Duration::from_nanos(16_666_666)));
+    }
+
+    #[test]
+    pub fn frame_deadlines() {
+        let start = Instant::now();
+        let frame_time = Duration::from_millis(10);
+
+        // On time: frames are scheduled at regular intervals, regardless of the time spent rendering.
+        assert_eq!(next_deadline(start, start, frame_time), start + frame_time);
+
+        // Late: the schedule starts over from the current frame.
+        let late = start + Duration::from_millis(25);
+        assert_eq!(next_deadline(start, late, frame_time), late + frame_time);
+    }
+}
@@ -1,19 +1,16 @@
-#[allow(dead_code)]
-
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::ops::Range;
 use ash::vk;
-use nohash_hasher::IntMap;
-use crate::graph::attachment::{Attachment, AttachmentID, AttachmentOptions};
-use crate::graph::buffer::{Buffer, BufferID, BufferOptions};
+use crate::graph::attachment::{Attachment, AttachmentID};
+use crate::graph::buffer::{Buffer, BufferID};
 use crate::graph::manager::Manager;
 use crate::graph::pass::{Pass, PassID};
-use crate::graph::resource::{Identifiable, PhysicalResourceID, Resource, ResourceID};
-use crate::graph::texture::{Texture, TextureID, TextureOptions};
+use crate::graph::resource::{Identifiable, PhysicalResourceID, Resource, ResourceAccessFlags, ResourceID};
+use crate::graph::texture::{Texture, TextureID};
+use crate::orchestration::rendering::RenderingContext;
 use crate::utils::topological_sort::TopologicalSorter;
-use crate::vk::command_buffer::CommandBuffer;
-use crate::vk::command_pool::CommandPool;
-use crate::vk::image::Image;
-use crate::vk::logical_device::LogicalDevice;
+use crate::vk::command_buffer::{BarrierPhase, CommandBuffer};
+use crate::vk::image::{Image, ImageCreateInfo};
 
 pub mod attachment;
 pub mod buffer;
@@ -22,25 +19,100 @@ pub mod resource;
 pub mod pass;
 pub mod texture;
 
+/// A render graph: a set of passes, and of the resources they read from and write to.
+///
+/// # Description
+///
+/// Passes declare the resources they use, as well as the layout textures must be in. A pass that uses a resource
+/// produced by another pass refers to it through [`ResourceID::virtualize`], which schedules it after that pass.
+///
+/// [`Graph::build`] orders the passes and computes the layout transitions and barriers needed between them;
+/// [`Graph::execute`] then records them, along with the commands of every pass.
+///
+/// This first version only tracks textures: buffers and attachments order passes, but no barrier is recorded for
+/// them.
 pub struct Graph {
     pub(in crate) passes : Manager<Pass>,
     pub(in crate) textures : Manager<Texture>,
     pub(in crate) buffers : Manager<Buffer>,
     pub(in crate) attachments : Manager<Attachment>,
+}
+
+/// The passes of a [`Graph`] in execution order, along with the barriers recorded before each of them.
+pub struct Schedule {
+    passes : Vec<ScheduledPass>,
+    aliases : Vec<Vec<TextureID>>,
+}
+
+impl Schedule {
+    /// Returns the passes of the graph, in execution order.
+    pub fn passes(&self) -> impl Iterator<Item = PassID> + '_ {
+        self.passes.iter().map(|pass| pass.pass)
+    }
+
+    /// Returns the transitions recorded before the given pass.
+    pub fn transitions(&self, pass : PassID) -> &[ImageTransition] {
+        self.passes.iter()
+            .find(|scheduled| scheduled.pass == pass)
+            .map(|scheduled| scheduled.transitions.as_slice())
+            .unwrap_or_default()
+    }
 
-    // Optionally provides a command pool for the given queue family.
-    command_pools : IntMap<u32, CommandPool>,
+    /// Returns the transient textures of the graph, grouped by the memory they share. The textures of a group are
+    /// used one after the other, in the order of the group.
+    #[inline] pub fn aliases(&self) -> &[Vec<TextureID>] { &self.aliases }
+}
+
+struct ScheduledPass {
+    pass : PassID,
+    transitions : Vec<ImageTransition>,
+}
+
+/// A barrier on a texture, which may also transition it to another layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageTransition {
+    pub texture : TextureID,
+    pub old_layout : vk::ImageLayout,
+    pub new_layout : vk::ImageLayout,
+    pub src_stage : vk::PipelineStageFlags,
+    pub src_access : vk::AccessFlags,
+    pub dst_stage : vk::PipelineStageFlags,
+    pub dst_access : vk::AccessFlags,
 }
 
 impl Graph { // Graph compilation functions
-    /// Builds this graph into a render pass.
-    pub fn build(&mut self) {
+    /// Orders the passes of this graph and computes the barriers needed between them.
+    ///
+    /// # Description
+    ///
+    /// Each texture starts in the layout it was declared with (see [`Texture::with_layout`]). Before a pass uses a
+    /// texture, a barrier is scheduled if the pass needs another layout, or if the texture is written to either by
+    /// the pass or by its previous user.
+    ///
+    /// Transient textures whose lifetimes, from the first to the last pass using them, do not overlap are grouped so
+    /// that they share their memory. The first use of such a texture waits for the writes of the texture that used the
+    /// memory before it.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the dependencies between passes are cyclic.
+    /// * Panics if a pass depends on a pass that is not registered on this graph, or on a resource that pass does not
+    ///   write to.
+    pub fn build(&self) -> Schedule {
         let topology = {
-            let mut sorter = TopologicalSorter::<PassID>::default();
+            // Every pass is a node, even if it does not depend on other passes.
+            let mut sorter = self.passes.iter()
+                .fold(TopologicalSorter::<PassID>::default(), |sorter, pass| sorter.add_node(pass.id(), vec![]));
+
             for pass in self.passes.iter() {
-                for resource in pass.inputs() {
-                    if let ResourceID::Virtual(resource, _) = resource {
-                        sorter = sorter.add_edge(*resource, pass.id());
+                for resource in pass.inputs().chain(pass.outputs()) {
+                    if let ResourceID::Virtual(producer, physical) = resource {
+                        let producer_pass = self.passes.find(*producer)
+                            .unwrap_or_else(|| panic!("Pass {} depends on a pass that is not part of this graph", pass.name()));
+                        assert!(producer_pass.outputs().any(|output| output.devirtualize() == physical),
+                            "Pass {} depends on a resource that pass {} does not write to", pass.name(), producer_pass.name());
+
+                        sorter = sorter.add_edge(*producer, pass.id());
                     }
                 }
             }
@@ -51,74 +123,143 @@ impl Graph { // Graph compilation functions
             }
         };
 
-        // Walk the topology and process resources
-        /*let graphics_queues = self.renderer.device.get_queues(QueueAffinity::Graphics);
-        let command_buffer : CommandBuffer = todo!(); // self.get_command_buffer(&graphics_queues[0], vk::CommandBufferLevel::SECONDARY);
+        let aliases = alias_groups(self.transient_lifetimes(&topology));
+        // The transient texture that used the memory of each transient texture before it.
+        let predecessors = aliases.iter()
+            .flat_map(|group| group.windows(2).map(|pair| (pair[1], pair[0])))
+            .collect::<HashMap<_, _>>();
+
+        // The layout of each texture, and how it was last used.
+        let mut states = HashMap::<TextureID, (vk::ImageLayout, ResourceAccessFlags)>::new();
+        let passes = topology.into_iter().map(|pass_id| {
+            let pass = pass_id.get(self);
+
+            let mut textures = pass.textures.iter().collect::<Vec<_>>();
+            textures.sort_by_key(|(texture, _)| **texture);
+
+            let transitions = textures.into_iter().filter_map(|(texture, options)| {
+                let initial_layout = texture.get(self)
+                    .unwrap_or_else(|| panic!("Pass {} uses a texture that is not part of this graph", pass.name()))
+                    .layout();
+                // The layout of the texture, and the layout in which its memory was last accessed.
+                let (old_layout, src_layout, previous_access) = match (states.get(texture), predecessors.get(texture)) {
+                    (Some(&(layout, access)), _) => (layout, layout, access),
+                    // The memory was last written through another texture, whose contents are irrelevant.
+                    (None, Some(predecessor)) => {
+                        let (layout, access) = states[predecessor];
+                        (initial_layout, layout, access)
+                    },
+                    (None, None) => (initial_layout, initial_layout, ResourceAccessFlags::none()),
+                };
 
-        let mut texture_state_tracker = HashMap::<TextureID, TextureState>::new();
-        for pass in &topology {
-            let pass = self.passes.find(*pass).unwrap();
+                let new_layout = options.layout.unwrap_or(old_layout);
+                states.insert(*texture, (new_layout, options.access));
 
-            // Find a command pool that works with the pass's affinity.
+                let hazard = previous_access.contains(ResourceAccessFlags::Write)
+                    || (options.access.contains(ResourceAccessFlags::Write) && !previous_access.is_none());
+                if new_layout == old_layout && !hazard {
+                    return None;
+                }
 
-            for resource in pass.resources() {
-                let physical_resource = resource.devirtualize();
-                match physical_resource {
-                    PhysicalResourceID::Texture(texture) => {
-                        let options = texture.get_options(pass).unwrap();
-                        let texture = texture.get(self).unwrap();
+                let (src_stage, src_access) = barrier_scope(src_layout, previous_access & ResourceAccessFlags::Write);
+                let (dst_stage, dst_access) = barrier_scope(new_layout, options.access);
+                Some(ImageTransition { texture : *texture, old_layout, new_layout, src_stage, src_access, dst_stage, dst_access })
+            }).collect();
 
-                        // Create the tracking state for this resource if it doesn't exist yet.
-                        let state = texture_state_tracker.entry(texture.id())
-                            .or_insert_with(|| TextureState::new(texture, &self.renderer.device));
+            ScheduledPass { pass : pass_id, transitions }
+        }).collect();
 
-                        // Process the update.
-                        self.process_texture(&command_buffer, options, state);
-                    },
-                    PhysicalResourceID::Buffer(buffer) => {
-                        let options = buffer.get_options(pass).unwrap();
-                        let buffer = buffer.get(self).unwrap();
+        Schedule { passes, aliases }
+    }
 
-                        self.process_buffer(pass, buffer, options);
-                    },
-                    PhysicalResourceID::Attachment(attachment) => {
-                        let options = attachment.get_options(pass).unwrap();
-                        let attachment = attachment.get(self).unwrap();
-                        self.process_attachment(pass, attachment, options);
-                    }
-                };
-            }
+    /// Returns the range of passes, as indices in the given execution order, over which each transient texture is used.
+    fn transient_lifetimes(&self, topology : &[PassID]) -> Vec<(TextureID, Range<usize>)> {
+        let mut lifetimes = HashMap::<TextureID, Range<usize>>::new();
+        for (index, pass) in topology.iter().enumerate() {
+            for texture in pass.get(self).textures.keys() {
+                if !texture.get(self).is_some_and(Texture::is_transient) {
+                    continue;
+                }
 
-            if let Some(pass_emitter) = pass.command_emitter {
-                pass_emitter(&command_buffer);
+                lifetimes.entry(*texture)
+                    .and_modify(|lifetime| lifetime.end = index + 1)
+                    .or_insert(index..index + 1);
             }
+        }
+
+        let mut lifetimes = lifetimes.into_iter().collect::<Vec<_>>();
+        lifetimes.sort_by_key(|(texture, _)| *texture);
+        lifetimes
+    }
 
-            // Persist the command buffer here.
-        }*/
-    }
-
-    fn process_texture(
-        &self,
-        command_buffer : &CommandBuffer,
-        options: &TextureOptions,
-        state : &mut TextureState)
-    {
-        // If a layout was requested and it differs from the current one, update the state and
-        // record a layout transition command.
-        // TODO: Accumulate barriers and schedule the layout transitions as late as possible ?
-        //       Wouldn't this still stall if the transitions get flushed before a new transition happen?
-        //       In that case, the first transition could be considered redundant and we could effectively
-        //       collapse the intermediary layout, I guess...
-        //       Something to keep in mind for V2.
-
-        if let Some(new_layout) = options.layout {
-            state.emit_transition(command_buffer, new_layout);
+    /// Creates the images of the transient textures of this graph. Textures grouped by [`Schedule::aliases`] share
+    /// a single allocation.
+    ///
+    /// # Description
+    ///
+    /// The usage of each image is the union of the usages requested by the passes using its texture (see
+    /// [`TextureOptions::usage_flags`](texture::TextureOptions::usage_flags)). The returned images can be extended
+    /// with the images of the other textures, and handed to [`Graph::execute`].
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The schedule returned by [`Graph::build`].
+    /// * `context` - The rendering context.
+    pub fn create_transients(&self, schedule : &Schedule, context : &RenderingContext) -> HashMap<TextureID, Image> {
+        let mut images = HashMap::new();
+        for group in &schedule.aliases {
+            let infos = group.iter()
+                .map(|texture| texture.get(self).unwrap().image_info(self.texture_usage(*texture)))
+                .collect();
+
+            images.extend(group.iter().copied().zip(ImageCreateInfo::build_aliased(infos, context)));
         }
+        images
+    }
+
+    /// Returns the union of the usages requested by the passes using the given texture.
+    fn texture_usage(&self, texture : TextureID) -> vk::ImageUsageFlags {
+        self.passes.iter()
+            .filter_map(|pass| pass.textures.get(&texture))
+            .fold(vk::ImageUsageFlags::empty(), |usage, options| usage | options.usage_flags)
     }
 
-    fn process_buffer(&self, pass: &Pass, buffer: &Buffer, options: &BufferOptions) {}
+    /// Records the passes of this graph in the order of the given schedule, preceded by their barriers.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The schedule returned by [`Graph::build`].
+    /// * `cmd` - The command buffer to record on.
+    /// * `images` - The images backing the textures of this graph. Their layouts are updated as they are transitioned.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if a texture used by a pass has no image.
+    pub fn execute(&self, schedule : &Schedule, cmd : &CommandBuffer, images : &mut HashMap<TextureID, Image>) {
+        for scheduled in &schedule.passes {
+            let pass = scheduled.pass.get(self);
+
+            for transition in &scheduled.transitions {
+                let image = images.get_mut(&transition.texture)
+                    .unwrap_or_else(|| panic!("No image backs a texture used by pass {}", pass.name()));
+
+                // The schedule knows better than the image which layout the texture is in: aliased textures start
+                // out undefined, whichever layout the memory was last used in.
+                cmd.image_layout_transition(image,
+                    BarrierPhase::ignore_queue(transition.src_access, transition.src_stage),
+                    BarrierPhase::ignore_queue(transition.dst_access, transition.dst_stage),
+                    vk::DependencyFlags::empty(),
+                    transition.old_layout,
+                    transition.new_layout);
+            }
 
-    fn process_attachment(&self, pass : &Pass, attachment : &Attachment, options : &AttachmentOptions) {}
+            if let Some(emitter) = pass.command_emitter {
+                cmd.begin_label(pass.name(), [0.0; 4]);
+                emitter(cmd);
+                cmd.end_label();
+            }
+        }
+    }
 }
 
 impl Graph { // Public API
@@ -128,8 +269,6 @@ impl Graph { // Public API
             textures: Default::default(),
             buffers: Default::default(),
             attachments: Default::default(),
-
-            command_pools : IntMap::<u32, CommandPool>::default(),
         }
     }
 
@@ -160,94 +299,265 @@ impl Graph { // Public API
     }
 }
 
-/// Tracks the properties of a texture during the compilation of a render graph.
-struct TextureState<'a> {
-    /// The current layout of the texture. This value is used to track necessary layout transitions
-    /// when walking the graph topology.
-    pub current_layout : vk::ImageLayout,
+/// Groups textures whose lifetimes do not overlap, so that they can share their memory.
+///
+/// # Description
+///
+/// Textures are assigned, in order of first use, to the first group whose last texture is no longer used. Textures
+/// within a group are sorted by first use.
+///
+/// # Arguments
+///
+/// * `lifetimes` - The range of passes over which each texture is used.
+fn alias_groups(mut lifetimes : Vec<(TextureID, Range<usize>)>) -> Vec<Vec<TextureID>> {
+    lifetimes.sort_by_key(|(texture, lifetime)| (lifetime.start, *texture));
+
+    // The end of the lifetime of the last texture of each group, and the group itself.
+    let mut groups = Vec::<(usize, Vec<TextureID>)>::new();
+    for (texture, lifetime) in lifetimes {
+        match groups.iter_mut().find(|(end, _)| *end <= lifetime.start) {
+            Some((end, group)) => {
+                *end = lifetime.end;
+                group.push(texture);
+            },
+            None => groups.push((lifetime.end, vec![texture])),
+        }
+    }
 
-    pub device : Arc<LogicalDevice>,
-    /// The initial state of this texture, as defined in the graph.
-    pub texture_info : &'a Texture,
+    groups.into_iter().map(|(_, group)| group).collect()
+}
 
-    /// The actual image handle.
-    pub handle : Option<Image>
+/// Returns the pipeline stages and accesses of a texture in the given layout.
+///
+/// # Description
+///
+/// Only writes need to be made available to later passes, so the source scope of a barrier only considers writes.
+///
+/// # Arguments
+///
+/// * `layout` - The layout of the texture.
+/// * `access` - How the texture is accessed.
+fn barrier_scope(layout : vk::ImageLayout, access : ResourceAccessFlags) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    let (stages, read, write) = match layout {
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PREINITIALIZED =>
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::NONE, vk::AccessFlags::NONE),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL =>
+            (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::COLOR_ATTACHMENT_READ, vk::AccessFlags::COLOR_ATTACHMENT_WRITE),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL =>
+            (vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ, vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL | vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL =>
+            (vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ, vk::AccessFlags::NONE),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL =>
+            (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::NONE),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL =>
+            (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::NONE, vk::AccessFlags::TRANSFER_WRITE),
+        vk::ImageLayout::PRESENT_SRC_KHR =>
+            (vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::NONE, vk::AccessFlags::NONE),
+        _ => (vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::MEMORY_READ, vk::AccessFlags::MEMORY_WRITE),
+    };
+
+    let mut mask = vk::AccessFlags::NONE;
+    if access.contains(ResourceAccessFlags::Read) {
+        mask |= read;
+    }
+    if access.contains(ResourceAccessFlags::Write) {
+        mask |= write;
+    }
+    (stages, mask)
 }
 
-impl TextureState<'_> {
-    pub fn new<'a>(texture : &'a Texture, device : &Arc<LogicalDevice>) -> TextureState<'a> {
-        TextureState {
-            device : device.clone(),
-            texture_info : texture,
-            current_layout : texture.layout(),
-            handle : None,
-        }
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use crate::graph::attachment::{Attachment, AttachmentLoadOperation, AttachmentOptions, AttachmentStoreOperation};
+    use crate::graph::pass::Pass;
+    use crate::graph::resource::{ResourceAccessFlags, ResourceID, ResourceOptions};
+    use crate::graph::texture::{Texture, TextureOptions};
+
+    use super::{alias_groups, barrier_scope, Graph};
+
+    #[test]
+    pub fn linear_schedule() {
+        let extent = vk::Extent2D { width : 64, height : 64 };
+
+        let mut graph = Graph::new();
+        let color = ResourceID::texture(Texture::new("Color", &extent).register(&mut graph));
+        let output = ResourceID::texture(Texture::new("Output", &extent).register(&mut graph));
+
+        let scene = Pass::new("Scene")
+            .add_texture("color", &color, TextureOptions::write(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .register(&mut graph);
+        let post = Pass::new("Post")
+            .add_texture("color", &color.virtualize(scene), TextureOptions::read(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .add_texture("output", &output, TextureOptions::write(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .register(&mut graph);
+        let present = Pass::new("Present")
+            .add_texture("output", &output.virtualize(post), TextureOptions::read(vk::ImageLayout::TRANSFER_SRC_OPTIMAL))
+            .register(&mut graph);
+
+        let schedule = graph.build();
+        assert_eq!(schedule.passes().collect::<Vec<_>>(), vec![scene, post, present]);
+
+        let transitions = schedule.transitions(scene);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!((transitions[0].old_layout, transitions[0].new_layout), (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL));
+        assert_eq!(transitions[0].src_access, vk::AccessFlags::NONE);
+
+        // The color written by the scene is made visible to the fragment shader of the post-processing pass.
+        let transitions = schedule.transitions(post);
+        assert_eq!(transitions.len(), 2);
+        assert_eq!((transitions[0].old_layout, transitions[0].new_layout), (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+        assert_eq!((transitions[0].src_stage, transitions[0].src_access), (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::COLOR_ATTACHMENT_WRITE));
+        assert!(transitions[0].dst_stage.contains(vk::PipelineStageFlags::FRAGMENT_SHADER));
+        assert_eq!(transitions[0].dst_access, vk::AccessFlags::SHADER_READ);
+        assert_eq!(transitions[1].new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        let transitions = schedule.transitions(present);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!((transitions[0].src_access, transitions[0].dst_access), (vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::AccessFlags::TRANSFER_READ));
     }
 
-    /// Records a layout transition command on the provided command buffer from the current image layout
-    /// to the given layout.
-    /// 
-    /// # Arguments
-    /// 
-    /// * `command_buffer` - The command buffer on which to record the layout transition.
-    /// * `to` - The layout to transition to.
-    pub fn emit_transition(&mut self, command_buffer : &CommandBuffer , to : vk::ImageLayout) {
-        self.emit_layout_transition(command_buffer, self.current_layout, to);
+    #[test]
+    pub fn barriers_without_transitions() {
+        let extent = vk::Extent2D { width : 64, height : 64 };
+
+        let mut graph = Graph::new();
+        let storage = ResourceID::texture(Texture::new("Storage", &extent)
+            .with_layout(vk::ImageLayout::GENERAL)
+            .register(&mut graph));
+
+        let first = Pass::new("First")
+            .add_texture("storage", &storage, TextureOptions::write(vk::ImageLayout::GENERAL))
+            .register(&mut graph);
+        let second = Pass::new("Second")
+            .add_texture("storage", &storage.virtualize(first), TextureOptions::read(vk::ImageLayout::GENERAL))
+            .register(&mut graph);
+        let third = Pass::new("Third")
+            .add_texture("storage", &storage.virtualize(first), TextureOptions::read(vk::ImageLayout::GENERAL))
+            .register(&mut graph);
+
+        let schedule = graph.build();
+
+        // The first pass neither changes the layout nor follows a write.
+        assert!(schedule.transitions(first).is_empty());
+
+        // The second pass reads what the first pass wrote, in the same layout.
+        let transitions = schedule.transitions(second).iter().chain(schedule.transitions(third)).collect::<Vec<_>>();
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].old_layout, transitions[0].new_layout);
+        assert_eq!((transitions[0].src_access, transitions[0].dst_access), (vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::MEMORY_READ));
     }
 
-    /// Records a layout transition command on the provided command buffer between the given image layouts.
-    /// 
-    /// # Description
-    /// 
-    /// This function does nothing if both layouts are identical.
-    /// 
-    /// If the texture was not yet allocated on the device, it will be before recording the command.
-    /// On top of this, if the initial state of the texture was not defined when it was added to the graph,
-    /// the first layout transition is suppressed; the image is created with the final layout as its initial
-    /// layout.
-    /// 
-    /// # Arguments
-    /// 
-    /// * `command_buffer` - The command buffer on which to record commands.
-    /// * `from` - The layout to transition from.
-    /// * `to` - The layout to transition to.
-    pub fn emit_layout_transition(&mut self, command_buffer : &CommandBuffer, from : vk::ImageLayout, to : vk::ImageLayout) {
-        // Nothing to be done if the layout does not change.
-        if from == to {
-            return;
-        }
+    #[test]
+    #[should_panic]
+    pub fn missing_producer_output() {
+        let extent = vk::Extent2D { width : 64, height : 64 };
 
-        // Create the image now if it doesn't exist. This has the added benefit of not creating the image
-        // if it is never used in the graph.
-        /*let record_command_transition = if self.handle.is_none() {
-            let mut create_info = self.texture_info.create_info();
+        let mut graph = Graph::new();
+        let color = ResourceID::texture(Texture::new("Color", &extent).register(&mut graph));
 
-            // Only record a layout transition if the image's layout wasn't undefined.
-            // If it was undefined, we pretend the image was initially created with the
-            // final layout of the transition.
-            let mut record_command_transition = true;
-            if self.current_layout == vk::ImageLayout::UNDEFINED {
-                create_info.initial_layout = to;
+        let reader = Pass::new("Reader")
+            .add_texture("color", &color, TextureOptions::read(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .register(&mut graph);
+        Pass::new("Other reader")
+            .add_texture("color", &color.virtualize(reader), TextureOptions::read(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .register(&mut graph);
 
-                record_command_transition = false;
-            }
+        graph.build();
+    }
 
-            let aspect_mask = Image::derive_aspect_flags(to, create_info.format);
-
-            self.handle = Image::new(self.texture_info.name().to_owned(),
-                &self.device,
-                create_info,
-                aspect_mask).into();
-            
-            record_command_transition
-        } else { true };
-
-        if record_command_transition {
-            // Record the layout transition command on the provided command buffer.
-            self.handle.as_ref()
-                .unwrap()
-                .layout_transition(command_buffer, from, to, vk::DependencyFlags::empty());
-        }
-        self.current_layout = to;*/
+    #[test]
+    pub fn barrier_scopes() {
+        assert_eq!(barrier_scope(vk::ImageLayout::UNDEFINED, ResourceAccessFlags::Write),
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::NONE));
+        assert_eq!(barrier_scope(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, ResourceAccessFlags::Read | ResourceAccessFlags::Write),
+            (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE));
+        assert_eq!(barrier_scope(vk::ImageLayout::TRANSFER_DST_OPTIMAL, ResourceAccessFlags::none()),
+            (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::NONE));
+    }
+
+    #[test]
+    pub fn transient_aliasing() {
+        let extent = vk::Extent2D { width : 64, height : 64 };
+
+        let mut graph = Graph::new();
+        let (depth_id, bloom_id) = (Texture::new("Depth", &extent).transient().register(&mut graph),
+            Texture::new("Bloom", &extent).transient().register(&mut graph));
+        let (depth, bloom) = (ResourceID::texture(depth_id), ResourceID::texture(bloom_id));
+        let output = ResourceID::texture(Texture::new("Output", &extent).register(&mut graph));
+
+        let scene = Pass::new("Scene")
+            .add_texture("depth", &depth, TextureOptions::write(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL))
+            .add_texture("output", &output, TextureOptions::write(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .register(&mut graph);
+        let bright = Pass::new("Bright")
+            .add_texture("output", &output.virtualize(scene), TextureOptions::read(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .add_texture("bloom", &bloom, TextureOptions::write(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .register(&mut graph);
+        let blur = Pass::new("Blur")
+            .add_texture("bloom", &bloom.virtualize(bright), TextureOptions::read(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .register(&mut graph);
+
+        let schedule = graph.build();
+        assert_eq!(schedule.passes().collect::<Vec<_>>(), vec![scene, bright, blur]);
+
+        // The depth is no longer used when the bloom is first written, so both share their memory.
+        assert_eq!(schedule.aliases(), &[vec![depth_id, bloom_id]]);
+
+        // The bloom waits for the depth writes before overwriting the memory.
+        let transition = schedule.transitions(bright).iter().find(|transition| transition.texture == bloom_id).unwrap();
+        assert_eq!(transition.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(transition.src_access, vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+
+        // The usage of the images is derived from the layouts the passes use the textures in.
+        assert_eq!(graph.texture_usage(depth_id), vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
+        assert_eq!(graph.texture_usage(bloom_id), vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED);
+    }
+
+    #[test]
+    pub fn overlapping_transients() {
+        let extent = vk::Extent2D { width : 64, height : 64 };
+
+        let mut graph = Graph::new();
+        let textures = ["First", "Second", "Third"].map(|name| Texture::new(name, &extent).transient().register(&mut graph));
+
+        // The first texture lives over the first two passes, the second over the second pass only, and the third
+        // over the third pass only.
+        let groups = alias_groups(vec![(textures[1], 1..2), (textures[0], 0..2), (textures[2], 2..3)]);
+        assert_eq!(groups, vec![vec![textures[0], textures[2]], vec![textures[1]]]);
+    }
+
+    #[test]
+    pub fn attachment_ordering() {
+        let mut graph = Graph::new();
+        let color = ResourceID::attachment(Attachment::new("Color").register(&mut graph));
+
+        let clear = AttachmentOptions {
+            load_operation : AttachmentLoadOperation::Clear(vk::ClearValue::default()),
+            store_operation : AttachmentStoreOperation::Store,
+            ..Default::default()
+        };
+        let load = AttachmentOptions {
+            load_operation : AttachmentLoadOperation::Load,
+            store_operation : AttachmentStoreOperation::DontCare,
+            ..Default::default()
+        };
+        assert_eq!(clear.access_flags(), ResourceAccessFlags::Write);
+        assert_eq!(load.access_flags(), ResourceAccessFlags::Read);
+
+        // The attachment is the only resource the passes share.
+        let scene = Pass::new("Scene")
+            .add_attachment("color", &color, clear)
+            .register(&mut graph);
+        let overlay = Pass::new("Overlay")
+            .add_attachment("color", &color.virtualize(scene), load)
+            .register(&mut graph);
+
+        assert!(scene.get(&graph).outputs().any(|output| output == &color));
+        assert!(overlay.get(&graph).inputs().any(|input| input.devirtualize() == color.devirtualize()));
+        assert_eq!(graph.build().passes().collect::<Vec<_>>(), vec![scene, overlay]);
     }
 }
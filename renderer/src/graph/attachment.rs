@@ -77,13 +77,13 @@ impl ResourceOptions for AttachmentOptions {
         let mut flags = ResourceAccessFlags::none();
 
         match self.load_operation {
-            AttachmentLoadOperation::Load => flags = flags.and(ResourceAccessFlags::Read),
-            AttachmentLoadOperation::Clear(_) => flags = flags.and(ResourceAccessFlags::Write),
+            AttachmentLoadOperation::Load => flags |= ResourceAccessFlags::Read,
+            AttachmentLoadOperation::Clear(_) => flags |= ResourceAccessFlags::Write,
             AttachmentLoadOperation::DontCare => (),
         };
 
         match self.store_operation {
-            AttachmentStoreOperation::Store => flags = flags.and(ResourceAccessFlags::Write),
+            AttachmentStoreOperation::Store => flags |= ResourceAccessFlags::Write,
             AttachmentStoreOperation::DontCare => (),
         };
 
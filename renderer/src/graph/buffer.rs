@@ -58,10 +58,13 @@ impl Identifiable for Buffer {
     fn name(&self) -> &'static str { self.name }
 }
 
-pub struct BufferOptions { }
+pub struct BufferOptions {
+    /// Whether the pass reads from the buffer, writes to it, or both.
+    pub access : ResourceAccessFlags,
+}
 
 impl ResourceOptions for BufferOptions {
     fn access_flags(&self) -> ResourceAccessFlags {
-        todo!()
+        self.access
     }
 }
@@ -69,6 +69,16 @@ impl ResourceID {
         Self::Physical(PhysicalResourceID::Attachment(att))
     }
 
+    /// Returns a virtual resource referring to the same physical resource, as used by the given pass. Passes using the
+    /// virtual resource are scheduled after that pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - The pass that produces the resource.
+    pub fn virtualize(&self, pass : PassID) -> ResourceID {
+        ResourceID::Virtual(pass, self.devirtualize().clone())
+    }
+
     pub fn devirtualize(&self) -> &PhysicalResourceID {
         match self {
             ResourceID::Physical(res) => res,
@@ -4,6 +4,7 @@ use crate::graph::Graph;
 use crate::graph::manager::Identifier;
 use crate::graph::pass::Pass;
 use crate::graph::resource::{Identifiable, PhysicalResourceID, ResourceAccessFlags, ResourceID, ResourceOptions};
+use crate::vk::image::{Image, ImageCreateInfo};
 
 pub struct Texture { // Graph wrapper for vk::Image
     id   : TextureID,
@@ -16,6 +17,7 @@ pub struct Texture { // Graph wrapper for vk::Image
     levels : u32,
     layers : u32,
     format : vk::Format,
+    transient : bool,
 }
 
 /// A trait that provides image extent and image type.
@@ -53,6 +55,36 @@ impl Texture { // Vulkan API exposed
             .image_type(self.image_type)
             .tiling(self.tiling)
     }
+
+    /// Returns the description of an image backing this texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `usage` - How the image is used.
+    pub fn image_info(&self, usage : vk::ImageUsageFlags) -> ImageCreateInfo {
+        let view_type = match self.image_type {
+            vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+            vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+            _ => vk::ImageViewType::TYPE_2D,
+        };
+        let aspect_layout = if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+
+        ImageCreateInfo::default()
+            .name(self.name.to_owned())
+            .format(self.format)
+            .aspect(Image::derive_aspect_flags(aspect_layout, self.format))
+            .extent(self.extent)
+            .image_type(self.image_type, view_type)
+            .levels(0, self.levels)
+            .layers(0, self.layers)
+            .tiling(self.tiling)
+            .usage(usage)
+            .initial_layout(self.layout)
+    }
 }
 
 impl Texture {
@@ -75,7 +107,8 @@ impl Texture {
             layout : vk::ImageLayout::UNDEFINED,
             levels : 1,
             layers : 1,
-            format : vk::Format::UNDEFINED
+            format : vk::Format::UNDEFINED,
+            transient : false,
         }
     }
 
@@ -83,11 +116,27 @@ impl Texture {
     #[inline] pub fn levels(&self) -> u32 { self.levels }
     #[inline] pub fn layers(&self) -> u32 { self.layers }
     #[inline] pub fn tiling(&self) -> vk::ImageTiling { self.tiling }
+    #[inline] pub fn format(&self) -> vk::Format { self.format }
+    #[inline] pub fn is_transient(&self) -> bool { self.transient }
 
     value_builder! { with_layout, layout, vk::ImageLayout }
     value_builder! { with_levels, levels, u32 }
     value_builder! { with_layers, layers, u32 }
     value_builder! { with_tiling, tiling, vk::ImageTiling }
+    value_builder! { with_format, format, vk::Format }
+
+    /// Marks this texture as transient: its contents are only needed between the first and the last pass that use it
+    /// within a frame. The graph creates the images of transient textures, and the textures whose lifetimes do not
+    /// overlap share their memory (see [`Graph::create_transients`]).
+    ///
+    /// # Panics
+    ///
+    /// * Panics if this texture was given an initial layout; transient textures start undefined.
+    #[inline] pub fn transient(mut self) -> Self {
+        assert_eq!(self.layout, vk::ImageLayout::UNDEFINED, "The contents of transient textures are undefined when first used");
+        self.transient = true;
+        self
+    }
 
     /// Registers this attachment on the given graph.
     ///
@@ -165,14 +214,62 @@ impl Identifiable for Texture {
     fn name(&self) -> &'static str { self.name }
 }
 
-#[derive(Default)]
 pub struct TextureOptions {
+    /// The usages the image backing the texture needs for the pass. [`TextureOptions::read`] and
+    /// [`TextureOptions::write`] derive them from the layout; other usages can be added to them.
     pub usage_flags : vk::ImageUsageFlags,
+    /// The layout the texture must be in during the pass, or [`None`] to keep its current layout.
     pub layout : Option<vk::ImageLayout>,
+    /// Whether the pass reads from the texture, writes to it, or both.
+    pub access : ResourceAccessFlags,
+}
+
+impl TextureOptions {
+    /// Returns the options of a texture that a pass reads from in the given layout.
+    pub fn read(layout : vk::ImageLayout) -> Self {
+        Self { usage_flags : layout_usage(layout), layout : Some(layout), access : ResourceAccessFlags::Read }
+    }
+
+    /// Returns the options of a texture that a pass writes to in the given layout.
+    pub fn write(layout : vk::ImageLayout) -> Self {
+        Self { usage_flags : layout_usage(layout), layout : Some(layout), access : ResourceAccessFlags::Write }
+    }
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            usage_flags : Default::default(),
+            layout : None,
+            access : ResourceAccessFlags::none(),
+        }
+    }
 }
 
 impl ResourceOptions for TextureOptions {
     fn access_flags(&self) -> ResourceAccessFlags {
-        todo!()
+        self.access
+    }
+}
+
+/// Returns the usages an image needs to be used in the given layout.
+///
+/// # Arguments
+///
+/// * `layout` - The layout of the image.
+fn layout_usage(layout : vk::ImageLayout) -> vk::ImageUsageFlags {
+    match layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+            | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        // Depth read-only layouts are used both for depth tests and for sampling.
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL =>
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::SAMPLED,
+        vk::ImageLayout::GENERAL => vk::ImageUsageFlags::STORAGE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::ImageUsageFlags::TRANSFER_DST,
+        _ => vk::ImageUsageFlags::empty(),
     }
 }
@@ -1 +1,3 @@
-pub mod context;
\ No newline at end of file
+pub mod context;
+pub mod fonts;
+pub mod theme;
\ No newline at end of file
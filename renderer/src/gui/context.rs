@@ -1,15 +1,20 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::mem::{size_of, size_of_val};
+use std::mem::{self, size_of};
+use std::path::Path;
 use std::slice;
+use std::sync::Arc;
+use std::time::Duration;
 use ash::vk::{self};
-use bytemuck::bytes_of;
 use egui::epaint::{ImageDelta, Primitive};
-use egui::{Color32, Context, FontDefinitions, Style, TextureId, TexturesDelta, Ui, ViewportId};
+use egui::{Color32, Context, FontDefinitions, Style, TextureFilter, TextureId, TextureOptions, TextureWrapMode, TexturesDelta, Ui, ViewportId, Visuals};
+use egui_winit::winit::dpi::PhysicalSize;
 use egui_winit::winit::event::WindowEvent;
 use egui_winit::EventResponse;
 use gpu_allocator::vulkan::AllocatorVisualizer;
 use puffin::profile_scope;
+use crate::gui::fonts;
+use crate::gui::theme::Theme;
 use crate::orchestration::rendering::{Renderer, RenderingContext};
 use crate::traits::handle::Handle;
 use crate::vk::buffer::{Buffer, DynamicBufferBuilder, DynamicInitializer, StaticBufferBuilder, StaticInitializer};
@@ -17,14 +22,15 @@ use crate::vk::command_buffer::{BarrierPhase, CommandBuffer};
 use crate::vk::command_pool::CommandPool;
 use crate::vk::descriptor::layout::DescriptorSetLayout;
 use crate::vk::descriptor::set::DescriptorSetInfo;
+use crate::vk::fence_pool::FencePool;
 use crate::vk::frame_data::FrameData;
-use crate::vk::framebuffer::Framebuffer;
-use crate::vk::helpers::{prepare_buffer_image_copy, with_delta};
+use crate::vk::framebuffer::{Framebuffer, FramebufferCache};
 use crate::vk::image::{Image, ImageCreateInfo};
 use crate::vk::pipeline::layout::{PipelineLayout, PipelineLayoutInfo};
-use crate::vk::pipeline::{DepthOptions, Pipeline, PipelineInfo, Vertex};
+use crate::vk::pipeline::{DepthOptions, Pipeline, PipelineInfo, StencilOptions, Vertex};
 use crate::vk::queue::{Queue, QueueAffinity};
 use crate::vk::render_pass::{RenderPass, SubpassAttachment};
+use crate::vk::renderer::DynamicState;
 use crate::vk::sampler::Sampler;
 use crate::vk::swapchain::Swapchain;
 use crate::window::Window;
@@ -32,6 +38,8 @@ use crate::window::Window;
 // A GUI texture.
 struct Texture {
     image : Image,
+    // The filtering and wrapping egui requested for this texture.
+    options : TextureOptions,
 }
 
 impl Texture {
@@ -79,15 +87,39 @@ impl Vertex for InterfaceVertex {
 }
 
 impl<T : Default> Renderer for Interface<T> {
-    fn create_framebuffers(&self, swapchain : &Swapchain) -> Vec<Framebuffer> {
-        let mut framebuffers = vec![];
-        for image in &swapchain.images {
-            framebuffers.push(self.render_pass.create_framebuffer(swapchain, image));
+    fn create_framebuffers(&self, swapchain : &Swapchain, cache : &mut FramebufferCache) -> Vec<Arc<Framebuffer>> {
+        if self.stencil_images.is_empty() {
+            return self.render_pass.framebuffers(swapchain, cache);
         }
-        framebuffers
+
+        // The stencil attachment is owned by the interface rather than by the swapchain.
+        swapchain.images.iter().zip(&self.stencil_images)
+            .map(|(image, stencil)| {
+                let attachments = match &image.resolve {
+                    Some(multisampled) if self.samples != vk::SampleCountFlags::TYPE_1 => vec![multisampled.view(), stencil.view(), image.present.view()],
+                    _ => vec![image.present.view(), stencil.view()],
+                };
+                cache.get(&self.rendering_context, self.render_pass.handle(), &attachments, swapchain.extent, swapchain.layer_count())
+            })
+            .collect()
     }
 
+    /// Forwards an event to egui.
+    ///
+    /// # Description
+    ///
+    /// Keyboard shortcuts for cut, copy and paste are translated by egui-winit, which also reads from the system
+    /// clipboard when pasting. Text copied by egui is written to the clipboard when the platform output of the frame
+    /// is handled in [`Renderer::record_commands`]. Both require [`WindowEvent::ModifiersChanged`] to be forwarded here
+    /// as well.
     fn handle_event(&mut self, event : &WindowEvent) -> EventResponse {
+        // The application recreates the swapchain when the scale factor changes, but the interface may be drawn with
+        // the new scale factor before that happens.
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            let (pixels_per_point, _) = rescale(*scale_factor, self.rendering_context.window.handle().inner_size());
+            self.scale_factor = pixels_per_point.into();
+        }
+
         self.egui.on_window_event(self.rendering_context.window.handle(), event)
     }
 
@@ -103,6 +135,7 @@ impl<T : Default> Renderer for Interface<T> {
 
         let output = self.context.end_frame();
         self.egui.handle_platform_output(window.handle(), output.platform_output.clone());
+        self.repaint_delay = repaint_delay(&output);
 
         let clipped_meshes = self.context.tessellate(output.shapes, self.scale_factor as _);
         self.paint(&frame.cmd, swapchain, framebuffer, frame.index, clipped_meshes, output.textures_delta);
@@ -111,16 +144,75 @@ impl<T : Default> Renderer for Interface<T> {
     fn marker_data<'a>(&self) -> (&'a str, [f32; 4]) {
         ("Draw GUI", [0.0; 4] )
     }
+
+    fn on_swapchain_recreated(&mut self, swapchain : &Swapchain) {
+        // The window may have moved to a monitor with a different scale factor.
+        self.scale_factor = self.rendering_context.window.handle().scale_factor();
+
+        if let Some(format) = self.stencil_format {
+            self.stencil_images = Self::create_stencil_images(&self.rendering_context, swapchain, format, self.samples);
+        }
+
+        // Per-image resources are indexed by frame; rebuild them if the number of images changed. Descriptor set
+        // layouts are all identical, so the pipeline layout remains compatible with the new ones.
+        if self.frame_data.len() != swapchain.image_count() {
+            // The device is idle while the swapchain is recreated, so retired textures can be destroyed right away,
+            // along with the descriptor sets that reference them.
+            self.frame_data.clear();
+
+            let descriptor_set_layouts = (0..swapchain.image_count())
+                .map(|_| Self::create_descriptor_set_layout(&self.rendering_context))
+                .collect::<Vec<_>>();
+            self.frame_data = Self::create_frame_data(&self.rendering_context, descriptor_set_layouts);
+        }
+    }
+
+    fn is_enabled(&self) -> bool { self.enabled }
+
+    fn set_enabled(&mut self, enabled : bool) { self.enabled = enabled; }
+
+    fn repaint_after(&self) -> Option<Duration> { self.repaint_delay }
 }
 
 // --
 
+/// Resources written by the CPU while recording a frame. There is one instance per frame in flight; the viewport
+/// waits on the in-flight fence of a frame before recording it again, so these are never overwritten while the GPU
+/// still reads them.
 pub struct InterfaceFrameData {
     vertex_buffer : Buffer,
     index_buffer : Buffer,
     descriptor_set_layout : DescriptorSetLayout,
 }
 
+/// Resources that frames in flight may still use, retired by the frame that last used them.
+///
+/// # Description
+///
+/// Resources retired while recording a frame are released the next time that frame is recorded. By then, the viewport
+/// waited for the frame, and every frame before it, to complete, so that no command buffer uses them anymore.
+struct Retirement<T> {
+    frames : Vec<Vec<T>>,
+}
+
+impl<T> Retirement<T> {
+    /// Creates an empty retirement list for the given number of frames in flight.
+    fn new(frames_in_flight : usize) -> Self {
+        Self { frames : (0..frames_in_flight).map(|_| vec![]).collect() }
+    }
+
+    /// Returns the resources retired the last time the given frame was recorded. They can be destroyed once the
+    /// frame completed.
+    fn release(&mut self, frame_index : usize) -> Vec<T> {
+        mem::take(&mut self.frames[frame_index])
+    }
+
+    /// Retires resources that the given frame is the last to use.
+    fn retire(&mut self, frame_index : usize, resources : impl IntoIterator<Item = T>) {
+        self.frames[frame_index].extend(resources);
+    }
+}
+
 type InterfaceRenderDelegate<T> = fn(&Context, &mut T);
 
 pub struct Interface<State : Default> {
@@ -132,13 +224,25 @@ pub struct Interface<State : Default> {
     pipeline_layout : PipelineLayout,
     pipeline : Pipeline,
     command_pool : CommandPool,
+    fence_pool : FencePool,
     frame_data : Vec<InterfaceFrameData>,
     render_pass : RenderPass,
+    // Only set if the interface was created with a stencil attachment.
+    stencil_format : Option<vk::Format>,
+    stencil_images : Vec<Image>,
+    // The sample count of the attachments the interface is drawn to.
+    samples : vk::SampleCountFlags,
+    mask_pipeline : Option<Pipeline>,
     pub scale_factor : f64,
-    // The sampler used when updating textures used by the GUI.
-    sampler : Sampler,
+    // The samplers used by textures of the GUI, keyed by the options egui requested.
+    samplers : HashMap<TextureOptions, Sampler>,
     textures : HashMap<TextureId, Texture>,
+    // Textures freed or replaced by a frame, destroyed the next time that frame is recorded.
+    retired_textures : Retirement<Texture>,
     delegate : InterfaceRenderDelegate<State>,
+    enabled : bool,
+    // The delay after which egui asked to be repainted when the last frame was drawn.
+    repaint_delay : Option<Duration>,
 
     pub(in crate) visualizer : AllocatorVisualizer,
 
@@ -150,6 +254,92 @@ pub struct Interface<State : Default> {
 pub struct InterfaceOptions {
     pub fonts : FontDefinitions,
     pub style : Style,
+    /// If set, replaces the visuals of [`InterfaceOptions::style`].
+    pub visuals : Option<Visuals>,
+    /// If set, the interface is drawn with a stencil attachment; see [`InterfaceOptions::stencil`].
+    pub stencil : Option<StencilOptions>,
+    /// If set, the interface is drawn to the multisampled color attachment of the swapchain; see
+    /// [`InterfaceOptions::multisampled`].
+    pub multisampled : bool,
+}
+
+impl InterfaceOptions {
+    value_builder! { fonts, fonts, FontDefinitions }
+    value_builder! { style, style, Style }
+
+    /// Overrides the visuals of the style used by the interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `visuals` - The visuals to use; these take precedence over the ones provided by [`InterfaceOptions::style`].
+    #[inline] pub fn visuals(mut self, visuals : Visuals) -> Self {
+        self.visuals = Some(visuals);
+        self
+    }
+
+    /// Loads the fonts found in the given directory. Fonts go in its `proportional` and `monospace` subdirectories,
+    /// and are prioritized by file name; egui's built-in fonts are used as fallbacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to load fonts from.
+    ///
+    /// See [`fonts::load_fonts_from`] for details.
+    #[inline] pub fn load_fonts_from<P : AsRef<Path>>(mut self, dir : P) -> Self {
+        self.fonts = fonts::load_fonts_from(dir);
+        self
+    }
+
+    /// Draws the interface with a stencil attachment, which allows clipping meshes to arbitrary shapes with
+    /// [`StencilCommand`].
+    ///
+    /// # Description
+    ///
+    /// The stencil attachment is cleared to zero at the beginning of every frame. Meshes are drawn with the given
+    /// stencil options, except for the reference value and compare mask, which are set by [`StencilCommand::Mask`] and
+    /// [`StencilCommand::Unmask`]. [`InterfaceOptions::default_stencil`] is what most applications want.
+    ///
+    /// # Arguments
+    ///
+    /// * `stencil` - The stencil options of the pipeline meshes are drawn with.
+    #[inline] pub fn stencil(mut self, stencil : StencilOptions) -> Self {
+        self.stencil = Some(stencil);
+        self
+    }
+
+    /// Draws the interface to the multisampled color attachment of the swapchain, rather than to the presented image.
+    ///
+    /// # Description
+    ///
+    /// By default, the interface owns a standalone render pass that draws over the presented image, after the scene
+    /// was resolved into it; its pipelines are single-sampled. When multisampled, the interface instead draws to the
+    /// multisampled color attachment the scene was rendered to, which it resolves into the presented image; its
+    /// pipelines and stencil attachment then use the sample count of the swapchain. This is required whenever the
+    /// interface shares its render pass, or its multisampled attachments, with a multisampled scene. The scene must
+    /// store its multisampled color attachment, as [`Swapchain::create_render_pass`] does.
+    ///
+    /// If the swapchain is not multisampled, this has no effect. This can't be used with a scaled swapchain, whose
+    /// multisampled attachments are smaller than the presented image.
+    #[inline] pub fn multisampled(mut self) -> Self {
+        self.multisampled = true;
+        self
+    }
+
+    /// Draws the interface with a stencil attachment, where meshes are only drawn if the stencil attachment holds the
+    /// reference value. See [`InterfaceOptions::stencil`].
+    #[inline] pub fn default_stencil(self) -> Self {
+        self.stencil(StencilOptions::new(vk::CompareOp::EQUAL))
+    }
+
+    /// Uses the style of the given theme.
+    ///
+    /// # Arguments
+    ///
+    /// * `theme` - The theme to apply.
+    #[inline] pub fn theme(mut self, theme : &dyn Theme) -> Self {
+        self.style = theme.style();
+        self
+    }
 }
 
 impl<State : Default> Interface<State> {
@@ -165,30 +355,74 @@ impl<State : Default> Interface<State> {
         } else {
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
         };
-        
+
+        let samples = interface_samples(options.multisampled, swapchain.sample_count);
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+
+        // A multisampled attachment holds the scene, and is resolved again once the interface is drawn over it.
         let render_pass = RenderPass::builder()
             .color_attachment(
                 swapchain.color_format(),
-                vk::SampleCountFlags::TYPE_1,
+                samples,
                 vk::AttachmentLoadOp::LOAD,
                 vk::AttachmentStoreOp::STORE,
                 vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                final_format
-            )
-            .subpass(vk::PipelineBindPoint::GRAPHICS, &[
-                SubpassAttachment::color(0)
-            ], None)
-            .dependency(
-                vk::SUBPASS_EXTERNAL, 0,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-            ).build(context);
+                if multisampled { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL } else { final_format }
+            );
+        let render_pass = if multisampled {
+            render_pass.resolve_attachment(swapchain.color_format(), final_format)
+        } else {
+            render_pass
+        };
+
+        let mut stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+        let mut access = vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+        let (render_pass, stencil_attachment) = match options.stencil {
+            Some(_) => {
+                stages |= vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+                access |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+
+                // Masks only live for the duration of a frame, so the depth aspect and the contents are discarded.
+                let render_pass = render_pass.depth_stencil_attachment(
+                    Self::find_stencil_format(context),
+                    samples,
+                    (vk::AttachmentLoadOp::DONT_CARE, vk::AttachmentStoreOp::DONT_CARE),
+                    (vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::DONT_CARE));
+                (render_pass, Some(SubpassAttachment::depth(0)))
+            },
+            None => (render_pass, None),
+        };
+
+        let mut attachments = vec![SubpassAttachment::color(0)];
+        if multisampled {
+            attachments.push(SubpassAttachment::resolve(0));
+        }
+
+        let render_pass = render_pass
+            .subpass(vk::PipelineBindPoint::GRAPHICS, &attachments, stencil_attachment)
+            .dependency(vk::SUBPASS_EXTERNAL, 0, stages, stages, access, access)
+            .build(context);
 
         Self::new(options, swapchain, context, render_pass, delegate)
     }
 
+    /// Creates a new interface that draws to the given render pass.
+    ///
+    /// # Description
+    ///
+    /// If [`InterfaceOptions::stencil`] is set, the render pass must have exactly two attachments: the color
+    /// attachment, followed by a depth/stencil attachment with the format returned by
+    /// [`Interface::find_stencil_format`], which is cleared when the render pass begins. The interface owns the
+    /// images of the stencil attachment. [`Interface::supplier`] creates such a render pass.
+    ///
+    /// If [`InterfaceOptions::multisampled`] is set, the color attachment, as well as the stencil attachment if any,
+    /// must have the sample count of the swapchain, and be followed by a resolve attachment. Otherwise, they must be
+    /// single-sampled.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if [`InterfaceOptions::multisampled`] is set and the swapchain is scaled, since the interface is drawn
+    ///   at the native resolution of the swapchain.
     pub fn new(
         options : InterfaceOptions,
         swapchain : &Swapchain,
@@ -196,9 +430,14 @@ impl<State : Default> Interface<State> {
         render_pass : RenderPass,
         delegate : InterfaceRenderDelegate<State>
     ) -> Interface<State> {
+        assert!(!options.multisampled || !swapchain.is_scaled(), "A multisampled interface cannot draw to a scaled swapchain");
+
         let egui_context = Context::default();
         egui_context.set_fonts(options.fonts);
         egui_context.set_style(options.style);
+        if let Some(visuals) = options.visuals {
+            egui_context.set_visuals(visuals);
+        }
 
         let egui = egui_winit::State::new(egui_context.clone(),
             ViewportId::ROOT,
@@ -207,12 +446,9 @@ impl<State : Default> Interface<State> {
             Some(context.device.physical_device.properties.limits.max_image_dimension2_d as usize));
 
         // Create a descriptor pool.
-        let descriptor_set_layouts = (0..swapchain.image_count()).map(|_|
-            DescriptorSetLayout::builder()
-                .sets(1024)
-                .binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, 1)
-                .build(&context)
-        ).collect::<Vec<_>>();
+        let descriptor_set_layouts = (0..swapchain.image_count())
+            .map(|_| Self::create_descriptor_set_layout(context))
+            .collect::<Vec<_>>();
 
         let pipeline_layout = PipelineLayoutInfo::default()
             .layouts(&descriptor_set_layouts)
@@ -224,35 +460,149 @@ impl<State : Default> Interface<State> {
             .build(&context);
         context.device.set_handle_name(pipeline_layout.handle(), &"GUI Pipeline layout".to_owned());
 
-        let pipeline = PipelineInfo::default()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .layout(pipeline_layout.handle())
-            .depth(DepthOptions::disabled())
+        let depth = match options.stencil {
+            Some(stencil) => DepthOptions::disabled().stencil(stencil
+                .reference(DynamicState::Dynamic)
+                .compare_mask(DynamicState::Dynamic)),
+            None => DepthOptions::disabled(),
+        };
+        let samples = interface_samples(options.multisampled, swapchain.sample_count);
+        let pipeline = Self::pipeline_info(&pipeline_layout, &render_pass, swapchain.color_format(), samples)
+            .depth(depth)
             .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default()
                 .color_write_mask(vk::ColorComponentFlags::RGBA)
                 .blend_enable(true)
                 .src_color_blend_factor(vk::BlendFactor::ONE)
                 .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA))
+            .build(&context);
+        context.device.set_handle_name(pipeline.handle(), &"GUI Pipeline".to_owned());
+
+        // Masks are drawn with a pipeline that writes the reference value to the stencil attachment, and nothing to
+        // the color attachment.
+        let mask_pipeline = options.stencil.map(|_| {
+            let pipeline = Self::pipeline_info(&pipeline_layout, &render_pass, swapchain.color_format(), samples)
+                .depth(DepthOptions::disabled().stencil(StencilOptions::new(vk::CompareOp::ALWAYS)
+                    .ops(vk::StencilOp::KEEP, vk::StencilOp::REPLACE, vk::StencilOp::KEEP)
+                    .reference(DynamicState::Dynamic)))
+                .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default()
+                    .color_write_mask(vk::ColorComponentFlags::empty()))
+                .build(&context);
+            context.device.set_handle_name(pipeline.handle(), &"GUI Mask Pipeline".to_owned());
+            pipeline
+        });
+
+        let stencil_format = options.stencil.map(|_| Self::find_stencil_format(context));
+        let stencil_images = stencil_format
+            .map(|format| Self::create_stencil_images(context, swapchain, format, samples))
+            .unwrap_or_default();
+
+        let frame_data = Self::create_frame_data(context, descriptor_set_layouts);
+        let retired_textures = Retirement::new(frame_data.len());
+
+        let graphics_queue = context.device.get_queue(QueueAffinity::Graphics, 0).unwrap();
+        let command_pool = CommandPool::builder(graphics_queue.family())
+            .reset()
+            .build(&context);
+        let fence_pool = FencePool::new(&context);
+
+        Self {
+            context: egui_context,
+            egui,
+            
+            rendering_context : context.clone(),
+            pipeline_layout,
+            pipeline,
+
+            frame_data,
+            samplers : HashMap::default(),
+            command_pool,
+            fence_pool,
+
+            scale_factor : context.window.handle().scale_factor(),
+
+            textures : HashMap::default(),
+            retired_textures,
+            render_pass,
+            stencil_format,
+            stencil_images,
+            samples,
+            mask_pipeline,
+
+            delegate,
+            enabled : true,
+            repaint_delay : Some(Duration::ZERO),
+
+            state : State::default(),
+            visualizer : AllocatorVisualizer::new(),
+        }
+    }
+
+    /// Returns the parts of the pipelines of the interface that do not depend on the stencil options.
+    ///
+    /// # Description
+    ///
+    /// egui blends in gamma space: vertex colors and texels are sRGB-encoded, and so is their product. The fragment
+    /// shader outputs that product as is to `_UNORM` targets, and converts it to linear for `_SRGB` targets, which
+    /// encode it back when writing.
+    fn pipeline_info(layout : &PipelineLayout, render_pass : &RenderPass, color_format : vk::Format, samples : vk::SampleCountFlags) -> PipelineInfo {
+        PipelineInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .layout(layout)
             .cull_mode(vk::CullModeFlags::NONE)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
             .render_pass(render_pass.handle(), 0)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(samples)
             .pool()
             .vertex::<InterfaceVertex>()
             .add_shader("./assets/gui.vert".into(), vk::ShaderStageFlags::VERTEX)
             .add_shader("./assets/gui.frag".into(), vk::ShaderStageFlags::FRAGMENT)
-            .build(&context);
-        context.device.set_handle_name(pipeline.handle(), &"GUI Pipeline".to_owned());
+            // LINEAR_OUTPUT
+            .add_specialization(&vk::Bool32::from(is_srgb_format(color_format)), 0)
+    }
 
-        let sampler = Sampler::builder()
-            .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE, vk::SamplerAddressMode::CLAMP_TO_EDGE, vk::SamplerAddressMode::CLAMP_TO_EDGE)
-            .anisotropy(false)
-            .filter(vk::Filter::LINEAR, vk::Filter::LINEAR)
-            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-            .lod(0.0, vk::LOD_CLAMP_NONE)
-            .build(&context);
-        context.device.set_handle_name(sampler.handle(), &"GUI Sampler".to_owned());
+    /// Returns the format of the stencil attachment of the interface, preferring formats without a depth aspect.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the device does not support any format with a stencil aspect as a depth/stencil attachment.
+    pub fn find_stencil_format(context : &RenderingContext) -> vk::Format {
+        RenderPass::find_supported_format(context,
+            &[
+                vk::Format::S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D16_UNORM_S8_UINT,
+            ],
+            vk::ImageTiling::OPTIMAL,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+        ).expect("Failed to find an usable stencil format")
+    }
+
+    /// Creates the images of the stencil attachment, one per image of the swapchain.
+    fn create_stencil_images(context : &RenderingContext, swapchain : &Swapchain, format : vk::Format, samples : vk::SampleCountFlags) -> Vec<Image> {
+        (0..swapchain.image_count()).map(|i| {
+            ImageCreateInfo::default()
+                .aspect(stencil_aspect(format))
+                .name(format!("GUI/Stencil[{}]", i))
+                .image_type(vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .levels(0, 1)
+                .layers(0, 1)
+                .samples(samples)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .extent(vk::Extent3D {
+                    width : swapchain.extent.width,
+                    height : swapchain.extent.height,
+                    depth : 1
+                })
+                .build(context)
+        }).collect()
+    }
 
+    /// Creates the per-image resources of the interface: vertex and index buffers, as well as a descriptor pool.
+    fn create_frame_data(context : &RenderingContext, descriptor_set_layouts : Vec<DescriptorSetLayout>) -> Vec<InterfaceFrameData> {
         let mut frame_data = vec![];
         for descriptor_set_layout in descriptor_set_layouts.into_iter() {
             let vertex_buffer = StaticBufferBuilder::fixed_size()
@@ -262,6 +612,7 @@ impl<State : Default> Interface<State> {
                 .build(&context, 1024 * 1024 * 4);
             context.device.set_handle_name(vertex_buffer.handle(), &"GUI Vertex buffer".to_owned());
 
+            // egui only produces 32-bit indices; 16-bit index buffers are meant for user geometry.
             let index_buffer = StaticBufferBuilder::fixed_size()
                 .cpu_to_gpu()
                 .linear(true)
@@ -277,33 +628,33 @@ impl<State : Default> Interface<State> {
             });
         }
 
-        let graphics_queue = context.device.get_queue(QueueAffinity::Graphics, 0).unwrap();
-        let command_pool = CommandPool::builder(graphics_queue.family())
-            .reset()
-            .build(&context);
-
-        Self {
-            context: egui_context,
-            egui,
-            
-            rendering_context : context.clone(),
-            pipeline_layout,
-            pipeline,
-
-            frame_data,
-            sampler,
-            command_pool,
-
-            scale_factor : context.window.handle().scale_factor(),
+        frame_data
+    }
 
-            textures : HashMap::default(),
-            render_pass,
+    /// Creates a sampler that filters and wraps textures as requested by egui.
+    fn create_sampler(context : &RenderingContext, options : TextureOptions) -> Sampler {
+        let address_mode = address_mode(options.wrap_mode);
+        let mipmap_mode = match options.minification {
+            TextureFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+            TextureFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+        };
 
-            delegate,
+        let sampler = Sampler::builder()
+            .address_mode(address_mode, address_mode, address_mode)
+            .anisotropy(false)
+            .filter(filter(options.minification), filter(options.magnification))
+            .mipmap_mode(mipmap_mode)
+            .lod(0.0, vk::LOD_CLAMP_NONE)
+            .build(context);
+        context.device.set_handle_name(sampler.handle(), &format!("GUI Sampler ({:?})", options));
+        sampler
+    }
 
-            state : State::default(),
-            visualizer : AllocatorVisualizer::new(),
-        }
+    fn create_descriptor_set_layout(context : &RenderingContext) -> DescriptorSetLayout {
+        DescriptorSetLayout::builder()
+            .sets(1024)
+            .binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, 1)
+            .build(context)
     }
 
     pub fn begin_frame(&mut self, window : &Window) {
@@ -311,39 +662,117 @@ impl<State : Default> Interface<State> {
         self.context.begin_frame(raw_input);
     }
 
+    /// Returns true if the interface is interested in pointer events, for example because the pointer hovers a window
+    /// or a widget is being dragged. The application should not react to the pointer when this is the case.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context.wants_pointer_input()
+    }
+
+    /// Returns true if the interface is interested in keyboard events, for example because a text field has the focus.
+    /// The application should not react to the keyboard when this is the case.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.context.wants_keyboard_input()
+    }
+
     pub fn end_frame(&mut self, window : &Window) -> egui::FullOutput {
         let output = self.context.end_frame();
         self.egui.handle_platform_output(window.handle(), output.platform_output.clone());
+        self.repaint_delay = repaint_delay(&output);
 
         output
     }
 
+    /// Records the commands drawing the interface.
+    ///
+    /// # Description
+    ///
+    /// As egui expects, the textures of `texture_delta` are created or updated before the meshes are drawn, and
+    /// textures are freed after. Freed textures, as well as textures that were replaced, may still be sampled by the
+    /// frames in flight; they are only destroyed the next time `frame_index` is drawn, once the viewport waited for
+    /// the frame recorded here, and every frame before it, to complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The command buffer of the frame.
+    /// * `swapchain` - The swapchain the interface is drawn to.
+    /// * `framebuffer` - The framebuffer of the current image of the swapchain.
+    /// * `frame_index` - The index of the frame in flight.
+    /// * `clipped_meshes` - The tessellated shapes of the interface.
+    /// * `texture_delta` - The textures to create, update and free.
     pub fn paint(&mut self,
         cmd : &CommandBuffer,
         swapchain : &Swapchain,
         framebuffer : &Framebuffer,
-        swapchain_image_index : usize,
+        frame_index : usize,
         clipped_meshes : Vec<egui::ClippedPrimitive>,
         texture_delta : TexturesDelta
     ) {
         profile_scope!("GUI Paint");
 
+        let released_textures = self.retired_textures.release(frame_index);
+        self.destroy_textures(released_textures);
+
+        let mut retired_textures = vec![];
         for (id, image_delta) in texture_delta.set {
-            self.update_texture(id, image_delta);
+            retired_textures.extend(self.update_texture(id, image_delta));
         }
 
-        let frame_data = &mut self.frame_data[swapchain_image_index];
+        self.record_draws(cmd, swapchain, framebuffer, frame_index, clipped_meshes);
 
-        let mut vertex_buffer = frame_data.vertex_buffer.map();
-        let mut index_buffer = frame_data.index_buffer.map();
+        retired_textures.extend(texture_delta.free.iter().filter_map(|id| self.textures.remove(id)));
+        self.retired_textures.retire(frame_index, retired_textures);
+    }
+
+    /// Destroys textures that are no longer sampled by any frame, as well as the descriptor sets that reference them.
+    fn destroy_textures(&mut self, textures : Vec<Texture>) {
+        for texture in textures {
+            let info = texture.descriptor_set(&self.samplers[&texture.options]);
+            for frame_data in &mut self.frame_data {
+                if let Some(set) = frame_data.descriptor_set_layout.get(&info) {
+                    // SAFETY: The texture was released by a frame whose fence signalled, and no later frame used it.
+                    unsafe {
+                        frame_data.descriptor_set_layout.free(set);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the render pass drawing the meshes of the interface.
+    fn record_draws(&mut self,
+        cmd : &CommandBuffer,
+        swapchain : &Swapchain,
+        framebuffer : &Framebuffer,
+        frame_index : usize,
+        clipped_meshes : Vec<egui::ClippedPrimitive>,
+    ) {
+        let frame_data = &mut self.frame_data[frame_index];
+
+        // Only the stencil attachment is cleared; the color attachment is loaded.
+        let clear_values = match self.stencil_format {
+            Some(_) => vec![vk::ClearValue::default(), vk::ClearValue {
+                depth_stencil : vk::ClearDepthStencilValue { depth : 0.0, stencil : 0 }
+            }],
+            None => vec![],
+        };
 
         cmd.begin_render_pass(&self.render_pass, framebuffer, vk::Rect2D {
             extent : swapchain.extent,
             offset : vk::Offset2D { x : 0, y : 0 }
-        }, &[], vk::SubpassContents::INLINE);
+        }, &clear_values, vk::SubpassContents::INLINE);
         cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, &self.pipeline);
+
+        let mut stencil = StencilState::default();
+        if self.mask_pipeline.is_some() {
+            cmd.set_stencil_reference(stencil.reference());
+            cmd.set_stencil_compare_mask(stencil.compare_mask);
+        }
+
         cmd.bind_vertex_buffers(0, &[(&frame_data.vertex_buffer, 0)]);
         cmd.bind_index_buffer(&frame_data.index_buffer, 0);
+
+        let mut vertices = frame_data.vertex_buffer.map_slice::<egui::epaint::Vertex>();
+        let mut indices = frame_data.index_buffer.map_slice::<u32>();
         cmd.set_viewport(0, &[
             vk::Viewport::default()
                 .x(0.0)
@@ -354,10 +783,8 @@ impl<State : Default> Interface<State> {
                 .height(swapchain.extent.height as f32)
         ]);
 
-        let width_points = swapchain.extent.width as f32 / self.scale_factor as f32;
-        let height_points = swapchain.extent.height as f32 / self.scale_factor as f32;
-        cmd.push_constants(&self.pipeline, vk::ShaderStageFlags::VERTEX, 0,                                 bytes_of(&width_points));
-        cmd.push_constants(&self.pipeline, vk::ShaderStageFlags::VERTEX, size_of_val(&width_points) as u32, bytes_of(&height_points));
+        let screen_size_points = screen_size_points(swapchain.extent, self.scale_factor as f32);
+        cmd.push_constants_typed(&self.pipeline, vk::ShaderStageFlags::VERTEX, 0, &screen_size_points);
 
         // Render the meshes
         let mut vertex_base = 0;
@@ -365,7 +792,26 @@ impl<State : Default> Interface<State> {
         for egui::ClippedPrimitive { clip_rect, primitive } in clipped_meshes {
             let mesh = match primitive {
                 Primitive::Mesh(mesh) => mesh,
-                Primitive::Callback(_) => todo!(),
+                Primitive::Callback(callback) => {
+                    // Callbacks this painter does not know about are not drawn, as egui expects from backends.
+                    let command = callback.callback.downcast_ref::<StencilCommand>();
+                    if let (Some(mask_pipeline), Some(command)) = (&self.mask_pipeline, command) {
+                        let was_writing = stencil.is_writing();
+                        stencil.apply(*command);
+
+                        // Both pipelines share their layout, so bound descriptor sets and push constants remain valid.
+                        if stencil.is_writing() != was_writing {
+                            let pipeline = if stencil.is_writing() { mask_pipeline } else { &self.pipeline };
+                            cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, pipeline);
+                        }
+
+                        cmd.set_stencil_reference(stencil.reference());
+                        if !stencil.is_writing() {
+                            cmd.set_stencil_compare_mask(stencil.compare_mask);
+                        }
+                    }
+                    continue;
+                },
             };
 
             if mesh.is_empty() {
@@ -375,48 +821,24 @@ impl<State : Default> Interface<State> {
             let texture_info = self.textures.get(&mesh.texture_id);
             if let Some(texture_info) = texture_info {
                 cmd.bind_descriptor_sets(vk::PipelineBindPoint::GRAPHICS, &self.pipeline, 0,
-                    &[frame_data.descriptor_set_layout.request(texture_info.descriptor_set(&self.sampler))],
+                    &[frame_data.descriptor_set_layout.request(texture_info.descriptor_set(&self.samplers[&texture_info.options]))],
                     &[]
                 );
             }
 
-            let v_slice = &mesh.vertices;
-            let v_size = size_of_val(&v_slice[0]);
-            let v_copy_size = v_slice.len() * v_size;
-
-            let i_slice = &mesh.indices;
-            let i_size = size_of_val(&i_slice[0]);
-            let i_copy_size = i_slice.len() * i_size;
-
-            unsafe {
-                vertex_buffer.copy_from(v_slice.as_ptr() as *const u8, v_copy_size);
-                index_buffer.copy_from(i_slice.as_ptr() as *const u8, i_copy_size);
-                vertex_buffer = vertex_buffer.add(v_copy_size);
-                index_buffer = index_buffer.add(i_copy_size);
+            vertices.get_mut(vertex_base..vertex_base + mesh.vertices.len())
+                .expect("The interface does not fit in its vertex buffer")
+                .copy_from_slice(&mesh.vertices);
+            indices.get_mut(index_base..index_base + mesh.indices.len())
+                .expect("The interface does not fit in its index buffer")
+                .copy_from_slice(&mesh.indices);
+
+            // Record draw commands; clip rects that are entirely off-screen or empty are skipped, but their
+            // geometry was still uploaded so that the bases keep advancing.
+            if let Some(scissor) = scissor_rect(clip_rect, self.scale_factor as f32, swapchain.extent) {
+                cmd.set_scissors(0, &[scissor]);
+                cmd.draw_indexed(mesh.indices.len() as _, 1, index_base as _, vertex_base as _, 0);
             }
-
-            let min = egui::Pos2 {
-                x : f32::clamp(clip_rect.min.x * self.scale_factor as f32, 0.0, swapchain.extent.width as f32),
-                y : f32::clamp(clip_rect.min.y * self.scale_factor as f32, 0.0, swapchain.extent.height as f32)
-            };
-            let max = egui::Pos2 {
-                x : f32::clamp(clip_rect.max.x * self.scale_factor as f32, min.x, swapchain.extent.width as f32),
-                y : f32::clamp(clip_rect.max.y * self.scale_factor as f32, min.y, swapchain.extent.height as f32),
-            };
-
-            // Record draw commands
-            cmd.set_scissors(0, &[
-                vk::Rect2D::default()
-                    .offset(vk::Offset2D::default()
-                        .x(min.x.round() as i32)
-                        .y(min.y.round() as i32)
-                    )
-                    .extent(vk::Extent2D::default()
-                        .width((max.x - min.x).round() as u32)
-                        .height((max.y - min.y).round() as u32)
-                    )
-            ]);
-            cmd.draw_indexed(mesh.indices.len() as _, 1, index_base as _, vertex_base as _, 0);
             
             vertex_base += mesh.vertices.len();
             index_base += mesh.indices.len();
@@ -424,14 +846,18 @@ impl<State : Default> Interface<State> {
         cmd.end_render_pass();
     }
     
-    fn update_texture(&mut self, tex_id : TextureId, delta : ImageDelta) {
+    /// Creates or updates a texture.
+    ///
+    /// # Returns
+    ///
+    /// The texture that was replaced, if any. It may still be sampled by the frames in flight.
+    fn update_texture(&mut self, tex_id : TextureId, delta : ImageDelta) -> Option<Texture> {
         let data = match &delta.image {
             egui::ImageData::Color(color) => color.pixels.iter().flat_map(Color32::to_array).collect::<Vec<_>>(),
             egui::ImageData::Font(font) => font.srgba_pixels(None).flat_map(|c| c.to_array()).collect(),
         };
 
-        // Create a fence
-        let fence = self.rendering_context.device.create_fence(vk::FenceCreateFlags::empty(), "GUI Texture update fence".to_owned().into());
+        let fence = self.fence_pool.acquire();
 
         let graphics_queue : &Queue = self.rendering_context.device.get_queues(QueueAffinity::Graphics)
             .get(0).expect("Could not find graphics queue");
@@ -468,25 +894,7 @@ impl<State : Default> Interface<State> {
 
         cmd.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         cmd.begin_label("GUI texture upload", [0.0; 4]);
-        // Transition the new image to transfer dest
-        cmd.image_memory_barrier(&mut image,
-            BarrierPhase::ignore_queue(vk::AccessFlags::NONE_KHR,       vk::PipelineStageFlags::HOST),
-            BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
-            vk::DependencyFlags::BY_REGION,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL
-        );
-        cmd.copy_buffer_to_image(&transfer_src, &image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[
-            // TODO: This is kind of obscure, clean this up. The amount of lines of code Vulkan
-            //       forces me to write here is a bit insane.
-            with_delta(&delta, prepare_buffer_image_copy(&image, 0))
-        ]);
-        // Transition the new image to shader src
-        cmd.image_memory_barrier(&mut image,
-            BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
-            BarrierPhase::ignore_queue(vk::AccessFlags::SHADER_READ,    vk::PipelineStageFlags::VERTEX_SHADER),
-            vk::DependencyFlags::BY_REGION,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-        );
+        image.upload_from_buffer(&cmd, &transfer_src, Some(vk::PipelineStageFlags::FRAGMENT_SHADER));
         cmd.end_label();
         cmd.end();
 
@@ -494,6 +902,7 @@ impl<State : Default> Interface<State> {
         self.rendering_context.device.wait_for_fence(fence);
 
         // The texture now lives in GPU memory, so we should decide if it has to be registered as a new texture, or update an existing one
+        let mut replaced = None;
         if let Some(pos) = delta.pos {
             // Blit texture data to the existing texture if delta pos exists (which can happen if a font changes)
             let existing_texture = self.textures.get_mut(&tex_id);
@@ -519,7 +928,7 @@ impl<State : Default> Interface<State> {
                 );
                 let dst_subresource = existing_texture.image.make_subresource_layer(0, None, None);
                 cmd.blit_image(&image,
-                    &mut existing_texture.image,
+                    &existing_texture.image,
                     &[
                         vk::ImageBlit::default()
                             .src_subresource(image.make_subresource_layer(0, None, None))
@@ -562,14 +971,418 @@ impl<State : Default> Interface<State> {
                 // ??? What's going on ???
             }
         } else {
-            self.textures.insert(tex_id, Texture {
-                image
+            let rendering_context = &self.rendering_context;
+            self.samplers.entry(delta.options)
+                .or_insert_with(|| Self::create_sampler(rendering_context, delta.options));
+
+            replaced = self.textures.insert(tex_id, Texture {
+                image,
+                options : delta.options,
             });
         }
+
+        self.fence_pool.release(fence);
+        replaced
     }
 
     pub fn render_visualizer(&self, ui : &mut Ui) {
+        match self.rendering_context.memory_budget() {
+            Some(heaps) => {
+                for (index, heap) in heaps.iter().enumerate() {
+                    ui.label(format!("Heap {}{}: {} MiB used / {} MiB available ({} MiB total)",
+                        index,
+                        if heap.device_local { " (VRAM)" } else { "" },
+                        heap.usage / (1024 * 1024),
+                        heap.budget / (1024 * 1024),
+                        heap.size / (1024 * 1024)));
+                }
+            },
+            None => { ui.label("Memory budget information is not available on this device."); },
+        }
+
         // Broken with version mismatch required by the visualizer feature
         // self.visualizer.render_breakdown_ui(ui, self.rendering_context.device.allocator().lock().unwrap().borrow())
     }
-}
\ No newline at end of file
+}
+
+/// Converts an egui texture filter to its Vulkan equivalent.
+fn filter(filter : TextureFilter) -> vk::Filter {
+    match filter {
+        TextureFilter::Nearest => vk::Filter::NEAREST,
+        TextureFilter::Linear => vk::Filter::LINEAR,
+    }
+}
+
+/// Converts an egui texture wrap mode to its Vulkan equivalent.
+fn address_mode(wrap_mode : TextureWrapMode) -> vk::SamplerAddressMode {
+    match wrap_mode {
+        TextureWrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        TextureWrapMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        TextureWrapMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+    }
+}
+
+/// Returns the size of a framebuffer in points, as expected by the vertex shader.
+///
+/// # Arguments
+///
+/// * `extent` - The extent of the framebuffer.
+/// * `scale_factor` - The amount of pixels per point.
+fn screen_size_points(extent : vk::Extent2D, scale_factor : f32) -> [f32; 2] {
+    [extent.width as f32 / scale_factor, extent.height as f32 / scale_factor]
+}
+
+/// Returns the amount of pixels per point, and the size of a window in points, once its scale factor changed.
+///
+/// # Arguments
+///
+/// * `scale_factor` - The new scale factor of the window.
+/// * `inner_size` - The size of the window in pixels, once it was resized to the new scale factor.
+fn rescale(scale_factor : f64, inner_size : PhysicalSize<u32>) -> (f32, [f32; 2]) {
+    let pixels_per_point = scale_factor as f32;
+    let extent = vk::Extent2D { width : inner_size.width, height : inner_size.height };
+    (pixels_per_point, screen_size_points(extent, pixels_per_point))
+}
+
+/// Converts an egui clip rectangle to a scissor rectangle in framebuffer pixels.
+///
+/// # Arguments
+///
+/// * `clip_rect` - The clip rectangle, in points.
+/// * `scale_factor` - The amount of pixels per point.
+/// * `extent` - The extent of the framebuffer.
+///
+/// # Returns
+///
+/// The scissor rectangle, clamped to the framebuffer, or `None` if it has no area.
+fn scissor_rect(clip_rect : egui::Rect, scale_factor : f32, extent : vk::Extent2D) -> Option<vk::Rect2D> {
+    let min = egui::Pos2 {
+        x : f32::clamp(clip_rect.min.x * scale_factor, 0.0, extent.width as f32).round(),
+        y : f32::clamp(clip_rect.min.y * scale_factor, 0.0, extent.height as f32).round(),
+    };
+    let max = egui::Pos2 {
+        x : f32::clamp(clip_rect.max.x * scale_factor, 0.0, extent.width as f32).round(),
+        y : f32::clamp(clip_rect.max.y * scale_factor, 0.0, extent.height as f32).round(),
+    };
+
+    let width = (max.x - min.x).max(0.0) as u32;
+    let height = (max.y - min.y).max(0.0) as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    Some(vk::Rect2D::default()
+        .offset(vk::Offset2D::default()
+            .x(min.x as i32)
+            .y(min.y as i32)
+        )
+        .extent(vk::Extent2D::default()
+            .width(width)
+            .height(height)
+        ))
+}
+
+/// A command that controls stencil masking while the interface is painted. Commands are added to the shapes of a
+/// painter with [`StencilCommand::paint`], and apply to the shapes painted after them.
+///
+/// # Description
+///
+/// Commands are ignored unless the interface was created with a stencil attachment (see
+/// [`InterfaceOptions::stencil`]). To clip contents to a rounded panel, paint [`StencilCommand::BeginMask`], the
+/// panel's shape, [`StencilCommand::EndMask`] and [`StencilCommand::Mask`] with the same value, followed by the
+/// contents; finally, paint [`StencilCommand::Unmask`] so that the rest of the interface is drawn normally. Scissor
+/// rects still apply to masks and contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StencilCommand {
+    /// Shapes painted after this command write the given value to the stencil attachment instead of being drawn.
+    BeginMask(u32),
+    /// Shapes painted after this command are drawn again.
+    EndMask,
+    /// Shapes painted after this command are only drawn where the stencil attachment holds the given value (with the
+    /// default stencil options; see [`InterfaceOptions::default_stencil`]).
+    Mask(u32),
+    /// Shapes painted after this command are drawn regardless of the contents of the stencil attachment.
+    Unmask,
+}
+
+impl StencilCommand {
+    /// Adds this command to the shapes of a painter.
+    pub fn paint(self, painter : &egui::Painter) {
+        painter.add(egui::PaintCallback {
+            rect : painter.clip_rect(),
+            callback : Arc::new(self),
+        });
+    }
+}
+
+/// The dynamic stencil state of the interface while it is painted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct StencilState {
+    /// The value written to the stencil attachment, if a mask is being drawn.
+    writing : Option<u32>,
+    /// The reference value meshes are compared with.
+    reference : u32,
+    /// The compare mask; when this is zero, meshes are drawn regardless of the stencil attachment.
+    compare_mask : u32,
+}
+
+impl StencilState {
+    fn apply(&mut self, command : StencilCommand) {
+        match command {
+            StencilCommand::BeginMask(value) => self.writing = Some(value),
+            StencilCommand::EndMask => self.writing = None,
+            StencilCommand::Mask(value) => {
+                self.reference = value;
+                self.compare_mask = u32::MAX;
+            },
+            StencilCommand::Unmask => {
+                self.reference = 0;
+                self.compare_mask = 0;
+            },
+        }
+    }
+
+    #[inline] fn is_writing(&self) -> bool { self.writing.is_some() }
+
+    /// Returns the stencil reference value for the pipeline that should be bound.
+    #[inline] fn reference(&self) -> u32 { self.writing.unwrap_or(self.reference) }
+}
+
+/// Returns the aspects of the view of a stencil attachment. Views of depth/stencil attachments must include both
+/// aspects of their format.
+fn stencil_aspect(format : vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+    }
+}
+
+/// Returns true if the given color format is sRGB-encoded, meaning that the device converts linear values written to
+/// it.
+fn is_srgb_format(format : vk::Format) -> bool {
+    matches!(format,
+        vk::Format::R8_SRGB
+        | vk::Format::R8G8_SRGB
+        | vk::Format::R8G8B8_SRGB
+        | vk::Format::B8G8R8_SRGB
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A8B8G8R8_SRGB_PACK32)
+}
+
+/// Returns the sample count of the attachments the interface is drawn to.
+///
+/// # Arguments
+///
+/// * `multisampled` - Whether the interface is drawn to the multisampled color attachment of the swapchain.
+/// * `swapchain_samples` - The sample count of the multisampled attachments of the swapchain.
+fn interface_samples(multisampled : bool, swapchain_samples : vk::SampleCountFlags) -> vk::SampleCountFlags {
+    if multisampled { swapchain_samples } else { vk::SampleCountFlags::TYPE_1 }
+}
+
+/// Returns the delay after which egui wants the root viewport to be repainted, or `None` if it only needs to be
+/// repainted in response to input.
+fn repaint_delay(output : &egui::FullOutput) -> Option<Duration> {
+    output.viewport_output.get(&ViewportId::ROOT)
+        .map(|viewport| viewport.repaint_delay)
+        .filter(|&delay| delay != Duration::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ash::vk;
+
+    use egui_winit::winit::dpi::PhysicalSize;
+
+    use egui::{FullOutput, TextureFilter, TextureWrapMode, ViewportBuilder, ViewportClass, ViewportId, ViewportOutput};
+
+    use super::{address_mode, filter, interface_samples, is_srgb_format, repaint_delay, scissor_rect, rescale, screen_size_points, stencil_aspect, Retirement, StencilCommand, StencilState};
+
+    #[test]
+    pub fn degenerate_clip_rects() {
+        let extent = vk::Extent2D { width : 800, height : 600 };
+
+        let scissor = scissor_rect(egui::Rect::from_min_max(egui::pos2(10.0, 20.0), egui::pos2(110.0, 70.0)), 2.0, extent)
+            .expect("A visible clip rect should produce a scissor");
+        assert_eq!((scissor.offset.x, scissor.offset.y), (20, 40));
+        assert_eq!((scissor.extent.width, scissor.extent.height), (200, 100));
+
+        // Partially off-screen rects are clamped to the framebuffer.
+        let scissor = scissor_rect(egui::Rect::from_min_max(egui::pos2(-50.0, 500.0), egui::pos2(50.0, 900.0)), 1.0, extent)
+            .expect("A partially visible clip rect should produce a scissor");
+        assert_eq!((scissor.offset.x, scissor.offset.y), (0, 500));
+        assert_eq!((scissor.extent.width, scissor.extent.height), (50, 100));
+
+        // Inverted, empty and off-screen rects produce no scissor.
+        assert!(scissor_rect(egui::Rect::from_min_max(egui::pos2(100.0, 100.0), egui::pos2(50.0, 50.0)), 1.0, extent).is_none());
+        assert!(scissor_rect(egui::Rect::from_min_max(egui::pos2(100.0, 100.0), egui::pos2(100.0, 200.0)), 1.0, extent).is_none());
+        assert!(scissor_rect(egui::Rect::from_min_max(egui::pos2(900.0, 0.0), egui::pos2(1000.0, 100.0)), 1.0, extent).is_none());
+        assert!(scissor_rect(egui::Rect::from_min_max(egui::pos2(0.0, -200.0), egui::pos2(100.0, -100.0)), 1.0, extent).is_none());
+        assert!(scissor_rect(egui::Rect::NOTHING, 1.0, extent).is_none());
+    }
+
+    #[test]
+    pub fn scale_factor_change() {
+        let extent = vk::Extent2D { width : 800, height : 600 };
+        let clip_rect = egui::Rect::from_min_max(egui::pos2(10.0, 10.0), egui::pos2(110.0, 60.0));
+
+        assert_eq!(screen_size_points(extent, 1.0), [800.0, 600.0]);
+        let scissor = scissor_rect(clip_rect, 1.0, extent).unwrap();
+        assert_eq!((scissor.extent.width, scissor.extent.height), (100, 50));
+
+        // Moving the window to a monitor with twice the density halves the size of the framebuffer in points, and
+        // doubles the size of clip rects in pixels.
+        assert_eq!(screen_size_points(extent, 2.0), [400.0, 300.0]);
+        let scissor = scissor_rect(clip_rect, 2.0, extent).unwrap();
+        assert_eq!((scissor.offset.x, scissor.offset.y), (20, 20));
+        assert_eq!((scissor.extent.width, scissor.extent.height), (200, 100));
+    }
+
+    #[test]
+    pub fn texture_options() {
+        assert_eq!(filter(TextureFilter::Nearest), vk::Filter::NEAREST);
+        assert_eq!(filter(TextureFilter::Linear), vk::Filter::LINEAR);
+
+        assert_eq!(address_mode(TextureWrapMode::ClampToEdge), vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        assert_eq!(address_mode(TextureWrapMode::Repeat), vk::SamplerAddressMode::REPEAT);
+        assert_eq!(address_mode(TextureWrapMode::MirroredRepeat), vk::SamplerAddressMode::MIRRORED_REPEAT);
+    }
+
+    #[test]
+    pub fn repaint_delays() {
+        let output = |delay| {
+            let mut output = FullOutput::default();
+            output.viewport_output.insert(ViewportId::ROOT, ViewportOutput {
+                parent : ViewportId::ROOT,
+                class : ViewportClass::Root,
+                builder : ViewportBuilder::default(),
+                viewport_ui_cb : None,
+                commands : vec![],
+                repaint_delay : delay,
+            });
+            output
+        };
+
+        assert_eq!(repaint_delay(&output(Duration::ZERO)), Some(Duration::ZERO));
+        assert_eq!(repaint_delay(&output(Duration::from_millis(500))), Some(Duration::from_millis(500)));
+        // egui uses the largest possible delay when it does not need to be repainted.
+        assert_eq!(repaint_delay(&output(Duration::MAX)), None);
+        assert_eq!(repaint_delay(&FullOutput::default()), None);
+    }
+
+    #[test]
+    pub fn stencil_commands() {
+        let mut state = StencilState::default();
+        assert!(!state.is_writing());
+        assert_eq!((state.reference(), state.compare_mask), (0, 0));
+
+        state.apply(StencilCommand::BeginMask(3));
+        assert!(state.is_writing());
+        assert_eq!(state.reference(), 3);
+
+        // Ending the mask does not enable masking by itself.
+        state.apply(StencilCommand::EndMask);
+        assert!(!state.is_writing());
+        assert_eq!((state.reference(), state.compare_mask), (0, 0));
+
+        state.apply(StencilCommand::Mask(3));
+        assert_eq!((state.reference(), state.compare_mask), (3, u32::MAX));
+
+        // Nested masks are written with their own value, and the previous mask is restored afterwards.
+        state.apply(StencilCommand::BeginMask(4));
+        assert_eq!(state.reference(), 4);
+        state.apply(StencilCommand::EndMask);
+        assert_eq!((state.reference(), state.compare_mask), (3, u32::MAX));
+
+        state.apply(StencilCommand::Unmask);
+        assert_eq!(state, StencilState::default());
+    }
+
+    #[test]
+    pub fn stencil_aspects() {
+        assert_eq!(stencil_aspect(vk::Format::S8_UINT), vk::ImageAspectFlags::STENCIL);
+        assert_eq!(stencil_aspect(vk::Format::D24_UNORM_S8_UINT), vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+    }
+
+    #[test]
+    pub fn srgb_formats() {
+        assert!(is_srgb_format(vk::Format::B8G8R8A8_SRGB));
+        assert!(is_srgb_format(vk::Format::A8B8G8R8_SRGB_PACK32));
+        assert!(!is_srgb_format(vk::Format::B8G8R8A8_UNORM));
+        assert!(!is_srgb_format(vk::Format::A2B10G10R10_UNORM_PACK32));
+    }
+
+    /// Mirrors `linear_from_gamma` in `assets/gui.frag`.
+    fn shader_linear_from_gamma(gamma : f32) -> f32 {
+        if gamma < 0.04045 { gamma / 12.92 } else { ((gamma + 0.055) / 1.055).powf(2.4) }
+    }
+
+    #[test]
+    pub fn linear_output() {
+        for gamma in 0..=u8::MAX {
+            let color = gamma as f32 / 255.0;
+
+            // The conversion matches the reference implementation of egui.
+            let linear = shader_linear_from_gamma(color);
+            assert!((linear - egui::ecolor::linear_f32_from_gamma_u8(gamma)).abs() < 1e-6);
+
+            // Whichever the format of the target, the stored value is the sRGB-encoded color egui blended: UNORM
+            // targets store the shader output as is, and sRGB targets encode it.
+            for format in [vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB] {
+                let stored = if is_srgb_format(format) {
+                    egui::ecolor::gamma_u8_from_linear_f32(linear)
+                } else {
+                    (color * 255.0).round() as u8
+                };
+                assert_eq!(stored, gamma, "{:?} stores {} as {}", format, gamma, stored);
+            }
+        }
+    }
+
+    #[test]
+    pub fn sample_counts() {
+        // Standalone interfaces draw over the resolved image.
+        assert_eq!(interface_samples(false, vk::SampleCountFlags::TYPE_4), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(interface_samples(true, vk::SampleCountFlags::TYPE_4), vk::SampleCountFlags::TYPE_4);
+        assert_eq!(interface_samples(true, vk::SampleCountFlags::TYPE_1), vk::SampleCountFlags::TYPE_1);
+    }
+
+    #[test]
+    pub fn retirement_cycle() {
+        let mut retirement = Retirement::new(2);
+
+        // Frame 0 retires a texture; frame 1 is recorded while frame 0 may still be in flight.
+        assert!(retirement.release(0).is_empty());
+        retirement.retire(0, ["replaced"]);
+        assert!(retirement.release(1).is_empty());
+        retirement.retire(1, ["freed"]);
+
+        // Recording frame 0 again means it completed: what it retired is released, once.
+        assert_eq!(retirement.release(0), vec!["replaced"]);
+        assert!(retirement.release(0).is_empty());
+        retirement.retire(0, []);
+
+        assert_eq!(retirement.release(1), vec!["freed"]);
+        assert!(retirement.release(1).is_empty());
+    }
+
+    #[test]
+    pub fn rescaled_window() {
+        // Moving a 800x600 window to a monitor with twice the density doubles its size in pixels, but not in points.
+        let (pixels_per_point, screen_size) = rescale(2.0, PhysicalSize::new(1600, 1200));
+        assert_eq!(pixels_per_point, 2.0);
+        assert_eq!(screen_size, [800.0, 600.0]);
+
+        let (pixels_per_point, screen_size) = rescale(1.25, PhysicalSize::new(1000, 500));
+        assert_eq!(pixels_per_point, 1.25);
+        assert_eq!(screen_size, [800.0, 400.0]);
+
+        // Clip rects in points cover the same part of the window.
+        let extent = vk::Extent2D { width : 1000, height : 500 };
+        let clip_rect = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(screen_size[0], screen_size[1]));
+        let scissor = scissor_rect(clip_rect, pixels_per_point, extent).unwrap();
+        assert_eq!((scissor.extent.width, scissor.extent.height), (1000, 500));
+    }
+}
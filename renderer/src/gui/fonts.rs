@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use egui::{FontData, FontDefinitions, FontFamily};
+
+/// Builds font definitions from an ordered list of font files.
+///
+/// # Arguments
+///
+/// * `families` - For each font family, the font files to use, by decreasing priority.
+///
+/// # Description
+///
+/// Each font is registered under its file stem, stripped of anything up to the first `-`, both in its family and as a
+/// standalone [`FontFamily::Name`]. The fonts built into egui are kept after the provided fonts so that they act as
+/// fallbacks for glyphs (emoji, symbols) that are missing from the provided fonts.
+///
+/// Files that cannot be read, or that are not TrueType or OpenType fonts, are skipped with a warning.
+pub fn font_definitions(families : &[(FontFamily, Vec<PathBuf>)]) -> FontDefinitions {
+    let mut fonts = vec![];
+    for (family, paths) in families {
+        for path in paths {
+            let data = match fs::read(path) {
+                Ok(data) => data,
+                Err(err) => {
+                    println!("An error occured while loading font {:?}: {}", path, err);
+                    continue;
+                }
+            };
+
+            if !is_supported_font(&data) {
+                println!("Skipping font {:?}: only TrueType and OpenType fonts are supported", path);
+                continue;
+            }
+
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                println!("Skipping font {:?}: its name is not valid UTF-8", path);
+                continue;
+            };
+            let name = stem.split_once('-').map_or(stem, |(_, name)| name);
+
+            fonts.push((family.clone(), name.to_owned(), data));
+        }
+    }
+
+    with_fonts(fonts)
+}
+
+/// Builds font definitions from the fonts found in a directory.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search.
+///
+/// # Description
+///
+/// Fonts are expected to be in the `proportional` and `monospace` subdirectories of `dir`. Within each of these,
+/// fonts are prioritized by file name, so prefixing file names with a number (`00-`, `01-`, ...) controls the
+/// fallback order.
+pub fn load_fonts_from<P : AsRef<Path>>(dir : P) -> FontDefinitions {
+    let families = [("proportional", FontFamily::Proportional), ("monospace", FontFamily::Monospace)]
+        .into_iter()
+        .map(|(subdirectory, family)| {
+            let mut paths = fs::read_dir(dir.as_ref().join(subdirectory))
+                .map(|entries| entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .collect::<Vec<_>>())
+                .unwrap_or_default();
+            paths.sort();
+
+            (family, paths)
+        })
+        .collect::<Vec<_>>();
+
+    font_definitions(&families)
+}
+
+/// Builds font definitions from fonts already loaded in memory.
+///
+/// # Arguments
+///
+/// * `fonts` - The family, name and data of each font, by decreasing priority.
+fn with_fonts(fonts : Vec<(FontFamily, String, Vec<u8>)>) -> FontDefinitions {
+    let mut definitions = FontDefinitions::default();
+
+    // Remove the built-in fonts from their families and put them back after the provided fonts.
+    let builtins = definitions.families.iter_mut()
+        .map(|(family, names)| (family.clone(), std::mem::take(names)))
+        .collect::<Vec<_>>();
+
+    for (family, name, data) in fonts {
+        definitions.font_data.insert(name.clone(), FontData::from_owned(data));
+        definitions.families.entry(family).or_default().push(name.clone());
+        definitions.families.insert(FontFamily::Name(name.as_str().into()), vec![name]);
+    }
+
+    for (family, names) in builtins {
+        let entry = definitions.families.entry(family).or_default();
+        for name in names {
+            if !entry.contains(&name) {
+                entry.push(name);
+            }
+        }
+    }
+
+    definitions
+}
+
+/// Returns true if the given data looks like a font egui can load.
+///
+/// # Arguments
+///
+/// * `data` - The contents of a font file.
+fn is_supported_font(data : &[u8]) -> bool {
+    match data.get(0..4) {
+        Some([0x00, 0x01, 0x00, 0x00]) => true, // TrueType
+        Some(b"OTTO") => true,                  // OpenType with CFF outlines
+        Some(b"true") => true,                  // Apple TrueType
+        Some(b"ttcf") => true,                  // Font collection
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use egui::{FontDefinitions, FontFamily};
+
+    use super::{is_supported_font, with_fonts};
+
+    #[test]
+    pub fn font_formats() {
+        assert!(is_supported_font(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x10]));
+        assert!(is_supported_font(b"OTTO\0\x0A"));
+        assert!(!is_supported_font(b"wOFF\0\x01\0\0"));
+        assert!(!is_supported_font(b"<svg"));
+        assert!(!is_supported_font(&[0x00, 0x01]));
+    }
+
+    #[test]
+    pub fn builtin_fonts_are_fallbacks() {
+        let builtins = FontDefinitions::default();
+
+        let definitions = with_fonts(vec![
+            (FontFamily::Proportional, "B".to_owned(), vec![]),
+            (FontFamily::Proportional, "A".to_owned(), vec![]),
+            (FontFamily::Monospace, "C".to_owned(), vec![]),
+        ]);
+
+        let proportional = &definitions.families[&FontFamily::Proportional];
+        assert_eq!(proportional[..2], ["B".to_owned(), "A".to_owned()]);
+        assert_eq!(proportional[2..], builtins.families[&FontFamily::Proportional][..]);
+
+        let monospace = &definitions.families[&FontFamily::Monospace];
+        assert_eq!(monospace[0], "C");
+        assert_eq!(monospace[1..], builtins.families[&FontFamily::Monospace][..]);
+
+        assert_eq!(definitions.families[&FontFamily::Name("A".into())], ["A".to_owned()]);
+        assert!(definitions.font_data.contains_key("C"));
+    }
+}
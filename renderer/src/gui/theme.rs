@@ -0,0 +1,7 @@
+use egui::Style;
+
+/// A theme for the user interface.
+pub trait Theme {
+    /// Returns the complete style of this theme, including its [`egui::Visuals`].
+    fn style(&self) -> Style;
+}
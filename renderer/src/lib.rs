@@ -11,4 +11,6 @@ pub mod vk;
 pub mod window;
 
 // Private modules
+#[cfg(all(test, any(target_os = "linux", target_os = "windows")))]
+mod testing;
 mod utils;
@@ -9,25 +9,29 @@ macro_rules! valueless_builder {
 }
 
 macro_rules! value_builder {
-    ($fn:ident, $type:ty) => {
+    ($(#[$meta:meta])* $fn:ident, $type:ty) => {
+        $(#[$meta])*
         #[inline] pub fn $fn(mut self, $fn : $type) -> Self {
             self.$fn = $fn;
             self
         }
     };
-    ($fn:ident, $an:ident, &$lf:lifetime $type:ty) => {
+    ($(#[$meta:meta])* $fn:ident, $an:ident, &$lf:lifetime $type:ty) => {
+        $(#[$meta])*
         #[inline] pub fn $fn(mut self, $an : &$lf $type) -> Self {
             self.$an = $an;
             self
         }
     };
-    ($fn:ident, $an:ident, $type:ty) => {
+    ($(#[$meta:meta])* $fn:ident, $an:ident, $type:ty) => {
+        $(#[$meta])*
         #[inline] pub fn $fn(mut self, $an : $type) -> Self {
             self.$an = $an;
             self
         }
     };
-    ($fn:ident, $an:ident, $mn:ident, $type:path) => {
+    ($(#[$meta:meta])* $fn:ident, $an:ident, $mn:ident, $type:path) => {
+        $(#[$meta])*
         #[inline] pub fn $fn(mut self, $an : $type) -> Self {
             self.$mn = $an;
             self
@@ -1 +1,2 @@
+pub mod frame_stats;
 pub mod rendering;
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use ash::vk;
+
+/// The amount of frames over which statistics are computed.
+pub const FRAME_HISTORY : usize = 120;
+
+/// Timing statistics over the most recent frames.
+///
+/// # Description
+///
+/// Frame times are stored in a fixed-size ring buffer, so recording a frame never allocates. The frame time is the
+/// CPU time elapsed between the start of two consecutive frames; it includes everything the application does between
+/// frames, as well as the time spent waiting on the GPU.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    samples : [Duration; FRAME_HISTORY],
+    count : usize,
+    next : usize,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            samples : [Duration::ZERO; FRAME_HISTORY],
+            count : 0,
+            next : 0,
+        }
+    }
+}
+
+impl FrameStats {
+    /// Records the duration of a frame, evicting the oldest frame if the history is full.
+    pub fn push(&mut self, frame_time : Duration) {
+        self.samples[self.next] = frame_time;
+        self.next = (self.next + 1) % FRAME_HISTORY;
+        self.count = (self.count + 1).min(FRAME_HISTORY);
+    }
+
+    /// Returns the amount of frames statistics are computed over.
+    #[inline] pub fn len(&self) -> usize { self.count }
+
+    /// Returns true if no frame was recorded yet.
+    #[inline] pub fn is_empty(&self) -> bool { self.count == 0 }
+
+    /// Returns the recorded frame times, from the oldest to the most recent.
+    pub fn frame_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        let start = (self.next + FRAME_HISTORY - self.count) % FRAME_HISTORY;
+        (0..self.count).map(move |i| self.samples[(start + i) % FRAME_HISTORY])
+    }
+
+    /// Returns the duration of the most recent frame.
+    pub fn last(&self) -> Option<Duration> {
+        (self.count != 0).then(|| self.samples[(self.next + FRAME_HISTORY - 1) % FRAME_HISTORY])
+    }
+
+    /// Returns the average frame time.
+    pub fn average(&self) -> Option<Duration> {
+        (self.count != 0).then(|| self.frame_times().sum::<Duration>() / self.count as u32)
+    }
+
+    /// Returns the average amount of frames per second.
+    pub fn fps(&self) -> Option<f32> {
+        self.average()
+            .filter(|average| !average.is_zero())
+            .map(|average| 1.0 / average.as_secs_f32())
+    }
+
+    /// Returns the frame time below which the given proportion of frames fall, using the nearest-rank method.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - A value between `0.0` and `1.0`. For example, `0.99` returns the 99th percentile, which
+    ///                  measures stutter better than the average does.
+    pub fn percentile(&self, percentile : f32) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let mut sorted = [Duration::ZERO; FRAME_HISTORY];
+        for (sample, frame_time) in sorted.iter_mut().zip(self.frame_times()) {
+            *sample = frame_time;
+        }
+        let sorted = &mut sorted[..self.count];
+        sorted.sort_unstable();
+
+        let rank = (percentile.clamp(0.0, 1.0) * self.count as f32).ceil() as usize;
+        Some(sorted[rank.clamp(1, self.count) - 1])
+    }
+}
+
+/// Statistics about the acquisition and presentation of the images of a swapchain, accumulated over the lifetime of
+/// a viewport.
+///
+/// # Description
+///
+/// Frequent suboptimal or out-of-date results, and the swapchain recreations they cause, usually point at resize churn
+/// or at a present mode the surface does not like. A high acquire latency means the presentation engine is holding on
+/// to images, for example because of vertical synchronization.
+#[derive(Clone, Debug, Default)]
+pub struct SwapchainStats {
+    suboptimal : u64,
+    out_of_date : u64,
+    recreations : u64,
+    acquire_latency : FrameStats,
+}
+
+impl SwapchainStats {
+    /// Records the outcome of an image acquisition or presentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Whether the swapchain was reported as suboptimal, or the error returned.
+    pub(in crate) fn record_result(&mut self, result : Result<bool, vk::Result>) {
+        match result {
+            Ok(true) | Err(vk::Result::SUBOPTIMAL_KHR) => self.suboptimal += 1,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.out_of_date += 1,
+            _ => (),
+        }
+    }
+
+    /// Records the time spent acquiring an image.
+    #[inline] pub(in crate) fn record_acquire(&mut self, latency : Duration) { self.acquire_latency.push(latency); }
+
+    /// Records a recreation of the swapchain.
+    #[inline] pub(in crate) fn record_recreation(&mut self) { self.recreations += 1; }
+
+    /// Returns the amount of acquisitions and presentations that reported the swapchain as suboptimal.
+    #[inline] pub fn suboptimal_count(&self) -> u64 { self.suboptimal }
+
+    /// Returns the amount of acquisitions and presentations that reported the swapchain as out of date.
+    #[inline] pub fn out_of_date_count(&self) -> u64 { self.out_of_date }
+
+    /// Returns the amount of times the swapchain was recreated.
+    #[inline] pub fn recreation_count(&self) -> u64 { self.recreations }
+
+    /// Returns the time spent acquiring each of the most recent images.
+    #[inline] pub fn acquire_latency(&self) -> &FrameStats { &self.acquire_latency }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ash::vk;
+
+    use super::{FrameStats, SwapchainStats, FRAME_HISTORY};
+
+    #[test]
+    pub fn frame_statistics() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.average(), None);
+        assert_eq!(stats.percentile(0.5), None);
+
+        for ms in [4, 1, 3, 2] {
+            stats.push(Duration::from_millis(ms));
+        }
+
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats.last(), Some(Duration::from_millis(2)));
+        assert_eq!(stats.average(), Some(Duration::from_micros(2500)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(stats.percentile(0.5), Some(Duration::from_millis(2)));
+        assert_eq!(stats.percentile(0.99), Some(Duration::from_millis(4)));
+    }
+
+    #[test]
+    pub fn frame_history_wraps() {
+        let mut stats = FrameStats::default();
+        for ms in 0..(FRAME_HISTORY + 10) as u64 {
+            stats.push(Duration::from_millis(ms));
+        }
+
+        assert_eq!(stats.len(), FRAME_HISTORY);
+        assert_eq!(stats.frame_times().next(), Some(Duration::from_millis(10)));
+        assert_eq!(stats.last(), Some(Duration::from_millis(FRAME_HISTORY as u64 + 9)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    pub fn swapchain_statistics() {
+        let mut stats = SwapchainStats::default();
+        stats.record_result(Ok(false));
+        stats.record_result(Ok(true));
+        stats.record_result(Err(vk::Result::SUBOPTIMAL_KHR));
+        stats.record_result(Err(vk::Result::ERROR_OUT_OF_DATE_KHR));
+        stats.record_result(Err(vk::Result::TIMEOUT));
+        stats.record_recreation();
+        stats.record_acquire(Duration::from_millis(3));
+
+        assert_eq!(stats.suboptimal_count(), 2);
+        assert_eq!(stats.out_of_date_count(), 1);
+        assert_eq!(stats.recreation_count(), 1);
+        assert_eq!(stats.acquire_latency().last(), Some(Duration::from_millis(3)));
+    }
+}
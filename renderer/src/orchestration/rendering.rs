@@ -1,27 +1,39 @@
-use std::ffi::CString;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
 use std::mem::ManuallyDrop;
 use std::slice;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use ash::prelude::VkResult;
 use ash::vk::{self};
 use egui::ahash::HashMapExt;
 use egui_winit::winit::event::WindowEvent;
+use egui_winit::winit::window::WindowId;
 use egui_winit::EventResponse;
+use gpu_allocator::vulkan::Allocator;
 use nohash_hasher::IntMap;
 use puffin::profile_scope;
 
 use crate::application::RendererError;
+use crate::orchestration::frame_stats::{FrameStats, SwapchainStats};
 use crate::traits::handle::Handle;
-use crate::vk::context::Context;
+use crate::vk::command_buffer::{BarrierPhase, CommandBuffer};
+use crate::vk::context::{filter_extensions, Context};
 use crate::vk::frame_data::FrameData;
-use crate::vk::framebuffer::Framebuffer;
+use crate::vk::image::destination_phase;
+use crate::vk::framebuffer::{Framebuffer, FramebufferCache};
 use crate::vk::logical_device::LogicalDevice;
+use crate::vk::physical_device::{MemoryHeapBudget, PhysicalDevice};
 use crate::vk::queue::{QueueAffinity, QueueFamily};
 use crate::vk::renderer::RendererOptions;
 use crate::vk::swapchain::Swapchain;
 use crate::window::Window;
 
 /// A renderer is effectively a type that declares the need to work with its own render pass.
+///
+/// Renderers are dropped by their [`RendererOrchestrator`] once the device is idle, before the swapchain they draw to;
+/// see [`RendererOrchestrator`] for the complete teardown order.
 pub trait Renderer {
     /// Returns a recorded command buffer that contains all the commands needed to render the contents of this renderer.
     /// 
@@ -32,12 +44,13 @@ pub trait Renderer {
     /// * `frame_data` - A frame-specific data structure.
     fn record_commands(&mut self, swapchain : &Swapchain, framebuffer : &Framebuffer, frame_data : &FrameData);
     
-    /// Returns an array of compatible framebuffers for this renderer.
+    /// Returns an array of compatible framebuffers for this renderer, one per image of the swapchain.
     /// 
     /// # Arguments
     /// 
     /// * `swapchain` - The swapchain currently in use.
-    fn create_framebuffers(&self, swapchain : &Swapchain) -> Vec<Framebuffer>;
+    /// * `cache` - The cache of the viewport, which framebuffers should be obtained from.
+    fn create_framebuffers(&self, swapchain : &Swapchain, cache : &mut FramebufferCache) -> Vec<Arc<Framebuffer>>;
 
     /// Returns a debug marker used with [`ash::vk::DebugUtilsLabelEXT`].
     fn marker_data<'a>(&self) -> (&'a str, [f32; 4]);
@@ -47,6 +60,47 @@ pub trait Renderer {
     }
 
     fn update(&mut self) { }
+
+    /// Called when the swapchain this renderer draws to was recreated, typically because its window was resized.
+    ///
+    /// # Description
+    ///
+    /// This is called before [`Renderer::create_framebuffers`], so that renderers can rebuild any resource that depends
+    /// on the size or the number of images of the swapchain. Framebuffers themselves are managed by the orchestrator.
+    ///
+    /// # Arguments
+    ///
+    /// * `swapchain` - The new swapchain.
+    fn on_swapchain_recreated(&mut self, _swapchain : &Swapchain) { }
+
+    /// Returns true if this renderer draws at the render scale of the swapchain, with a render pass created by
+    /// [`Swapchain::create_render_pass`]; see [`SwapchainOptions::render_scale`](crate::vk::swapchain::SwapchainOptions::render_scale).
+    ///
+    /// # Description
+    ///
+    /// If the swapchain is scaled, the orchestrator upscales the scene into the swapchain's image once every scaled
+    /// renderer recorded its commands. Renderers recording their commands after a scaled renderer must not be scaled,
+    /// and find the image in [`vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL`], holding the upscaled scene.
+    fn is_scaled(&self) -> bool { false }
+
+    /// Returns true if this renderer should record its commands for the current frame.
+    ///
+    /// # Description
+    ///
+    /// Every renderer owns its render pass, so skipping a disabled renderer skips its render pass as a whole; the
+    /// subpasses of other renderers are unaffected, and subpass sequencing within a render pass remains the
+    /// responsibility of the renderer that owns it.
+    ///
+    /// The last renderer to record its commands usually transitions the swapchain image for presentation (see
+    /// [`RendererOrchestrator::insert_renderer`]); disabling it leaves the image in the wrong layout when it is presented.
+    fn is_enabled(&self) -> bool { true }
+
+    /// Enables or disables this renderer. Renderers that can't be disabled ignore this.
+    fn set_enabled(&mut self, _enabled : bool) { }
+
+    /// Returns the delay after which this renderer wants the next frame to be drawn, or [`None`] if it only needs to be
+    /// redrawn in response to input. This is only used with [`RedrawMode::Reactive`](crate::application::RedrawMode).
+    fn repaint_after(&self) -> Option<Duration> { None }
 }
 
 pub struct RenderingContextImpl {
@@ -57,13 +111,88 @@ pub struct RenderingContextImpl {
     pub graphics_queue : QueueFamily,
     pub presentation_queue : QueueFamily,
     pub transfer_queue : QueueFamily,
+    pub compute_queue : QueueFamily,
 
     pub options : RendererOptions,
+    /// The extensions enabled on the logical device, kept so that the device can be recreated if it is lost.
+    pub(in crate) device_extensions : Vec<CString>,
 }
 pub type RenderingContext = Arc<RenderingContextImpl>;
 
+impl RenderingContextImpl {
+    /// Returns the memory allocator. There is exactly one allocator per logical device, and every buffer or image
+    /// created through this context is allocated from it.
+    #[inline] pub fn allocator(&self) -> &Arc<Mutex<Allocator>> { self.device.allocator() }
+
+    /// Returns the Vulkan instance the device was created from; see [`Context::raw`] to load the functions of instance
+    /// extensions.
+    #[inline] pub fn instance(&self) -> &Arc<Context> { &self.context }
+
+    /// Returns the memory usage and budget of each memory heap, or [`None`] if `VK_EXT_memory_budget` is not supported.
+    pub fn memory_budget(&self) -> Option<Vec<MemoryHeapBudget>> {
+        if self.device.memory_budget {
+            Some(self.device.physical_device.memory_budget(&self.context))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if compute work is submitted to a queue family that is distinct from the graphics queue family.
+    #[inline] pub fn has_async_compute(&self) -> bool { self.compute_queue.index() != self.graphics_queue.index() }
+
+    /// Returns true if the given device extension is enabled on the logical device; see
+    /// [`RendererOptions::optional_device_extension`].
+    pub fn is_device_extension_enabled(&self, extension : &CStr) -> bool {
+        self.device_extensions.iter().any(|enabled| enabled.as_c_str() == extension)
+    }
+
+    /// Submits compute work to the compute queue.
+    /// 
+    /// # Description
+    /// 
+    /// If the device does not expose a queue family dedicated to compute operations, work is submitted
+    /// to the graphics queue instead. Synchronization with graphics work is done through semaphores.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `command_buffers`   - An array of command buffers to submit.
+    /// * `wait_info`         - An array of semaphores to wait on before executing the command buffers.
+    /// * `signal_semaphores` - An array of semaphores that will be signalled when all command buffers have completed execution.
+    /// * `fence`             - A fence that will be signalled when all command buffers have completed execution.
+    pub fn submit_compute(&self,
+        command_buffers : &[&CommandBuffer],
+        wait_info : &[(vk::Semaphore, vk::PipelineStageFlags)],
+        signal_semaphores : &[vk::Semaphore],
+        fence : vk::Fence
+    ) {
+        let compute_queue = self.device.get_queue(QueueAffinity::Compute, self.compute_queue.index())
+            .expect("Failed to recover the compute queue");
+
+        self.device.submit(compute_queue, command_buffers, wait_info, signal_semaphores, fence);
+    }
+}
+
+impl Drop for RenderingContextImpl {
+    fn drop(&mut self) {
+        // Every object created from this context holds a reference to it, so they were all destroyed already. The
+        // instance is released last, when `context` is dropped.
+        self.device.wait_idle();
+        self.window.destroy_surface();
+        unsafe {
+            self.device.destroy();
+        }
+    }
+}
+
 pub type RendererFn = fn(context : &RenderingContext, swapchain : &Swapchain) -> Box<dyn Renderer>;
 
+/// Identifies a [`Renderer`] within a [`RendererOrchestrator`].
+///
+/// Renderers added through [`Orchestrator::add_renderer`] are identified by the order in which they were added,
+/// starting at zero.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RendererID(usize);
+
 pub struct Orchestrator {
     context : Arc<Context>,
     renderers : Vec<RendererFn>,
@@ -100,15 +229,24 @@ impl Orchestrator {
         self
     }
 
+    /// Creates the logical device, with the device extensions requested by the options, and the primary viewport.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if no physical device supports the required extensions and features; see
+    ///   [`RendererOptions::device_extension`] and [`RendererOptions::required_features`].
     pub fn build(&self,
-        options : RendererOptions,
+        mut options : RendererOptions,
         window : Window,
-        device_extensions : Vec<CString>,
     ) -> RendererOrchestrator {
         assert_eq!(self.renderers.len(), self.render_order.len());
         assert_eq!(self.renderers.len(), self.update_order.len());
 
-        let (device, graphics_queue, presentation_queue, transfer_queue) = self.create_device(&window, &options, device_extensions);
+        let (device, device_extensions, graphics_queue, presentation_queue, transfer_queue, compute_queue) = self.create_device(&window, &options);
+
+        // Everything that is multisampled (swapchain attachments, render passes, pipelines) reads the sample count
+        // from these options, so clamping it here keeps them all in agreement.
+        options.clamp_multisampling(device.physical_device.max_msaa_samples());
 
         let context = Arc::new(RenderingContextImpl {
             context : self.context.clone(),
@@ -118,180 +256,440 @@ impl Orchestrator {
             graphics_queue,
             presentation_queue,
             transfer_queue,
+            compute_queue,
 
             options,
+            device_extensions,
         });
 
-        let swapchain = Swapchain::new(&context, &options, vec![graphics_queue, presentation_queue]);
+        let primary = Viewport::new(&context, None, &self.renderers, self.render_order.clone(), self.update_order.clone());
 
-        let (renderers, framebuffers, frames) = self.create_frame_data(&swapchain, &context);
-        
         RendererOrchestrator {
             context,
-            swapchain : ManuallyDrop::new(swapchain),
-
-            renderers,
-            render_order : self.render_order.clone(),
-            update_order : self.update_order.clone(),
+            primary,
+            viewports : HashMap::new(),
 
-            framebuffers,
-            frames,
-            frame_index : 0,
-            image_index : 0
+            frame_stats : FrameStats::default(),
+            last_frame : None,
         }
     }
 
-    fn create_device(&self, window : &Window, settings : &RendererOptions, device_extensions : Vec<CString>)
-        -> (LogicalDevice, QueueFamily, QueueFamily, QueueFamily)
+    fn create_device(&self, window : &Window, settings : &RendererOptions)
+        -> (LogicalDevice, Vec<CString>, QueueFamily, QueueFamily, QueueFamily, QueueFamily)
     {
-        let (physical_device, graphics_queue, presentation_queue, transfer_queue) = self.context.select_physical_device(&window, &device_extensions);
+        let (physical_device, graphics_queue, presentation_queue, transfer_queue, compute_queue) = self.context.select_physical_device(&window, &settings.device_extensions, &settings.required_features);
 
-        let queue_families = { // Deduplicate the graphics and presentation queues.
-            let mut queue_families_map = IntMap::<u32, QueueFamily>::with_capacity(3);
-            queue_families_map.entry(graphics_queue.index()).or_insert(graphics_queue);
-            queue_families_map.entry(presentation_queue.index()).or_insert(presentation_queue);
-            queue_families_map.entry(transfer_queue.index()).or_insert(transfer_queue);
-
-            queue_families_map.into_values().collect::<Vec<_>>()
-        };
+        let available_extensions = physical_device.extensions().iter()
+            .map(CString::as_c_str)
+            .collect::<Vec<_>>();
+        let device_extensions = filter_extensions("device", &available_extensions, &settings.device_extensions, &settings.optional_device_extensions)
+            .expect("The selected physical device does not support the required extensions");
 
-        let device = physical_device.create_logical_device(
-            &self.context,
-            queue_families.iter()
-                .map(|queue : &QueueFamily| ((settings.get_queue_count)(queue), queue))
-                .collect::<Vec<_>>(),
-            |_index, _family| 1.0_f32,
+        let device = create_logical_device(&self.context, &physical_device,
+            &[graphics_queue, presentation_queue, transfer_queue, compute_queue],
+            settings,
             &device_extensions,
-            (settings.get_pipeline_cache_file)(),
-            &window,
-        );
+            window);
 
-        (device, graphics_queue, presentation_queue, transfer_queue)
+        (device, device_extensions, graphics_queue, presentation_queue, transfer_queue, compute_queue)
     }
+}
 
-    fn create_frame_data(&self, swapchain : &Swapchain, context : &RenderingContext) -> (Vec<Box<dyn Renderer>>, Vec<Framebuffer>, Vec<FrameData>) {
-        let mut framebuffers = vec![];
-        let mut created_renderers = vec![];
-        let renderer_count = self.renderers.len();
-        for renderer in &self.renderers {
-            let renderer = renderer(context, swapchain);
-
-            framebuffers.extend(renderer.create_framebuffers(&swapchain));
-            created_renderers.push(renderer);
+/// Creates a logical device on the given physical device.
+///
+/// # Arguments
+///
+/// * `context` - The Vulkan instance.
+/// * `physical_device` - The physical device.
+/// * `queues` - The queue families used by the application. Duplicates are ignored.
+/// * `settings` - The renderer options, which decide how many queues are created and their priority.
+/// * `device_extensions` - The extensions to enable on the device.
+/// * `window` - The window the device will present to.
+fn create_logical_device(context : &Context,
+    physical_device : &PhysicalDevice,
+    queues : &[QueueFamily],
+    settings : &RendererOptions,
+    device_extensions : &Vec<CString>,
+    window : &Window,
+) -> LogicalDevice {
+    let queue_families = { // Deduplicate the graphics, presentation, transfer and compute queues.
+        let mut queue_families_map = IntMap::<u32, QueueFamily>::with_capacity(queues.len());
+        for queue in queues {
+            queue_families_map.entry(queue.index()).or_insert(*queue);
         }
 
-        assert_eq!(renderer_count * swapchain.image_count(), framebuffers.len());
+        queue_families_map.into_values().collect::<Vec<_>>()
+    };
+
+    physical_device.create_logical_device(
+        context,
+        queue_families.iter()
+            .map(|queue : &QueueFamily| ((settings.get_queue_count)(queue), queue))
+            .collect::<Vec<_>>(),
+        settings.get_queue_priority,
+        device_extensions,
+        settings,
+        window,
+    )
+}
 
-        // Create frame data
-        let frames = {
-            let mut frames = Vec::<FrameData>::with_capacity(swapchain.image_count());
-            for i in 0..swapchain.image_count() {
-                frames.push(FrameData::new(i, &context));
-            }
-            frames
+/// The amount of times a frame is drawn again after the swapchain was recreated, before giving up on it.
+const SWAPCHAIN_RECREATION_ATTEMPTS : usize = 2;
+
+/// Draws a frame, recreating the swapchain and drawing again if it was invalidated.
+///
+/// # Arguments
+///
+/// * `target` - What is drawn to.
+/// * `draw` - Draws the frame.
+/// * `recreate` - Recreates the swapchain.
+///
+/// # Returns
+///
+/// [`RendererError::InvalidSwapchain`] if the swapchain is still invalid after [`SWAPCHAIN_RECREATION_ATTEMPTS`]
+/// recreations, in which case the caller should try again later, or any other error of `draw`.
+fn draw_with_recreation<T>(
+    target : &mut T,
+    draw : impl Fn(&mut T) -> Result<(), RendererError>,
+    recreate : impl Fn(&mut T),
+) -> Result<(), RendererError> {
+    let mut result = draw(target);
+    for _ in 0..SWAPCHAIN_RECREATION_ATTEMPTS {
+        let Err(RendererError::InvalidSwapchain) = result else {
+            break;
         };
 
-        (created_renderers, framebuffers, frames)
+        recreate(target);
+        result = draw(target);
     }
+    result
 }
 
-pub struct RendererOrchestrator {
-    pub context : RenderingContext,
-    pub swapchain : ManuallyDrop<Swapchain>,
+/// Converts an error returned while acquiring or presenting a swapchain image.
+///
+/// # Arguments
+///
+/// * `error` - The error.
+/// * `operation` - What was being done, used if the error is unrecoverable.
+///
+/// # Panics
+///
+/// * Panics if the error is neither a timeout, an invalid swapchain, nor a device loss.
+fn swapchain_error(error : vk::Result, operation : &str) -> RendererError {
+    match error {
+        vk::Result::TIMEOUT | vk::Result::NOT_READY => RendererError::Timeout,
+        vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => RendererError::InvalidSwapchain,
+        vk::Result::ERROR_DEVICE_LOST => RendererError::DeviceLost,
+        error => panic!("Error while {}: {:?}", operation, error),
+    }
+}
+
+/// Records the upscaling of the scene into the image of the swapchain; see [`Renderer::is_scaled`].
+///
+/// # Arguments
+///
+/// * `cmd` - The command buffer of the frame.
+/// * `swapchain` - The swapchain, which must be scaled.
+/// * `image_index` - The index of the image of the swapchain the frame is drawn to.
+/// * `final_layout` - The layout the image of the swapchain is left in.
+fn record_upscale(cmd : &CommandBuffer, swapchain : &mut Swapchain, image_index : usize, final_layout : vk::ImageLayout) {
+    let (src_extent, dst_extent) = (swapchain.render_extent, swapchain.extent);
+    let image = &mut swapchain.images[image_index];
+    let scaled = image.scaled.as_mut().expect("The swapchain is not scaled");
+
+    // The render pass of the scene leaves the scaled image ready to be blitted from, and the previous contents of the
+    // image of the swapchain are discarded.
+    scaled.layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+    image.present.layout = vk::ImageLayout::UNDEFINED;
+
+    cmd.image_memory_barrier(scaled,
+        BarrierPhase::ignore_queue(vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+        destination_phase(vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+        vk::DependencyFlags::empty(),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+    // The image of the swapchain is only available once the semaphore waited on at this stage is signaled.
+    cmd.image_memory_barrier(&mut image.present,
+        BarrierPhase::ignore_queue(vk::AccessFlags::NONE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+        destination_phase(vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+        vk::DependencyFlags::empty(),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+    cmd.blit_image(scaled, &image.present, &[upscale_blit(src_extent, dst_extent)], vk::Filter::LINEAR);
+    image.present.layout_transition(cmd, final_layout, vk::DependencyFlags::empty());
+}
+
+/// Returns the region blitting a whole image of the given extent onto a whole image of another extent.
+fn upscale_blit(src_extent : vk::Extent2D, dst_extent : vk::Extent2D) -> vk::ImageBlit {
+    let corner = |extent : vk::Extent2D| vk::Offset3D { x : extent.width as i32, y : extent.height as i32, z : 1 };
+    let subresource = vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    vk::ImageBlit::default()
+        .src_subresource(subresource)
+        .src_offsets([vk::Offset3D::default(), corner(src_extent)])
+        .dst_subresource(subresource)
+        .dst_offsets([vk::Offset3D::default(), corner(dst_extent)])
+}
+
+/// A renderer of a viewport, along with the framebuffers it draws to.
+struct RendererEntry {
+    id : RendererID,
+    priority : usize,
+    renderer : Box<dyn Renderer>,
+    framebuffers : Vec<Arc<Framebuffer>>,
+    /// The function that created the renderer, used to create it again if the device is lost.
+    create : RendererFn,
+}
+
+/// Rendering state associated with a single window: a swapchain, the renderers drawing to it, and the per-frame data
+/// needed to drive them.
+struct Viewport {
+    /// The window this viewport presents to, or [`None`] if this viewport presents to the primary window owned by
+    /// the rendering context.
+    window : Option<Window>,
+    swapchain : ManuallyDrop<Swapchain>,
+    framebuffers : FramebufferCache,
+    // Set by Viewport::release until Viewport::restore is called; the swapchain is destroyed in the meantime.
+    released : bool,
+
+    // Renderers, sorted by ascending priority; this is the order in which they record commands.
+    renderers : Vec<RendererEntry>,
+    update_order : Vec<RendererID>,
+    next_id : usize,
 
-    renderers : Vec<Box<dyn Renderer>>,
-    render_order : Vec<usize>,
-    update_order : Vec<usize>,
-    // This should be a bidimensional array but for the sake of memory layout, we use a single dimensional array.
-    // The layout is effectively [renderer 1's framebuffers], [renderer 2's framebuffers], ...
-    framebuffers : Vec<Framebuffer>,
-    
     frames : Vec<FrameData>,
     image_index : usize,
     frame_index : usize,
+
+    swapchain_stats : SwapchainStats,
 }
-impl RendererOrchestrator {
-    pub fn update(&mut self) {
-        for i in &self.update_order {
-            self.renderers[*i].update();
+
+impl Viewport {
+    fn new(context : &RenderingContext,
+        window : Option<Window>,
+        renderers : &[RendererFn],
+        render_order : Vec<usize>,
+        update_order : Vec<usize>
+    ) -> Self {
+        let swapchain = Swapchain::new(context,
+            window.as_ref().unwrap_or(&context.window),
+            &context.options,
+            vec![context.graphics_queue, context.presentation_queue]
+        );
+
+        let frames = Self::create_frames(context, &swapchain);
+
+        let mut this = Self {
+            window,
+            swapchain : ManuallyDrop::new(swapchain),
+            framebuffers : FramebufferCache::default(),
+            released : false,
+
+            renderers : Vec::with_capacity(renderers.len()),
+            update_order : update_order.into_iter().map(RendererID).collect(),
+            next_id : renderers.len(),
+
+            frames,
+            image_index : 0,
+            frame_index : 0,
+
+            swapchain_stats : SwapchainStats::default(),
+        };
+
+        for (priority, index) in render_order.into_iter().enumerate() {
+            let entry = this.create_renderer(context, RendererID(index), priority, renderers[index]);
+            this.renderers.push(entry);
         }
+
+        this
     }
 
-    pub fn draw_frame(&mut self) -> Result<(), RendererError> {
-        profile_scope!("Application rendering");
+    fn create_renderer(&mut self, context : &RenderingContext, id : RendererID, priority : usize, create : RendererFn) -> RendererEntry {
+        let renderer = create(context, &self.swapchain);
+        let framebuffers = renderer.create_framebuffers(&self.swapchain, &mut self.framebuffers);
+        assert_eq!(self.swapchain.image_count(), framebuffers.len());
+
+        RendererEntry { id, priority, renderer, framebuffers, create }
+    }
+
+    /// Creates a new renderer and inserts it after all renderers with a lower or equal priority.
+    fn insert_renderer(&mut self, context : &RenderingContext, priority : usize, renderer : RendererFn) -> RendererID {
+        let id = RendererID(self.next_id);
+        self.next_id += 1;
+
+        let entry = self.create_renderer(context, id, priority, renderer);
+        let position = self.renderers.partition_point(|entry| entry.priority <= priority);
+        self.renderers.insert(position, entry);
+        self.update_order.push(id);
 
-        let (image_acquired, _) = self.acquire_image()?;
+        id
+    }
+
+    /// Removes a renderer. The caller is responsible for making sure the device is idle.
+    fn remove_renderer(&mut self, id : RendererID) -> bool {
+        self.update_order.retain(|&entry| entry != id);
+        match self.renderers.iter().position(|entry| entry.id == id) {
+            Some(position) => {
+                self.renderers.remove(position);
+                // The render pass of the renderer is gone, so its framebuffers must not be handed out anymore.
+                self.framebuffers.evict_unused();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Enables or disables a renderer. Returns `false` if no such renderer exists.
+    fn set_renderer_enabled(&mut self, id : RendererID, enabled : bool) -> bool {
+        match self.renderers.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.renderer.set_enabled(enabled);
+                true
+            },
+            None => false
+        }
+    }
+
+    fn create_frames(context : &RenderingContext, swapchain : &Swapchain) -> Vec<FrameData> {
+        let mut frames = Vec::<FrameData>::with_capacity(swapchain.image_count());
+        for i in 0..swapchain.image_count() {
+            frames.push(FrameData::new(i, context));
+        }
+        frames
+    }
+
+    /// Returns the window this viewport presents to.
+    fn window<'a>(&'a self, context : &'a RenderingContextImpl) -> &'a Window {
+        self.window.as_ref().unwrap_or(&context.window)
+    }
+
+    fn update(&mut self) {
+        for id in &self.update_order {
+            if let Some(entry) = self.renderers.iter_mut().find(|entry| entry.id == *id) {
+                entry.renderer.update();
+            }
+        }
+    }
+
+    /// Returns the shortest repaint delay requested by the enabled renderers of this viewport.
+    fn repaint_after(&self) -> Option<Duration> {
+        self.renderers.iter()
+            .filter(|entry| entry.renderer.is_enabled())
+            .filter_map(|entry| entry.renderer.repaint_after())
+            .min()
+    }
+
+    fn draw_frame(&mut self, context : &RenderingContext, timeout : u64) -> Result<(), RendererError> {
+        let (image_acquired, _) = self.acquire_image(context, timeout)?;
         let frame = &self.frames[self.frame_index];
 
         frame.cmd.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-        for i in &self.render_order {
-            let renderer = &mut self.renderers[*i];
+        // Whether the scaled scene was drawn, but not yet upscaled into the image of the swapchain.
+        let mut pending_upscale = false;
+        for entry in &mut self.renderers {
+            let renderer = &mut entry.renderer;
+            if !renderer.is_enabled() {
+                continue;
+            }
+
+            if pending_upscale && !renderer.is_scaled() {
+                record_upscale(&frame.cmd, &mut self.swapchain, self.image_index, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+                pending_upscale = false;
+            }
+            pending_upscale |= renderer.is_scaled() && self.swapchain.is_scaled();
+
             profile_scope!("Renderer ", renderer.marker_data().0);
 
-            let framebuffer = &self.framebuffers[self.frames.len() * i + self.frame_index];
+            // Framebuffers wrap swapchain images, which are not acquired in the same order as frames in flight.
+            let framebuffer = &entry.framebuffers[self.image_index];
 
             let marker_data = renderer.marker_data();
             frame.cmd.begin_label(marker_data.0, marker_data.1);
             renderer.record_commands(&self.swapchain, framebuffer, frame);
             frame.cmd.end_label();
         }
+        if pending_upscale {
+            record_upscale(&frame.cmd, &mut self.swapchain, self.image_index, vk::ImageLayout::PRESENT_SRC_KHR);
+        }
         frame.cmd.end();
 
-        let signal_semaphore = self.submit_frame(&[(image_acquired, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)]);
-        self.present_frame(signal_semaphore)?;
+        let signal_semaphore = self.submit_frame(context, &[(image_acquired, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)])?;
+        self.present_frame(context, signal_semaphore)?;
 
         Ok(())
     }
 
-    pub fn handle_event(&mut self, event : &WindowEvent) {
-        profile_scope!("Event handling");
-
+    /// Forwards an event to the renderers of this viewport, until one of them consumes it.
+    ///
+    /// Renderers that record their commands last draw on top of the others, so they receive events first; this way,
+    /// an interface drawn over the scene gets to consume the events aimed at it.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a renderer consumed the event.
+    fn handle_event(&mut self, event : &WindowEvent) -> bool {
         let mut repaint_instructions = Vec::<bool>::with_capacity(self.renderers.len());
-        for i in 0..self.renderers.len() {
-            let event_response = self.renderers[i].handle_event(event);
+        for entry in self.renderers.iter_mut().rev() {
+            let event_response = entry.renderer.handle_event(event);
             repaint_instructions.push(event_response.repaint);
             if event_response.consumed {
-                break;
+                return true;
             }
         }
 
         // TOOD: do somethign with the repaint instructions.
+        false
     }
 
-    fn acquire_image(&mut self) -> Result<(vk::Semaphore, usize), RendererError> {
+    /// Acquires the next swapchain image.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait, in nanoseconds, for the previous use of this frame to complete and for an image
+    ///               to become available. If either takes longer, [`RendererError::Timeout`] is returned.
+    fn acquire_image(&mut self, context : &RenderingContext, timeout : u64) -> Result<(vk::Semaphore, usize), RendererError> {
         profile_scope!("Frame acquisition");
 
-        self.context.device.wait_for_fence(self.frames[self.frame_index].in_flight);
+        match context.device.wait_for_fence_timeout(self.frames[self.frame_index].in_flight, timeout) {
+            Ok(_) => (),
+            Err(vk::Result::TIMEOUT) => return Err(RendererError::Timeout),
+            Err(vk::Result::ERROR_DEVICE_LOST) => return Err(RendererError::DeviceLost),
+            Err(error) => panic!("Error while waiting for the frame to complete: {:?}", error)
+        }
+
+        // The GPU is done with this frame, so the semaphores it used can now be recycled.
+        self.frames[self.frame_index].semaphore_pool.reset();
 
         let acquired_semaphore = self.frames[self.frame_index].image_available;
 
-        let image_index = match self.swapchain.acquire_image(acquired_semaphore, vk::Fence::null(), u64::MAX) {
-            Ok((image_index, _)) => image_index,
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                return Err(RendererError::InvalidSwapchain);
-            },
-            Err(vk::Result::SUBOPTIMAL_KHR) => {
-                return Err(RendererError::InvalidSwapchain);
-            },
-            Err(error) => panic!("Error while acquiring next image: {:?}", error)
-        };
+        let acquire_start = Instant::now();
+        let result = self.swapchain.acquire_image(acquired_semaphore, vk::Fence::null(), timeout);
+        self.swapchain_stats.record_acquire(acquire_start.elapsed());
+        self.swapchain_stats.record_result(result.map(|(_, suboptimal)| suboptimal));
+
+        let image_index = result
+            .map(|(image_index, _)| image_index)
+            .map_err(|error| swapchain_error(error, "acquiring next image"))?;
 
-        assert!((image_index as usize) < self.frames.len());
+        assert!((image_index as usize) < self.swapchain.image_count());
         self.image_index = image_index as _;
 
-        // Set the image index returned by acquisition as the current frame.
-        self.context.device.reset_fences(slice::from_ref(&self.frames[self.frame_index].in_flight));
+        // The fence is only reset once we know this frame will be submitted.
+        context.device.reset_fences(slice::from_ref(&self.frames[self.frame_index].in_flight));
 
         Ok((acquired_semaphore, self.frame_index))
     }
 
-    fn submit_frame(&mut self, wait_info : &[(vk::Semaphore, vk::PipelineStageFlags)]) -> vk::Semaphore {
+    fn submit_frame(&mut self, context : &RenderingContext, wait_info : &[(vk::Semaphore, vk::PipelineStageFlags)]) -> Result<vk::Semaphore, RendererError> {
         profile_scope!("Frame submission");
 
         let signal_semaphore = self.frames[self.frame_index].render_finished;
 
-        let graphics_queue = self.context.device.get_queues(QueueAffinity::Graphics)[0];
-        self.context.device.submit(graphics_queue,
+        let graphics_queue = context.device.get_queues(QueueAffinity::Graphics)[0];
+        let result = context.device.try_submit(graphics_queue,
             &[
                 &self.frames[self.frame_index].cmd
             ],
@@ -299,11 +697,15 @@ impl RendererOrchestrator {
             &[signal_semaphore],
             self.frames[self.frame_index].in_flight
         );
-    
-        signal_semaphore
+
+        match result {
+            Ok(_) => Ok(signal_semaphore),
+            Err(vk::Result::ERROR_DEVICE_LOST) => Err(RendererError::DeviceLost),
+            Err(error) => panic!("Error while submitting frame: {:?}", error)
+        }
     }
 
-    fn present_frame(&mut self, wait_semaphore: vk::Semaphore) -> Result<(), RendererError> {
+    fn present_frame(&mut self, context : &RenderingContext, wait_semaphore: vk::Semaphore) -> Result<(), RendererError> {
         profile_scope!("Frame presentation");
 
         let wait_semaphores = [wait_semaphore];
@@ -316,46 +718,539 @@ impl RendererOrchestrator {
             .image_indices(&image_indices);
 
         unsafe {
-            let presentation_queue = self.context.device.get_queues(QueueAffinity::Graphics)[0]; // TODO: Use the present queue here, not the graphics queue
+            let presentation_queue = context.device.get_queues(QueueAffinity::Graphics)[0]; // TODO: Use the present queue here, not the graphics queue
             let result = self.swapchain.loader
                 .queue_present(presentation_queue.handle(), &present_info);
 
             self.frame_index = (self.frame_index + 1) % self.frames.len();
-            self.frames[self.frame_index].semaphore_pool.reset();
+            self.swapchain_stats.record_result(result);
 
-            match result {
-                Ok(_) => Ok(()),
-                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(RendererError::InvalidSwapchain),
-                Err(vk::Result::SUBOPTIMAL_KHR) => Err(RendererError::InvalidSwapchain),
-                Err(error) => panic!("Error while presenting frame: {:?}", error)
-            }
+            result
+                .map(|_| ())
+                .map_err(|error| swapchain_error(error, "presenting frame"))
         }
     }
 
-    pub fn recreate_swapchain(&mut self) {
-        self.context.device.wait_idle();
-
-        self.framebuffers.clear();
+    /// Recreates the swapchain of this viewport, as well as all the objects that depend on it.
+    ///
+    /// The caller is responsible for making sure the device is idle.
+    fn recreate_swapchain(&mut self, context : &RenderingContext) {
+        for entry in &mut self.renderers {
+            entry.framebuffers.clear();
+        }
+        self.framebuffers.evict_views(&self.swapchain.views());
         self.frames.clear();
 
         unsafe {
             ManuallyDrop::drop(&mut self.swapchain);
         }
 
-        self.swapchain = ManuallyDrop::new(Swapchain::new(&self.context, &self.context.options, vec![
-            self.context.graphics_queue,
-            self.context.presentation_queue
+        // The swapchain is typically recreated because the surface changed.
+        self.window(context).invalidate_surface();
+        self.swapchain = ManuallyDrop::new(Swapchain::new(context, self.window(context), &context.options, vec![
+            context.graphics_queue,
+            context.presentation_queue
         ]));
 
-        for renderer in &mut self.renderers {
-            self.framebuffers.extend(renderer.create_framebuffers(&self.swapchain));
+        for entry in &mut self.renderers {
+            entry.renderer.on_swapchain_recreated(&self.swapchain);
+            entry.framebuffers = entry.renderer.create_framebuffers(&self.swapchain, &mut self.framebuffers);
+            assert_eq!(self.swapchain.image_count(), entry.framebuffers.len());
         }
 
-        self.frames = Vec::<FrameData>::with_capacity(self.swapchain.image_count());
-        for i in 0..self.swapchain.image_count() {
-            self.frames.push(FrameData::new(i, &self.context));
-        }
+        self.frames = Self::create_frames(context, &self.swapchain);
+        self.frame_index = 0;
+        self.swapchain_stats.record_recreation();
 
         // I think that's it? Everything should drop.
     }
+
+    /// Destroys the swapchain, frames and renderers of this viewport, but keeps its window.
+    ///
+    /// The caller is responsible for making sure the device is idle, and must call [`Viewport::restore`] with the
+    /// returned renderers before this viewport is used again. Releasing a viewport twice returns no renderers.
+    ///
+    /// # Returns
+    ///
+    /// The identifier, priority, state and creation function of each renderer, in recording order.
+    fn release(&mut self) -> Vec<(RendererID, usize, bool, RendererFn)> {
+        if self.released {
+            return vec![];
+        }
+
+        self.released = true;
+        self.frames.clear();
+        let renderers = self.renderers.drain(..)
+            .map(|entry| (entry.id, entry.priority, entry.renderer.is_enabled(), entry.create))
+            .collect();
+        self.framebuffers.clear();
+
+        unsafe {
+            ManuallyDrop::drop(&mut self.swapchain);
+        }
+
+        renderers
+    }
+
+    /// Recreates the swapchain, frames and renderers of a viewport after a call to [`Viewport::release`].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderers` - The renderers returned by [`Viewport::release`].
+    fn restore(&mut self, context : &RenderingContext, renderers : Vec<(RendererID, usize, bool, RendererFn)>) {
+        self.window(context).invalidate_surface();
+        let swapchain = Swapchain::new(context, self.window(context), &context.options, vec![
+            context.graphics_queue,
+            context.presentation_queue
+        ]);
+        self.swapchain = ManuallyDrop::new(swapchain);
+        self.released = false;
+        self.frames = Self::create_frames(context, &self.swapchain);
+        self.image_index = 0;
+        self.frame_index = 0;
+
+        for (id, priority, enabled, create) in renderers {
+            let mut entry = self.create_renderer(context, id, priority, create);
+            entry.renderer.set_enabled(enabled);
+            self.renderers.push(entry);
+        }
+    }
+
+    /// Destroys this viewport. The caller is responsible for making sure the device is idle.
+    fn destroy(mut self) {
+        _ = self.release();
+
+        if let Some(window) = &mut self.window {
+            window.destroy_surface();
+        }
+    }
+}
+
+/// Drives all [`Renderer`]s of the application.
+///
+/// Rendering is organized around viewports: the primary viewport presents to the window owned by the
+/// [`RenderingContextImpl`], while secondary viewports each own an additional window. All viewports share the same
+/// logical device, memory allocator and pipeline cache, but each viewport has its own swapchain, framebuffers,
+/// frames in flight and renderers.
+///
+/// # Teardown
+///
+/// Objects are destroyed in an explicit order when the orchestrator is dropped, rather than in the order fields are
+/// declared in:
+///
+/// 1. The device waits until it is idle.
+/// 2. Secondary viewports are destroyed: their frames, renderers, framebuffers, swapchain and window surface.
+/// 3. The primary viewport is destroyed the same way, except for the window surface, which belongs to the context.
+/// 4. Once the last reference to the [`RenderingContext`] is dropped, it destroys the surface of the primary window,
+///    then the memory allocator, the pipeline cache and the logical device, and finally releases the instance.
+///
+/// Every object created from the context (buffers, images, pipelines...) holds a reference to it, so objects owned by
+/// the application outside of renderers keep the device alive until they are dropped. They must not be leaked.
+pub struct RendererOrchestrator {
+    pub context : RenderingContext,
+
+    primary : Viewport,
+    viewports : HashMap<WindowId, Viewport>,
+
+    frame_stats : FrameStats,
+    last_frame : Option<Instant>,
+}
+
+impl RendererOrchestrator {
+    /// Returns the swapchain of the primary window.
+    #[inline] pub fn swapchain(&self) -> &Swapchain { &self.primary.swapchain }
+
+    /// Returns timing statistics over the most recently drawn frames.
+    #[inline] pub fn frame_stats(&self) -> &FrameStats { &self.frame_stats }
+
+    /// Returns statistics about the swapchain of the primary window.
+    #[inline] pub fn swapchain_stats(&self) -> &SwapchainStats { &self.primary.swapchain_stats }
+
+    /// Returns statistics about the swapchain of a secondary window, or `None` if no such viewport exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `window_id` - The identifier of the window.
+    pub fn viewport_swapchain_stats(&self, window_id : WindowId) -> Option<&SwapchainStats> {
+        self.viewports.get(&window_id).map(|viewport| &viewport.swapchain_stats)
+    }
+
+    /// Returns the shortest delay after which any renderer of any viewport wants the next frame to be drawn. See
+    /// [`Renderer::repaint_after`].
+    pub fn repaint_after(&self) -> Option<Duration> {
+        self.viewports.values()
+            .filter_map(Viewport::repaint_after)
+            .chain(self.primary.repaint_after())
+            .min()
+    }
+
+    pub fn update(&mut self) {
+        self.primary.update();
+        for viewport in self.viewports.values_mut() {
+            viewport.update();
+        }
+    }
+
+    /// Renders a frame on every viewport.
+    ///
+    /// When the swapchain of a window is out of date, it is recreated and the frame is drawn again. Errors are only
+    /// returned for the primary window, and [`RendererError::InvalidSwapchain`] only if its swapchain remained invalid
+    /// after being recreated. The only exception is [`RendererError::DeviceLost`], which is returned regardless of the
+    /// window that detected it.
+    pub fn draw_frame(&mut self) -> Result<(), RendererError> {
+        self.draw_frame_timeout(u64::MAX)
+    }
+
+    /// Renders a frame on every viewport, giving up on viewports whose next swapchain image isn't available in time.
+    ///
+    /// # Description
+    ///
+    /// If the primary window times out, [`RendererError::Timeout`] is returned and the caller may simply skip the frame.
+    /// A timeout of zero never blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait for each viewport, in nanoseconds.
+    pub fn draw_frame_timeout(&mut self, timeout : u64) -> Result<(), RendererError> {
+        profile_scope!("Application rendering");
+
+        // The device was lost and could not be recreated.
+        if self.primary.released {
+            return Err(RendererError::DeviceLost);
+        }
+
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.frame_stats.push(now - last_frame);
+        }
+
+        let context = &self.context;
+        let draw = |viewport : &mut Viewport| viewport.draw_frame(context, timeout);
+        let recreate = |viewport : &mut Viewport| {
+            context.device.wait_idle();
+            viewport.recreate_swapchain(context);
+        };
+
+        draw_with_recreation(&mut self.primary, draw, recreate)?;
+
+        for viewport in self.viewports.values_mut() {
+            if viewport.window(context).is_minimized() {
+                continue;
+            }
+
+            if let Err(RendererError::DeviceLost) = draw_with_recreation(viewport, draw, recreate) {
+                return Err(RendererError::DeviceLost);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new renderer for the primary window and returns its identifier.
+    ///
+    /// # Description
+    ///
+    /// Renderers record their commands in ascending priority; renderers added through [`Orchestrator::add_renderer`]
+    /// have a priority equal to their position in the render order. A renderer inserted with the same priority as an
+    /// existing renderer records its commands after it. The new renderer is updated after all existing renderers.
+    ///
+    /// Each renderer owns its render pass, so inserting or removing a renderer never triggers a rebuild of the render
+    /// passes of other renderers. However, only the last renderer to record its commands should transition the
+    /// swapchain image for presentation; it is up to the caller to make sure the renderers agree on that.
+    ///
+    /// # Arguments
+    ///
+    /// * `priority` - The priority of the renderer.
+    /// * `renderer` - A function creating the renderer.
+    pub fn insert_renderer(&mut self, priority : usize, renderer : RendererFn) -> RendererID {
+        self.primary.insert_renderer(&self.context, priority, renderer)
+    }
+
+    /// Removes a renderer from the primary window. Returns `false` if no such renderer exists.
+    ///
+    /// This waits for the device to be idle, since the renderer's resources may still be in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the renderer to remove.
+    pub fn remove_renderer(&mut self, id : RendererID) -> bool {
+        self.context.device.wait_idle();
+        self.primary.remove_renderer(id)
+    }
+
+    /// Enables or disables a renderer of the primary window without removing it. Returns `false` if no such renderer exists.
+    ///
+    /// See [`Renderer::is_enabled`] for the implications of disabling a renderer.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the renderer.
+    /// * `enabled` - Whether the renderer should record its commands.
+    pub fn set_renderer_enabled(&mut self, id : RendererID, enabled : bool) -> bool {
+        self.primary.set_renderer_enabled(id, enabled)
+    }
+
+    /// Forwards an event of the primary window to its renderers.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a renderer consumed the event (for example, because the user is typing in a text field of the
+    /// interface), in which case the application should not handle it on its own.
+    pub fn handle_event(&mut self, event : &WindowEvent) -> bool {
+        profile_scope!("Event handling");
+
+        self.primary.handle_event(event)
+    }
+
+    /// Forwards an event of a secondary window to its renderers.
+    ///
+    /// # Arguments
+    ///
+    /// * `window_id` - The identifier of the window that received the event.
+    /// * `event` - The event.
+    pub fn handle_viewport_event(&mut self, window_id : WindowId, event : &WindowEvent) {
+        profile_scope!("Event handling");
+
+        if let Some(viewport) = self.viewports.get_mut(&window_id) {
+            _ = viewport.handle_event(event);
+        }
+    }
+
+    /// Adds a secondary window to this orchestrator and returns its identifier.
+    ///
+    /// # Description
+    ///
+    /// A surface and a swapchain are created for the window, as well as one instance of each of the given renderers.
+    /// Renderers are updated and recorded in the order they are provided. Renderers that rely on the rendering
+    /// context's window (such as [`Interface`](crate::gui::context::Interface)) keep targetting the primary window.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The window to render to.
+    /// * `renderers` - Functions creating the renderers that will draw to this window.
+    ///
+    /// # Returns
+    ///
+    /// The identifier of the window, or the error returned by the driver if a surface could not be created for it.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the presentation queue family selected for the primary window can't present to this window.
+    pub fn add_viewport(&mut self, mut window : Window, renderers : &[RendererFn]) -> VkResult<WindowId> {
+        window.create_surface(&self.context.context)?;
+        assert!(window.get_surface_support(&self.context.device.physical_device, &self.context.presentation_queue),
+            "The presentation queue can't present to this window");
+
+        let window_id = window.handle().id();
+        let order = (0..renderers.len()).collect::<Vec<_>>();
+        let viewport = Viewport::new(&self.context, Some(window), renderers, order.clone(), order);
+        self.viewports.insert(window_id, viewport);
+
+        Ok(window_id)
+    }
+
+    /// Removes a secondary window from this orchestrator, destroying its swapchain, renderers and the window itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `window_id` - The identifier of the window to remove.
+    pub fn remove_viewport(&mut self, window_id : WindowId) {
+        if let Some(viewport) = self.viewports.remove(&window_id) {
+            self.context.device.wait_idle();
+            viewport.destroy();
+        }
+    }
+
+    /// Returns true if the given window is a secondary window managed by this orchestrator.
+    #[inline] pub fn has_viewport(&self, window_id : WindowId) -> bool { self.viewports.contains_key(&window_id) }
+
+    /// Recreates the logical device after it was lost, as well as every object that depends on it.
+    ///
+    /// # Description
+    ///
+    /// A device is typically lost when the driver resets the GPU after it stopped responding. When that happens,
+    /// rendering returns [`RendererError::DeviceLost`]; this function then destroys the swapchains, frames and
+    /// renderers of all windows, creates a new logical device on the same physical device, and creates everything
+    /// again. Renderers are recreated with the functions that initially created them, so any state they held is lost.
+    ///
+    /// # Returns
+    ///
+    /// [`RendererError::DeviceLost`] if objects created from the rendering context (buffers, images, pipelines...) are
+    /// still alive outside of the renderers of this orchestrator, since they can't outlive the device they were created
+    /// from. The viewports then remain released: nothing is drawn anymore, and the application should exit.
+    pub fn recover_device_lost(&mut self) -> Result<(), RendererError> {
+        self.context.device.wait_idle();
+
+        let primary = self.primary.release();
+        let viewports = self.viewports.iter_mut()
+            .map(|(&window_id, viewport)| (window_id, viewport.release()))
+            .collect::<Vec<_>>();
+
+        let Some(context) = Arc::get_mut(&mut self.context) else {
+            return Err(RendererError::DeviceLost);
+        };
+        context.device = create_logical_device(&context.context,
+            &context.device.physical_device,
+            &[context.graphics_queue, context.presentation_queue, context.transfer_queue, context.compute_queue],
+            &context.options,
+            &context.device_extensions,
+            &context.window);
+
+        self.primary.restore(&self.context, primary);
+        for (window_id, renderers) in viewports {
+            if let Some(viewport) = self.viewports.get_mut(&window_id) {
+                viewport.restore(&self.context, renderers);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Destroys every viewport; see the teardown order of [`RendererOrchestrator`].
+    fn teardown(&mut self) {
+        self.context.device.wait_idle();
+
+        for (_, viewport) in self.viewports.drain() {
+            viewport.destroy();
+        }
+
+        // The surface of the primary window is destroyed by the rendering context.
+        _ = self.primary.release();
+    }
+
+    /// Recreates the swapchains of all windows that are not minimized.
+    pub fn recreate_swapchain(&mut self) {
+        if self.primary.released {
+            return;
+        }
+
+        self.context.device.wait_idle();
+
+        self.primary.recreate_swapchain(&self.context);
+        for viewport in self.viewports.values_mut() {
+            if !viewport.window(&self.context).is_minimized() {
+                viewport.recreate_swapchain(&self.context);
+            }
+        }
+    }
+}
+
+impl Drop for RendererOrchestrator {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ash::vk;
+
+    use crate::application::RendererError;
+    use crate::gui::context::{Interface, InterfaceOptions};
+
+    use super::{draw_with_recreation, swapchain_error, upscale_blit, SWAPCHAIN_RECREATION_ATTEMPTS};
+
+    /// A swapchain that is out of date until it was recreated a given amount of times.
+    struct FakeSwapchain {
+        draws : usize,
+        recreations : usize,
+        invalid_until : usize,
+    }
+
+    fn draw(swapchain : &mut FakeSwapchain) -> Result<(), RendererError> {
+        swapchain.draws += 1;
+        if swapchain.recreations < swapchain.invalid_until {
+            Err(swapchain_error(vk::Result::ERROR_OUT_OF_DATE_KHR, "presenting frame"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn recreate(swapchain : &mut FakeSwapchain) { swapchain.recreations += 1; }
+
+    #[test]
+    pub fn out_of_date_swapchain() {
+        // The first attempt finds the swapchain out of date, the second succeeds.
+        let mut swapchain = FakeSwapchain { draws : 0, recreations : 0, invalid_until : 1 };
+        assert!(draw_with_recreation(&mut swapchain, draw, recreate).is_ok());
+        assert_eq!((swapchain.draws, swapchain.recreations), (2, 1));
+    }
+
+    #[test]
+    pub fn persistently_invalid_swapchain() {
+        let mut swapchain = FakeSwapchain { draws : 0, recreations : 0, invalid_until : usize::MAX };
+        let result = draw_with_recreation(&mut swapchain, draw, recreate);
+
+        assert!(matches!(result, Err(RendererError::InvalidSwapchain)));
+        assert_eq!(swapchain.recreations, SWAPCHAIN_RECREATION_ATTEMPTS);
+    }
+
+    #[test]
+    pub fn unrecoverable_errors() {
+        let mut recreations = 0;
+        let result = draw_with_recreation(&mut recreations,
+            |_| Err(swapchain_error(vk::Result::ERROR_DEVICE_LOST, "acquiring next image")),
+            |recreations| *recreations += 1);
+
+        assert!(matches!(result, Err(RendererError::DeviceLost)));
+        assert_eq!(recreations, 0);
+        assert!(matches!(swapchain_error(vk::Result::NOT_READY, "acquiring next image"), RendererError::Timeout));
+    }
+
+    #[test]
+    pub fn upscale_regions() {
+        let blit = upscale_blit(vk::Extent2D { width : 960, height : 540 }, vk::Extent2D { width : 1920, height : 1080 });
+
+        assert_eq!((blit.src_offsets[0].x, blit.src_offsets[0].y), (0, 0));
+        assert_eq!((blit.src_offsets[1].x, blit.src_offsets[1].y, blit.src_offsets[1].z), (960, 540, 1));
+        assert_eq!((blit.dst_offsets[1].x, blit.dst_offsets[1].y, blit.dst_offsets[1].z), (1920, 1080, 1));
+        assert_eq!(blit.src_subresource.aspect_mask, vk::ImageAspectFlags::COLOR);
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn released_context_ownership() {
+        let (_event_loop, mut app) = crate::testing::test_application();
+        let orchestrator = &mut app.orchestrator;
+        orchestrator.insert_renderer(0, |context, swapchain| {
+            Box::new(Interface::<()>::supplier(swapchain, context, true, |_, _| (), InterfaceOptions::default()))
+        });
+        assert!(!orchestrator.primary.framebuffers.is_empty());
+
+        // Once every viewport is released, nothing else may hold on to the context, or the device can't be recreated.
+        orchestrator.context.device.wait_idle();
+        let renderers = orchestrator.primary.release();
+        assert_eq!(Arc::strong_count(&orchestrator.context), 1);
+
+        orchestrator.primary.restore(&orchestrator.context, renderers);
+        assert!(!orchestrator.primary.framebuffers.is_empty());
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn recovery_ownership() {
+        let (_event_loop, mut app) = crate::testing::test_application();
+        let orchestrator = &mut app.orchestrator;
+        orchestrator.insert_renderer(0, |context, swapchain| {
+            Box::new(Interface::<()>::supplier(swapchain, context, true, |_, _| (), InterfaceOptions::default()))
+        });
+
+        // The device does not need to be lost to be recreated.
+        assert!(orchestrator.recover_device_lost().is_ok());
+        assert_eq!(orchestrator.primary.renderers.len(), 1);
+
+        // An object created from the context outlives the renderers, so the device can't be recreated; nothing is
+        // drawn afterwards, and the orchestrator can still be dropped.
+        let context = orchestrator.context.clone();
+        assert!(matches!(orchestrator.recover_device_lost(), Err(RendererError::DeviceLost)));
+        assert!(matches!(orchestrator.draw_frame(), Err(RendererError::DeviceLost)));
+        orchestrator.recreate_swapchain();
+
+        drop(app);
+        assert_eq!(Arc::strong_count(&context), 1);
+    }
 }
@@ -0,0 +1,85 @@
+//! Scaffolding for tests that need a window and a Vulkan device.
+//!
+//! winit only allows a single event loop per process, so tests using [`test_application`] must each run in their own
+//! process, for example through `cargo test -- --ignored --exact <test>`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ash::vk;
+use egui_winit::winit::event_loop::{EventLoop, EventLoopBuilder};
+
+use crate::application::{Application, ApplicationOptions};
+use crate::orchestration::rendering::RenderingContext;
+use crate::vk::command_buffer::CommandBuffer;
+use crate::vk::command_pool::CommandPool;
+use crate::vk::queue::QueueAffinity;
+
+/// Creates an application with default options. The event loop is returned so that it outlives the application.
+pub fn test_application() -> (EventLoop<()>, Application) {
+    #[cfg(target_os = "linux")]
+    use egui_winit::winit::platform::x11::EventLoopBuilderExtX11;
+    #[cfg(target_os = "windows")]
+    use egui_winit::winit::platform::windows::EventLoopBuilderExtWindows;
+
+    // Tests don't run on the main thread.
+    let event_loop = EventLoopBuilder::new().with_any_thread(true).build().unwrap();
+    let app = Application::try_new(ApplicationOptions::default(), &event_loop).unwrap();
+    (event_loop, app)
+}
+
+/// A GLSL source file in the temporary directory, deleted when dropped.
+///
+/// Each file has a path of its own, so that tests running concurrently in several processes don't overwrite each
+/// other's shaders.
+pub struct ShaderFile(PathBuf);
+
+impl ShaderFile {
+    /// Writes a shader to a new file.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file, which is prefixed to make its path unique.
+    /// * `source` - The GLSL source of the shader.
+    pub fn new(name : &str, source : &str) -> Self {
+        static NEXT_ID : AtomicUsize = AtomicUsize::new(0);
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("renderer-{}-{}-{}", process::id(), id, name));
+        fs::write(&path, source).expect("Writing the shader failed");
+        Self(path)
+    }
+
+    #[inline] pub fn path(&self) -> &Path { &self.0 }
+}
+
+impl Drop for ShaderFile {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Records commands in a one-time command buffer, submits it to a graphics queue, and waits until it completed.
+///
+/// # Arguments
+///
+/// * `context` - The rendering context.
+/// * `pool` - The pool the command buffer is allocated from.
+/// * `record` - Records the commands.
+pub fn submit_and_wait(context : &RenderingContext, pool : &CommandPool, record : impl FnOnce(&CommandBuffer)) {
+    let cmd = CommandBuffer::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .pool(pool)
+        .build_one(context);
+    cmd.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    record(&cmd);
+    cmd.end();
+
+    let queue = context.device.get_queue(QueueAffinity::Graphics, 0).unwrap();
+    let fence = context.device.create_fence(vk::FenceCreateFlags::empty(), None);
+    context.device.submit(queue, &[&cmd], &[], &[], fence);
+    context.device.wait_for_fence(fence);
+    context.device.destroy_fence(fence);
+}
@@ -3,6 +3,7 @@ pub mod context;
 pub mod command_buffer;
 pub mod command_pool;
 pub mod descriptor;
+pub mod fence_pool;
 pub mod framebuffer;
 pub mod frame_data;
 pub mod helpers;
@@ -15,4 +16,5 @@ pub mod renderer;
 pub mod render_pass;
 pub mod sampler;
 pub mod semaphore_pool;
+pub mod surface;
 pub mod swapchain;
@@ -1,10 +1,16 @@
 use std::ffi::c_void;
 use std::marker::PhantomData;
 use std::mem::align_of;
+use std::mem::size_of;
 use std::mem::replace;
 use std::mem::size_of_val;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::ops::Range;
+use std::slice;
 use ash::util::Align;
 use ash::vk;
+use bytemuck::Pod;
 use gpu_allocator::vulkan::Allocation;
 use gpu_allocator::vulkan::AllocationCreateDesc;
 use gpu_allocator::vulkan::AllocationScheme;
@@ -78,7 +84,11 @@ impl StaticInitializer for BufferBuilder<StaticInitializerTag> {
 
 impl DynamicInitializer for BufferBuilder<DynamicInitializerTag> {
     fn build<T : Sized + Copy>(self, context : &RenderingContext, pool : &CommandPool, data : &[T]) -> Buffer {
-        let size = size_of_val(data) as u64;
+        let size = if self.usage.contains(vk::BufferUsageFlags::INDEX_BUFFER) {
+            index_data_size(self.index_type, data)
+        } else {
+            size_of_val(data) as u64
+        };
         let mut this = self.build_impl(context, size);
         match &self.memory_location {
             MemoryLocation::GpuOnly => {
@@ -126,9 +136,23 @@ impl DynamicInitializer for BufferBuilder<DynamicInitializerTag> {
 impl<T> BufferBuilder<T> {
     value_builder! { sharing_mode, vk::SharingMode }
     value_builder! { name, name, &'static str }
-    value_builder! { index, index_type, vk::IndexType }
     value_builder! { linear, linear, bool }
 
+    /// Sets the type of the indices stored in this buffer, if it is used as an index buffer.
+    ///
+    /// # Description
+    ///
+    /// [`vk::IndexType::UINT16`] halves the memory used by meshes with fewer than 65536 vertices.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if `index_type` is neither [`vk::IndexType::UINT16`] nor [`vk::IndexType::UINT32`].
+    #[inline] pub fn index(mut self, index_type : vk::IndexType) -> Self {
+        assert!(index_size(index_type).is_some(), "Index type {:?} is not supported", index_type);
+        self.index_type = index_type;
+        self
+    }
+
     #[inline] pub fn usage(mut self, usage : vk::BufferUsageFlags) -> Self {
         self.usage = usage;
         if usage == vk::BufferUsageFlags::VERTEX_BUFFER {
@@ -151,6 +175,9 @@ impl<T> BufferBuilder<T> {
                 usage |= vk::BufferUsageFlags::TRANSFER_DST;
             }
 
+            assert!(!usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) || context.device.buffer_device_address,
+                "Buffers with device addresses require RendererOptions::buffer_device_address");
+
             let create_info = vk::BufferCreateInfo::default()
                 .usage(usage)
                 .sharing_mode(self.sharing_mode)
@@ -165,7 +192,7 @@ impl<T> BufferBuilder<T> {
 
             let requirements = context.device.handle().get_buffer_memory_requirements(buffer);
             
-            let allocation = context.device.allocator()
+            let allocation = context.allocator()
                 .lock()
                 .unwrap()
                 .allocate(&AllocationCreateDesc {
@@ -184,6 +211,8 @@ impl<T> BufferBuilder<T> {
                 context : context.clone(),
                 handle : buffer,
                 allocation,
+                size,
+                usage,
                 index_type : self.index_type,
                 element_count : 0
             }
@@ -195,34 +224,109 @@ pub struct Buffer {
     context : RenderingContext,
     handle : vk::Buffer,
     allocation : Allocation,
+    size : u64,
+    usage : vk::BufferUsageFlags,
     index_type : vk::IndexType,
     element_count : u32,
 }
 
 impl Buffer {
+    /// Writes data at the beginning of this buffer, which must be host visible. The amount of elements written becomes
+    /// the element count of this buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The elements to write.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if `data` does not fit in this buffer.
+    /// * Panics if this is an index buffer and the size of `T` does not match its index type.
     pub fn update<T : Copy>(&mut self, data : &[T]) {
-        let size = size_of_val(data) as u64;
-        assert!(self.allocation.size() >= size, "The data you're trying to write to the buffer is too large to fit.");
+        self.write(0, data);
+        self.element_count = data.len() as u32;
+    }
+
+    /// Writes data at the given offset of this buffer, which must be host visible. The element count of this buffer is
+    /// extended to cover the elements written, if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset at which data is written, in bytes. It must be a multiple of the alignment of `T`.
+    /// * `data` - The elements to write.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if `data` does not fit in this buffer once offset.
+    /// * Panics if this is an index buffer and the size of `T` does not match its index type.
+    pub fn update_at<T : Copy>(&mut self, offset : u64, data : &[T]) {
+        let end = self.write(offset, data);
+        if size_of::<T>() != 0 {
+            self.element_count = self.element_count.max((end / size_of::<T>() as u64) as u32);
+        }
+    }
 
+    /// Writes data at the given offset of this buffer, and returns the offset of the end of the data.
+    fn write<T : Copy>(&mut self, offset : u64, data : &[T]) -> u64 {
+        let size = if self.is_index_buffer() {
+            index_data_size(self.index_type, data)
+        } else {
+            size_of_val(data) as u64
+        };
+        assert_eq!(offset % align_of::<T>() as u64, 0, "Writes to a buffer must be aligned to the elements written");
+
+        let range = write_range(self.size, offset, size);
         unsafe {
             let mapped_data = self.allocation.mapped_ptr()
                 .expect("This memory allocation should be host visible. If it can't be, consider using a staging buffer.")
-                .as_ptr();
+                .as_ptr() as *mut u8;
             let mut mapping_slice = Align::new(
-                mapped_data as *mut c_void,
+                mapped_data.add(range.start as usize) as *mut c_void,
                 align_of::<T>() as u64,
-                size
+                range.end - range.start
             );
             mapping_slice.copy_from_slice(data);
         }
 
-        self.element_count = data.len() as u32;
+        range.end
     }
 
     pub fn map(&self) -> *mut u8 {
         self.allocation.mapped_ptr().unwrap().as_ptr() as *mut u8
     }
 
+    /// Maps this buffer, which must be host visible, as a slice of `T` covering as many whole elements as fit in it.
+    ///
+    /// # Description
+    ///
+    /// Writes go straight to the memory of the buffer. When the returned guard is dropped, they are flushed if that
+    /// memory is not host coherent. Buffers remain persistently mapped, so dropping the guard does not unmap them.
+    ///
+    /// The element count of this buffer is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if this buffer is not host visible.
+    /// * Panics if the mapped memory is not aligned for `T`.
+    pub fn map_slice<T : Pod>(&mut self) -> MappedSlice<'_, T> {
+        let ptr = self.allocation.mapped_ptr()
+            .expect("This memory allocation should be host visible. If it can't be, consider using a staging buffer.")
+            .as_ptr();
+        assert_eq!(ptr as usize % align_of::<T>(), 0, "The mapped memory of this buffer is not aligned for this type");
+
+        let len = match size_of::<T>() {
+            0 => 0,
+            size => (self.size / size as u64) as usize,
+        };
+
+        MappedSlice { buffer : self, len, _marker : PhantomData }
+    }
+
+    /// Returns the size of this buffer, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     pub fn element_count(&self) -> u32 {
         self.element_count
     }
@@ -231,7 +335,15 @@ impl Buffer {
         self.allocation.memory()
     }
 
-    pub fn get_device_address(&self) -> u64 {
+    /// Returns the address of this buffer, which shaders can use to access it.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if this buffer was not created with [`vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS`].
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        assert!(self.usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS),
+            "This buffer must be created with SHADER_DEVICE_ADDRESS usage for its address to be queried");
+
         unsafe {
             self.context.device.handle().get_buffer_device_address(&vk::BufferDeviceAddressInfo::default()
                 .buffer(self.handle))
@@ -239,6 +351,185 @@ impl Buffer {
     }
 
     #[inline] pub fn index_type(&self) -> vk::IndexType { self.index_type }
+
+    /// Returns true if this buffer can be bound as an index buffer.
+    #[inline] pub fn is_index_buffer(&self) -> bool { self.usage.contains(vk::BufferUsageFlags::INDEX_BUFFER) }
+}
+
+/// A host-visible [`Buffer`] mapped as a slice of `T`, returned by [`Buffer::map_slice`].
+///
+/// Writes are flushed to the device when this guard is dropped, if the memory of the buffer is not host coherent.
+pub struct MappedSlice<'a, T : Pod> {
+    buffer : &'a mut Buffer,
+    len : usize,
+    _marker : PhantomData<&'a mut [T]>,
+}
+
+impl<T : Pod> Deref for MappedSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.buffer.map() as *const T, self.len) }
+    }
+}
+
+impl<T : Pod> DerefMut for MappedSlice<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.buffer.map() as *mut T, self.len) }
+    }
+}
+
+impl<T : Pod> Drop for MappedSlice<'_, T> {
+    fn drop(&mut self) {
+        let allocation = &self.buffer.allocation;
+        if allocation.memory_properties().contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+            return;
+        }
+
+        let device = &self.buffer.context.device;
+        let atom_size = device.physical_device.properties().limits.non_coherent_atom_size;
+        let (offset, size) = flush_range(allocation.offset(), self.buffer.size, allocation.offset() + allocation.size(), atom_size);
+        unsafe {
+            device.handle().flush_mapped_memory_ranges(&[vk::MappedMemoryRange::default()
+                .memory(allocation.memory())
+                .offset(offset)
+                .size(size)
+            ]).expect("Flushing mapped memory failed");
+        }
+    }
+}
+
+/// A uniform buffer holding a single value of type `T`, with one copy per frame in flight.
+///
+/// # Description
+///
+/// The GPU may still be reading the value written for a frame while the CPU records the next one, so each frame in
+/// flight gets its own copy, indexed by [`FrameData::index`](crate::vk::frame_data::FrameData::index). Copies live in
+/// host-visible memory and are written to directly.
+pub struct UniformBuffer<T : Copy> {
+    buffers : Vec<Buffer>,
+    _marker : PhantomData<T>,
+}
+
+impl<T : Copy> UniformBuffer<T> {
+    /// Creates a new uniform buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `name` - The debug name of the buffer.
+    /// * `frames` - The amount of frames in flight; usually, the amount of images of the swapchain.
+    pub fn new(context : &RenderingContext, name : &'static str, frames : usize) -> Self {
+        let buffers = (0..frames).map(|_| {
+            StaticBufferBuilder::fixed_size()
+                .name(name)
+                .cpu_to_gpu()
+                .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+                .build(context, size_of::<T>() as u64)
+        }).collect();
+
+        Self { buffers, _marker : PhantomData }
+    }
+
+    /// Returns the amount of copies of the value held by this buffer.
+    #[inline] pub fn frames(&self) -> usize { self.buffers.len() }
+
+    /// Writes the value used by the given frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The index of the frame.
+    /// * `value` - The new value.
+    pub fn update(&mut self, frame : usize, value : &T) {
+        self.buffers[frame].update(slice::from_ref(value));
+    }
+
+    /// Returns the buffer holding the value used by the given frame.
+    #[inline] pub fn buffer(&self, frame : usize) -> &Buffer { &self.buffers[frame] }
+
+    /// Returns a descriptor of the value used by the given frame, to be bound to a
+    /// [`vk::DescriptorType::UNIFORM_BUFFER`] binding.
+    pub fn descriptor_info(&self, frame : usize) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffers[frame].handle)
+            .offset(0)
+            .range(size_of::<T>() as u64)
+    }
+}
+
+/// Returns the range of bytes written to a buffer.
+///
+/// # Arguments
+///
+/// * `buffer_size` - The size of the buffer, in bytes.
+/// * `offset` - The offset at which data is written, in bytes.
+/// * `size` - The size of the data written, in bytes.
+///
+/// # Panics
+///
+/// * Panics if the data does not fit in the buffer.
+fn write_range(buffer_size : u64, offset : u64, size : u64) -> Range<u64> {
+    let end = offset.checked_add(size)
+        .filter(|&end| end <= buffer_size)
+        .unwrap_or_else(|| panic!("Writing {} bytes at offset {} overflows a buffer of {} bytes", size, offset, buffer_size));
+
+    offset..end
+}
+
+/// Returns the offset and size of the memory range to flush for a buffer, both aligned to the non-coherent atom size
+/// of the device as Vulkan requires.
+///
+/// # Description
+///
+/// Rounding the end of the range up may move it past the end of the memory object, which Vulkan forbids. The end of
+/// the allocation is the only bound known, so whenever the rounded range would leave the allocation, the range
+/// instead extends to the end of the memory object, with a size of [`vk::WHOLE_SIZE`].
+///
+/// # Arguments
+///
+/// * `offset` - The offset of the buffer in its memory object, in bytes.
+/// * `size` - The size of the buffer, in bytes.
+/// * `allocation_end` - The offset of the end of the allocation backing the buffer in its memory object, in bytes.
+/// * `atom_size` - The non-coherent atom size of the device, in bytes.
+fn flush_range(offset : u64, size : u64, allocation_end : u64, atom_size : u64) -> (u64, u64) {
+    let atom_size = atom_size.max(1);
+    let start = offset - offset % atom_size;
+    let end = (offset + size).div_ceil(atom_size) * atom_size;
+
+    if end > allocation_end {
+        (start, vk::WHOLE_SIZE)
+    } else {
+        (start, end - start)
+    }
+}
+
+/// Returns the size of a single index of the given type, in bytes, or `None` if the index type is not supported.
+///
+/// # Arguments
+///
+/// * `index_type` - The type of the indices.
+pub fn index_size(index_type : vk::IndexType) -> Option<u64> {
+    match index_type {
+        vk::IndexType::UINT16 => Some(2),
+        vk::IndexType::UINT32 => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns the size of the given indices, in bytes.
+///
+/// # Arguments
+///
+/// * `index_type` - The index type of the buffer the indices are written to.
+/// * `indices` - The indices.
+///
+/// # Panics
+///
+/// * Panics if the size of `T` does not match `index_type`.
+fn index_data_size<T>(index_type : vk::IndexType, indices : &[T]) -> u64 {
+    assert_eq!(index_size(index_type), Some(size_of::<T>() as u64),
+        "The indices provided do not match the index type {:?} of this buffer", index_type);
+    size_of_val(indices) as u64
 }
 
 impl Drop for Buffer {
@@ -247,10 +538,88 @@ impl Drop for Buffer {
             self.context.device.handle().destroy_buffer(self.handle, None);
 
             let memory = replace(&mut self.allocation, Allocation::default()); 
-            _ = self.context.device.allocator().lock().unwrap().free(memory);
+            _ = self.context.allocator().lock().unwrap().free(memory);
         }
 
     }
 }
 
-make_handle! { Buffer, vk::Buffer }
\ No newline at end of file
+make_handle! { Buffer, vk::Buffer }
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::{flush_range, index_data_size, index_size, write_range, BufferBuilder, StaticInitializerTag};
+
+    #[test]
+    pub fn index_types() {
+        assert_eq!(index_size(vk::IndexType::UINT16), Some(2));
+        assert_eq!(index_size(vk::IndexType::UINT32), Some(4));
+        assert_eq!(index_size(vk::IndexType::NONE_KHR), None);
+
+        let builder = BufferBuilder::<StaticInitializerTag>::fixed_size()
+            .usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .index(vk::IndexType::UINT16);
+        assert_eq!(builder.index_type, vk::IndexType::UINT16);
+    }
+
+    #[test]
+    pub fn index_data_sizes() {
+        assert_eq!(index_data_size(vk::IndexType::UINT16, &[0u16, 1, 2, 2, 1, 3]), 12);
+        assert_eq!(index_data_size(vk::IndexType::UINT32, &[0u32, 1, 2, 2, 1, 3]), 24);
+        assert_eq!(index_data_size::<u16>(vk::IndexType::UINT16, &[]), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn mismatched_index_data() {
+        _ = index_data_size(vk::IndexType::UINT16, &[0u32, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn unsupported_index_type() {
+        _ = BufferBuilder::<StaticInitializerTag>::fixed_size()
+            .index(vk::IndexType::UINT8_EXT);
+    }
+
+    #[test]
+    pub fn write_ranges() {
+        assert_eq!(write_range(64, 0, 64), 0..64);
+        assert_eq!(write_range(64, 16, 32), 16..48);
+        assert_eq!(write_range(64, 64, 0), 64..64);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn oversized_write() {
+        _ = write_range(64, 0, 65);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn offset_write_overflow() {
+        _ = write_range(64, 48, 32);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn wrapping_write() {
+        _ = write_range(64, u64::MAX, 2);
+    }
+
+    #[test]
+    pub fn flush_ranges() {
+        assert_eq!(flush_range(0, 64, 256, 64), (0, 64));
+        assert_eq!(flush_range(0, 100, 256, 64), (0, 128));
+        assert_eq!(flush_range(96, 64, 256, 64), (64, 128));
+        assert_eq!(flush_range(10, 20, 256, 1), (10, 20));
+        assert_eq!(flush_range(10, 20, 256, 0), (10, 20));
+
+        // Ranges ending on the end of the allocation, aligned or not.
+        assert_eq!(flush_range(128, 128, 256, 64), (128, 128));
+        assert_eq!(flush_range(0, 100, 100, 64), (0, vk::WHOLE_SIZE));
+        assert_eq!(flush_range(160, 90, 250, 64), (128, vk::WHOLE_SIZE));
+    }
+}
@@ -1,14 +1,16 @@
+use std::cell::Cell;
 use std::ffi::{CStr, CString};
+use std::mem::size_of;
 
 use ash::vk::{self, ClearValue};
 
 use crate::orchestration::rendering::RenderingContext;
 use crate::traits::handle::Handle;
-use crate::vk::buffer::Buffer;
+use crate::vk::buffer::{index_size, Buffer};
 use crate::vk::command_pool::CommandPool;
 use crate::vk::framebuffer::Framebuffer;
 use crate::vk::image::Image;
-use crate::vk::pipeline::Pipeline;
+use crate::vk::pipeline::{validate_viewports, Pipeline};
 use crate::vk::render_pass::RenderPass;
 
 pub struct CommandBuffer {
@@ -16,6 +18,8 @@ pub struct CommandBuffer {
 
     handle : vk::CommandBuffer,
     level : vk::CommandBufferLevel,
+    // The contents of the current subpass, or None outside of render passes.
+    subpass_contents : Cell<Option<vk::SubpassContents>>,
 }
 
 impl CommandBuffer {
@@ -97,6 +101,9 @@ impl CommandBuffer {
 
     /// Begins recording this command buffer.
     pub fn begin(&self, flags : vk::CommandBufferUsageFlags) {
+        // Beginning a command buffer resets it, including any render pass left active by a previous recording.
+        self.subpass_contents.set(None);
+
         unsafe {
             let begin_info = vk::CommandBufferBeginInfo::default()
                 .flags(flags);
@@ -113,13 +120,42 @@ impl CommandBuffer {
         dst : BarrierPhase,
         dependency : vk::DependencyFlags,
         new_layout : vk::ImageLayout
+    ) {
+        let old_layout = image.layout();
+        self.image_layout_transition(image, src, dst, dependency, old_layout, new_layout);
+    }
+
+    /// Records a barrier on an image, transitioning it from the given layout rather than from the layout the image
+    /// was last known to be in.
+    ///
+    /// # Description
+    ///
+    /// This is needed when the contents of the image are discarded (by transitioning from
+    /// [`vk::ImageLayout::UNDEFINED`]), or when the layout of the image was changed by commands this image does not
+    /// track, such as a render pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image. Its layout is updated to `new_layout`.
+    /// * `src` - The queue family, accesses and stages that must complete before the barrier.
+    /// * `dst` - The queue family, accesses and stages that wait on the barrier.
+    /// * `dependency` - The dependency flags of the barrier.
+    /// * `old_layout` - The layout the image is in before the barrier.
+    /// * `new_layout` - The layout the image is in after the barrier.
+    pub fn image_layout_transition(&self,
+        image : &mut Image,
+        src : BarrierPhase,
+        dst : BarrierPhase,
+        dependency : vk::DependencyFlags,
+        old_layout : vk::ImageLayout,
+        new_layout : vk::ImageLayout
     ) {
         let barrier = vk::ImageMemoryBarrier::default()
             .dst_access_mask(dst.1)
             .src_access_mask(src.1)
             .dst_queue_family_index(dst.0)
             .src_queue_family_index(src.0)
-            .old_layout(image.layout())
+            .old_layout(old_layout)
             .new_layout(new_layout)
             .subresource_range(vk::ImageSubresourceRange::default()
                 .aspect_mask(image.aspect())
@@ -138,8 +174,87 @@ impl CommandBuffer {
         }
     }
 
+    /// Clears a color image outside of a render pass.
+    /// 
+    /// # Description
+    /// 
+    /// All mip levels and array layers of the image are cleared. If the image is neither in [`vk::ImageLayout::GENERAL`]
+    /// nor in [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`], it is first transitioned to the latter.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `image` - The image to clear. It must have been created with [`vk::ImageUsageFlags::TRANSFER_DST`].
+    /// * `color` - The value to clear the image with.
+    pub fn clear_color_image(&self, image : &mut Image, color : vk::ClearColorValue) {
+        assert!(image.aspect().contains(vk::ImageAspectFlags::COLOR), "clear_color_image can only clear color images");
+
+        self.prepare_clear(image);
+
+        let range = Self::clear_range(image, vk::ImageAspectFlags::COLOR);
+        unsafe {
+            self.context.device.handle()
+                .cmd_clear_color_image(self.handle, image.handle(), image.layout(), &color, &[range]);
+        }
+    }
+
+    /// Clears a depth/stencil image outside of a render pass.
+    /// 
+    /// # Description
+    /// 
+    /// All mip levels and array layers of the image are cleared, for every depth and stencil aspect the image has. If the
+    /// image is neither in [`vk::ImageLayout::GENERAL`] nor in [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`], it is first
+    /// transitioned to the latter.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `image` - The image to clear. It must have been created with [`vk::ImageUsageFlags::TRANSFER_DST`].
+    /// * `value` - The value to clear the image with.
+    pub fn clear_depth_stencil_image(&self, image : &mut Image, value : vk::ClearDepthStencilValue) {
+        let aspect = image.aspect() & (vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+        assert!(!aspect.is_empty(), "clear_depth_stencil_image can only clear depth or stencil images");
+
+        self.prepare_clear(image);
+
+        let range = Self::clear_range(image, aspect);
+        unsafe {
+            self.context.device.handle()
+                .cmd_clear_depth_stencil_image(self.handle, image.handle(), image.layout(), &value, &[range]);
+        }
+    }
+
+    /// Transitions an image to a layout it can be cleared in, if it isn't in one already.
+    fn prepare_clear(&self, image : &mut Image) {
+        if image.layout() != vk::ImageLayout::GENERAL && image.layout() != vk::ImageLayout::TRANSFER_DST_OPTIMAL {
+            self.image_memory_barrier(image,
+                BarrierPhase::ignore_queue(vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS),
+                BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+                vk::DependencyFlags::empty(),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        }
+    }
+
+    fn clear_range(image : &Image, aspect : vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(aspect)
+            .base_mip_level(image.base_mip_level())
+            .level_count(image.level_count())
+            .base_array_layer(image.base_array_layer())
+            .layer_count(image.layer_count())
+    }
+
     /// Begins a new render pass.
+    ///
+    /// # Description
+    ///
+    /// `contents` decides how the first subpass is recorded, and can differ from one frame to the next. With
+    /// [`vk::SubpassContents::INLINE`], commands are recorded directly in this command buffer; with
+    /// [`vk::SubpassContents::SECONDARY_COMMAND_BUFFERS`], [`CommandBuffer::execute_commands`] is the only command that
+    /// may be recorded until the next subpass or the end of the render pass. This is validated when commands are
+    /// recorded.
     pub fn begin_render_pass(&self, render_pass : &RenderPass, framebuffer : &Framebuffer, render_area : vk::Rect2D, clear_values : &[ClearValue], contents : vk::SubpassContents) {
+        assert!(self.subpass_contents.get().is_none(), "A render pass is already active in this command buffer");
+        self.subpass_contents.set(Some(contents));
+
         unsafe {
             let begin_info = vk::RenderPassBeginInfo::default()
                 .render_pass(render_pass.handle())
@@ -152,15 +267,25 @@ impl CommandBuffer {
         }
     }
 
-    /// Transitions to the next subpass of the current render pass.
+    /// Transitions to the next subpass of the current render pass, whose commands are recorded as described by
+    /// `contents`; see [`CommandBuffer::begin_render_pass`].
     pub fn next_subpass(&self, contents : vk::SubpassContents) {
+        assert!(self.subpass_contents.get().is_some(), "No render pass is active in this command buffer");
+        self.subpass_contents.set(Some(contents));
+
         unsafe {
             self.context.device.handle().cmd_next_subpass(self.handle, contents);
         }
     }
 
     /// Executes commands from a given array of command buffers.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if this is called within a subpass whose contents are [`vk::SubpassContents::INLINE`].
     pub fn execute_commands(&self, commands : &[CommandBuffer]) {
+        validate_subpass_contents(self.subpass_contents.get(), vk::SubpassContents::SECONDARY_COMMAND_BUFFERS, "vkCmdExecuteCommands");
+
         unsafe {
             let handles = commands.iter()
                 .map(CommandBuffer::handle)
@@ -170,8 +295,10 @@ impl CommandBuffer {
         }
     }
 
-    /// Executes commands from a given command buffer.
+    /// Executes commands from a given command buffer. See [`CommandBuffer::execute_commands`].
     pub fn execute_command(&self, command : &CommandBuffer) {
+        validate_subpass_contents(self.subpass_contents.get(), vk::SubpassContents::SECONDARY_COMMAND_BUFFERS, "vkCmdExecuteCommands");
+
         unsafe {
             let handle = [command.handle()];
 
@@ -181,26 +308,93 @@ impl CommandBuffer {
 
     /// Binds a pipeline object to this command buffer.
     pub fn bind_pipeline(&self, point : vk::PipelineBindPoint, pipeline : &Pipeline) {
+        self.record_inline("vkCmdBindPipeline");
         unsafe {
             self.context.device.handle().cmd_bind_pipeline(self.handle, point, pipeline.handle());
         }
     }
 
     /// Sets the viewport dynamically for this command buffer.
+    /// 
+    /// Setting more than one viewport requires the `multiViewport` feature; the bound pipeline must be created with
+    /// [`PipelineInfo::viewports`](crate::vk::pipeline::PipelineInfo::viewports) to use them.
     pub fn set_viewport(&self, first_viewport : u32, viewports : &[vk::Viewport]) {
+        self.record_inline("vkCmdSetViewport");
+        self.validate_viewports(first_viewport, viewports.len());
+
         unsafe {
             self.context.device.handle().cmd_set_viewport(self.handle, first_viewport, viewports);
         }
     }
 
     /// Sets the scissors dynamically for this command buffer.
+    /// 
+    /// Setting more than one scissor requires the `multiViewport` feature; the bound pipeline must be created with
+    /// [`PipelineInfo::viewports`](crate::vk::pipeline::PipelineInfo::viewports) to use them.
     pub fn set_scissors(&self, first_scissor : u32, scissors : &[vk::Rect2D]) {
+        self.record_inline("vkCmdSetScissor");
+        self.validate_viewports(first_scissor, scissors.len());
+
         unsafe {
             self.context.device.handle().cmd_set_scissor(self.handle, first_scissor, scissors);
         }
     }
 
+    /// Ensures a command that must be recorded inline is not recorded in a subpass whose contents are secondary
+    /// command buffers.
+    fn record_inline(&self, command : &str) {
+        validate_subpass_contents(self.subpass_contents.get(), vk::SubpassContents::INLINE, command);
+    }
+
+    fn validate_viewports(&self, first : u32, count : usize) {
+        validate_viewports(first, count as u32, self.context.device.features.multi_viewport != 0,
+            self.context.device.physical_device.properties.limits.max_viewports);
+    }
+
+    /// Sets the line width dynamically for this command buffer.
+    /// 
+    /// The bound pipeline must have been created with a [`DynamicState::Dynamic`](crate::vk::renderer::DynamicState::Dynamic) line width.
+    pub fn set_line_width(&self, line_width : f32) {
+        self.record_inline("vkCmdSetLineWidth");
+        unsafe {
+            self.context.device.handle().cmd_set_line_width(self.handle, line_width);
+        }
+    }
+
+    /// Sets the depth bias factors dynamically for this command buffer.
+    /// 
+    /// The bound pipeline must have been created with [`PipelineInfo::dynamic_depth_bias`](crate::vk::pipeline::PipelineInfo::dynamic_depth_bias).
+    pub fn set_depth_bias(&self, constant : f32, clamp : f32, slope : f32) {
+        self.record_inline("vkCmdSetDepthBias");
+        unsafe {
+            self.context.device.handle().cmd_set_depth_bias(self.handle, constant, clamp, slope);
+        }
+    }
+
+    /// Sets the stencil reference value dynamically for this command buffer, for both faces.
+    ///
+    /// The bound pipeline must have been created with a [`DynamicState::Dynamic`](crate::vk::renderer::DynamicState::Dynamic)
+    /// reference (see [`StencilOptions::reference`](crate::vk::pipeline::StencilOptions::reference)).
+    pub fn set_stencil_reference(&self, reference : u32) {
+        self.record_inline("vkCmdSetStencilReference");
+        unsafe {
+            self.context.device.handle().cmd_set_stencil_reference(self.handle, vk::StencilFaceFlags::FRONT_AND_BACK, reference);
+        }
+    }
+
+    /// Sets the stencil compare mask dynamically for this command buffer, for both faces.
+    ///
+    /// The bound pipeline must have been created with a [`DynamicState::Dynamic`](crate::vk::renderer::DynamicState::Dynamic)
+    /// compare mask (see [`StencilOptions::compare_mask`](crate::vk::pipeline::StencilOptions::compare_mask)).
+    pub fn set_stencil_compare_mask(&self, compare_mask : u32) {
+        self.record_inline("vkCmdSetStencilCompareMask");
+        unsafe {
+            self.context.device.handle().cmd_set_stencil_compare_mask(self.handle, vk::StencilFaceFlags::FRONT_AND_BACK, compare_mask);
+        }
+    }
+
     pub fn draw_indexed(&self, index_count : u32, instance_count : u32, first_index : u32, vertex_offset : i32, first_instance : u32) {
+        self.record_inline("vkCmdDrawIndexed");
         unsafe {
             self.context.device.handle()
                 .cmd_draw_indexed(self.handle, index_count, instance_count, first_index, vertex_offset, first_instance)
@@ -209,6 +403,7 @@ impl CommandBuffer {
 
     /// Binds vertex buffers to this command buffer.
     pub fn bind_vertex_buffers(&self, first_binding : u32, buffers : &[(&Buffer, vk::DeviceSize)]) {
+        self.record_inline("vkCmdBindVertexBuffers");
         let mut handles = Vec::<vk::Buffer>::with_capacity(buffers.len());
         let mut offsets = Vec::<vk::DeviceSize>::with_capacity(buffers.len());
         for (buffer, offset) in buffers {
@@ -221,8 +416,41 @@ impl CommandBuffer {
         }
     }
 
-    /// Binds an index buffer to this command buffer.
+    /// Draws every instance of a mesh.
+    ///
+    /// # Description
+    ///
+    /// The per-vertex buffer is bound to binding 0, and the per-instance buffer to binding 1, which matches a
+    /// [`Vertex`](crate::vk::pipeline::Vertex) that declares a [`vk::VertexInputRate::VERTEX`] binding followed by a
+    /// [`vk::VertexInputRate::INSTANCE`] binding.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertices` - The vertices of the mesh.
+    /// * `instances` - The data of each instance.
+    pub fn draw_instanced(&self, vertices : &Buffer, instances : &Buffer) {
+        self.bind_vertex_buffers(0, &[(vertices, 0), (instances, 0)]);
+        self.draw(vertices.element_count(), instances.element_count(), 0, 0);
+    }
+
+    /// Binds an index buffer to this command buffer. The type of the indices is the one the buffer was created with.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The index buffer.
+    /// * `offset` - The offset, in bytes, of the first index in the buffer.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if `buffer` was not created with [`vk::BufferUsageFlags::INDEX_BUFFER`].
+    /// * Panics if `offset` is not a multiple of the size of an index.
     pub fn bind_index_buffer(&self, buffer : &Buffer, offset : vk::DeviceSize) {
+        self.record_inline("vkCmdBindIndexBuffer");
+
+        assert!(buffer.is_index_buffer(), "This buffer was not created with INDEX_BUFFER usage");
+
+        validate_index_offset(buffer.index_type(), offset);
+
         unsafe {
             self.context.device.handle().cmd_bind_index_buffer(self.handle, buffer.handle(), offset, buffer.index_type())
         }
@@ -230,6 +458,8 @@ impl CommandBuffer {
     
     /// Ends the current render pass.
     pub fn end_render_pass(&self) {
+        assert!(self.subpass_contents.take().is_some(), "No render pass is active in this command buffer");
+
         unsafe {
             self.context.device.handle().cmd_end_render_pass(self.handle);
         }
@@ -237,11 +467,47 @@ impl CommandBuffer {
 
     /// Draws primitives.
     pub fn draw(&self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
+        self.record_inline("vkCmdDraw");
         unsafe {
             self.context.device.handle().cmd_draw(self.handle, vertex_count, instance_count, first_vertex, first_instance)
         }
     }
 
+    /// Dispatches compute work items.
+    pub fn dispatch(&self, group_count_x : u32, group_count_y : u32, group_count_z : u32) {
+        unsafe {
+            self.context.device.handle().cmd_dispatch(self.handle, group_count_x, group_count_y, group_count_z)
+        }
+    }
+
+    /// Records a memory barrier covering the whole buffer.
+    ///
+    /// # Description
+    ///
+    /// If the queue families of both phases differ, the barrier transfers the ownership of the buffer from the source
+    /// family to the destination family. It must then be recorded twice, with identical phases: once on a queue of the
+    /// source family to release the buffer, and once on a queue of the destination family to acquire it, after the
+    /// release completed (typically by waiting on a semaphore). See [`BarrierPhase::queue`].
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer.
+    /// * `src` - The accesses and stages that must complete before the barrier, and the family that owns the buffer.
+    /// * `dst` - The accesses and stages that wait for the barrier, and the family that will own the buffer.
+    /// * `dependency` - Dependency flags of the barrier.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if only one of the phases ignores queue families.
+    pub fn buffer_memory_barrier(&self, buffer : &Buffer, src : BarrierPhase, dst : BarrierPhase, dependency : vk::DependencyFlags) {
+        let barrier = make_buffer_barrier(buffer.handle(), &src, &dst);
+
+        unsafe {
+            self.context.device.handle()
+                .cmd_pipeline_barrier(self.handle, src.2, dst.2, dependency, &[], &[barrier], &[]);
+        }
+    }
+
     /// Copies data between buffer regions.
     pub fn copy_buffer(&self, source : &Buffer, dest : &Buffer, regions : &[vk::BufferCopy]) {
         unsafe {
@@ -249,8 +515,68 @@ impl CommandBuffer {
         }
     }
 
+    /// Fills a region of a buffer with a repeated 32-bit value.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `buffer` - The buffer to fill. It must have been created with [`vk::BufferUsageFlags::TRANSFER_DST`].
+    /// * `offset` - The offset, in bytes, at which to start filling. Must be a multiple of 4.
+    /// * `size` - The number of bytes to fill. Must be a multiple of 4, or [`vk::WHOLE_SIZE`] to fill until the end of the buffer.
+    /// * `data` - The value to write.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if the alignment constraints above are not respected.
+    /// * Panics if the region does not fit in the buffer.
+    pub fn fill_buffer(&self, buffer : &Buffer, offset : vk::DeviceSize, size : vk::DeviceSize, data : u32) {
+        assert!(offset % 4 == 0, "The offset of a buffer fill must be a multiple of 4");
+        assert!(offset < buffer.size(), "The offset of a buffer fill must be within the buffer");
+        if size != vk::WHOLE_SIZE {
+            assert!(size > 0 && size % 4 == 0, "The size of a buffer fill must be a non-zero multiple of 4");
+            assert!(size <= buffer.size() - offset, "The region to fill does not fit in the buffer");
+        }
+
+        unsafe {
+            self.context.device.handle().cmd_fill_buffer(self.handle, buffer.handle(), offset, size, data);
+        }
+    }
+
+    /// Updates a region of a buffer with data embedded in the command buffer.
+    /// 
+    /// # Description
+    /// 
+    /// This is meant for small updates; larger uploads should go through a staging buffer and [`CommandBuffer::copy_buffer`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `buffer` - The buffer to update. It must have been created with [`vk::BufferUsageFlags::TRANSFER_DST`].
+    /// * `offset` - The offset, in bytes, at which to write. Must be a multiple of 4.
+    /// * `data` - The data to write. Its size must be a non-zero multiple of 4 and may not exceed 65536 bytes.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if the alignment or size constraints above are not respected.
+    /// * Panics if the region does not fit in the buffer.
+    pub fn update_buffer(&self, buffer : &Buffer, offset : vk::DeviceSize, data : &[u8]) {
+        assert!(offset % 4 == 0, "The offset of a buffer update must be a multiple of 4");
+        assert!(!data.is_empty() && data.len() % 4 == 0, "The size of a buffer update must be a non-zero multiple of 4");
+        assert!(data.len() <= 65536, "Buffer updates are limited to 65536 bytes; use a staging buffer instead");
+        assert!(offset < buffer.size() && data.len() as u64 <= buffer.size() - offset, "The region to update does not fit in the buffer");
+
+        unsafe {
+            self.context.device.handle().cmd_update_buffer(self.handle, buffer.handle(), offset, data);
+        }
+    }
+
     /// Copies data from a buffer to an image.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if `dst_layout` is not the current layout of `dest`, or is not a valid layout for a transfer destination.
     pub fn copy_buffer_to_image(&self, source : &Buffer, dest : &Image, dst_layout : vk::ImageLayout, regions : &[vk::BufferImageCopy]) {
+        assert_eq!(dst_layout, dest.layout(), "The destination image is not in the layout it is being copied to");
+        assert!(is_transfer_dst_layout(dst_layout), "{:?} is not a valid layout for the destination of a copy", dst_layout);
+
         unsafe {
             self.context.device.handle().cmd_copy_buffer_to_image(self.handle, source.handle(), dest.handle(), dst_layout, regions);
         }
@@ -258,13 +584,36 @@ impl CommandBuffer {
 
     /// Updates the values of push constants.
     pub fn push_constants(&self, pipeline : &Pipeline, stage : vk::ShaderStageFlags, offset : u32, constants : &[u8]) {
+        self.record_inline("vkCmdPushConstants");
         unsafe {
             self.context.device.handle()
                 .cmd_push_constants(self.handle, pipeline.layout(), stage, offset, constants);
         }
     }
 
+    /// Updates the values of push constants from a typed value.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - The pipeline whose layout declares the push constants.
+    /// * `stage` - The shader stages the push constants are updated for.
+    /// * `offset` - The offset, in bytes, at which the value is written.
+    /// * `constants` - The value to write.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the bytes written are not entirely contained in a push constant range declared for `stage` by
+    ///   the layout of the pipeline.
+    pub fn push_constants_typed<T : bytemuck::Pod>(&self, pipeline : &Pipeline, stage : vk::ShaderStageFlags, offset : u32, constants : &T) {
+        let size = size_of::<T>() as u32;
+        assert!(is_push_constant_range_declared(pipeline.push_constant_ranges(), stage, offset, size),
+            "Push constants of {} bytes at offset {} for {:?} are not declared by the pipeline layout", size, offset, stage);
+
+        self.push_constants(pipeline, stage, offset, bytemuck::bytes_of(constants));
+    }
+
     pub fn bind_descriptor_sets(&self, point : vk::PipelineBindPoint, pipeline : &Pipeline, first_set : u32, descriptor_sets : &[vk::DescriptorSet], dynamic_offsets : &[u32]) {
+        self.record_inline("vkCmdBindDescriptorSets");
         unsafe {
             self.context.device.handle()
                 .cmd_bind_descriptor_sets(self.handle, point, pipeline.layout(), first_set, descriptor_sets, dynamic_offsets)
@@ -272,10 +621,21 @@ impl CommandBuffer {
     }
 
     /// Copies regions of an image, potentially performing format conversion.
-    pub fn blit_image(&self, source : &Image, dest : &mut Image, blit : &[vk::ImageBlit], filter : vk::Filter) {
+    /// 
+    /// # Description
+    /// 
+    /// Neither image changes layout; they must have been transitioned beforehand, typically to
+    /// [`vk::ImageLayout::TRANSFER_SRC_OPTIMAL`] and [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`] respectively.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if either image is not in a layout valid for its role in the blit.
+    pub fn blit_image(&self, source : &Image, dest : &Image, blit : &[vk::ImageBlit], filter : vk::Filter) {
         debug_assert!(source.sample_count() == vk::SampleCountFlags::TYPE_1 && dest.sample_count() == vk::SampleCountFlags::TYPE_1,
             "blit_image must not be used for multisampled source or destination images. Use resolve_image for this purpose."
         );
+        assert!(is_transfer_src_layout(source.layout()), "{:?} is not a valid layout for the source of a blit", source.layout());
+        assert!(is_transfer_dst_layout(dest.layout()), "{:?} is not a valid layout for the destination of a blit", dest.layout());
 
         unsafe {
             self.context.device.handle().cmd_blit_image(self.handle,
@@ -285,8 +645,6 @@ impl CommandBuffer {
                 dest.layout(),
                 blit,
                 filter);
-
-            dest.layout = source.layout;
         }
     }
 
@@ -332,7 +690,7 @@ impl CommandBufferBuilder {
             let handles = context.device.handle().allocate_command_buffers(&create_info)
                 .expect("Unable to allocate a command buffer");
 
-            CommandBuffer { handle : handles[0], level : self.level, context : context.clone() }
+            CommandBuffer { handle : handles[0], level : self.level, context : context.clone(), subpass_contents : Cell::new(None) }
         }
     }
 
@@ -347,7 +705,7 @@ impl CommandBufferBuilder {
                 .expect("Unable to allocate a command buffer")
                 .into_iter()
                 .map(|handle| {
-                    CommandBuffer { handle, level : self.level, context : context.clone() }
+                    CommandBuffer { handle, level : self.level, context : context.clone(), subpass_contents : Cell::new(None) }
                 })
                 .collect()
         }
@@ -362,4 +720,308 @@ impl BarrierPhase {
     pub fn ignore_queue(access_flags : vk::AccessFlags, stage : vk::PipelineStageFlags) -> Self {
         Self(vk::QUEUE_FAMILY_IGNORED, access_flags, stage)
     }
-}
\ No newline at end of file
+
+    /// Describes one side of a barrier that transfers the ownership of a resource between queue families.
+    ///
+    /// # Description
+    ///
+    /// When the barrier releases the resource, the access flags of the destination phase are ignored; when it acquires
+    /// the resource, the access flags of the source phase are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - The index of the queue family that owns the resource on this side of the barrier.
+    /// * `access_flags` - The accesses performed on this side of the barrier.
+    /// * `stage` - The pipeline stages performing these accesses.
+    pub fn queue(family : u32, access_flags : vk::AccessFlags, stage : vk::PipelineStageFlags) -> Self {
+        Self(family, access_flags, stage)
+    }
+}
+
+/// Returns a barrier covering the whole buffer. See [`CommandBuffer::buffer_memory_barrier`].
+fn make_buffer_barrier(buffer : vk::Buffer, src : &BarrierPhase, dst : &BarrierPhase) -> vk::BufferMemoryBarrier<'static> {
+    assert_eq!(src.0 == vk::QUEUE_FAMILY_IGNORED, dst.0 == vk::QUEUE_FAMILY_IGNORED,
+        "Either both or none of the phases of a barrier must ignore queue families");
+
+    vk::BufferMemoryBarrier::default()
+        .src_access_mask(src.1)
+        .dst_access_mask(dst.1)
+        .src_queue_family_index(src.0)
+        .dst_queue_family_index(dst.0)
+        .buffer(buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE)
+}
+
+/// Returns true if an image in the given layout can be the source of a transfer command.
+fn is_transfer_src_layout(layout : vk::ImageLayout) -> bool {
+    matches!(layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL | vk::ImageLayout::GENERAL | vk::ImageLayout::SHARED_PRESENT_KHR)
+}
+
+/// Ensures a command can be recorded in the current subpass.
+///
+/// # Arguments
+///
+/// * `current` - The contents of the current subpass, or `None` outside of render passes, where any command can be
+///   recorded as far as subpass contents are concerned.
+/// * `required` - The contents the subpass must have for the command to be recorded.
+/// * `command` - The name of the command, used to report errors.
+///
+/// # Panics
+///
+/// * Panics if the current subpass has different contents.
+fn validate_subpass_contents(current : Option<vk::SubpassContents>, required : vk::SubpassContents, command : &str) {
+    if let Some(current) = current {
+        assert!(current == required, "{} can't be recorded in a subpass whose contents are {:?}", command, current);
+    }
+}
+
+/// Asserts that an index buffer can be bound at the given offset.
+///
+/// # Arguments
+///
+/// * `index_type` - The index type of the buffer.
+/// * `offset` - The offset, in bytes, of the first index in the buffer.
+///
+/// # Panics
+///
+/// * Panics if `index_type` is not supported, or if `offset` is not a multiple of the size of an index.
+fn validate_index_offset(index_type : vk::IndexType, offset : vk::DeviceSize) {
+    let index_size = index_size(index_type).expect("Unsupported index type");
+    assert!(offset % index_size == 0, "Index buffer offsets must be a multiple of the index size ({} bytes)", index_size);
+}
+
+/// Returns true if an image in the given layout can be the destination of a transfer command.
+fn is_transfer_dst_layout(layout : vk::ImageLayout) -> bool {
+    matches!(layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL | vk::ImageLayout::GENERAL | vk::ImageLayout::SHARED_PRESENT_KHR)
+}
+
+/// Returns true if `size` bytes at `offset` are contained in a push constant range declared for every stage in `stage`.
+fn is_push_constant_range_declared(ranges : &[vk::PushConstantRange], stage : vk::ShaderStageFlags, offset : u32, size : u32) -> bool {
+    ranges.iter().any(|range| {
+        range.stage_flags.contains(stage)
+            && offset >= range.offset
+            && offset as u64 + size as u64 <= range.offset as u64 + range.size as u64
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use crate::traits::handle::Handle;
+    use crate::vk::buffer::{DynamicBufferBuilder, DynamicInitializer, StaticBufferBuilder, StaticInitializer};
+    use crate::vk::command_pool::CommandPool;
+    use crate::vk::framebuffer::Framebuffer;
+    use crate::vk::image::ImageCreateInfo;
+    use crate::vk::pipeline::layout::PipelineLayoutInfo;
+    use crate::vk::pipeline::{PipelineInfo, Vertex};
+    use crate::vk::render_pass::{RenderPass, SubpassAttachment};
+
+    use super::{is_push_constant_range_declared, is_transfer_dst_layout, is_transfer_src_layout, make_buffer_barrier, validate_index_offset, validate_subpass_contents, BarrierPhase};
+
+    /// A vertex made of a position in clip space.
+    struct PositionVertex;
+
+    impl Vertex for PositionVertex {
+        fn bindings() -> Vec<(u32, vk::VertexInputRate)> {
+            vec![(8, vk::VertexInputRate::VERTEX)]
+        }
+
+        fn format_offset() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![vk::VertexInputAttributeDescription::default().binding(0).offset(0).location(0).format(vk::Format::R32G32_SFLOAT)]
+        }
+    }
+
+    #[test]
+    pub fn transfer_layouts() {
+        assert!(is_transfer_src_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL));
+        assert!(is_transfer_src_layout(vk::ImageLayout::GENERAL));
+        assert!(!is_transfer_src_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL));
+        assert!(!is_transfer_src_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+
+        assert!(is_transfer_dst_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL));
+        assert!(is_transfer_dst_layout(vk::ImageLayout::GENERAL));
+        assert!(!is_transfer_dst_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL));
+        assert!(!is_transfer_dst_layout(vk::ImageLayout::UNDEFINED));
+    }
+
+    #[test]
+    pub fn push_constant_ranges() {
+        let ranges = [
+            vk::PushConstantRange::default().stage_flags(vk::ShaderStageFlags::VERTEX).offset(0).size(8),
+            vk::PushConstantRange::default().stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS).offset(16).size(16),
+        ];
+
+        assert!(is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::VERTEX, 0, 8));
+        assert!(is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::VERTEX, 4, 4));
+        assert!(is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::FRAGMENT, 16, 16));
+
+        // Overflowing the range, or crossing into another one.
+        assert!(!is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::VERTEX, 4, 8));
+        assert!(!is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::VERTEX, 8, 8));
+        // Stages the range was not declared for.
+        assert!(!is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::FRAGMENT, 0, 4));
+        assert!(!is_push_constant_range_declared(&ranges, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, 4));
+    }
+
+    #[test]
+    pub fn buffer_ownership_transfer() {
+        let release = make_buffer_barrier(vk::Buffer::null(),
+            &BarrierPhase::queue(2, vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            &BarrierPhase::queue(0, vk::AccessFlags::VERTEX_ATTRIBUTE_READ, vk::PipelineStageFlags::VERTEX_INPUT));
+        assert_eq!((release.src_queue_family_index, release.dst_queue_family_index), (2, 0));
+        assert_eq!(release.src_access_mask, vk::AccessFlags::TRANSFER_WRITE);
+        assert_eq!((release.offset, release.size), (0, vk::WHOLE_SIZE));
+
+        let barrier = make_buffer_barrier(vk::Buffer::null(),
+            &BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            &BarrierPhase::ignore_queue(vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER));
+        assert_eq!(barrier.src_queue_family_index, vk::QUEUE_FAMILY_IGNORED);
+        assert_eq!(barrier.dst_queue_family_index, vk::QUEUE_FAMILY_IGNORED);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn partial_ownership_transfer() {
+        _ = make_buffer_barrier(vk::Buffer::null(),
+            &BarrierPhase::queue(2, vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            &BarrierPhase::ignore_queue(vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER));
+    }
+
+    #[test]
+    pub fn index_offsets() {
+        validate_index_offset(vk::IndexType::UINT16, 0);
+        validate_index_offset(vk::IndexType::UINT16, 6);
+        validate_index_offset(vk::IndexType::UINT32, 8);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn misaligned_u16_index_offset() {
+        validate_index_offset(vk::IndexType::UINT16, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn u16_offset_in_u32_indices() {
+        validate_index_offset(vk::IndexType::UINT32, 6);
+    }
+
+    #[test]
+    pub fn subpass_contents() {
+        // Outside of render passes, both kinds of commands can be recorded.
+        validate_subpass_contents(None, vk::SubpassContents::INLINE, "vkCmdDraw");
+        validate_subpass_contents(None, vk::SubpassContents::SECONDARY_COMMAND_BUFFERS, "vkCmdExecuteCommands");
+
+        validate_subpass_contents(Some(vk::SubpassContents::INLINE), vk::SubpassContents::INLINE, "vkCmdDraw");
+        validate_subpass_contents(Some(vk::SubpassContents::SECONDARY_COMMAND_BUFFERS), vk::SubpassContents::SECONDARY_COMMAND_BUFFERS, "vkCmdExecuteCommands");
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn inline_commands_in_secondary_subpass() {
+        validate_subpass_contents(Some(vk::SubpassContents::SECONDARY_COMMAND_BUFFERS), vk::SubpassContents::INLINE, "vkCmdDraw");
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn secondary_commands_in_inline_subpass() {
+        validate_subpass_contents(Some(vk::SubpassContents::INLINE), vk::SubpassContents::SECONDARY_COMMAND_BUFFERS, "vkCmdExecuteCommands");
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn indexed_draw_u16() {
+        use crate::testing::{submit_and_wait, ShaderFile};
+
+        let (_event_loop, app) = crate::testing::test_application();
+        let context = &app.orchestrator.context;
+        let extent = vk::Extent2D { width : 4, height : 4 };
+
+        let vertex_shader = ShaderFile::new("indexed_draw.vert",
+            "#version 450\nlayout(location = 0) in vec2 inPosition;\nvoid main() { gl_Position = vec4(inPosition, 0.0, 1.0); }\n");
+        let fragment_shader = ShaderFile::new("indexed_draw.frag",
+            "#version 450\nlayout(location = 0) out vec4 outColor;\nvoid main() { outColor = vec4(1.0, 0.0, 0.0, 1.0); }\n");
+
+        let target = ImageCreateInfo::default()
+            .color()
+            .layers(0, 1)
+            .levels(0, 1)
+            .image_type(vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .extent(vk::Extent3D { width : extent.width, height : extent.height, depth : 1 })
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .build(context);
+        let render_pass = RenderPass::builder()
+            .color_attachment(vk::Format::R8G8B8A8_UNORM, vk::SampleCountFlags::TYPE_1,
+                vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE,
+                vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .subpass(vk::PipelineBindPoint::GRAPHICS, &[SubpassAttachment::color(0)], None)
+            .build(context);
+        let framebuffer = Framebuffer::new(context, vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass.handle())
+            .attachments(&[target.view()])
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1));
+
+        let layout = PipelineLayoutInfo::default().build(context);
+        let pipeline = PipelineInfo::default()
+            .layout(&layout)
+            .render_pass(render_pass.handle(), 0)
+            .vertex::<PositionVertex>()
+            .add_shader(vertex_shader.path().to_path_buf(), vk::ShaderStageFlags::VERTEX)
+            .add_shader(fragment_shader.path().to_path_buf(), vk::ShaderStageFlags::FRAGMENT)
+            .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default().color_write_mask(vk::ColorComponentFlags::RGBA))
+            .build(context);
+
+        // A quad covering the left half of the target, drawn through 16-bit indices.
+        let pool = CommandPool::builder(&context.graphics_queue).build(context);
+        let vertices = DynamicBufferBuilder::dynamic()
+            .cpu_to_gpu()
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .build(context, &pool, &[[-1.0f32, -1.0], [0.0, -1.0], [-1.0, 1.0], [0.0, 1.0]]);
+        let indices = DynamicBufferBuilder::dynamic()
+            .cpu_to_gpu()
+            .usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .index(vk::IndexType::UINT16)
+            .build(context, &pool, &[0u16, 1, 2, 2, 1, 3]);
+        let mut readback = StaticBufferBuilder::fixed_size()
+            .gpu_to_cpu()
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .build(context, (extent.width * extent.height * 4) as u64);
+
+        submit_and_wait(context, &pool, |cmd| {
+            cmd.begin_render_pass(&render_pass, &framebuffer, vk::Rect2D::default().extent(extent), &[vk::ClearValue::default()],
+                vk::SubpassContents::INLINE);
+            cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, &pipeline);
+            cmd.set_viewport(0, &[vk::Viewport::default().width(extent.width as f32).height(extent.height as f32).max_depth(1.0)]);
+            cmd.set_scissors(0, &[vk::Rect2D::default().extent(extent)]);
+            cmd.bind_vertex_buffers(0, &[(&vertices, 0)]);
+            cmd.bind_index_buffer(&indices, 0);
+            cmd.draw_indexed(6, 1, 0, 0, 0);
+            cmd.end_render_pass();
+            unsafe {
+                context.device.handle().cmd_copy_image_to_buffer(cmd.handle(), target.handle(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    readback.handle(), &[vk::BufferImageCopy::default()
+                        .image_subresource(vk::ImageSubresourceLayers::default().aspect_mask(vk::ImageAspectFlags::COLOR).layer_count(1))
+                        .image_extent(vk::Extent3D { width : extent.width, height : extent.height, depth : 1 })]);
+            }
+            cmd.buffer_memory_barrier(&readback,
+                BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+                BarrierPhase::ignore_queue(vk::AccessFlags::HOST_READ, vk::PipelineStageFlags::HOST),
+                vk::DependencyFlags::empty());
+        });
+
+        let pixels = readback.map_slice::<[u8; 4]>();
+        for (index, pixel) in pixels.iter().enumerate() {
+            let expected = if index as u32 % extent.width < extent.width / 2 { [0xFF, 0, 0, 0xFF] } else { [0; 4] };
+            assert_eq!(*pixel, expected, "Unexpected pixel at index {}", index);
+        }
+    }
+}
@@ -1,4 +1,9 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use ash::vk;
+use ash::vk::Handle as _;
 use crate::make_handle;
 use crate::orchestration::rendering::RenderingContext;
 use crate::vk::logical_device::LogicalDevice;
@@ -10,11 +15,47 @@ pub struct CommandPool {
     family : u32,
 }
 
+thread_local! {
+    // The command pools created by CommandPool::thread_local on this thread, keyed by device and queue family.
+    static THREAD_POOLS : RefCell<HashMap<(u64, u32), Rc<CommandPool>>> = RefCell::new(HashMap::new());
+}
+
 impl CommandPool {
     pub fn builder(family : &QueueFamily) -> CommandPoolBuilder {
         CommandPoolBuilder::default(family)
     }
 
+    /// Returns the command pool of the calling thread for the given queue family, creating it if needed.
+    ///
+    /// # Description
+    ///
+    /// Command pools must be externally synchronized, so threads recording commands in parallel each need their own.
+    /// The returned pool can't be sent to another thread. Command buffers allocated from it can be reset individually
+    /// (see [`CommandPoolBuilder::reset`]).
+    ///
+    /// Pools live until [`CommandPool::release_thread_local`] is called on the thread that created them, or until the
+    /// thread exits. They keep the logical device alive, so threads that outlive the renderer should release them.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `family` - The queue family command buffers allocated from the pool will be submitted to.
+    pub fn thread_local(context : &RenderingContext, family : &QueueFamily) -> Rc<CommandPool> {
+        let key = (context.device.handle().handle().as_raw(), family.index());
+
+        THREAD_POOLS.with_borrow_mut(|pools| {
+            pools.entry(key)
+                .or_insert_with(|| Rc::new(CommandPool::builder(family).reset().build(context)))
+                .clone()
+        })
+    }
+
+    /// Destroys the command pools created by [`CommandPool::thread_local`] on the calling thread, once every other
+    /// reference to them is dropped.
+    pub fn release_thread_local() {
+        THREAD_POOLS.with_borrow_mut(HashMap::clear);
+    }
+
     pub fn family(&self) -> u32 { self.family }
 
     pub fn device(&self) -> &LogicalDevice { &self.context.device }
@@ -58,14 +99,15 @@ impl CommandPool {
     /// Trims the command pool, recycling unused memory back to the system. Command buffers allocated from the pool
     /// are not affected.
     /// 
-    /// This is a somewhat expensive operation; if don't know what you're doing, don't use it.
-    /// 
-    /// # Arguments
+    /// # Description
     /// 
-    /// * `flags` - Reserved for future uses.
-    pub fn trim(&self, flags : vk::CommandPoolTrimFlags) {
+    /// Pools keep the memory of freed or reset command buffers around for future allocations. After a burst of
+    /// allocations, such as recording the uploads of a large batch of textures, trimming releases that memory. This is
+    /// a somewhat expensive operation and should not be done every frame.
+    pub fn trim(&self) {
         unsafe {
-            self.context.device.handle().trim_command_pool(self.handle, flags);
+            // Trim flags are reserved for future uses.
+            self.context.device.handle().trim_command_pool(self.handle, vk::CommandPoolTrimFlags::empty());
         }
     }
 }
@@ -1,13 +1,12 @@
 use std::backtrace::Backtrace;
-use std::collections::HashSet;
-use std::ffi::CStr;
+use std::ffi::{c_void, CStr};
+use std::fmt::Write;
 use std::ptr::null;
 use std::{hint, slice};
 use std::{cmp::Ordering, ffi::CString, sync::Arc};
 
 use ash::vk;
 
-use crate::traits::handle::Handle;
 use crate::vk::physical_device::PhysicalDevice;
 use crate::window::Window;
 
@@ -16,20 +15,63 @@ use super::queue::QueueFamily;
 pub struct Context {
     pub(in crate) entry : Arc<ash::Entry>,
     pub(in crate) instance : ash::Instance,
-    debug_utils : ash::ext::debug_utils::Instance,
-    debug_messenger : vk::DebugUtilsMessengerEXT,
-    
+    api_version : u32,
+    available_extensions : Vec<CString>,
+    enabled_extensions : Vec<CString>,
+    // Only loaded when VK_EXT_debug_utils is enabled.
+    debug_utils : Option<ash::ext::debug_utils::Instance>,
+    // Only created when validation is enabled.
+    debug_messenger : Option<vk::DebugUtilsMessengerEXT>,
+    // Read by the debug messenger callback; boxed so that its address is stable.
+    _debug_options : Box<DebugMessengerOptions>,
 }
 
-impl Context {
-    pub fn get_device_extensions(&self, device : &PhysicalDevice) -> Vec<vk::ExtensionProperties> {
-        unsafe {
-            self.instance.enumerate_device_extension_properties(device.handle())
-                .expect("Failed to enumerate device extensions")
+/// How much information accompanies messages of the validation layer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugVerbosity {
+    /// Only the message itself.
+    Message,
+    /// The message, followed by the active queue labels, command buffer labels and objects.
+    Labels,
+    /// Everything above, followed by a Rust backtrace. This is slow.
+    Backtrace,
+}
+
+/// Receives messages of the validation layer.
+pub type DebugCallback = fn(vk::DebugUtilsMessageSeverityFlagsEXT, &str);
+
+/// Controls which messages of the validation layer are reported, and how.
+#[derive(Debug, Copy, Clone)]
+pub struct DebugMessengerOptions {
+    pub severity : vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub verbosity : DebugVerbosity,
+    pub callback : DebugCallback,
+}
+
+impl Default for DebugMessengerOptions {
+    fn default() -> Self {
+        Self {
+            severity : vk::DebugUtilsMessageSeverityFlagsEXT::WARNING | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            verbosity : DebugVerbosity::Labels,
+            callback : print_debug_message,
         }
     }
+}
+
+/// The default [`DebugCallback`], which prints messages to the standard output.
+pub fn print_debug_message(severity : vk::DebugUtilsMessageSeverityFlagsEXT, message : &str) {
+    let severity = match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "VERBOSE",
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "INFO",
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "WARNING",
+        _ => "ERROR",
+    };
 
-    /// Selects a [`PhysicalDevice`] and its associated graphics and presentation [`queue families`](QueueFamily).
+    println!("[{}] {}", severity, message);
+}
+
+impl Context {
+    /// Selects a [`PhysicalDevice`] and its associated graphics, presentation, transfer and compute [`queue families`](QueueFamily).
     ///
     /// Device selection is done according to its classification, with the following order:
     ///
@@ -39,8 +81,12 @@ impl Context {
     /// 4. [`vk::PhysicalDeviceType::CPU`]
     /// 5. [`vk::PhysicalDeviceType::OTHER`]
     ///
+    /// Devices that do not support all of the required extensions and features are ignored.
+    ///
     /// If possible, the graphics and presentation queue families will be the same to reduce internal synchronization.
-    pub fn select_physical_device(&self, window : &Window, device_extensions : &[CString]) -> (PhysicalDevice, QueueFamily, QueueFamily, QueueFamily) {
+    /// The compute queue family is preferably one that does not support graphics operations, so that compute work
+    /// can run asynchronously; if the device exposes no such family, the graphics queue family is used instead.
+    pub fn select_physical_device(&self, window : &Window, device_extensions : &[CString], features : &vk::PhysicalDeviceFeatures) -> (PhysicalDevice, QueueFamily, QueueFamily, QueueFamily, QueueFamily) {
         self.get_physical_devices(|left, right| {
             // DISCRETE_GPU > INTEGRATED_GPU > VIRTUAL_GPU > CPU > OTHER
             match (right.properties().device_type, left.properties().device_type) {
@@ -75,25 +121,13 @@ impl Context {
         .into_iter()
         .filter(|device| -> bool {
             // 1. First, check for device extensions.
-            // We start by collecting a device's extensions and then remove them from the extensions
-            // we asked for. If no extension subside, we're good.
-            let extensions_supported = {
-                let device_extensions_names = self.get_device_extensions(device).into_iter()
-                    .map(|device_extension| {
-                        unsafe {
-                            CStr::from_ptr(device_extension.extension_name.as_ptr()).to_owned()
-                        }
-                    }).collect::<Vec<_>>();
-
-                let mut required_extensions = device_extensions.iter().collect::<HashSet<_>>();
-                for extension_name in device_extensions_names {
-                    required_extensions.remove(&extension_name);
-                }
+            let extensions_supported = device_extensions.iter()
+                .all(|extension| device.supports_extension(extension));
 
-                required_extensions.is_empty()
-            };
+            // 2. Then, check for required features.
+            let features_supported = device.supports_features(self, features);
 
-            // 2. Finally, check for swapchain support.
+            // 3. Finally, check for swapchain support.
             let supports_present = {
                 let surface_formats = window.get_surface_formats(device);
                 let surface_present_modes = window.get_present_modes(device);
@@ -101,7 +135,7 @@ impl Context {
                 !surface_formats.is_empty() && !surface_present_modes.is_empty()
             };
 
-            return extensions_supported && supports_present
+            return extensions_supported && features_supported && supports_present
         }).find_map(|device| {
             // At this point, the current device is eligible and we just need to check for a present queue and a graphics queue.
             // To do that, we will grab the queue's families.
@@ -109,6 +143,7 @@ impl Context {
             let mut graphics_queue = None;
             let mut present_queue = None;
             let mut transfer_queue = None;
+            let mut compute_queue = None;
 
             for family in &device.queue_families[..] {
                 if family.is_graphics() {
@@ -130,10 +165,18 @@ impl Context {
                 if family.is_transfer() && ((!family.is_graphics() && !family.is_compute()) || transfer_queue.is_none()) {
                     transfer_queue = Some(family.clone());
                 }
+
+                // Prefer the first compute family that does not support graphics for async compute.
+                if family.is_async_compute() && compute_queue.is_none() {
+                    compute_queue = Some(family.clone());
+                }
             }
 
-            match (graphics_queue, present_queue, transfer_queue) {
-                (Some(g), Some(p), Some(t)) => Some((device, g, p, t)),
+            // Fall back to the graphics queue if there is no dedicated compute family.
+            let compute_queue = compute_queue.or(graphics_queue);
+
+            match (graphics_queue, present_queue, transfer_queue, compute_queue) {
+                (Some(g), Some(p), Some(t), Some(c)) => Some((device, g, p, t, c)),
                 _ => None
             }
         }).expect("Failed to select a physical device and an associated queue family")
@@ -146,76 +189,64 @@ impl Context {
         message_severity : vk::DebugUtilsMessageSeverityFlagsEXT,
         message_types : vk::DebugUtilsMessageTypeFlagsEXT,
         p_callback_data : *const vk::DebugUtilsMessengerCallbackDataEXT,
-        _p_user_data : *mut std::ffi::c_void,
+        p_user_data : *mut std::ffi::c_void,
     ) -> vk::Bool32 {
-        let severity = match message_severity {
-            vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[VERBOSE]",
-            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "[WARNING]",
-            vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "[ERROR]",
-            vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "[INFO]",
-            _ => hint::unreachable_unchecked()
-        };
-        let types = match message_types {
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[GENERAL]",
-            vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[PERFORMANCE]",
-            vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[VALIDATION]",
-            _ => hint::unreachable_unchecked()
-        };
+        let options = &*(p_user_data as *const DebugMessengerOptions);
         let callback_data = &*p_callback_data;
 
-        let message = CStr::from_ptr(callback_data.p_message);
-        println!("======================================================");
-        println!("A validation error occured in Vulkan");
-        println!("  {} {}: {:?}", severity, types, message);
-        #[cfg(debug_assertions)]
-        println!("The Rust stack trace follows:");
-        #[cfg(debug_assertions)]
-        println!("  {}", Backtrace::capture());
-
-        if callback_data.p_queue_labels != null() && callback_data.queue_label_count != 0 { // Print queue labels
-            let queue_labels = slice::from_raw_parts(
-                callback_data.p_queue_labels,
-                callback_data.queue_label_count as _
-            );
-
-            println!("The active queue labels were:");
-            for queue_label in queue_labels {
-                if let Some(label) =  queue_label.label_name_as_c_str() {
-                    println!("  - {:?}", label);
+        let mut message = format!("{:?}: {}", message_types, CStr::from_ptr(callback_data.p_message).to_string_lossy());
+
+        if options.verbosity >= DebugVerbosity::Labels {
+            if callback_data.p_queue_labels != null() && callback_data.queue_label_count != 0 { // Print queue labels
+                let queue_labels = slice::from_raw_parts(
+                    callback_data.p_queue_labels,
+                    callback_data.queue_label_count as _
+                );
+
+                message.push_str("\nThe active queue labels were:");
+                for queue_label in queue_labels {
+                    if let Some(label) = queue_label.label_name_as_c_str() {
+                        _ = write!(message, "\n  - {:?}", label);
+                    }
                 }
             }
-        }
 
-        if callback_data.p_cmd_buf_labels != null() && callback_data.cmd_buf_label_count != 0 { // Print command buffer labels
-            let labels = slice::from_raw_parts(
-                callback_data.p_cmd_buf_labels,
-                callback_data.cmd_buf_label_count as _
-            );
+            if callback_data.p_cmd_buf_labels != null() && callback_data.cmd_buf_label_count != 0 { // Print command buffer labels
+                let labels = slice::from_raw_parts(
+                    callback_data.p_cmd_buf_labels,
+                    callback_data.cmd_buf_label_count as _
+                );
 
-            println!("The active command buffers were:");
-            for label in labels {
-                if let Some(label) = label.label_name_as_c_str() {
-                    println!("  - {:?}", label);
+                message.push_str("\nThe active command buffers were:");
+                for label in labels {
+                    if let Some(label) = label.label_name_as_c_str() {
+                        _ = write!(message, "\n  - {:?}", label);
+                    }
                 }
             }
-        }
 
-        if callback_data.p_objects != null() && callback_data.object_count != 0 { // Print object labels
-            let labels = slice::from_raw_parts(
-                callback_data.p_objects,
-                callback_data.object_count as _
-            );
-
-            println!("The active objects were:");
-            for label in labels {
-                if let Some(label_str) = label.object_name_as_c_str() {
-                    println!("  - 0x{:#016x} : {:?}", label.object_handle, label_str);
-                } else {
-                    println!("  - 0x{:#016x}", label.object_handle);
+            if callback_data.p_objects != null() && callback_data.object_count != 0 { // Print object labels
+                let labels = slice::from_raw_parts(
+                    callback_data.p_objects,
+                    callback_data.object_count as _
+                );
+
+                message.push_str("\nThe active objects were:");
+                for label in labels {
+                    if let Some(label_str) = label.object_name_as_c_str() {
+                        _ = write!(message, "\n  - 0x{:#016x} : {:?}", label.object_handle, label_str);
+                    } else {
+                        _ = write!(message, "\n  - 0x{:#016x}", label.object_handle);
+                    }
                 }
             }
         }
-        println!("======================================================");
+
+        if options.verbosity >= DebugVerbosity::Backtrace {
+            _ = write!(message, "\nThe Rust stack trace follows:\n{}", Backtrace::force_capture());
+        }
+
+        (options.callback)(message_severity, &message);
 
         vk::FALSE
     }
@@ -224,6 +255,33 @@ impl Context {
 
     pub fn handle(&self) -> &ash::Instance { &self.instance }
 
+    /// Returns the underlying [`ash::Instance`], so that the functions of instance extensions this crate does not wrap
+    /// can be loaded. See [`LogicalDevice::raw`](crate::vk::logical_device::LogicalDevice::raw) for device extensions.
+    ///
+    /// # Safety
+    ///
+    /// This is an escape hatch; nothing done through the returned instance is tracked by this crate.
+    ///
+    /// * The instance must not be destroyed; it is owned by this object.
+    /// * Objects created through it must be destroyed by the caller before this object is dropped.
+    pub unsafe fn raw(&self) -> &ash::Instance { &self.instance }
+
+    /// Returns the version of the Vulkan API this instance was created with; see [`vk::api_version_major`] and
+    /// [`vk::api_version_minor`] to decode it. This is the lowest of Vulkan 1.3 and the version supported by the loader.
+    /// Physical devices may support an even lower version.
+    #[inline] pub fn api_version(&self) -> u32 { self.api_version }
+
+    /// Returns true if the given instance extension was enabled when creating this instance.
+    pub fn is_extension_enabled(&self, extension : &CStr) -> bool {
+        self.enabled_extensions.iter().any(|enabled| enabled.as_c_str() == extension)
+    }
+
+    /// Returns true if the given instance extension is provided by the loader or by one of the enabled layers. It may
+    /// not be enabled; see [`Context::is_extension_enabled`].
+    pub fn supports_instance_extension(&self, extension : &CStr) -> bool {
+        self.available_extensions.iter().any(|available| available.as_c_str() == extension)
+    }
+
     /// Returns all physical devices of this Vulkan instance. The returned [`Vec`] is sorted according to the provided comparator.
     /// # Arguments
     /// 
@@ -254,48 +312,101 @@ impl Context {
     /// # Arguments
     /// 
     /// * `app_name` - The name of the application.
-    /// * `instance_extensions` - An array of extensions to apply to this instance.
+    /// * `required_extensions` - Extensions that must be enabled on this instance.
+    /// * `optional_extensions` - Extensions that are enabled on this instance only if they are available. See
+    ///                           [`Context::is_extension_enabled`].
+    /// * `validation` - Whether to enable the Khronos validation layer. If the layer is not installed, validation is
+    ///                  disabled and a warning is printed.
+    /// * `debug_options` - Controls how messages of the validation layer are reported.
     ///
     /// # Panics
     ///
+    /// * Panics if any of the required extensions is not available.
     /// * Panics if [`vkCreateInstance`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCreateInstance.html) failed.
     /// * Panics if [`vkCreateDebugUtilsMessengerEXT`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCreateDebugUtilsMessengerEXT.html) failed.
-    pub fn new(app_name : CString, instance_extensions: Vec<CString>) -> Self {
+    pub fn new(app_name : CString,
+        required_extensions : Vec<CString>,
+        optional_extensions : Vec<CString>,
+        validation : bool,
+        debug_options : DebugMessengerOptions
+    ) -> Self {
         let entry = Arc::new(unsafe { ash::Entry::load().unwrap() });
+        let debug_options = Box::new(debug_options);
         let mut debug_utils_messenger_create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
             .flags(vk::DebugUtilsMessengerCreateFlagsEXT::empty())
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-            )
+            .message_severity(debug_options.severity)
             .message_type(
                 vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
             )
-            .pfn_user_callback(Some(Self::vulkan_debug_utils_callback));
+            .pfn_user_callback(Some(Self::vulkan_debug_utils_callback))
+            .user_data(&*debug_options as *const DebugMessengerOptions as *mut c_void);
+
+        // Loaders that predate Vulkan 1.1 don't expose vkEnumerateInstanceVersion, and only support Vulkan 1.0.
+        let api_version = negotiate_api_version(unsafe { entry.try_enumerate_instance_version() }.ok().flatten());
 
         let app_info = vk::ApplicationInfo::default()
             .application_name(&app_name)
             .application_version(vk::make_api_version(1, 0, 0, 0))
-            .api_version(vk::API_VERSION_1_3);
+            .api_version(api_version);
 
-        const VALIDATION: [&'static str; 1] = ["VK_LAYER_KHRONOS_validation"];
+        const VALIDATION: [&'static CStr; 1] = [c"VK_LAYER_KHRONOS_validation"];
 
-        let extension_names = instance_extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+        let available_layers = unsafe {
+            entry.enumerate_instance_layer_properties()
+                .expect("Failed to enumerate instance layers")
+        };
+        let layer_names = if validation {
+            VALIDATION.iter()
+                .filter(|&&layer| {
+                    let available = available_layers.iter()
+                        .any(|properties| properties.layer_name_as_c_str() == Ok(layer));
+                    if !available {
+                        println!("The layer {:?} is not installed and will not be enabled.", layer);
+                    }
+                    available
+                })
+                .map(|layer| layer.as_ptr())
+                .collect::<Vec<_>>()
+        } else {
+            vec![]
+        };
 
-        let raw_layer_names = VALIDATION.iter()
-            .map(|&l| CString::new(l).unwrap())
-            .collect::<Vec<_>>();
-        let layer_names = raw_layer_names.iter()
-            .map(|l| l.as_ptr())
+        // Extensions can be provided by the loader as well as by the enabled layers.
+        let mut available_extensions = unsafe {
+            entry.enumerate_instance_extension_properties(None)
+                .expect("Failed to enumerate instance extensions")
+        };
+        for &layer in &layer_names {
+            unsafe {
+                if let Ok(extensions) = entry.enumerate_instance_extension_properties(Some(CStr::from_ptr(layer))) {
+                    available_extensions.extend(extensions);
+                }
+            }
+        }
+        let available_extensions = available_extensions.iter()
+            .filter_map(|properties| properties.extension_name_as_c_str().ok())
             .collect::<Vec<_>>();
-        
-        let instance_create_info = vk::InstanceCreateInfo::default()
-            .push_next(&mut debug_utils_messenger_create_info)
+
+        let enabled_extensions = match filter_extensions("instance", &available_extensions, &required_extensions, &optional_extensions) {
+            Ok(enabled_extensions) => enabled_extensions,
+            Err(missing) => panic!("The following instance extensions are required but not available: {:?}", missing),
+        };
+        let extension_names = enabled_extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+        // The debug messenger needs the debug utilities extension; the validation layer still prints its messages
+        // without it.
+        let debug_utils = enabled_extensions.iter().any(|extension| extension.as_c_str() == ash::ext::debug_utils::NAME);
+        let validation = !layer_names.is_empty() && debug_utils;
+
+        let mut instance_create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
             .enabled_extension_names(&extension_names)
             .enabled_layer_names(&layer_names);
+        if validation {
+            instance_create_info = instance_create_info.push_next(&mut debug_utils_messenger_create_info);
+        }
 
         let instance = unsafe {
             entry.create_instance(&instance_create_info, None)
@@ -303,18 +414,24 @@ impl Context {
         };
 
         // setup debug utils
-        let debug_utils_loader = ash::ext::debug_utils::Instance::new(&entry, &instance);
-        let debug_messenger = unsafe {
-            debug_utils_loader
-                .create_debug_utils_messenger(&debug_utils_messenger_create_info, None)
-                .expect("Failed to create debug utils messenger")
-        };
+        let debug_utils_loader = debug_utils.then(|| ash::ext::debug_utils::Instance::new(&entry, &instance));
+        let debug_messenger = debug_utils_loader.as_ref()
+            .filter(|_| validation)
+            .map(|debug_utils_loader| unsafe {
+                debug_utils_loader
+                    .create_debug_utils_messenger(&debug_utils_messenger_create_info, None)
+                    .expect("Failed to create debug utils messenger")
+            });
 
         Self {
             entry,
             instance,
+            api_version,
+            available_extensions : available_extensions.into_iter().map(CStr::to_owned).collect(),
+            enabled_extensions,
             debug_utils : debug_utils_loader,
-            debug_messenger
+            debug_messenger,
+            _debug_options : debug_options,
         }
     }
 }
@@ -322,8 +439,97 @@ impl Context {
 impl Drop for Context {
     fn drop(&mut self) {
         unsafe {
-            self.debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
+            if let (Some(debug_utils), Some(debug_messenger)) = (&self.debug_utils, self.debug_messenger) {
+                debug_utils.destroy_debug_utils_messenger(debug_messenger, None);
+            }
             self.instance.destroy_instance(None);
         }
     }
 }
+
+/// Returns the version of the Vulkan API to request when creating an instance.
+///
+/// # Arguments
+///
+/// * `loader_version` - The version of the Vulkan API supported by the loader, or `None` if it only supports Vulkan 1.0.
+fn negotiate_api_version(loader_version : Option<u32>) -> u32 {
+    // Patch versions don't change the API, so only compare major and minor versions.
+    let loader_version = loader_version.map_or(vk::API_VERSION_1_0, |version| {
+        vk::make_api_version(0, vk::api_version_major(version), vk::api_version_minor(version), 0)
+    });
+
+    loader_version.min(vk::API_VERSION_1_3)
+}
+
+/// Selects the instance or device extensions to enable.
+///
+/// # Arguments
+///
+/// * `kind` - What the extensions are enabled on, used to report unavailable optional extensions.
+/// * `available` - The extensions that are available.
+/// * `required` - The extensions that must be enabled.
+/// * `optional` - The extensions that should be enabled if they are available. Those that aren't are reported.
+///
+/// # Returns
+///
+/// The extensions to enable, without duplicates, or the required extensions that are not available.
+pub(in crate) fn filter_extensions(kind : &str, available : &[&CStr], required : &[CString], optional : &[CString]) -> Result<Vec<CString>, Vec<CString>> {
+    let is_available = |extension : &CString| available.contains(&extension.as_c_str());
+
+    let missing = required.iter()
+        .filter(|extension| !is_available(extension))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    let mut enabled = Vec::<CString>::with_capacity(required.len() + optional.len());
+    for extension in required {
+        if !enabled.contains(extension) {
+            enabled.push(extension.clone());
+        }
+    }
+    for extension in optional {
+        if !is_available(extension) {
+            println!("The {} extension {:?} is not available and will not be enabled.", kind, extension);
+        } else if !enabled.contains(extension) {
+            enabled.push(extension.clone());
+        }
+    }
+
+    Ok(enabled)
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+
+    use ash::vk;
+
+    use super::{filter_extensions, negotiate_api_version};
+
+    #[test]
+    pub fn extension_filtering() {
+        let available = [c"VK_KHR_surface", c"VK_KHR_xlib_surface", c"VK_EXT_debug_utils"];
+        let surface = CString::from(c"VK_KHR_surface");
+        let xlib = CString::from(c"VK_KHR_xlib_surface");
+        let debug_utils = CString::from(c"VK_EXT_debug_utils");
+        let wayland = CString::from(c"VK_KHR_wayland_surface");
+
+        assert_eq!(filter_extensions("instance", &available, &[surface.clone(), xlib.clone(), surface.clone()], &[debug_utils.clone(), wayland.clone()]),
+            Ok(vec![surface.clone(), xlib.clone(), debug_utils.clone()]));
+        assert_eq!(filter_extensions("instance", &available, &[surface.clone(), wayland.clone()], &[debug_utils.clone()]),
+            Err(vec![wayland.clone()]));
+        assert_eq!(filter_extensions("instance", &available[..2], &[surface.clone()], &[debug_utils.clone()]),
+            Ok(vec![surface.clone()]));
+    }
+
+    #[test]
+    pub fn api_version_negotiation() {
+        assert_eq!(negotiate_api_version(None), vk::API_VERSION_1_0);
+        assert_eq!(negotiate_api_version(Some(vk::make_api_version(0, 1, 2, 198))), vk::API_VERSION_1_2);
+        assert_eq!(negotiate_api_version(Some(vk::make_api_version(0, 1, 3, 250))), vk::API_VERSION_1_3);
+        assert_eq!(negotiate_api_version(Some(vk::make_api_version(0, 1, 4, 0))), vk::API_VERSION_1_3);
+    }
+}
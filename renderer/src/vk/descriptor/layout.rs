@@ -23,8 +23,45 @@ impl DescriptorSetLayoutBuilder {
         self
     }
 
+    /// Declares a binding of uniform buffers, which shaders can read from.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `binding` - The binding number.
+    /// * `stage` - The shader stages that access the binding.
+    /// * `count` - The number of buffers in the binding.
+    #[inline] pub fn uniform_buffer(self, binding : u32, stage : vk::ShaderStageFlags, count : u32) -> Self {
+        self.binding(binding, vk::DescriptorType::UNIFORM_BUFFER, stage, count)
+    }
+
+    /// Declares a binding of storage buffers, which shaders can read from and write to.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `binding` - The binding number.
+    /// * `stage` - The shader stages that access the binding.
+    /// * `count` - The number of buffers in the binding.
+    #[inline] pub fn storage_buffer(self, binding : u32, stage : vk::ShaderStageFlags, count : u32) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_BUFFER, stage, count)
+    }
+
+    /// Declares a binding of storage images, which shaders can read from and write to.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `binding` - The binding number.
+    /// * `stage` - The shader stages that access the binding.
+    /// * `count` - The number of images in the binding.
+    #[inline] pub fn storage_image(self, binding : u32, stage : vk::ShaderStageFlags, count : u32) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_IMAGE, stage, count)
+    }
+
     value_builder! { pool_flags, vk::DescriptorPoolCreateFlags }
-    value_builder! { sets, count, sets, u32 }
+    value_builder! {
+        /// The amount of descriptor sets each descriptor pool can hold. Additional pools are created as needed when
+        /// more sets are requested.
+        sets, count, sets, u32
+    }
     value_builder! { flags, vk::DescriptorSetLayoutCreateFlags }
 
     pub fn build(self, context : &RenderingContext) -> DescriptorSetLayout {
@@ -32,20 +69,23 @@ impl DescriptorSetLayoutBuilder {
     }
 }
 
-/// A somewhat thin wrapped around [`vk::DescriptorSetLayout`]. This object also manages a pool of descriptors as well
-/// as known descriptor sets.
+/// A somewhat thin wrapped around [`vk::DescriptorSetLayout`]. This object also manages a chain of descriptor pools as
+/// well as known descriptor sets.
 /// 
 /// To instanciate this class, see [`DescriptorSetLayoutBuilder`]
 pub struct DescriptorSetLayout {
     context : RenderingContext,
     layout : vk::DescriptorSetLayout,
-    pool : vk::DescriptorPool,
+    pools : Vec<vk::DescriptorPool>,
+    pool_sizes : Vec<vk::DescriptorPoolSize>,
+    chain : PoolChain,
 
     // Store the info used to build this object.
     // TODO: Make this go away.
     info : DescriptorSetLayoutBuilder,
 
-    sets : HashMap<DescriptorSetInfo, vk::DescriptorSet>,
+    /// Known descriptor sets, along with the index of the pool they were allocated from.
+    sets : HashMap<DescriptorSetInfo, (vk::DescriptorSet, usize)>,
 }
 
 impl DescriptorSetLayout {
@@ -86,69 +126,121 @@ impl DescriptorSetLayout {
                 .create_descriptor_set_layout(&create_info, None)
                 .expect("Descriptor set layout creation failed");
 
-            let pool_create_info = vk::DescriptorPoolCreateInfo::default()
-                .max_sets(info.sets)
-                .pool_sizes(&pool_sizes)
-                .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET);
-
-            let pool = context.device.handle()
-                .create_descriptor_pool(&pool_create_info, None)
-                .expect("Descriptor pool creation failed");
-
             Self {
                 context : context.clone(),
                 layout,
-                pool,
+                pools : vec![],
+                pool_sizes,
+                chain : PoolChain::new(info.sets),
                 info,
                 sets : HashMap::new(),
             }
         }
     }
 
+    /// Returns a descriptor set matching the given description, allocating and writing it if it wasn't requested before.
+    ///
+    /// # Description
+    ///
+    /// Descriptor sets are allocated from a chain of descriptor pools. When every pool of the chain is exhausted, a new
+    /// pool is added to the chain, so this never fails because too many sets were requested.
     pub fn request(&mut self, info : DescriptorSetInfo) -> vk::DescriptorSet {
         assert!(!info.is_empty(), "Can't request an empty descriptor set");
 
-        unsafe {
-            let value = self.sets.get(&info);
-            match value {
-                Some(value) => *value,
+        if let Some((handle, _)) = self.sets.get(&info) {
+            return *handle;
+        }
+
+        let (handle, pool) = self.allocate();
+        self.update_sets(handle, &info);
+        self.sets.insert(info, (handle, pool));
+        handle
+    }
+
+    /// Returns the descriptor set matching the given description, if it was requested before and not forgotten since.
+    pub fn get(&self, info : &DescriptorSetInfo) -> Option<vk::DescriptorSet> {
+        self.sets.get(info).map(|(handle, _)| *handle)
+    }
+
+    /// Allocates a descriptor set from the first pool of the chain that isn't exhausted, growing the chain if needed.
+    fn allocate(&mut self) -> (vk::DescriptorSet, usize) {
+        loop {
+            let pool = match self.chain.available() {
+                Some(pool) => pool,
                 None => {
-                    let handle = self.context.device.handle()
-                        .allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::default()
-                            .descriptor_pool(self.pool)
-                            .set_layouts(&[self.layout])
-                        )
-                        .expect("Descriptor set allocation failed")[0];
-
-                    self.update_sets(handle, &info);
-                    self.sets.insert(info, handle.clone());
-                    handle
+                    self.pools.push(self.create_pool());
+                    self.chain.push()
                 }
+            };
+
+            let result = unsafe {
+                self.context.device.handle()
+                    .allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(self.pools[pool])
+                        .set_layouts(&[self.layout])
+                    )
+            };
+
+            match result {
+                Ok(sets) => {
+                    self.chain.allocate(pool);
+                    return (sets[0], pool);
+                },
+                // Pools can run out of memory before running out of sets, for example if they are fragmented.
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) if !self.chain.is_unused(pool) => {
+                    self.chain.exhaust(pool);
+                },
+                Err(err) => panic!("Descriptor set allocation failed: {:?}", err),
             }
         }
     }
 
+    fn create_pool(&self) -> vk::DescriptorPool {
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(self.info.sets)
+            .pool_sizes(&self.pool_sizes)
+            .flags(self.info.pool_flags | vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET);
+
+        unsafe {
+            self.context.device.handle()
+                .create_descriptor_pool(&create_info, None)
+                .expect("Descriptor pool creation failed")
+        }
+    }
+
     fn update_sets(&mut self, set : vk::DescriptorSet, info : &DescriptorSetInfo) {
         let capacity = info.buffers.len() + info.images.len();
         let mut write_sets = Vec::<vk::WriteDescriptorSet>::with_capacity(capacity);
 
+        let storage_alignment = self.context.device.physical_device.properties.limits.min_storage_buffer_offset_alignment;
         for (binding, info) in &info.buffers {
+            let (descriptor_type, _, count) = self.info.bindings[binding];
+            validate_array_range(*binding, count, 0, info.len());
+            assert!(descriptor_type != vk::DescriptorType::STORAGE_BUFFER || info.iter().all(|info| info.offset % storage_alignment == 0),
+                "Storage buffer offsets must be multiples of minStorageBufferOffsetAlignment");
+
             write_sets.push(vk::WriteDescriptorSet::default()
                 .dst_set(set)
                 .dst_binding(*binding)
                 .dst_array_element(0)
-                .descriptor_type(self.info.bindings[binding].0)
+                .descriptor_type(descriptor_type)
                 .buffer_info(&info[..])
             );
         }
 
-        for (binding, info) in &info.images {
+        for (binding, images) in &info.images {
+            let (descriptor_type, _, count) = self.info.bindings[binding];
+            let first_element = info.array_element(*binding);
+            validate_array_range(*binding, count, first_element, images.len());
+            assert!(descriptor_type != vk::DescriptorType::STORAGE_IMAGE || images.iter().all(|info| info.image_layout == vk::ImageLayout::GENERAL),
+                "Storage images must be bound in the GENERAL layout");
+
             write_sets.push(vk::WriteDescriptorSet::default()
                 .dst_set(set)
                 .dst_binding(*binding)
-                .dst_array_element(0)
-                .descriptor_type(self.info.bindings[binding].0)
-                .image_info(&info[..])
+                .dst_array_element(first_element)
+                .descriptor_type(descriptor_type)
+                .image_info(&images[..])
             );
         }
 
@@ -158,22 +250,65 @@ impl DescriptorSetLayout {
         }
     }
 
+    /// Frees a descriptor set previously returned by [`DescriptorSetLayout::request`].
+    ///
+    /// # Description
+    ///
+    /// This waits for the device to be idle, so that no command buffer still uses the set. Prefer
+    /// [`DescriptorSetLayout::free`] when the command buffers using the set are known to have completed.
     pub fn forget(&mut self, set : vk::DescriptorSet) {
         self.context.device.wait_idle();
 
         unsafe {
-            self.context.device.handle()
-                .free_descriptor_sets(self.pool, slice::from_ref(&set))
-                .expect("Failed to free a descriptor set");
+            self.free(set);
         }
     }
 
-    pub fn reset_pool(&self) {
+    /// Frees a descriptor set previously returned by [`DescriptorSetLayout::request`], without waiting for the
+    /// device.
+    ///
+    /// # Safety
+    ///
+    /// Every command buffer that used the set must have completed execution, for example because the fence of the
+    /// frame that last used it was signalled.
+    pub unsafe fn free(&mut self, set : vk::DescriptorSet) {
+        let mut pool = None;
+        self.sets.retain(|_, (handle, index)| {
+            if *handle == set {
+                pool = Some(*index);
+            }
+            *handle != set
+        });
+        let Some(pool) = pool else {
+            return;
+        };
+
+        self.context.device.handle()
+            .free_descriptor_sets(self.pools[pool], slice::from_ref(&set))
+            .expect("Failed to free a descriptor set");
+        self.chain.free(pool);
+    }
+
+    /// Frees every descriptor set allocated from this layout at once.
+    ///
+    /// # Description
+    ///
+    /// All pools of the chain are reset and kept around, so that sets requested afterwards reuse them instead of
+    /// creating new pools.
+    ///
+    /// Every [`vk::DescriptorSet`] previously returned by [`DescriptorSetLayout::request`] becomes dangling: it must
+    /// not be bound or freed anymore, and command buffers that still reference it must have completed execution.
+    pub fn reset(&mut self) {
         unsafe {
-            self.context.device.handle()
-                .reset_descriptor_pool(self.pool, vk::DescriptorPoolResetFlags::default())
-                .expect("Failed to reset descriptor pool.");
+            for pool in &self.pools {
+                self.context.device.handle()
+                    .reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::default())
+                    .expect("Failed to reset descriptor pool.");
+            }
         }
+
+        self.sets.clear();
+        self.chain.reset();
     }
 
     pub fn get_descriptor_type(&self, binding : u32) -> vk::DescriptorType {
@@ -190,10 +325,215 @@ impl Drop for DescriptorSetLayout {
         unsafe {
             self.context.device.handle()
                 .destroy_descriptor_set_layout(self.layout, None);
-            self.context.device.handle()
-                .destroy_descriptor_pool(self.pool, None);
+            for pool in &self.pools {
+                self.context.device.handle()
+                    .destroy_descriptor_pool(*pool, None);
+            }
         }
     }
 }
 
 make_handle! { DescriptorSetLayout, vk::DescriptorSetLayout, layout }
+
+/// Ensures that a write to a binding fits within the descriptors the binding declares.
+///
+/// # Arguments
+///
+/// * `binding` - The binding number.
+/// * `count` - The number of descriptors in the binding.
+/// * `first_element` - The first array element written.
+/// * `len` - The number of descriptors written.
+///
+/// # Panics
+///
+/// * Panics if the written range exceeds the binding.
+fn validate_array_range(binding : u32, count : u32, first_element : u32, len : usize) {
+    assert!(first_element as usize + len <= count as usize,
+        "Writing {len} descriptors from element {first_element} overflows binding {binding}, which holds {count} descriptors");
+}
+
+/// Keeps track of the amount of descriptor sets that can still be allocated from each pool of a chain.
+struct PoolChain {
+    capacity : u32,
+    free : Vec<u32>,
+}
+
+impl PoolChain {
+    /// Creates an empty chain of pools that can each hold `capacity` sets.
+    fn new(capacity : u32) -> Self {
+        Self { capacity, free : vec![] }
+    }
+
+    /// Returns the index of the first pool that sets can be allocated from, or `None` if all pools are exhausted.
+    fn available(&self) -> Option<usize> {
+        self.free.iter().position(|free| *free != 0)
+    }
+
+    /// Adds a pool to the chain and returns its index.
+    fn push(&mut self) -> usize {
+        self.free.push(self.capacity);
+        self.free.len() - 1
+    }
+
+    /// Returns true if no set is currently allocated from the given pool.
+    fn is_unused(&self, pool : usize) -> bool {
+        self.free[pool] == self.capacity
+    }
+
+    /// Records that a set was allocated from the given pool.
+    fn allocate(&mut self, pool : usize) {
+        self.free[pool] -= 1;
+    }
+
+    /// Records that a set allocated from the given pool was freed.
+    fn free(&mut self, pool : usize) {
+        self.free[pool] = (self.free[pool] + 1).min(self.capacity);
+    }
+
+    /// Records that the given pool can't allocate sets anymore, even though it isn't full.
+    fn exhaust(&mut self, pool : usize) {
+        self.free[pool] = 0;
+    }
+
+    /// Records that every set of every pool was freed.
+    fn reset(&mut self) {
+        self.free.fill(self.capacity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+
+    use ash::vk;
+
+    use crate::traits::handle::Handle;
+    use crate::vk::buffer::{DynamicBufferBuilder, DynamicInitializer};
+    use crate::vk::command_buffer::BarrierPhase;
+    use crate::vk::command_pool::CommandPool;
+    use crate::vk::descriptor::set::DescriptorSetInfo;
+    use crate::vk::pipeline::layout::PipelineLayoutInfo;
+    use crate::vk::pipeline::shader::Shader;
+
+    use super::{validate_array_range, DescriptorSetLayout, PoolChain};
+
+    #[test]
+    pub fn pool_chain_growth() {
+        let mut chain = PoolChain::new(2);
+        assert_eq!(chain.available(), None);
+
+        let first = chain.push();
+        chain.allocate(first);
+        chain.allocate(first);
+        assert_eq!(chain.available(), None);
+
+        let second = chain.push();
+        assert_eq!(second, 1);
+        chain.allocate(second);
+        assert_eq!(chain.available(), Some(second));
+
+        // Freeing a set makes the first pool available again.
+        chain.free(first);
+        assert_eq!(chain.available(), Some(first));
+
+        chain.exhaust(first);
+        assert_eq!(chain.available(), Some(second));
+        assert!(!chain.is_unused(second));
+    }
+
+    #[test]
+    pub fn pool_chain_reset() {
+        let mut chain = PoolChain::new(4);
+
+        let allocate = |chain : &mut PoolChain| {
+            let pool = chain.available().unwrap_or_else(|| chain.push());
+            chain.allocate(pool);
+        };
+
+        for _ in 0..8 {
+            allocate(&mut chain);
+        }
+        assert_eq!(chain.free, [0, 0]);
+
+        // After a reset, the same amount of sets fits in the existing pools.
+        chain.reset();
+        assert!(chain.is_unused(0) && chain.is_unused(1));
+        for _ in 0..8 {
+            allocate(&mut chain);
+        }
+        assert_eq!(chain.free, [0, 0]);
+    }
+
+    #[test]
+    pub fn array_ranges() {
+        validate_array_range(0, 16, 0, 16);
+        validate_array_range(0, 16, 12, 4);
+        validate_array_range(0, 1, 0, 1);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn array_range_overflow() {
+        validate_array_range(0, 16, 12, 5);
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn storage_buffer_readback() {
+        use crate::testing::{submit_and_wait, ShaderFile};
+
+        let (_event_loop, app) = crate::testing::test_application();
+        let context = &app.orchestrator.context;
+
+        let source = ShaderFile::new("storage_buffer_readback.comp", "#version 450\n\
+            layout(local_size_x = 64) in;\n\
+            layout(std430, set = 0, binding = 0) buffer Values { uint values[]; };\n\
+            void main() { values[gl_GlobalInvocationID.x] *= 2; }\n");
+        let shader = Shader::new(context, source.path().to_path_buf(), vk::ShaderStageFlags::COMPUTE);
+
+        let mut layout = DescriptorSetLayout::builder()
+            .sets(1)
+            .storage_buffer(0, vk::ShaderStageFlags::COMPUTE, 1)
+            .build(context);
+        let pipeline_layout = PipelineLayoutInfo::default().layout(&layout).build(context);
+
+        // There is no compute pipeline abstraction yet.
+        let entry_point = CString::new("main").unwrap();
+        let pipeline = unsafe {
+            context.device.handle().create_compute_pipelines(vk::PipelineCache::null(), &[vk::ComputePipelineCreateInfo::default()
+                .stage(vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(shader.handle())
+                    .name(&entry_point))
+                .layout(pipeline_layout.handle())
+            ], None).unwrap()[0]
+        };
+
+        let pool = CommandPool::builder(&context.graphics_queue).build(context);
+        let mut values = DynamicBufferBuilder::dynamic()
+            .cpu_to_gpu()
+            .linear(true)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .build(context, &pool, &(0..64u32).collect::<Vec<_>>());
+        let set = layout.request(DescriptorSetInfo::default()
+            .storage_buffers(0, vec![vk::DescriptorBufferInfo::default().buffer(values.handle()).range(vk::WHOLE_SIZE)]));
+
+        submit_and_wait(context, &pool, |cmd| {
+            unsafe {
+                context.device.handle().cmd_bind_pipeline(cmd.handle(), vk::PipelineBindPoint::COMPUTE, pipeline);
+                context.device.handle().cmd_bind_descriptor_sets(cmd.handle(), vk::PipelineBindPoint::COMPUTE, pipeline_layout.handle(), 0, &[set], &[]);
+            }
+            cmd.dispatch(1, 1, 1);
+            cmd.buffer_memory_barrier(&values,
+                BarrierPhase::ignore_queue(vk::AccessFlags::SHADER_WRITE, vk::PipelineStageFlags::COMPUTE_SHADER),
+                BarrierPhase::ignore_queue(vk::AccessFlags::HOST_READ, vk::PipelineStageFlags::HOST),
+                vk::DependencyFlags::empty());
+        });
+        unsafe {
+            context.device.handle().destroy_pipeline(pipeline, None);
+        }
+
+        assert_eq!(&*values.map_slice::<u32>(), &(0..64u32).map(|value| value * 2).collect::<Vec<_>>()[..]);
+    }
+}
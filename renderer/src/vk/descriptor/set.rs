@@ -7,6 +7,8 @@ use nohash_hasher::IntMap;
 pub struct DescriptorSetInfo {
     pub buffers : IntMap<u32, Vec<vk::DescriptorBufferInfo>>,
     pub images : IntMap<u32, Vec<vk::DescriptorImageInfo>>,
+    /// The first array element written in each binding, if not zero.
+    pub array_elements : IntMap<u32, u32>,
 }
 
 impl DescriptorSetInfo {
@@ -20,6 +22,66 @@ impl DescriptorSetInfo {
         self
     }
 
+    /// Binds uniform buffers to the given slot.
+    /// 
+    /// # Description
+    /// 
+    /// The buffers must have been created with [`vk::BufferUsageFlags::UNIFORM_BUFFER`] (see
+    /// [`UniformBuffer`](crate::vk::buffer::UniformBuffer)), and the slot must be declared as a
+    /// [`vk::DescriptorType::UNIFORM_BUFFER`] binding in the layout.
+    pub fn uniform_buffers(self, slot : u32, infos : Vec<vk::DescriptorBufferInfo>) -> Self {
+        self.buffers(slot, infos)
+    }
+
+    /// Binds storage buffers to the given slot.
+    /// 
+    /// # Description
+    /// 
+    /// The buffers must have been created with [`vk::BufferUsageFlags::STORAGE_BUFFER`], and the slot must be declared as
+    /// a [`vk::DescriptorType::STORAGE_BUFFER`] binding in the layout.
+    pub fn storage_buffers(self, slot : u32, infos : Vec<vk::DescriptorBufferInfo>) -> Self {
+        self.buffers(slot, infos)
+    }
+
+    /// Binds storage images to the given slot.
+    /// 
+    /// # Description
+    /// 
+    /// Storage images are accessed in the [`vk::ImageLayout::GENERAL`] layout, which this function enforces on every
+    /// element of `infos`. The images must have been created with [`vk::ImageUsageFlags::STORAGE`], and the slot must
+    /// be declared as a [`vk::DescriptorType::STORAGE_IMAGE`] binding in the layout.
+    pub fn storage_images(mut self, slot : u32, infos : Vec<vk::DescriptorImageInfo>) -> Self {
+        let infos = infos.into_iter()
+            .map(|info| info.image_layout(vk::ImageLayout::GENERAL))
+            .collect();
+
+        self.images.insert(slot, infos);
+        self
+    }
+
+    /// Writes a contiguous range of an array of images.
+    /// 
+    /// # Description
+    /// 
+    /// The slot must be declared in the layout with at least `start_index + infos.len()` descriptors, which shaders
+    /// index into. The elements of the array outside of the range are left untouched.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `slot` - The binding of the array.
+    /// * `start_index` - The index of the first array element to write.
+    /// * `infos` - The images written to the array, in order.
+    pub fn image_array(mut self, slot : u32, start_index : u32, infos : &[vk::DescriptorImageInfo]) -> Self {
+        self.images.insert(slot, infos.to_vec());
+        self.array_elements.insert(slot, start_index);
+        self
+    }
+
+    /// Returns the index of the first array element written in the given slot.
+    pub fn array_element(&self, slot : u32) -> u32 {
+        self.array_elements.get(&slot).copied().unwrap_or_default()
+    }
+
     pub fn is_empty(&self) -> bool { self.images.is_empty() && self.buffers.is_empty() }
 }
 
@@ -40,6 +102,7 @@ impl Hash for DescriptorSetInfo {
                 d.image_view.hash(state);
                 d.sampler.hash(state);
             }
+            self.array_element(*k).hash(state);
         }
     }
 }
@@ -88,8 +151,52 @@ impl PartialEq for DescriptorSetInfo {
             vec_eq(left, right, |left, right| {
                 left.image_layout == right.image_layout && left.image_view == right.image_view && left.sampler == right.sampler
             })
-        })
+        }) && self.images.keys().all(|slot| self.array_element(*slot) == other.array_element(*slot))
     }
 }
 
-impl Eq for DescriptorSetInfo { }
\ No newline at end of file
+impl Eq for DescriptorSetInfo { }
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::DescriptorSetInfo;
+
+    #[test]
+    pub fn storage_buffers() {
+        let info = DescriptorSetInfo::default()
+            .storage_buffers(1, vec![
+                vk::DescriptorBufferInfo::default().offset(256).range(vk::WHOLE_SIZE),
+            ]);
+
+        assert!(info.images.is_empty());
+        assert_eq!(info.buffers[&1][0].offset, 256);
+        assert!(!info.is_empty());
+    }
+
+    #[test]
+    pub fn storage_images() {
+        let info = DescriptorSetInfo::default()
+            .storage_images(3, vec![
+                vk::DescriptorImageInfo::default().image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                vk::DescriptorImageInfo::default(),
+            ]);
+
+        assert!(info.buffers.is_empty());
+        assert!(info.images[&3].iter().all(|info| info.image_layout == vk::ImageLayout::GENERAL));
+    }
+
+    #[test]
+    pub fn image_arrays() {
+        let images = [vk::DescriptorImageInfo::default().image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL); 4];
+        let info = DescriptorSetInfo::default().image_array(2, 8, &images);
+
+        assert_eq!(info.images[&2].len(), 4);
+        assert_eq!((info.array_element(2), info.array_element(0)), (8, 0));
+
+        // The same images written at another offset describe another set.
+        assert!(info != DescriptorSetInfo::default().image_array(2, 4, &images));
+        assert!(info == DescriptorSetInfo::default().image_array(2, 8, &images));
+    }
+}
@@ -0,0 +1,63 @@
+use ash::vk;
+
+use crate::orchestration::rendering::RenderingContext;
+
+/// A pool of fences, modeled after [`SemaphorePool`](crate::vk::semaphore_pool::SemaphorePool).
+///
+/// Fences handed out by this pool are unsignaled. Once they are no longer in use, they must be given back to the pool,
+/// which resets them so they can be reused.
+pub struct FencePool {
+    context : RenderingContext,
+    handles : Vec<vk::Fence>,
+    available : Vec<vk::Fence>,
+}
+
+impl FencePool {
+    pub fn new(context : &RenderingContext) -> Self {
+        FencePool {
+            context : context.clone(),
+            handles : Vec::new(),
+            available : Vec::new(),
+        }
+    }
+
+    /// Requests an unsignaled fence from the pool. If no fence is available, a new fence will be created and managed.
+    pub fn acquire(&mut self) -> vk::Fence {
+        match self.available.pop() {
+            Some(fence) => fence,
+            None => {
+                let fence = self.context.device.create_fence(vk::FenceCreateFlags::empty(),
+                    format!("Pooled fence {}", self.handles.len()).into());
+
+                self.handles.push(fence);
+                fence
+            }
+        }
+    }
+
+    /// Returns a fence to the pool. The fence is reset, and must therefore not be in use by any pending submission.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the fence was not created by this pool.
+    pub fn release(&mut self, fence : vk::Fence) {
+        assert!(self.handles.contains(&fence), "This fence does not belong to this pool");
+        debug_assert!(!self.available.contains(&fence), "This fence was already returned to the pool");
+
+        self.context.device.reset_fences(&[fence]);
+        self.available.push(fence);
+    }
+
+    /// Returns the number of fences currently handed out by this pool.
+    pub fn get_active_count(&self) -> usize {
+        self.handles.len() - self.available.len()
+    }
+}
+
+impl Drop for FencePool {
+    fn drop(&mut self) {
+        for fence in self.handles.drain(..) {
+            self.context.device.destroy_fence(fence);
+        }
+    }
+}
@@ -38,8 +38,8 @@ impl FrameData {
             semaphore_pool : SemaphorePool::new(context),
             graphics_command_pool,
             cmd,
-            image_available : context.device.create_semaphore(),
-            render_finished : context.device.create_semaphore(),
+            image_available : context.device.create_semaphore(format!("Image available semaphore {}", index).into()),
+            render_finished : context.device.create_semaphore(format!("Render finished semaphore {}", index).into()),
         }
     }
 
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use ash::vk;
 use crate::make_handle;
 use crate::orchestration::rendering::RenderingContext;
@@ -29,4 +32,84 @@ impl Drop for Framebuffer {
             self.context.device.handle().destroy_framebuffer(self.handle, None);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Identifies a framebuffer by everything it was created from.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FramebufferKey {
+    render_pass : vk::RenderPass,
+    attachments : Vec<vk::ImageView>,
+    extent : vk::Extent2D,
+    layers : u32,
+}
+
+/// Creates framebuffers on demand and shares them between their users.
+///
+/// # Description
+///
+/// Framebuffers are identified by their render pass, attachments, extent and layer count; requesting a framebuffer
+/// that was already created returns the existing one. The cache only holds a reference to each framebuffer: a
+/// framebuffer is destroyed once it was evicted from the cache and nothing else uses it anymore.
+///
+/// Vulkan implementations may reuse the handles of destroyed objects, so framebuffers must be evicted before the image
+/// views or render passes they were created from are destroyed, with [`FramebufferCache::evict_views`] or
+/// [`FramebufferCache::evict_unused`].
+///
+/// The cache does not keep the rendering context alive, so that the logical device can be recreated once every
+/// framebuffer was evicted.
+#[derive(Default)]
+pub struct FramebufferCache {
+    framebuffers : HashMap<FramebufferKey, Arc<Framebuffer>>,
+}
+
+impl FramebufferCache {
+    /// Returns a framebuffer with the given properties, creating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context, used to create the framebuffer if it does not exist yet.
+    /// * `render_pass` - The render pass the framebuffer is compatible with.
+    /// * `attachments` - The image views bound to each attachment of the render pass.
+    /// * `extent` - The dimensions of the framebuffer.
+    /// * `layers` - The amount of layers of the framebuffer.
+    pub fn get(&mut self, context : &RenderingContext, render_pass : vk::RenderPass, attachments : &[vk::ImageView], extent : vk::Extent2D, layers : u32) -> Arc<Framebuffer> {
+        let key = FramebufferKey {
+            render_pass,
+            attachments : attachments.to_vec(),
+            extent,
+            layers,
+        };
+
+        self.framebuffers.entry(key)
+            .or_insert_with(|| Arc::new(Framebuffer::new(context, vk::FramebufferCreateInfo::default()
+                .render_pass(render_pass)
+                .attachments(attachments)
+                .width(extent.width)
+                .height(extent.height)
+                .layers(layers)
+            )))
+            .clone()
+    }
+
+    /// Evicts every framebuffer that uses any of the given image views. This must be called before these views are
+    /// destroyed.
+    pub fn evict_views(&mut self, views : &[vk::ImageView]) {
+        self.framebuffers.retain(|key, _| !key.attachments.iter().any(|view| views.contains(view)));
+    }
+
+    /// Evicts every framebuffer that is only referenced by this cache.
+    pub fn evict_unused(&mut self) {
+        self.framebuffers.retain(|_, framebuffer| Arc::strong_count(framebuffer) > 1);
+    }
+
+    /// Evicts every framebuffer.
+    pub fn clear(&mut self) {
+        self.framebuffers.clear();
+    }
+
+    /// Returns the amount of framebuffers in this cache.
+    #[inline] pub fn len(&self) -> usize { self.framebuffers.len() }
+
+    /// Returns true if this cache holds no framebuffer.
+    #[inline] pub fn is_empty(&self) -> bool { self.framebuffers.is_empty() }
+}
@@ -5,11 +5,7 @@ use crate::vk::image::Image;
 
 pub fn prepare_buffer_image_copy(image : &Image, mip_level : u32) -> vk::BufferImageCopy {
     vk::BufferImageCopy::default()
-        .image_subresource(vk::ImageSubresourceLayers::default()
-            .aspect_mask(image.aspect())
-            .base_array_layer(image.base_array_layer())
-            .layer_count(image.layer_count())
-            .mip_level(mip_level))
+        .image_subresource(image.make_subresource_layer(mip_level, None, None))
 }
 
 pub fn with_delta(delta : &ImageDelta, copy : vk::BufferImageCopy) -> vk::BufferImageCopy {
@@ -1,4 +1,5 @@
 use std::ops::Range;
+use std::sync::Arc;
 
 use ash::vk;
 use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc};
@@ -6,12 +7,13 @@ use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc};
 use crate::orchestration::rendering::RenderingContext;
 use crate::make_handle;
 
-use super::command_buffer::CommandBuffer;
+use super::buffer::Buffer;
+use super::command_buffer::{BarrierPhase, CommandBuffer};
 
 pub struct Image {
     context : RenderingContext,
     handle : vk::Image,
-    allocation : Option<Allocation>,
+    memory : ImageMemory,
     view : vk::ImageView,
 
     levels : Range<u32>,
@@ -23,6 +25,34 @@ pub struct Image {
     sample_count : vk::SampleCountFlags,
 }
 
+/// The memory backing an [`Image`].
+enum ImageMemory {
+    /// Memory owned by the image.
+    Owned(Allocation),
+    /// Memory shared with other images, freed once the last of them is dropped.
+    Aliased(Arc<AliasedMemory>),
+    /// Memory owned by the presentation engine; the image itself is destroyed with the swapchain.
+    Swapchain,
+}
+
+/// An allocation shared by images whose contents are never needed at the same time.
+struct AliasedMemory {
+    context : RenderingContext,
+    allocation : Option<Allocation>,
+}
+
+impl Drop for AliasedMemory {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            self.context.allocator()
+                .lock()
+                .unwrap()
+                .free(allocation)
+                .expect("Failed to free memory");
+        }
+    }
+}
+
 pub struct ImageCreateInfo {
     aspect : vk::ImageAspectFlags,
     levels : [u32; 2],
@@ -103,25 +133,12 @@ impl ImageCreateInfo {
 
     pub fn build(self, context : &RenderingContext) -> Image {
         unsafe {
-            let image = vk::ImageCreateInfo::default()
-                .image_type(self.image_type)
-                .format(self.format)
-                .extent(self.extent)
-                .mip_levels(self.levels[1])
-                .array_layers(self.layers[1])
-                .samples(self.samples)
-                .tiling(self.tiling)
-                .usage(self.usage)
-                .sharing_mode(self.sharing_mode);
-
-            let image = context.device.handle()
-                .create_image(&image, None)
-                .expect("Image creation failed");
+            let image = self.create_image(context);
 
             let requirements = context.device.handle()
                 .get_image_memory_requirements(image);
 
-            let allocation = context.device.allocator()
+            let allocation = context.allocator()
                 .lock()
                 .expect("Failed to obtain allocator")
                 .allocate(&AllocationCreateDesc {
@@ -138,36 +155,113 @@ impl ImageCreateInfo {
                 .bind_image_memory(image, allocation.memory(), allocation.offset())
                 .expect("Memory binding failed");
 
-            let image_view = vk::ImageViewCreateInfo::default()
-                .format(self.format)
-                .view_type(self.image_view_type)
-                .image(image)
-                .subresource_range(vk::ImageSubresourceRange::default()
-                    .aspect_mask(self.aspect)
-                    .base_mip_level(self.levels[0])
-                    .level_count(self.levels[1])
-                    .base_array_layer(self.layers[0])
-                    .layer_count(self.layers[1])
-                );
-
-            let image_view = context.device.handle()
-                .create_image_view(&image_view, None)
-                .expect("Image view creation failed");
-            context.device.set_handle_name(image_view, &format!("View/{}", self.name));
-            
-            Image {
-                context : context.clone(),
-                handle : image,
-                allocation : Some(allocation),
-                view : image_view,
-                levels : Range { start : self.levels[0], end : self.levels[0] + self.levels[1] },
-                layers : Range { start : self.layers[0], end : self.layers[0] + self.layers[1] },
-                layout : self.initial_layout,
-                format : self.format,
-                extent : self.extent,
-                aspect : self.aspect,
-                sample_count : self.samples,
+            self.create_view(context, image, ImageMemory::Owned(allocation))
+        }
+    }
+
+    /// Creates images that share a single memory allocation.
+    ///
+    /// # Description
+    ///
+    /// Writing to one of the images overwrites the contents of the others, so their contents must never be needed at
+    /// the same time, and each of them must be transitioned from [`vk::ImageLayout::UNDEFINED`] before its first use.
+    /// The memory is freed once every image is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `infos` - The images to create.
+    /// * `context` - The rendering context.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if `infos` is empty.
+    /// * Panics if no memory type can back every image.
+    pub fn build_aliased(infos : Vec<ImageCreateInfo>, context : &RenderingContext) -> Vec<Image> {
+        assert!(!infos.is_empty(), "At least one image is needed to allocate aliased memory");
+
+        unsafe {
+            let images = infos.iter()
+                .map(|info| info.create_image(context))
+                .collect::<Vec<_>>();
+
+            let requirements = aliased_requirements(images.iter()
+                .map(|image| context.device.handle().get_image_memory_requirements(*image)));
+
+            let names = infos.iter().map(|info| info.name.as_str()).collect::<Vec<_>>().join(", ");
+            let allocation = context.allocator()
+                .lock()
+                .expect("Failed to obtain allocator")
+                .allocate(&AllocationCreateDesc {
+                    name : format!("Allocation/Aliased({})", names).as_str(),
+                    requirements,
+                    location: gpu_allocator::MemoryLocation::GpuOnly,
+                    linear: false,
+                    allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged
+                })
+                .expect("Memory allocation failed");
+
+            for image in &images {
+                context.device.handle()
+                    .bind_image_memory(*image, allocation.memory(), allocation.offset())
+                    .expect("Memory binding failed");
             }
+
+            let memory = Arc::new(AliasedMemory { context : context.clone(), allocation : Some(allocation) });
+            infos.into_iter().zip(images)
+                .map(|(info, image)| info.create_view(context, image, ImageMemory::Aliased(memory.clone())))
+                .collect()
+        }
+    }
+
+    /// Creates the image, without binding any memory to it.
+    unsafe fn create_image(&self, context : &RenderingContext) -> vk::Image {
+        let image = vk::ImageCreateInfo::default()
+            .image_type(self.image_type)
+            .format(self.format)
+            .extent(self.extent)
+            .mip_levels(self.levels[1])
+            .array_layers(self.layers[1])
+            .samples(self.samples)
+            .tiling(self.tiling)
+            .usage(self.usage)
+            .sharing_mode(self.sharing_mode);
+
+        context.device.handle()
+            .create_image(&image, None)
+            .expect("Image creation failed")
+    }
+
+    /// Creates the view of an image whose memory is bound, and wraps both.
+    unsafe fn create_view(self, context : &RenderingContext, image : vk::Image, memory : ImageMemory) -> Image {
+        let image_view = vk::ImageViewCreateInfo::default()
+            .format(self.format)
+            .view_type(self.image_view_type)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange::default()
+                .aspect_mask(self.aspect)
+                .base_mip_level(self.levels[0])
+                .level_count(self.levels[1])
+                .base_array_layer(self.layers[0])
+                .layer_count(self.layers[1])
+            );
+
+        let image_view = context.device.handle()
+            .create_image_view(&image_view, None)
+            .expect("Image view creation failed");
+        context.device.set_handle_name(image_view, &format!("View/{}", self.name));
+        
+        Image {
+            context : context.clone(),
+            handle : image,
+            memory,
+            view : image_view,
+            levels : Range { start : self.levels[0], end : self.levels[0] + self.levels[1] },
+            layers : Range { start : self.layers[0], end : self.layers[0] + self.layers[1] },
+            layout : self.initial_layout,
+            format : self.format,
+            extent : self.extent,
+            aspect : self.aspect,
+            sample_count : self.samples,
         }
     }
 }
@@ -214,7 +308,7 @@ impl Image { // Construction
                         height: extent.height,
                         depth: 1
                     },
-                    allocation: None,
+                    memory: ImageMemory::Swapchain,
                     view: image_view,
                     format,
                     layout: vk::ImageLayout::UNDEFINED,
@@ -245,31 +339,46 @@ impl Image { // Getters
     /// 
     /// # Arguments
     /// 
-    /// * `mip_level` - The mimap level to identify.
-    /// * `layers`- The base layer and the amount of layers to access. If this parameter is not specified, all available layers will be accessed.
-    /// * `aspect_mask` - An optional parameter specifying which components of this image to access.
+    /// * `mip_level` - The mimap level to identify. It is clamped to the levels of this image.
+    /// * `layers`- The range of layers to access, clamped to the layers of this image. If this parameter is not specified, all available layers will be accessed.
+    /// * `aspect_mask` - An optional parameter specifying which components of this image to access. Defaults to all of them.
     pub fn make_subresource_layer(&self, mip_level : u32, layers : Option<Range<u32>>, aspect_mask : Option<vk::ImageAspectFlags>) -> vk::ImageSubresourceLayers {
-        let base_array_layer = match layers {
-            Some(ref layers) => u32::clamp(layers.start, self.layers.start, self.layers.end),
-            None => self.layers.start,
-        };
-
-        let end_array_layer = match layers {
-            Some(ref layers) => u32::clamp(layers.end, base_array_layer + 1, self.layers.end),
-            None => self.layers.end
-        };
-        let layer_count = end_array_layer - base_array_layer;
-        
-        assert_ne!(layer_count, 0, "Impossible layers requested or invalid image setup");
-
-        vk::ImageSubresourceLayers::default()
-            .aspect_mask(self.aspect)
-            .mip_level(mip_level.clamp(self.base_mip_level(), self.base_mip_level() + self.level_count()))
-            .base_array_layer(base_array_layer)
-            .layer_count(layer_count)
+        subresource_layers(&self.levels, &self.layers, aspect_mask.unwrap_or(self.aspect), mip_level, layers)
     }
 }
 
+/// Builds a structure specifying access to subresource layers of an image.
+///
+/// # Arguments
+///
+/// * `levels` - The mip levels of the image.
+/// * `layers` - The array layers of the image.
+/// * `aspect_mask` - The components of the image to access.
+/// * `mip_level` - The mip level to access, clamped to `levels`.
+/// * `requested_layers` - The array layers to access, clamped to `layers`. Defaults to all of them.
+///
+/// # Panics
+///
+/// * Panics if the image has no level or no layer.
+fn subresource_layers(levels : &Range<u32>, layers : &Range<u32>, aspect_mask : vk::ImageAspectFlags, mip_level : u32, requested_layers : Option<Range<u32>>) -> vk::ImageSubresourceLayers {
+    assert!(!levels.is_empty() && !layers.is_empty(), "Impossible layers requested or invalid image setup");
+
+    let (base_array_layer, end_array_layer) = match requested_layers {
+        Some(requested) => {
+            // At least one layer is always accessed.
+            let base = requested.start.clamp(layers.start, layers.end - 1);
+            (base, requested.end.clamp(base + 1, layers.end))
+        },
+        None => (layers.start, layers.end),
+    };
+
+    vk::ImageSubresourceLayers::default()
+        .aspect_mask(aspect_mask)
+        .mip_level(mip_level.clamp(levels.start, levels.end - 1))
+        .base_array_layer(base_array_layer)
+        .layer_count(end_array_layer - base_array_layer)
+}
+
 impl Image { // Utilities
     pub fn derive_aspect_flags(layout : vk::ImageLayout, format : vk::Format) -> vk::ImageAspectFlags {
         let mut aspect_flags = vk::ImageAspectFlags::COLOR;
@@ -284,94 +393,213 @@ impl Image { // Utilities
         aspect_flags
     }
 
-    /// Records a layout transition for this image.
+    /// Records the upload of the contents of a buffer to the base mip level of this image, and makes them available
+    /// to shaders.
+    ///
+    /// # Description
+    ///
+    /// The image is transitioned to [`vk::ImageLayout::TRANSFER_DST_OPTIMAL`] for the copy, then to
+    /// [`vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL`]. The buffer must contain tightly packed texels for all array
+    /// layers of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The command buffer on which the commands will be recorded.
+    /// * `buffer` - The buffer holding the texels.
+    /// * `stage` - The pipeline stage that samples the image. Defaults to [`vk::PipelineStageFlags::FRAGMENT_SHADER`].
+    pub fn upload_from_buffer(&mut self, cmd : &CommandBuffer, buffer : &Buffer, stage : Option<vk::PipelineStageFlags>) {
+        cmd.image_memory_barrier(self,
+            BarrierPhase::ignore_queue(vk::AccessFlags::NONE, vk::PipelineStageFlags::TOP_OF_PIPE),
+            BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            vk::DependencyFlags::BY_REGION,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL
+        );
+
+        cmd.copy_buffer_to_image(buffer, self, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[
+            vk::BufferImageCopy::default()
+                .image_subresource(self.make_subresource_layer(self.base_mip_level(), None, None))
+                .image_extent(self.extent)
+        ]);
+
+        cmd.image_memory_barrier(self,
+            BarrierPhase::ignore_queue(vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+            BarrierPhase::ignore_queue(vk::AccessFlags::SHADER_READ, stage.unwrap_or(vk::PipelineStageFlags::FRAGMENT_SHADER)),
+            vk::DependencyFlags::BY_REGION,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+
+    /// Records a layout transition for this image, from its current layout.
+    ///
+    /// # Description
+    ///
+    /// Access masks and pipeline stages are derived from the layouts on a best-effort basis; unknown layouts are
+    /// synchronized conservatively against all commands. For finer control, use
+    /// [`CommandBuffer::image_memory_barrier`] directly.
     ///
     /// # Arguments
     ///
     /// * `cmd` - The command buffer on which the command will be recorded.
-    /// * `from` - The old layout.
     /// * `to` - The new layout.
-    /// * `mip_levels` - The mipmap levels that should be transitioned.
-    pub fn layout_transition(&self, cmd : &CommandBuffer, from : vk::ImageLayout, to : vk::ImageLayout, flags : vk::DependencyFlags) {
-        let aspect_flags = Image::derive_aspect_flags(to, self.format);
-
-        let src_access_mask = match from {
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags::TRANSFER_WRITE,
-            vk::ImageLayout::PREINITIALIZED => vk::AccessFlags::HOST_WRITE,
-            vk::ImageLayout::GENERAL => vk::AccessFlags::MEMORY_WRITE | vk::AccessFlags::SHADER_WRITE,
-            _ => vk::AccessFlags::default(),
-        };
-
-        let dst_access_mask = match to {
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-                => vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-            vk::ImageLayout::GENERAL => vk::AccessFlags::empty(),
-            vk::ImageLayout::PRESENT_SRC_KHR => vk::AccessFlags::empty(),
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags::SHADER_READ,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags::TRANSFER_READ,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags::TRANSFER_WRITE,
-            _ => panic!("Incomprehensible layout transition"),
-        };
-
-        let src_stage = match from {
-            vk::ImageLayout::GENERAL => vk::PipelineStageFlags::ALL_COMMANDS,
-            vk::ImageLayout::PREINITIALIZED => vk::PipelineStageFlags::HOST,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::PipelineStageFlags::TRANSFER,
-            vk::ImageLayout::UNDEFINED => vk::PipelineStageFlags::TOP_OF_PIPE,
-            _ => vk::PipelineStageFlags::ALL_COMMANDS,
-        };
-
-        let dst_stage = match to {
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            vk::ImageLayout::GENERAL => vk::PipelineStageFlags::HOST,
-            vk::ImageLayout::PRESENT_SRC_KHR => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::PipelineStageFlags::FRAGMENT_SHADER,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::PipelineStageFlags::TRANSFER,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::PipelineStageFlags::TRANSFER,
-            _ => vk::PipelineStageFlags::ALL_COMMANDS,
-        };
-
-        let barrier = vk::ImageMemoryBarrier::default()
-            .image(self.handle)
-            .src_access_mask(src_access_mask)
-            .dst_access_mask(dst_access_mask)
-            .new_layout(to)
-            .old_layout(from)
-            .subresource_range(vk::ImageSubresourceRange::default()
-                .aspect_mask(aspect_flags)
-                .base_array_layer(self.layers.start)
-                .layer_count(self.layer_count())
-                .base_mip_level(self.levels.start)
-                .level_count(self.level_count()));
+    /// * `flags` - Dependency flags of the barrier.
+    pub fn layout_transition(&mut self, cmd : &CommandBuffer, to : vk::ImageLayout, flags : vk::DependencyFlags) {
+        let src = source_phase(self.layout);
+        let dst = destination_phase(to);
 
-        unsafe {
-            cmd.pipeline_barrier(src_stage,
-                dst_stage,
-                flags,
-                &[],
-                &[],
-                &[barrier]);
-        }
+        cmd.image_memory_barrier(self, src, dst, flags, to);
     }
 }
 
+/// Returns the accesses that must be made available, and the stages that must complete, before an image leaves the
+/// given layout.
+pub(in crate) fn source_phase(layout : vk::ImageLayout) -> BarrierPhase {
+    let (access, stage) = match layout {
+        vk::ImageLayout::UNDEFINED => (vk::AccessFlags::NONE, vk::PipelineStageFlags::TOP_OF_PIPE),
+        vk::ImageLayout::PREINITIALIZED => (vk::AccessFlags::HOST_WRITE, vk::PipelineStageFlags::HOST),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            => (vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            => (vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE, vk::PipelineStageFlags::LATE_FRAGMENT_TESTS),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+            => (vk::AccessFlags::NONE, vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            => (vk::AccessFlags::NONE, vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (vk::AccessFlags::NONE, vk::PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::PRESENT_SRC_KHR => (vk::AccessFlags::NONE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+        _ => (vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS),
+    };
+
+    BarrierPhase::ignore_queue(access, stage)
+}
+
+/// Returns the accesses, and the stages performing them, that must wait for an image to enter the given layout.
+pub(in crate) fn destination_phase(layout : vk::ImageLayout) -> BarrierPhase {
+    let (access, stage) = match layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            => (vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            => (vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE, vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+            => (vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            => (vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::PRESENT_SRC_KHR => (vk::AccessFlags::NONE, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+        _ => (vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS),
+    };
+
+    BarrierPhase::ignore_queue(access, stage)
+}
+
+/// Returns the memory requirements of an allocation shared by images with the given requirements.
+///
+/// # Panics
+///
+/// * Panics if no memory type satisfies every requirement.
+fn aliased_requirements(requirements : impl Iterator<Item = vk::MemoryRequirements>) -> vk::MemoryRequirements {
+    let requirements = requirements.fold(vk::MemoryRequirements { memory_type_bits : u32::MAX, ..Default::default() }, |merged, requirements| {
+        vk::MemoryRequirements {
+            size : merged.size.max(requirements.size),
+            alignment : merged.alignment.max(requirements.alignment),
+            memory_type_bits : merged.memory_type_bits & requirements.memory_type_bits,
+        }
+    });
+
+    assert_ne!(requirements.memory_type_bits, 0, "No memory type can back every aliased image");
+    requirements
+}
+
 make_handle! { Image, vk::Image }
 
 impl Drop for Image {
     fn drop(&mut self) {
         unsafe {
             self.context.device.handle().destroy_image_view(self.view, None);
-            if self.allocation.is_some() {
-                self.context.device.handle().destroy_image(self.handle, None);
-
-                self.context.device.allocator()
-                    .lock()
-                    .unwrap()
-                    .free(self.allocation.take().unwrap_unchecked())
-                    .expect("Failed to free memory");
+            match std::mem::replace(&mut self.memory, ImageMemory::Swapchain) {
+                ImageMemory::Owned(allocation) => {
+                    self.context.device.handle().destroy_image(self.handle, None);
+
+                    self.context.allocator()
+                        .lock()
+                        .unwrap()
+                        .free(allocation)
+                        .expect("Failed to free memory");
+                },
+                // The memory is freed when the last image using it is dropped, after this image is destroyed.
+                ImageMemory::Aliased(_memory) => self.context.device.handle().destroy_image(self.handle, None),
+                ImageMemory::Swapchain => (),
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::{aliased_requirements, destination_phase, source_phase, subresource_layers};
+
+    #[test]
+    pub fn layout_transition_phases() {
+        let general = destination_phase(vk::ImageLayout::GENERAL);
+        assert_eq!(general.2, vk::PipelineStageFlags::ALL_COMMANDS);
+        assert!(general.1.contains(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE));
+
+        let depth_read = destination_phase(vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL);
+        assert!(depth_read.1.contains(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ));
+        assert!(!depth_read.1.contains(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE));
+
+        let transfer = source_phase(vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!((transfer.1, transfer.2), (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER));
+
+        // Layouts without a dedicated mapping are synchronized conservatively instead of panicking.
+        let unknown = destination_phase(vk::ImageLayout::ATTACHMENT_FEEDBACK_LOOP_OPTIMAL_EXT);
+        assert_eq!(unknown.2, vk::PipelineStageFlags::ALL_COMMANDS);
+        assert_eq!(source_phase(vk::ImageLayout::UNDEFINED).2, vk::PipelineStageFlags::TOP_OF_PIPE);
+    }
+
+    #[test]
+    pub fn subresource_layer_clamping() {
+        // Mip levels 2, 3 and 4; array layers 3 to 6.
+        let (levels, layers) = (2..5, 3..7);
+        let color = vk::ImageAspectFlags::COLOR;
+
+        let all = subresource_layers(&levels, &layers, color, 3, None);
+        assert_eq!((all.mip_level, all.base_array_layer, all.layer_count), (3, 3, 4));
+
+        let some = subresource_layers(&levels, &layers, color, 2, Some(4..6));
+        assert_eq!((some.mip_level, some.base_array_layer, some.layer_count), (2, 4, 2));
+
+        // Out of range levels and layers are clamped to the last ones.
+        let clamped = subresource_layers(&levels, &layers, color, 5, Some(5..10));
+        assert_eq!((clamped.mip_level, clamped.base_array_layer, clamped.layer_count), (4, 5, 2));
+
+        let last = subresource_layers(&levels, &layers, color, 0, Some(7..9));
+        assert_eq!((last.mip_level, last.base_array_layer, last.layer_count), (2, 6, 1));
+
+        let depth = subresource_layers(&levels, &layers, vk::ImageAspectFlags::DEPTH, 2, Some(3..4));
+        assert_eq!((depth.aspect_mask, depth.layer_count), (vk::ImageAspectFlags::DEPTH, 1));
+    }
+
+    #[test]
+    pub fn aliased_memory_requirements() {
+        let requirements = aliased_requirements([
+            vk::MemoryRequirements { size : 4096, alignment : 256, memory_type_bits : 0b0110 },
+            vk::MemoryRequirements { size : 1024, alignment : 1024, memory_type_bits : 0b1100 },
+        ].into_iter());
+
+        assert_eq!((requirements.size, requirements.alignment, requirements.memory_type_bits), (4096, 1024, 0b0100));
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn incompatible_aliased_memory() {
+        _ = aliased_requirements([
+            vk::MemoryRequirements { size : 4096, alignment : 256, memory_type_bits : 0b0010 },
+            vk::MemoryRequirements { size : 1024, alignment : 1024, memory_type_bits : 0b0100 },
+        ].into_iter());
+    }
+}
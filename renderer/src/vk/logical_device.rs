@@ -1,12 +1,13 @@
-use std::{ffi::CString, mem::ManuallyDrop, path::PathBuf, slice, sync::{Arc, Mutex}};
+use std::{ffi::CString, mem::ManuallyDrop, slice, sync::{Arc, Mutex}};
 
-use ash::{ext::debug_utils, vk};
-use gpu_allocator::{vulkan::{Allocator, AllocatorCreateDesc}, AllocationSizes, AllocatorDebugSettings};
+use ash::{ext::debug_utils, prelude::VkResult, vk};
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 
 use crate::traits::handle::Handle;
 use crate::vk::context::Context;
 use crate::vk::physical_device::PhysicalDevice;
 use crate::vk::queue::{Queue, QueueAffinity};
+use crate::vk::renderer::RendererOptions;
 
 use super::{command_buffer::CommandBuffer, pipeline::pool::PipelinePool};
 
@@ -15,9 +16,10 @@ pub struct LogicalDevice {
     handle : ash::Device,
     pub physical_device : PhysicalDevice,
     allocator : ManuallyDrop<Arc<Mutex<Allocator>>>,
-    pub pipeline_pool : PipelinePool,
+    pub pipeline_pool : ManuallyDrop<PipelinePool>,
 
-    // Device-level debug utilities
+    // Device-level debug utilities, only loaded if VK_EXT_debug_utils is enabled on the instance. Debug names and
+    // labels are silently ignored otherwise.
     pub(in crate) debug_utils : Option<debug_utils::Device>,
 
     // All queues
@@ -25,11 +27,30 @@ pub struct LogicalDevice {
 
     pub features : vk::PhysicalDeviceFeatures,
     pub indexing_features : IndexingFeatures,
+    pub buffer_device_address : bool,
+    /// Whether `VK_EXT_memory_budget` is enabled on this device.
+    pub memory_budget : bool,
+
+    // Set once the device was destroyed by LogicalDevice::destroy.
+    destroyed : bool,
 }
 
 impl LogicalDevice {
     pub(in crate) fn handle(&self) -> &ash::Device { &self.handle }
 
+    /// Returns the underlying [`ash::Device`], so that the functions of device extensions this crate does not wrap
+    /// can be loaded, for example with `ash::khr::acceleration_structure::Device::new(context.raw(), device.raw())`.
+    ///
+    /// # Safety
+    ///
+    /// This is an escape hatch; nothing done through the returned device is tracked by this crate.
+    ///
+    /// * The device must not be destroyed; it is owned by this object.
+    /// * Objects created through it must be destroyed by the caller before this object is destroyed, or recreated
+    ///   after the device was lost (see [`RendererOrchestrator::recover_device_lost`](crate::orchestration::rendering::RendererOrchestrator::recover_device_lost)).
+    /// * Access to queues and to objects owned by this crate must be externally synchronized, as Vulkan requires.
+    pub unsafe fn raw(&self) -> &ash::Device { &self.handle }
+
     pub fn allocator(&self) -> &Arc<Mutex<Allocator>> { &self.allocator }
 
     pub fn new(context : &Context,
@@ -38,32 +59,68 @@ impl LogicalDevice {
         queues : Vec<Queue>,
         features : vk::PhysicalDeviceFeatures,
         indexing_features : IndexingFeatures,
-        cache_file : PathBuf,
+        memory_budget : bool,
+        options : &RendererOptions,
     )  -> Self {
         let allocator = Allocator::new(&AllocatorCreateDesc{
             instance: context.handle().clone(),
             device: device.clone(),
             physical_device: physical_device.handle().clone(),
 
-            // TODO: All these may need tweaking and fixing
-            debug_settings: AllocatorDebugSettings::default(),
-            allocation_sizes : AllocationSizes::default(),
-            buffer_device_address: false,
+            debug_settings: options.allocator_debug,
+            allocation_sizes : options.allocator_block_sizes,
+            buffer_device_address : options.buffer_device_address,
         }).expect("Error creating an allocator");
 
-        let pipeline_pool = PipelinePool::new(device.clone(), cache_file);
+        let pipeline_pool = PipelinePool::new(device.clone(), physical_device.properties(), Some((options.get_pipeline_cache_file)()));
 
         Self {
             handle : device.clone(),
             allocator : ManuallyDrop::new(Arc::new(Mutex::new(allocator))),
-            pipeline_pool,
+            pipeline_pool : ManuallyDrop::new(pipeline_pool),
             queues,
             physical_device,
             features,
             indexing_features,
-            // TODO: Fix this being optional if the extension is not available
-            debug_utils : Some(debug_utils::Device::new(&context.handle(), &device.clone())),
+            buffer_device_address : options.buffer_device_address,
+            memory_budget,
+            debug_utils : context.is_extension_enabled(debug_utils::NAME)
+                .then(|| debug_utils::Device::new(context.handle(), &device)),
+            destroyed : false,
+        }
+    }
+
+    /// Saves the pipeline cache, then destroys the memory allocator, the pipeline cache and the device, in that order.
+    /// Calling this more than once has no effect; the device is destroyed when dropped otherwise.
+    ///
+    /// # Safety
+    ///
+    /// The device must be idle, and every object created from it must have been destroyed; in particular, no
+    /// reference to the allocator returned by [`LogicalDevice::allocator`] may be held anymore.
+    pub(in crate) unsafe fn destroy(&mut self) {
+        if self.destroyed {
+            return;
         }
+        self.destroyed = true;
+
+        _ = self.pipeline_pool.save();
+        ManuallyDrop::drop(&mut self.pipeline_pool);
+
+        // The allocator frees its memory blocks when dropped, which requires the device.
+        debug_assert_eq!(Arc::strong_count(&self.allocator), 1, "The allocator outlives the device");
+        ManuallyDrop::drop(&mut self.allocator);
+
+        self.handle.destroy_device(None);
+    }
+
+    /// Creates a pipeline pool that is not backed by a file.
+    /// 
+    /// # Description
+    /// 
+    /// This is typically used to build pipelines from multiple threads, each with their own pool, which are then
+    /// merged into the device's pool (see [`PipelinePool::merge`]).
+    pub fn create_pipeline_pool(&self) -> PipelinePool {
+        PipelinePool::new(self.handle.clone(), self.physical_device.properties(), None)
     }
 
     /// Sets the name of a vulkan handle.
@@ -136,6 +193,22 @@ impl LogicalDevice {
         signal_semaphores : &[vk::Semaphore],
         fence : vk::Fence
     ) {
+        self.try_submit(queue, command_buffers, wait_info, signal_semaphores, fence)
+            .expect("Submission failed")
+    }
+
+    /// Submits a unit of work to this logical device. See [`LogicalDevice::submit`].
+    /// 
+    /// # Returns
+    /// 
+    /// The error returned by the driver if the submission failed, such as [`vk::Result::ERROR_DEVICE_LOST`].
+    pub fn try_submit(&self,
+        queue : &impl Handle<vk::Queue>,
+        command_buffers : &[&CommandBuffer],
+        wait_info : &[(vk::Semaphore, vk::PipelineStageFlags)],
+        signal_semaphores : &[vk::Semaphore],
+        fence : vk::Fence
+    ) -> VkResult<()> {
         unsafe {
             let command_buffers = command_buffers.iter().map(|cmd| cmd.handle()).collect::<Vec<_>>();
             let wait_semaphores = wait_info.iter().map(|t| t.0).collect::<Vec<_>>();
@@ -148,11 +221,15 @@ impl LogicalDevice {
                 .wait_dst_stage_mask(&wait_stages);
 
             self.handle.queue_submit(queue.handle(), slice::from_ref(&submit_info), fence)
-                .expect("Submission failed")
         }
     }
 
     /// Creates a new fence.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `flags` - Flags controlling the initial state of the fence.
+    /// * `name` - An optional debug name for the fence.
     pub fn create_fence(&self, flags : vk::FenceCreateFlags, name : Option<String>) -> vk::Fence
     {
         let create_info = vk::FenceCreateInfo::default()
@@ -170,10 +247,31 @@ impl LogicalDevice {
         }
     }
     
+    /// Destroys a fence created with [`LogicalDevice::create_fence`].
+    pub fn destroy_fence(&self, fence : vk::Fence) {
+        unsafe {
+            self.handle.destroy_fence(fence, None);
+        }
+    }
+
     pub fn wait_for_fence(&self, fence : vk::Fence) {
+        self.wait_for_fence_timeout(fence, u64::MAX)
+            .expect("Waiting for the fence failed");
+    }
+
+    /// Waits for a fence to become signaled, for at most the given amount of time.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `fence` - The fence to wait on.
+    /// * `timeout` - The maximum amount of time to wait, in nanoseconds.
+    /// 
+    /// # Returns
+    /// 
+    /// [`vk::Result::TIMEOUT`] if the fence was not signaled in time.
+    pub fn wait_for_fence_timeout(&self, fence : vk::Fence, timeout : u64) -> VkResult<()> {
         unsafe {
-            self.handle.wait_for_fences(&[fence], true, u64::MAX)
-                .expect("Waiting for the fence failed");
+            self.handle.wait_for_fences(&[fence], true, timeout)
         }
     }
     
@@ -184,13 +282,24 @@ impl LogicalDevice {
         }
     }
 
-    pub fn create_semaphore(&self) -> vk::Semaphore {
+    /// Creates a new binary semaphore.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - An optional debug name for the semaphore.
+    pub fn create_semaphore(&self, name : Option<String>) -> vk::Semaphore {
         unsafe {
             let create_info = vk::SemaphoreCreateInfo::default()
                 .flags(vk::SemaphoreCreateFlags::empty());
 
-            self.handle.create_semaphore(&create_info, None)
-                .expect("Failed to create a semaphore")
+            let handle = self.handle.create_semaphore(&create_info, None)
+                .expect("Failed to create a semaphore");
+
+            if let Some(name) = name {
+                self.set_handle_name(handle, &name);
+            }
+
+            handle
         }
     }
 }
@@ -211,11 +320,7 @@ impl LogicalDevice {
 impl Drop for LogicalDevice {
     fn drop(&mut self) {
         unsafe {
-            self.handle.destroy_pipeline_cache(self.pipeline_pool.handle(), None);
-
-            ManuallyDrop::drop(&mut self.allocator);
-
-            self.handle.destroy_device(None);
+            self.destroy();
         }
     }
 }
@@ -1,5 +1,5 @@
-use std::path::PathBuf;
-use std::{cmp::min, ffi::CString, ops::Range};
+use std::mem::size_of;
+use std::{ffi::{CStr, CString}, ops::Range, slice};
 
 use ash::vk;
 
@@ -7,6 +7,7 @@ use crate::{make_handle, window::Window};
 use crate::vk::context::Context;
 use crate::vk::logical_device::{IndexingFeatures, LogicalDevice};
 use crate::vk::queue::{Queue, QueueFamily};
+use crate::vk::renderer::RendererOptions;
 
 #[derive(Clone)]
 pub struct PhysicalDevice {
@@ -14,12 +15,21 @@ pub struct PhysicalDevice {
     memory_properties : vk::PhysicalDeviceMemoryProperties,
     pub properties : vk::PhysicalDeviceProperties,
     pub queue_families : Vec<QueueFamily>,
+    extensions : Vec<CString>,
 }
 
 impl PhysicalDevice {
     #[inline] pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties { &self.memory_properties }
     #[inline] pub fn properties(&self) -> &vk::PhysicalDeviceProperties { &self.properties }
 
+    /// Returns the extensions supported by this device.
+    #[inline] pub fn extensions(&self) -> &[CString] { &self.extensions }
+
+    /// Returns true if this device supports the given device extension.
+    pub fn supports_extension(&self, extension : &CStr) -> bool {
+        self.extensions.iter().any(|supported| supported.as_c_str() == extension)
+    }
+
     /// Creates a new physical device.
     /// 
     /// # Arguments
@@ -27,23 +37,32 @@ impl PhysicalDevice {
     /// * `instance` - An [`Instance`] modeling vulkan stuff. God knows.
     /// * `physical_device` - The [`PhysicalDevice`] attached to this queue family.
     /// * `queue_families` - A vector of queue families to use for this logical device, along with the requested number of queues for each family.
-    /// * `get_queue_priority` - A callable that will return a queue's priority given its index.
+    ///                      Requests are clamped to the number of queues each family exposes.
+    /// * `get_queue_priority` - A callable that will return a queue's priority given its index. Priorities must be
+    ///                          between `0.0` and `1.0`.
     /// * `extensions` - A set of device extensions to be enabled on the device.
+    /// * `options` - Renderer options; see [`RendererOptions::buffer_device_address`] and [`RendererOptions::pipeline_cache_file`].
     /// 
     /// # Panics
     /// 
     /// * Panics if [`vkCreateDevice`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCreateDevice.html) fails.
+    /// * Panics if buffer device addresses are requested but not supported.
+    /// * Panics if a queue priority is not between `0.0` and `1.0`.
     pub fn create_logical_device<F>(
         &self,
         instance : &Context,
         queue_families : Vec<(u32, &QueueFamily)>,
         get_queue_priority : F,
         extensions : &Vec<CString>,
-        cache_file : PathBuf,
+        options : &RendererOptions,
         window : &Window,
     ) -> LogicalDevice
         where F : Fn(u32, &QueueFamily) -> f32
     {
+        let queue_families = queue_families.into_iter()
+            .map(|(count, family)| (clamp_queue_count(count, family), family))
+            .collect::<Vec<_>>();
+
         // Store queue priorities in a flattened buffer; each queue family will index into
         // that buffer to slice out the amount of queue families.
         let mut queue_create_infos = Vec::with_capacity(queue_families.len());
@@ -52,8 +71,8 @@ impl PhysicalDevice {
         // Unfortunately has to happen in two loops because one borrow is immutable
         // and the other is mutable...
         for (count, family) in &queue_families {
-            for queue_index in 0..min(family.count(), *count) {
-                flat_queue_priorities.push(get_queue_priority(queue_index, family));
+            for queue_index in 0..*count {
+                flat_queue_priorities.push(validate_queue_priority(get_queue_priority(queue_index, family), queue_index, family));
             }
         }
         
@@ -84,6 +103,20 @@ impl PhysicalDevice {
             instance.handle().get_physical_device_features2(self.handle, &mut physical_device_features2);
         }
 
+        // Buffer device addresses change how memory is allocated, so only enable them when asked to.
+        let mut physical_device_buffer_device_address_features = vk::PhysicalDeviceBufferDeviceAddressFeatures::default();
+        unsafe {
+            instance.handle().get_physical_device_features2(self.handle, &mut vk::PhysicalDeviceFeatures2::default()
+                .push_next(&mut physical_device_buffer_device_address_features));
+        }
+        assert!(!options.buffer_device_address || physical_device_buffer_device_address_features.buffer_device_address != 0,
+            "Buffer device addresses are not supported by this device");
+
+        let mut physical_device_buffer_device_address_features = vk::PhysicalDeviceBufferDeviceAddressFeatures::default()
+            .buffer_device_address(options.buffer_device_address);
+        let mut physical_device_features2 = physical_device_features2
+            .push_next(&mut physical_device_buffer_device_address_features);
+
         let device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
             .push_next(&mut physical_device_features2)
@@ -106,7 +139,8 @@ impl PhysicalDevice {
             queues_objs,
             physical_device_features2.features,
             IndexingFeatures::new(physical_device_descriptor_indexing_features),
-            cache_file,
+            extensions.iter().any(|extension| extension.as_c_str() == ash::ext::memory_budget::NAME),
+            options,
         )
     }
 
@@ -129,19 +163,237 @@ impl PhysicalDevice {
             instance.handle().get_physical_device_queue_family_properties(device)
         }.iter().enumerate().map(|(index, &properties)| QueueFamily::new(index as u32, properties)).collect::<Vec<_>>();
 
+        let extensions = unsafe {
+            instance.handle().enumerate_device_extension_properties(device)
+                .expect("Failed to enumerate device extensions")
+        }.iter().filter_map(|properties| properties.extension_name_as_c_str().ok().map(CStr::to_owned)).collect::<Vec<_>>();
+
         Self {
             handle : device,
             memory_properties : physical_device_memory_properties,
             properties : physical_device_properties,
-            queue_families
+            queue_families,
+            extensions,
         }
     }
 
+    /// Returns the memory usage and budget of each memory heap of this device.
+    /// 
+    /// The `VK_EXT_memory_budget` extension must be enabled on the logical device created from this physical device.
+    pub fn memory_budget(&self, context : &Context) -> Vec<MemoryHeapBudget> {
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::default()
+            .push_next(&mut budget_properties);
+
+        unsafe {
+            context.handle().get_physical_device_memory_properties2(self.handle, &mut memory_properties);
+        }
+
+        let heap_count = memory_properties.memory_properties.memory_heap_count as usize;
+        let heaps = memory_properties.memory_properties.memory_heaps;
+        (0..heap_count).map(|index| MemoryHeapBudget {
+            size : heaps[index].size,
+            usage : budget_properties.heap_usage[index],
+            budget : budget_properties.heap_budget[index],
+            device_local : heaps[index].flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+        }).collect()
+    }
+
+    /// Returns the features supported by this physical device.
+    pub fn get_features(&self, context : &Context) -> vk::PhysicalDeviceFeatures {
+        unsafe {
+            context.handle().get_physical_device_features(self.handle)
+        }
+    }
+
+    /// Returns true if this physical device supports all the given features.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `context` - The global Vulkan instance.
+    /// * `required` - The features to check for. Features set to [`vk::FALSE`] are ignored.
+    pub fn supports_features(&self, context : &Context, required : &vk::PhysicalDeviceFeatures) -> bool {
+        let supported = self.get_features(context);
+
+        // vk::PhysicalDeviceFeatures is exclusively made of vk::Bool32 members.
+        let count = size_of::<vk::PhysicalDeviceFeatures>() / size_of::<vk::Bool32>();
+        let (supported, required) = unsafe {(
+            slice::from_raw_parts(&supported as *const _ as *const vk::Bool32, count),
+            slice::from_raw_parts(required as *const _ as *const vk::Bool32, count),
+        )};
+
+        supported.iter().zip(required).all(|(&supported, &required)| required == vk::FALSE || supported != vk::FALSE)
+    }
+
     pub fn get_format_properties(&self, context : &Context, format : vk::Format) -> Option<vk::FormatProperties> {
         unsafe {
             context.handle().get_physical_device_format_properties(self.handle, format).into()
         }
     }
+
+    /// Returns true if images of the given format and tiling support all the given features on this device.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `context` - The global Vulkan instance.
+    /// * `format` - The format of the images.
+    /// * `tiling` - The tiling of the images.
+    /// * `features` - The features to check for.
+    pub fn supports_format(&self, context : &Context, format : vk::Format, tiling : vk::ImageTiling, features : vk::FormatFeatureFlags) -> bool {
+        self.get_format_properties(context, format)
+            .is_some_and(|properties| format_supports(&properties, tiling, features))
+    }
+
+    /// Returns true if optimally tiled images of the given format can be both the source and the destination of blits.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `context` - The global Vulkan instance.
+    /// * `format` - The format of the images.
+    pub fn supports_blit(&self, context : &Context, format : vk::Format) -> bool {
+        self.supports_format(context, format, vk::ImageTiling::OPTIMAL, vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST)
+    }
+
+    /// Returns the highest sample count supported by framebuffers with both color and depth attachments.
+    pub fn max_msaa_samples(&self) -> vk::SampleCountFlags {
+        let limits = &self.properties.limits;
+        highest_sample_count(limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts)
+    }
+}
+
+/// Returns true if the given format properties support all the given features for the given tiling.
+///
+/// # Panics
+///
+/// * Panics if `tiling` is neither [`vk::ImageTiling::LINEAR`] nor [`vk::ImageTiling::OPTIMAL`].
+fn format_supports(properties : &vk::FormatProperties, tiling : vk::ImageTiling, features : vk::FormatFeatureFlags) -> bool {
+    match tiling {
+        vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(features),
+        vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features.contains(features),
+        _ => panic!("Unsupported tiling mode")
+    }
+}
+
+/// Returns the highest sample count in the given set, or [`vk::SampleCountFlags::TYPE_1`] if it is empty.
+pub(in crate) fn highest_sample_count(counts : vk::SampleCountFlags) -> vk::SampleCountFlags {
+    [
+        vk::SampleCountFlags::TYPE_64,
+        vk::SampleCountFlags::TYPE_32,
+        vk::SampleCountFlags::TYPE_16,
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ].into_iter()
+        .find(|&count| counts.contains(count))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
 }
 
 make_handle! { PhysicalDevice, vk::PhysicalDevice }
+
+/// The memory usage of a memory heap, as reported by `VK_EXT_memory_budget`.
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryHeapBudget {
+    /// The total size of the heap, in bytes.
+    pub size : u64,
+    /// The amount of memory currently used by this process in the heap, in bytes.
+    pub usage : u64,
+    /// An estimate of how much memory this process can use in the heap, in bytes.
+    pub budget : u64,
+    /// Whether this heap corresponds to device-local (video) memory.
+    pub device_local : bool,
+}
+
+/// Clamps the amount of queues requested from a family to the amount of queues it exposes.
+///
+/// # Arguments
+///
+/// * `requested` - The amount of queues requested.
+/// * `family` - The queue family the queues are requested from.
+///
+/// # Returns
+///
+/// A number of queues between 1 and the amount of queues exposed by the family. A warning is printed if `requested`
+/// was out of that range.
+fn clamp_queue_count(requested : u32, family : &QueueFamily) -> u32 {
+    let count = requested.clamp(1, family.count().max(1));
+    if count != requested {
+        println!("Requested {} queues from family {}, which exposes {}; {} will be created.", requested, family.index(), family.count(), count);
+    }
+    count
+}
+
+/// Ensures that a queue priority is within the range allowed by Vulkan.
+///
+/// # Panics
+///
+/// * Panics if `priority` is not between `0.0` and `1.0`.
+fn validate_queue_priority(priority : f32, queue_index : u32, family : &QueueFamily) -> f32 {
+    assert!((0.0..=1.0).contains(&priority),
+        "The priority of queue {} of family {} must be between 0 and 1, got {}", queue_index, family.index(), priority);
+    priority
+}
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use crate::vk::queue::QueueFamily;
+
+    use super::{clamp_queue_count, format_supports, highest_sample_count, validate_queue_priority};
+
+    #[test]
+    pub fn queue_count_clamping() {
+        let family = QueueFamily::new(0, vk::QueueFamilyProperties {
+            queue_flags : vk::QueueFlags::GRAPHICS,
+            queue_count : 1,
+            ..Default::default()
+        });
+        assert_eq!(clamp_queue_count(4, &family), 1);
+        assert_eq!(clamp_queue_count(1, &family), 1);
+        assert_eq!(clamp_queue_count(0, &family), 1);
+
+        let family = QueueFamily::new(1, vk::QueueFamilyProperties {
+            queue_flags : vk::QueueFlags::COMPUTE,
+            queue_count : 16,
+            ..Default::default()
+        });
+        assert_eq!(clamp_queue_count(4, &family), 4);
+        assert_eq!(clamp_queue_count(32, &family), 16);
+    }
+
+    #[test]
+    pub fn queue_priorities() {
+        let family = QueueFamily::new(0, vk::QueueFamilyProperties { queue_count : 2, ..Default::default() });
+        assert_eq!(validate_queue_priority(0.0, 0, &family), 0.0);
+        assert_eq!(validate_queue_priority(0.5, 1, &family), 0.5);
+        assert_eq!(validate_queue_priority(1.0, 0, &family), 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn invalid_queue_priority() {
+        let family = QueueFamily::new(0, vk::QueueFamilyProperties { queue_count : 1, ..Default::default() });
+        validate_queue_priority(1.5, 0, &family);
+    }
+
+    #[test]
+    pub fn format_features() {
+        let properties = vk::FormatProperties {
+            optimal_tiling_features : vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::BLIT_SRC,
+            linear_tiling_features : vk::FormatFeatureFlags::TRANSFER_SRC,
+            ..Default::default()
+        };
+
+        assert!(format_supports(&properties, vk::ImageTiling::OPTIMAL, vk::FormatFeatureFlags::SAMPLED_IMAGE));
+        assert!(!format_supports(&properties, vk::ImageTiling::OPTIMAL, vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST));
+        assert!(format_supports(&properties, vk::ImageTiling::LINEAR, vk::FormatFeatureFlags::TRANSFER_SRC));
+        assert!(!format_supports(&properties, vk::ImageTiling::LINEAR, vk::FormatFeatureFlags::SAMPLED_IMAGE));
+    }
+
+    #[test]
+    pub fn sample_counts() {
+        assert_eq!(highest_sample_count(vk::SampleCountFlags::empty()), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(highest_sample_count(vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_2), vk::SampleCountFlags::TYPE_2);
+        assert_eq!(highest_sample_count(vk::SampleCountFlags::from_raw(0b1111)), vk::SampleCountFlags::TYPE_8);
+    }
+}
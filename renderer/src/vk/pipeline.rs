@@ -1,9 +1,12 @@
 use std::{ffi::CString, ops::Range, path::PathBuf};
 
 use ash::vk;
+use puffin::profile_scope;
 use crate::orchestration::rendering::RenderingContext;
 use crate::{make_handle, traits::handle::Handle};
+use crate::vk::pipeline::layout::PipelineLayout;
 use crate::vk::pipeline::shader::Shader;
+use crate::vk::renderer::DynamicState;
 
 pub mod layout;
 pub mod pipeline;
@@ -16,7 +19,9 @@ pub trait Vertex {
     /// # Description
     ///
     /// This function returns an array of tuples consisting of the stride of the input, and the rate
-    /// of the input.
+    /// of the input. The position of each binding in the array is its binding number, which attributes
+    /// returned by [`Vertex::format_offset`] refer to; for example, per-instance data can be declared
+    /// as a second binding with [`vk::VertexInputRate::INSTANCE`].
     fn bindings() -> Vec<(u32, vk::VertexInputRate)>;
 
     /// Returns formats and offsets of elements in this vertex.
@@ -27,6 +32,7 @@ pub struct PipelineInfo {
     name : Option<&'static str>,
 
     layout : vk::PipelineLayout,
+    push_constant_ranges : Vec<vk::PushConstantRange>,
     render_pass : vk::RenderPass,
     subpass : u32,
     shaders : Vec<(PathBuf, vk::ShaderStageFlags)>,
@@ -34,7 +40,13 @@ pub struct PipelineInfo {
     cull_mode : vk::CullModeFlags,
     front_face : vk::FrontFace,
     topology : vk::PrimitiveTopology,
+    primitive_restart : bool,
+    patch_control_points : Option<u32>,
+    polygon_mode : vk::PolygonMode,
+    line_width : Option<DynamicState<f32>>,
+    depth_bias : Option<DynamicState<[f32; 3]>>,
     color_blend_attachments : Vec<vk::PipelineColorBlendAttachmentState>,
+    blend_constants : [f32; 4],
 
     specialization_data: Vec<u8>,
     specialization_entries: Vec<vk::SpecializationMapEntry>,
@@ -42,7 +54,11 @@ pub struct PipelineInfo {
     vertex_format_offset : Vec<vk::VertexInputAttributeDescription>,
     vertex_bindings : Vec<(u32, vk::VertexInputRate)>,
     samples : vk::SampleCountFlags,
+    viewports : u32,
     pool : bool,
+
+    flags : vk::PipelineCreateFlags,
+    base_pipeline : vk::Pipeline,
 }
 
 impl PipelineInfo {
@@ -51,6 +67,35 @@ impl PipelineInfo {
         self
     }
 
+    /// Allows other pipelines to derive from this pipeline (see [`PipelineInfo::derive_from`]).
+    #[inline] pub fn allow_derivatives(mut self) -> Self {
+        self.flags |= vk::PipelineCreateFlags::ALLOW_DERIVATIVES;
+        self
+    }
+
+    /// Makes this pipeline a derivative of another pipeline.
+    /// 
+    /// # Description
+    /// 
+    /// Derivatives are cheaper to create when they only differ slightly from their parent, such as blending or depth
+    /// state variants sharing the same shaders and layout.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `base` - The parent pipeline. It must have been created with [`PipelineInfo::allow_derivatives`].
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if `base` does not allow derivatives.
+    #[inline] pub fn derive_from(mut self, base : &Pipeline) -> Self {
+        assert!(base.info.flags.contains(vk::PipelineCreateFlags::ALLOW_DERIVATIVES),
+            "The base pipeline must be created with PipelineInfo::allow_derivatives");
+
+        self.flags |= vk::PipelineCreateFlags::DERIVATIVE;
+        self.base_pipeline = base.handle;
+        self
+    }
+
     #[inline] pub fn name(mut self, name : &'static str) -> Self {
         self.name = Some(name);
         self
@@ -62,37 +107,142 @@ impl PipelineInfo {
         self
     }
 
+    /// Adds the blend state of the next color attachment of the subpass this pipeline is used in.
     #[inline] pub fn color_blend_attachment(mut self, attachment : vk::PipelineColorBlendAttachmentState) -> Self {
         self.color_blend_attachments.push(attachment);
         self
     }
 
+    /// Sets the blend states of all color attachments of the subpass this pipeline is used in.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `attachments` - One blend state per color attachment, in the order they are declared in the subpass.
+    #[inline] pub fn color_blend_attachments(mut self, attachments : &[vk::PipelineColorBlendAttachmentState]) -> Self {
+        self.color_blend_attachments = attachments.to_vec();
+        self
+    }
+
+    value_builder! { blend_constants, [f32; 4] }
+
+    /// Enables depth bias, which offsets the depth values of rasterized fragments.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `constant` - A scalar factor controlling the constant depth value added to each fragment.
+    /// * `clamp` - The maximum (or minimum) depth bias of a fragment. Any value other than zero requires the `depthBiasClamp` feature.
+    /// * `slope` - A scalar factor applied to a fragment's slope in depth bias calculations.
+    #[inline] pub fn depth_bias(mut self, constant : f32, clamp : f32, slope : f32) -> Self {
+        self.depth_bias = Some(DynamicState::Fixed([constant, clamp, slope]));
+        self
+    }
+
+    /// Enables depth bias, with factors that must be set with [`CommandBuffer::set_depth_bias`](crate::vk::command_buffer::CommandBuffer::set_depth_bias)
+    /// before drawing.
+    #[inline] pub fn dynamic_depth_bias(mut self) -> Self {
+        self.depth_bias = Some(DynamicState::Dynamic);
+        self
+    }
+
     value_builder! { depth, depth, DepthOptions }
-    value_builder! { layout, layout, vk::PipelineLayout }
+    /// Sets the layout of this pipeline.
+    ///
+    /// # Description
+    ///
+    /// The push constant ranges of the layout are remembered, so that updates made with
+    /// [`CommandBuffer::push_constants_typed`](crate::vk::command_buffer::CommandBuffer::push_constants_typed)
+    /// can be validated against them.
+    #[inline] pub fn layout(mut self, layout : &PipelineLayout) -> Self {
+        self.layout = layout.handle();
+        self.push_constant_ranges = layout.push_constant_ranges().to_vec();
+        self
+    }
+
     value_builder! { cull_mode, mode, cull_mode, vk::CullModeFlags }
     value_builder! { samples, samples, vk::SampleCountFlags }
+
+    /// Sets the number of viewports and scissors this pipeline renders to. Both are dynamic, and must be set with
+    /// [`CommandBuffer::set_viewport`](crate::vk::command_buffer::CommandBuffer::set_viewport) and
+    /// [`CommandBuffer::set_scissors`](crate::vk::command_buffer::CommandBuffer::set_scissors) before drawing.
+    /// 
+    /// Shaders select the viewport of each primitive through `gl_ViewportIndex`. More than one viewport requires the
+    /// `multiViewport` feature; see [`RendererOptions::required_features`](crate::vk::renderer::RendererOptions::required_features).
+    #[inline] pub fn viewports(mut self, count : u32) -> Self {
+        self.viewports = count;
+        self
+    }
     value_builder! { front_face, front, front_face, vk::FrontFace }
     value_builder! { topology, topology, vk::PrimitiveTopology }
 
+    /// Enables or disables primitive restart.
+    /// 
+    /// # Description
+    /// 
+    /// When enabled, a special index value restarts the assembly of primitives in indexed draws. That value is
+    /// `0xFFFFFFFF` when the index buffer uses [`vk::IndexType::UINT32`], and `0xFFFF` when it uses [`vk::IndexType::UINT16`].
+    /// 
+    /// Primitive restart can only be enabled for strip and fan topologies.
+    #[inline] pub fn primitive_restart(mut self, primitive_restart : bool) -> Self {
+        self.primitive_restart = primitive_restart;
+        self
+    }
+    value_builder! { polygon_mode, mode, polygon_mode, vk::PolygonMode }
+
+    /// Sets the number of control points per patch. This is required for pipelines with tessellation shaders,
+    /// which must use the [`vk::PrimitiveTopology::PATCH_LIST`] topology.
+    /// 
+    /// See `assets/passthrough.tesc` and `assets/passthrough.tese` for a minimal pair of tessellation shaders.
+    #[inline] pub fn patch_control_points(mut self, count : u32) -> Self {
+        self.patch_control_points = Some(count);
+        self
+    }
+
+    /// Sets the width of rasterized lines. If this is never called, the line width configured in
+    /// [`RendererOptions`](crate::vk::renderer::RendererOptions) is used.
+    /// 
+    /// If the line width is [`DynamicState::Dynamic`], it must be set with [`CommandBuffer::set_line_width`](crate::vk::command_buffer::CommandBuffer::set_line_width)
+    /// before drawing.
+    #[inline] pub fn line_width(mut self, line_width : impl Into<DynamicState<f32>>) -> Self {
+        self.line_width = Some(line_width.into());
+        self
+    }
+
     #[inline] pub fn add_shader(mut self, path : PathBuf, flags : vk::ShaderStageFlags) -> Self {
         self.shaders.push((path, flags));
         self
     }
 
+    /// Sets the value of a specialization constant, for every shader stage of this pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The value of the constant. Boolean constants are 32-bit wide and should be given as a [`vk::Bool32`].
+    /// * `constant_id` - The ID of the constant, as declared by `layout(constant_id = ...)` in the shaders.
     #[inline] pub fn add_specialization<T>(mut self, data : &T, constant_id : u32) -> Self {
         let slice = unsafe {
             std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of_val(data))
         };
 
         let offset = self.specialization_data.len();
-        self.specialization_data.append(&mut slice.to_vec());
+        self.specialization_data.extend_from_slice(slice);
         self.specialization_entries.push(vk::SpecializationMapEntry::default()
             .constant_id(constant_id)
             .offset(offset as _)
-            .size(self.specialization_data.len()));
+            .size(slice.len()));
         self
     }
 
+    /// Returns the specialization constants of this pipeline, or `None` if it has none.
+    fn specialization_info(&self) -> Option<vk::SpecializationInfo<'_>> {
+        if self.specialization_entries.is_empty() {
+            return None;
+        }
+
+        Some(vk::SpecializationInfo::default()
+            .map_entries(&self.specialization_entries)
+            .data(&self.specialization_data))
+    }
+
     #[inline] pub fn vertex<T : Vertex>(mut self) -> Self {
         self.vertex_format_offset = T::format_offset();
         self.vertex_bindings = T::bindings();
@@ -102,6 +252,31 @@ impl PipelineInfo {
     pub fn build(self, context : &RenderingContext) -> Pipeline {
         Pipeline::new(context, self)
     }
+
+    /// Returns the rasterization state of this pipeline.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `line_width` - The width of rasterized lines.
+    fn rasterization_state(&self, line_width : f32) -> vk::PipelineRasterizationStateCreateInfo<'static> {
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(self.cull_mode)
+            // .depth_clamp_enable(false)
+            // .rasterizer_discard_enable(false)
+            .line_width(line_width)
+            .polygon_mode(self.polygon_mode)
+            .front_face(self.front_face);
+
+        match self.depth_bias {
+            Some(DynamicState::Fixed([constant, clamp, slope])) => rasterization_state
+                .depth_bias_enable(true)
+                .depth_bias_constant_factor(constant)
+                .depth_bias_clamp(clamp)
+                .depth_bias_slope_factor(slope),
+            Some(DynamicState::Dynamic) => rasterization_state.depth_bias_enable(true),
+            None => rasterization_state.depth_bias_enable(false),
+        }
+    }
 }
 
 impl Default for PipelineInfo {
@@ -110,27 +285,40 @@ impl Default for PipelineInfo {
             name : Some("Default Pipeline"),
 
             layout: vk::PipelineLayout::default(),
+            push_constant_ranges : vec![],
             shaders: vec![],
             depth : DepthOptions {
                 test : true,
                 write : true,
-                bounds : None
+                compare_op : vk::CompareOp::LESS,
+                bounds : None,
+                stencil : None,
             },
             cull_mode: vk::CullModeFlags::BACK,
             front_face: vk::FrontFace::COUNTER_CLOCKWISE,
             color_blend_attachments : vec![],
+            blend_constants : [0.0f32; 4],
 
             specialization_data : vec![],
             specialization_entries : vec![],
 
             samples : vk::SampleCountFlags::TYPE_1,
+            viewports : 1,
             topology : vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart : false,
+            patch_control_points : None,
+            polygon_mode : vk::PolygonMode::FILL,
+            line_width : None,
+            depth_bias : None,
 
             vertex_bindings : vec![],
             vertex_format_offset : vec![],
 
             pool : false,
 
+            flags : vk::PipelineCreateFlags::empty(),
+            base_pipeline : vk::Pipeline::null(),
+
             render_pass : vk::RenderPass::null(),
             subpass : 0,
         }
@@ -140,20 +328,57 @@ impl Default for PipelineInfo {
 pub struct DepthOptions {
     test : bool,
     write : bool,
+    compare_op : vk::CompareOp,
     bounds : Option<Range<f32>>,
+    stencil : Option<StencilOptions>,
 }
 
 impl DepthOptions {
     /// Returns a new instance of [`DepthOptions`] where depth testing will be disabled in the pipeline.
     pub fn disabled() -> Self {
-        Self { test : false, write : false, bounds : None }
+        Self { test : false, write : false, compare_op : vk::CompareOp::LESS, bounds : None, stencil : None }
     }
 
-    /// Returns a new instance of [`DepthOptions`] where depth testing will be enabled in the pipeline.
+    /// Returns a new instance of [`DepthOptions`] where depth testing and depth writes will be enabled in the
+    /// pipeline, as opaque geometry usually needs.
+    ///
+    /// This used to leave depth writes disabled, so that opaque geometry silently never wrote to the depth
+    /// attachment; use [`DepthOptions::test_only`] for pipelines that should not write depth.
     pub fn enabled() -> Self {
-        Self { test : true, write : false, bounds : None }
+        Self { test : true, write : true, compare_op : vk::CompareOp::LESS, bounds : None, stencil : None }
+    }
+
+    /// Returns a new instance of [`DepthOptions`] where depth testing will be enabled in the pipeline, but depth
+    /// writes will be disabled; for example, to draw transparent geometry over a scene.
+    pub fn test_only() -> Self {
+        Self { write : false, ..Self::enabled() }
+    }
+
+    /// Enables stencil testing. The render pass must have an attachment with a stencil aspect. Stencil testing is
+    /// independent from depth testing, and can be enabled on [`DepthOptions::disabled`].
+    #[inline] pub fn stencil(mut self, stencil : StencilOptions) -> Self {
+        self.stencil = Some(stencil);
+        self
+    }
+
+    /// Sets the comparison between the depth of fragments and the stored depth, which defaults to
+    /// [`vk::CompareOp::LESS`].
+    ///
+    /// # Description
+    ///
+    /// Reverse-Z depth buffers, which map the near plane to 1 and the far plane to 0, use [`vk::CompareOp::GREATER`]
+    /// or [`vk::CompareOp::GREATER_OR_EQUAL`]; their depth attachments must then be cleared to 0 rather than 1, with
+    /// [`RendererOptions::clear_depth`](crate::vk::renderer::RendererOptions::clear_depth).
+    ///
+    /// # Arguments
+    ///
+    /// * `compare_op` - The comparison operator used by depth testing.
+    #[inline] pub fn compare_op(mut self, compare_op : vk::CompareOp) -> Self {
+        self.compare_op = compare_op;
+        self
     }
 
+    /// Sets whether fragments that pass depth testing write their depth to the depth attachment.
     #[inline] pub fn write(mut self, write : bool) -> Self {
         self.write = write;
         self
@@ -168,7 +393,15 @@ impl DepthOptions {
         let info = vk::PipelineDepthStencilStateCreateInfo::default()
             .depth_test_enable(self.test)
             .depth_write_enable(self.write)
-            .depth_compare_op(vk::CompareOp::LESS);
+            .depth_compare_op(self.compare_op);
+
+        let info = match &self.stencil {
+            Some(stencil) => {
+                let state = stencil.op_state();
+                info.stencil_test_enable(true).front(state).back(state)
+            },
+            None => info.stencil_test_enable(false),
+        };
 
         match &self.bounds {
             Some(bounds) => {
@@ -183,6 +416,103 @@ impl DepthOptions {
     }
 }
 
+/// Describes how fragments are tested against, and written to, the stencil attachment. The same operations are used
+/// for front-facing and back-facing primitives.
+#[derive(Clone, Copy, Debug)]
+pub struct StencilOptions {
+    compare_op : vk::CompareOp,
+    fail_op : vk::StencilOp,
+    pass_op : vk::StencilOp,
+    depth_fail_op : vk::StencilOp,
+    compare_mask : DynamicState<u32>,
+    write_mask : u32,
+    reference : DynamicState<u32>,
+}
+
+impl StencilOptions {
+    /// Returns stencil options that compare the stencil value of fragments with the given operation, and keep the
+    /// contents of the stencil attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `compare_op` - The comparison between the reference value and the stored stencil value.
+    pub fn new(compare_op : vk::CompareOp) -> Self {
+        Self {
+            compare_op,
+            fail_op : vk::StencilOp::KEEP,
+            pass_op : vk::StencilOp::KEEP,
+            depth_fail_op : vk::StencilOp::KEEP,
+            compare_mask : DynamicState::Fixed(u32::MAX),
+            write_mask : u32::MAX,
+            reference : DynamicState::Fixed(0),
+        }
+    }
+
+    /// Sets the operations applied to the stored stencil value.
+    ///
+    /// # Arguments
+    ///
+    /// * `fail` - The operation applied when the stencil test fails.
+    /// * `pass` - The operation applied when both the stencil and depth tests pass.
+    /// * `depth_fail` - The operation applied when the stencil test passes but the depth test fails.
+    #[inline] pub fn ops(mut self, fail : vk::StencilOp, pass : vk::StencilOp, depth_fail : vk::StencilOp) -> Self {
+        self.fail_op = fail;
+        self.pass_op = pass;
+        self.depth_fail_op = depth_fail;
+        self
+    }
+
+    /// Sets the bits of the reference and stored values that take part in the comparison. If this is
+    /// [`DynamicState::Dynamic`], it must be set with
+    /// [`CommandBuffer::set_stencil_compare_mask`](crate::vk::command_buffer::CommandBuffer::set_stencil_compare_mask)
+    /// before drawing.
+    #[inline] pub fn compare_mask(mut self, compare_mask : impl Into<DynamicState<u32>>) -> Self {
+        self.compare_mask = compare_mask.into();
+        self
+    }
+
+    /// Sets the reference value fragments are compared with. If this is [`DynamicState::Dynamic`], it must be set with
+    /// [`CommandBuffer::set_stencil_reference`](crate::vk::command_buffer::CommandBuffer::set_stencil_reference) before
+    /// drawing.
+    #[inline] pub fn reference(mut self, reference : impl Into<DynamicState<u32>>) -> Self {
+        self.reference = reference.into();
+        self
+    }
+
+    value_builder! {
+        /// The bits of the stored value that are updated by the stencil operations.
+        write_mask, u32
+    }
+
+    /// Returns the states of the pipeline that are set while recording commands.
+    fn dynamic_states(&self) -> Vec<vk::DynamicState> {
+        let mut dynamic_states = vec![];
+        if self.compare_mask == DynamicState::Dynamic {
+            dynamic_states.push(vk::DynamicState::STENCIL_COMPARE_MASK);
+        }
+        if self.reference == DynamicState::Dynamic {
+            dynamic_states.push(vk::DynamicState::STENCIL_REFERENCE);
+        }
+        dynamic_states
+    }
+
+    fn op_state(&self) -> vk::StencilOpState {
+        let fixed = |state : DynamicState<u32>| match state {
+            DynamicState::Fixed(value) => value,
+            DynamicState::Dynamic => 0,
+        };
+
+        vk::StencilOpState::default()
+            .compare_op(self.compare_op)
+            .fail_op(self.fail_op)
+            .pass_op(self.pass_op)
+            .depth_fail_op(self.depth_fail_op)
+            .compare_mask(fixed(self.compare_mask))
+            .write_mask(self.write_mask)
+            .reference(fixed(self.reference))
+    }
+}
+
 pub struct Pipeline {
     context : RenderingContext,
     info : PipelineInfo,
@@ -192,113 +522,318 @@ pub struct Pipeline {
 impl Pipeline {
     #[inline] pub fn layout(&self) -> vk::PipelineLayout { self.info.layout }
 
+    /// Returns the push constant ranges declared by the layout of this pipeline.
+    #[inline] pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] { &self.info.push_constant_ranges }
+
+    /// Returns the number of viewports and scissors this pipeline renders to.
+    #[inline] pub fn viewport_count(&self) -> u32 { self.info.viewports }
+
     pub(in self) fn new(context : &RenderingContext, info : PipelineInfo) -> Self {
+        Self::build_many(context, vec![info]).pop().unwrap()
+    }
+
+    /// Creates multiple pipelines with a single call to `vkCreateGraphicsPipelines`.
+    /// 
+    /// # Description
+    /// 
+    /// If any of the pipelines requests the pipeline pool (see [`PipelineInfo::pool`]), the pool is used for the entire batch.
+    /// Pipelines in the batch can derive from pipelines created beforehand (see [`PipelineInfo::derive_from`]).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `context` - The rendering context.
+    /// * `infos` - The description of each pipeline.
+    /// 
+    /// # Returns
+    /// 
+    /// The pipelines, in the same order as `infos`.
+    pub fn build_many(context : &RenderingContext, infos : Vec<PipelineInfo>) -> Vec<Self> {
+        let shader_names = CString::new("main").unwrap();
+
+        // Pipeline creation is split in several passes because every structure handed to Vulkan borrows from the previous pass.
+        let resources = infos.iter()
+            .map(|info| PipelineResources::new(context, info))
+            .collect::<Vec<_>>();
+
+        let specializations = infos.iter()
+            .map(PipelineInfo::specialization_info)
+            .collect::<Vec<_>>();
+
+        let states = infos.iter().zip(&resources).zip(&specializations)
+            .map(|((info, resources), specialization)| PipelineStates::new(info, resources, specialization.as_ref(), &shader_names))
+            .collect::<Vec<_>>();
+
+        let create_infos = infos.iter().zip(&states)
+            .map(|(info, states)| states.create_info(info))
+            .collect::<Vec<_>>();
+
+        let pipelines = unsafe {
+            profile_scope!("Pipeline creation");
+
+            let pool_handle = if infos.iter().any(|info| info.pool) {
+                context.device.pipeline_pool.handle()
+            } else {
+                vk::PipelineCache::null()
+            };
+
+            context.device.handle().create_graphics_pipelines(pool_handle, &create_infos, None)
+                .map_err(|(_, err)| err)
+                .expect("Creating a graphics pipeline failed")
+        };
+
+        infos.into_iter().zip(pipelines).map(|(info, handle)| {
+            if let Some(name) = info.name {
+                context.device.set_handle_name(handle, &name.to_owned());
+            }
+
+            Self {
+                context : context.clone(),
+                handle,
+                info,
+            }
+        }).collect()
+    }
+}
+
+/// Resources owned during the creation of a pipeline, which the various pipeline states point to.
+struct PipelineResources {
+    shaders : Vec<Shader>,
+    patch_control_points : Option<u32>,
+    line_width : f32,
+    dynamic_states : Vec<vk::DynamicState>,
+    vertex_bindings : Vec<vk::VertexInputBindingDescription>,
+}
+
+impl PipelineResources {
+    fn new(context : &RenderingContext, info : &PipelineInfo) -> Self {
         let shaders = info.shaders.iter()
             .cloned() // TODO: remove this
             .map(|(path, flags)| Shader::new(context, path, flags))
             .collect::<Vec<_>>();
 
-        let shader_names = CString::new("main").unwrap();
+        if info.shaders.iter().any(|(_, stage)| stage.contains(vk::ShaderStageFlags::GEOMETRY)) {
+            assert!(context.device.features.geometry_shader != 0,
+                "Geometry shaders require the geometryShader feature; see RendererOptions::required_features");
+        }
 
-        let shader_stage_create_infos = shaders.iter().map(|shader| {
-            if info.specialization_entries.is_empty() {
-                shader.stage_info(None, &shader_names)
-            } else {
-                shader.stage_info(vk::SpecializationInfo::default()
-                    .map_entries(&info.specialization_entries)
-                    .data(&info.specialization_data)
-                    .into(), &shader_names)
-            }
-        }).collect::<Vec<_>>();
-
-        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
-            .scissor_count(1)
-            .viewport_count(1);
-
-        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
-            .dynamic_states(&[
-                vk::DynamicState::VIEWPORT,
-                vk::DynamicState::SCISSOR
-            ]);
-
-        let vertex_bindings = {
-            let mut bindings = vec![];
-            for (stride, rate) in &info.vertex_bindings {
-                bindings.push(vk::VertexInputBindingDescription::default()
-                    .binding(0)
-                    .input_rate(*rate)
-                    .stride(*stride)
-                );
+        let has_tessellation = info.shaders.iter().any(|(_, stage)| stage.intersects(
+            vk::ShaderStageFlags::TESSELLATION_CONTROL | vk::ShaderStageFlags::TESSELLATION_EVALUATION
+        ));
+        let patch_control_points = if has_tessellation {
+            assert!(context.device.features.tessellation_shader != 0, "Tessellation shaders require the tessellationShader feature");
+            assert_eq!(info.topology, vk::PrimitiveTopology::PATCH_LIST, "Pipelines with tessellation shaders must use the PATCH_LIST topology");
+
+            let patch_control_points = info.patch_control_points
+                .expect("Pipelines with tessellation shaders must specify the number of patch control points");
+            assert!(patch_control_points > 0 && patch_control_points <= context.device.physical_device.properties.limits.max_tessellation_patch_size,
+                "Unsupported number of patch control points");
+
+            Some(patch_control_points)
+        } else {
+            assert_ne!(info.topology, vk::PrimitiveTopology::PATCH_LIST, "The PATCH_LIST topology requires tessellation shaders");
+            None
+        };
+
+        assert!(info.polygon_mode == vk::PolygonMode::FILL || context.device.features.fill_mode_non_solid != 0,
+            "Polygon modes other than FILL require the fillModeNonSolid feature");
+        assert!(info.viewports > 0, "Pipelines must render to at least one viewport");
+        validate_viewports(0, info.viewports, context.device.features.multi_viewport != 0,
+            context.device.physical_device.properties.limits.max_viewports);
+
+        let line_width = info.line_width.unwrap_or(context.options.line_width);
+        let mut dynamic_states = vec![
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR
+        ];
+        let line_width = match line_width {
+            DynamicState::Fixed(line_width) => {
+                // Any value other than 1 requires a GPU feature
+                assert!(line_width == 1.0f32 || context.device.features.wide_lines != 0,
+                    "Line widths other than 1.0 require the wideLines feature");
+                line_width
+            },
+            DynamicState::Dynamic => {
+                dynamic_states.push(vk::DynamicState::LINE_WIDTH);
+                1.0f32
             }
-            bindings
         };
-        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+
+        match info.depth_bias {
+            Some(DynamicState::Fixed([_, clamp, _])) => {
+                assert!(clamp == 0.0f32 || context.device.features.depth_bias_clamp != 0,
+                    "Depth bias clamp values other than 0.0 require the depthBiasClamp feature");
+            },
+            Some(DynamicState::Dynamic) => dynamic_states.push(vk::DynamicState::DEPTH_BIAS),
+            None => (),
+        }
+
+        if let Some(stencil) = &info.depth.stencil {
+            dynamic_states.extend(stencil.dynamic_states());
+        }
+
+        assert!(!info.primitive_restart || matches!(info.topology,
+                vk::PrimitiveTopology::LINE_STRIP
+                | vk::PrimitiveTopology::TRIANGLE_STRIP
+                | vk::PrimitiveTopology::TRIANGLE_FAN
+                | vk::PrimitiveTopology::LINE_STRIP_WITH_ADJACENCY
+                | vk::PrimitiveTopology::TRIANGLE_STRIP_WITH_ADJACENCY
+            ), "Primitive restart can only be enabled for strip and fan topologies");
+
+        let vertex_bindings = vertex_bindings(&info.vertex_bindings, &info.vertex_format_offset);
+
+        Self {
+            shaders,
+            patch_control_points,
+            line_width,
+            dynamic_states,
+            vertex_bindings,
+        }
+    }
+}
+
+/// Returns the descriptions of the vertex input bindings of a pipeline.
+///
+/// # Arguments
+///
+/// * `bindings` - The stride and input rate of each binding; see [`Vertex::bindings`].
+/// * `attributes` - The vertex attributes; see [`Vertex::format_offset`].
+///
+/// # Panics
+///
+/// * Panics if an attribute refers to a binding that does not exist.
+fn vertex_bindings(bindings : &[(u32, vk::VertexInputRate)], attributes : &[vk::VertexInputAttributeDescription]) -> Vec<vk::VertexInputBindingDescription> {
+    for attribute in attributes {
+        assert!((attribute.binding as usize) < bindings.len(),
+            "Vertex attribute at location {} refers to binding {}, but only {} bindings are declared", attribute.location, attribute.binding, bindings.len());
+    }
+
+    bindings.iter()
+        .enumerate()
+        .map(|(binding, (stride, rate))| vk::VertexInputBindingDescription::default()
+            .binding(binding as u32)
+            .input_rate(*rate)
+            .stride(*stride))
+        .collect()
+}
+
+/// Ensures that a range of viewports or scissors is supported by the device.
+///
+/// # Arguments
+///
+/// * `first` - The index of the first viewport.
+/// * `count` - The number of viewports.
+/// * `multi_viewport` - Whether the `multiViewport` feature is enabled.
+/// * `max_viewports` - The maximum number of viewports supported by the device.
+///
+/// # Panics
+///
+/// * Panics if the range goes beyond the first viewport and the `multiViewport` feature is not enabled.
+/// * Panics if the range exceeds the maximum number of viewports.
+pub(in crate) fn validate_viewports(first : u32, count : u32, multi_viewport : bool, max_viewports : u32) {
+    assert!(multi_viewport || (first == 0 && count <= 1),
+        "Using viewports or scissors other than the first requires the multiViewport feature; see RendererOptions::required_features");
+    assert!(first + count <= max_viewports,
+        "Viewports {first} to {} exceed the maximum of {max_viewports} viewports", first + count);
+}
+
+/// The state structures of a pipeline, pointing into its [`PipelineResources`].
+struct PipelineStates<'a> {
+    stages : Vec<vk::PipelineShaderStageCreateInfo<'a>>,
+    tessellation : Option<vk::PipelineTessellationStateCreateInfo<'a>>,
+    viewport : vk::PipelineViewportStateCreateInfo<'a>,
+    dynamic : vk::PipelineDynamicStateCreateInfo<'a>,
+    vertex_input : vk::PipelineVertexInputStateCreateInfo<'a>,
+    input_assembly : vk::PipelineInputAssemblyStateCreateInfo<'a>,
+    rasterization : vk::PipelineRasterizationStateCreateInfo<'a>,
+    multisample : vk::PipelineMultisampleStateCreateInfo<'a>,
+    depth_stencil : vk::PipelineDepthStencilStateCreateInfo<'a>,
+    color_blend : vk::PipelineColorBlendStateCreateInfo<'a>,
+}
+
+impl<'a> PipelineStates<'a> {
+    fn new(
+        info : &'a PipelineInfo,
+        resources : &'a PipelineResources,
+        specialization : Option<&'a vk::SpecializationInfo<'a>>,
+        shader_names : &'a CString
+    ) -> Self {
+        let stages = resources.shaders.iter()
+            .map(|shader| shader.stage_info(specialization, shader_names))
+            .collect::<Vec<_>>();
+
+        let tessellation = resources.patch_control_points.map(|patch_control_points| {
+            vk::PipelineTessellationStateCreateInfo::default()
+                .patch_control_points(patch_control_points)
+        });
+
+        let viewport = vk::PipelineViewportStateCreateInfo::default()
+            .scissor_count(info.viewports)
+            .viewport_count(info.viewports);
+
+        let dynamic = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&resources.dynamic_states);
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
             .vertex_attribute_descriptions(&info.vertex_format_offset)
-            .vertex_binding_descriptions(&vertex_bindings);
+            .vertex_binding_descriptions(&resources.vertex_bindings);
 
-        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .primitive_restart_enable(false)
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .primitive_restart_enable(info.primitive_restart)
             .topology(info.topology);
 
-        // TODO: Allow for depth bias configuration
-        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-            .cull_mode(info.cull_mode)
-            // .depth_clamp_enable(false)
-            // .rasterizer_discard_enable(false)
-            // .depth_bias_enable(false)
-            .line_width(1.0f32) // Any value larger than 1 requires a GPU feature
-            .polygon_mode(vk::PolygonMode::FILL)
-            .front_face(info.front_face);
-        
-        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+        let rasterization = info.rasterization_state(resources.line_width);
+
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
             .sample_shading_enable(false)
             .rasterization_samples(info.samples)
             .min_sample_shading(1.0f32)
             .alpha_to_coverage_enable(false)
             .alpha_to_one_enable(false);
 
-        let depth_stencil_state = info.depth.build();
+        let depth_stencil = info.depth.build();
 
         // TODO: This array needs to be synced with render_pass.subpasses[all].colorAttachmentCount
-        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::default()
             .logic_op_enable(false)
             .logic_op(vk::LogicOp::COPY)
-            .blend_constants([0.0f32; 4])
+            .blend_constants(info.blend_constants)
             .attachments(&info.color_blend_attachments);
 
+        Self {
+            stages,
+            tessellation,
+            viewport,
+            dynamic,
+            vertex_input,
+            input_assembly,
+            rasterization,
+            multisample,
+            depth_stencil,
+            color_blend,
+        }
+    }
+
+    fn create_info(&'a self, info : &PipelineInfo) -> vk::GraphicsPipelineCreateInfo<'a> {
         let create_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stage_create_infos[..])
-            .viewport_state(&viewport_state)
-            .dynamic_state(&dynamic_state)
-            .vertex_input_state(&vertex_input_state)
-            .input_assembly_state(&input_assembly_state)
-            .rasterization_state(&rasterization_state)
-            .multisample_state(&multisample_state)
-            .depth_stencil_state(&depth_stencil_state)
-            .color_blend_state(&color_blend_state)
+            .flags(info.flags)
+            .base_pipeline_handle(info.base_pipeline)
+            .base_pipeline_index(-1)
+            .stages(&self.stages)
+            .viewport_state(&self.viewport)
+            .dynamic_state(&self.dynamic)
+            .vertex_input_state(&self.vertex_input)
+            .input_assembly_state(&self.input_assembly)
+            .rasterization_state(&self.rasterization)
+            .multisample_state(&self.multisample)
+            .depth_stencil_state(&self.depth_stencil)
+            .color_blend_state(&self.color_blend)
             .render_pass(info.render_pass)
             .subpass(info.subpass)
             .layout(info.layout);
 
-        let pipelines = unsafe {
-            let pool_handle = if info.pool {
-                context.device.pipeline_pool.handle()
-            } else {
-                vk::PipelineCache::null()
-            };
-
-            context.device.handle().create_graphics_pipelines(pool_handle, &[create_info], None)
-                .expect("Creating a graphics pipeline failed")
-        };
-
-        if let Some(name) = info.name {
-            context.device.set_handle_name(pipelines[0], &name.to_owned());
-        }
-
-        Self {
-            context : context.clone(),
-            handle : pipelines[0],
-            info,
+        match &self.tessellation {
+            Some(tessellation) => create_info.tessellation_state(tessellation),
+            None => create_info,
         }
     }
 }
@@ -312,3 +847,270 @@ impl Drop for Pipeline {
 }
 
 make_handle! { Pipeline, vk::Pipeline }
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::path::Path;
+
+    use ash::vk;
+
+    use crate::traits::handle::Handle;
+    use crate::vk::descriptor::layout::DescriptorSetLayout;
+    use crate::vk::pipeline::layout::PipelineLayoutInfo;
+    use crate::vk::render_pass::{RenderPass, SubpassAttachment};
+    use crate::vk::renderer::DynamicState;
+
+    use super::{validate_viewports, vertex_bindings, DepthOptions, Pipeline, PipelineInfo, PipelineResources, PipelineStates, StencilOptions, Vertex};
+
+    /// The vertex layout of the interface shaders.
+    struct TestVertex;
+
+    impl Vertex for TestVertex {
+        fn bindings() -> Vec<(u32, vk::VertexInputRate)> {
+            vec![(20, vk::VertexInputRate::VERTEX)]
+        }
+
+        fn format_offset() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![
+                vk::VertexInputAttributeDescription::default().binding(0).offset(0).location(0).format(vk::Format::R32G32_SFLOAT),
+                vk::VertexInputAttributeDescription::default().binding(0).offset(8).location(1).format(vk::Format::R32G32_SFLOAT),
+                vk::VertexInputAttributeDescription::default().binding(0).offset(16).location(2).format(vk::Format::R8G8B8A8_UNORM),
+            ]
+        }
+    }
+
+    #[test]
+    pub fn instanced_vertex_bindings() {
+        let attributes = [
+            // Per-vertex position
+            vk::VertexInputAttributeDescription::default().binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT),
+            // Per-instance offset
+            vk::VertexInputAttributeDescription::default().binding(1).location(1).format(vk::Format::R32G32B32_SFLOAT),
+        ];
+
+        let bindings = vertex_bindings(&[(12, vk::VertexInputRate::VERTEX), (16, vk::VertexInputRate::INSTANCE)], &attributes);
+        assert_eq!(bindings.len(), 2);
+        assert_eq!((bindings[0].binding, bindings[0].stride, bindings[0].input_rate), (0, 12, vk::VertexInputRate::VERTEX));
+        assert_eq!((bindings[1].binding, bindings[1].stride, bindings[1].input_rate), (1, 16, vk::VertexInputRate::INSTANCE));
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn missing_vertex_binding() {
+        let attributes = [
+            vk::VertexInputAttributeDescription::default().binding(1).location(0).format(vk::Format::R32G32B32_SFLOAT),
+        ];
+
+        vertex_bindings(&[(12, vk::VertexInputRate::VERTEX)], &attributes);
+    }
+
+    #[test]
+    pub fn depth_bias() {
+        let state = PipelineInfo::default().rasterization_state(1.0f32);
+        assert_eq!(state.depth_bias_enable, vk::FALSE);
+
+        let state = PipelineInfo::default()
+            .depth_bias(1.25f32, 0.0f32, 1.75f32)
+            .rasterization_state(1.0f32);
+        assert_eq!(state.depth_bias_enable, vk::TRUE);
+        assert_eq!(state.depth_bias_constant_factor, 1.25f32);
+        assert_eq!(state.depth_bias_clamp, 0.0f32);
+        assert_eq!(state.depth_bias_slope_factor, 1.75f32);
+
+        let state = PipelineInfo::default()
+            .dynamic_depth_bias()
+            .rasterization_state(1.0f32);
+        assert_eq!(state.depth_bias_enable, vk::TRUE);
+    }
+
+    #[test]
+    pub fn derivatives() {
+        let resources = PipelineResources {
+            shaders : vec![],
+            patch_control_points : None,
+            line_width : 1.0f32,
+            dynamic_states : vec![],
+            vertex_bindings : vec![],
+        };
+        let shader_names = CString::new("main").unwrap();
+
+        let info = PipelineInfo::default();
+        let states = PipelineStates::new(&info, &resources, None, &shader_names);
+        let create_info = states.create_info(&info);
+        assert!(create_info.flags.is_empty());
+        assert_eq!(create_info.base_pipeline_handle, vk::Pipeline::null());
+
+        let info = PipelineInfo::default().allow_derivatives();
+        let states = PipelineStates::new(&info, &resources, None, &shader_names);
+        let create_info = states.create_info(&info);
+        assert_eq!(create_info.flags, vk::PipelineCreateFlags::ALLOW_DERIVATIVES);
+        assert_eq!(create_info.base_pipeline_index, -1);
+    }
+
+    #[test]
+    #[ignore = "requires a display and a Vulkan device"]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    pub fn derivative_create_infos() {
+        let (_event_loop, app) = crate::testing::test_application();
+        let context = &app.orchestrator.context;
+
+        let render_pass = RenderPass::builder()
+            .color_attachment(vk::Format::R8G8B8A8_UNORM, vk::SampleCountFlags::TYPE_1,
+                vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE,
+                vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .subpass(vk::PipelineBindPoint::GRAPHICS, &[SubpassAttachment::color(0)], None)
+            .build(context);
+        let descriptor_set_layout = DescriptorSetLayout::builder()
+            .sets(1)
+            .binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, 1)
+            .build(context);
+        let layout = PipelineLayoutInfo::default()
+            .layout(&descriptor_set_layout)
+            .push_constant(vk::PushConstantRange::default().stage_flags(vk::ShaderStageFlags::VERTEX).size(8))
+            .build(context);
+
+        // The variants share their shaders and layout, and only differ by their blend state.
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets");
+        let variant = |index : u32| PipelineInfo::default()
+            .layout(&layout)
+            .render_pass(render_pass.handle(), 0)
+            .vertex::<TestVertex>()
+            .add_shader(assets.join("gui.vert"), vk::ShaderStageFlags::VERTEX)
+            .add_shader(assets.join("gui.frag"), vk::ShaderStageFlags::FRAGMENT)
+            .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default()
+                .blend_enable(index % 2 == 1)
+                .src_color_blend_factor(vk::BlendFactor::ONE)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_write_mask(vk::ColorComponentFlags::from_raw(index % 15 + 1)));
+
+        let shader_names = CString::new("main").unwrap();
+        let base = variant(0).allow_derivatives().build(context);
+        let resources = PipelineResources::new(context, &base.info);
+        let states = PipelineStates::new(&base.info, &resources, None, &shader_names);
+        let create_info = states.create_info(&base.info);
+        assert_eq!(create_info.flags, vk::PipelineCreateFlags::ALLOW_DERIVATIVES);
+        assert_eq!(create_info.base_pipeline_handle, vk::Pipeline::null());
+
+        // Derivatives reference their parent by handle, since it was created by an earlier call.
+        let infos = (1..4).map(|index| variant(index).derive_from(&base)).collect::<Vec<_>>();
+        for info in &infos {
+            let resources = PipelineResources::new(context, info);
+            let states = PipelineStates::new(info, &resources, None, &shader_names);
+            let create_info = states.create_info(info);
+            assert_eq!(create_info.flags, vk::PipelineCreateFlags::DERIVATIVE);
+            assert_eq!(create_info.base_pipeline_handle, base.handle);
+            assert_eq!(create_info.base_pipeline_index, -1);
+        }
+
+        let derivatives = Pipeline::build_many(context, infos);
+        assert_eq!(derivatives.len(), 3);
+        assert!(derivatives.iter().all(|pipeline| pipeline.handle != vk::Pipeline::null()));
+    }
+
+    #[test]
+    pub fn stencil() {
+        let depth = DepthOptions::disabled();
+        assert_eq!(depth.build().stencil_test_enable, vk::FALSE);
+
+        let stencil = StencilOptions::new(vk::CompareOp::EQUAL)
+            .ops(vk::StencilOp::KEEP, vk::StencilOp::REPLACE, vk::StencilOp::KEEP)
+            .compare_mask(0x0F)
+            .write_mask(0xF0)
+            .reference(DynamicState::Dynamic);
+        assert_eq!(stencil.dynamic_states(), vec![vk::DynamicState::STENCIL_REFERENCE]);
+
+        let depth = DepthOptions::disabled().stencil(stencil);
+        let state = depth.build();
+        assert_eq!(state.stencil_test_enable, vk::TRUE);
+        assert_eq!(state.depth_test_enable, vk::FALSE);
+        for face in [state.front, state.back] {
+            assert_eq!(face.compare_op, vk::CompareOp::EQUAL);
+            assert_eq!(face.pass_op, vk::StencilOp::REPLACE);
+            assert_eq!(face.fail_op, vk::StencilOp::KEEP);
+            assert_eq!(face.compare_mask, 0x0F);
+            assert_eq!(face.write_mask, 0xF0);
+        }
+
+        let stencil = stencil.compare_mask(DynamicState::Dynamic);
+        assert_eq!(stencil.dynamic_states(), vec![vk::DynamicState::STENCIL_COMPARE_MASK, vk::DynamicState::STENCIL_REFERENCE]);
+    }
+
+    #[test]
+    pub fn specializations() {
+        let info = PipelineInfo::default();
+        assert!(info.specialization_info().is_none());
+
+        let info = PipelineInfo::default()
+            .add_specialization(&vk::TRUE, 0)
+            .add_specialization(&2.5f64, 3);
+        let specialization = info.specialization_info().unwrap();
+        assert_eq!(specialization.data_size, 12);
+        assert_eq!(specialization.map_entry_count, 2);
+        assert_eq!((info.specialization_entries[0].offset, info.specialization_entries[0].size), (0, 4));
+        assert_eq!((info.specialization_entries[1].offset, info.specialization_entries[1].size), (4, 8));
+        assert_eq!(info.specialization_entries[1].constant_id, 3);
+    }
+
+    #[test]
+    pub fn viewports() {
+        validate_viewports(0, 1, false, 1);
+        validate_viewports(0, 2, true, 16);
+        validate_viewports(14, 2, true, 16);
+
+        let info = PipelineInfo::default();
+        assert_eq!(info.viewports, 1);
+        assert_eq!(info.viewports(2).viewports, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn multiple_viewports_without_feature() {
+        validate_viewports(0, 2, false, 16);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn too_many_viewports() {
+        validate_viewports(15, 2, true, 16);
+    }
+
+    #[test]
+    pub fn depth_compare_op() {
+        let resources = PipelineResources {
+            shaders : vec![],
+            patch_control_points : None,
+            line_width : 1.0f32,
+            dynamic_states : vec![],
+            vertex_bindings : vec![],
+        };
+        let shader_names = CString::new("main").unwrap();
+
+        let info = PipelineInfo::default();
+        let states = PipelineStates::new(&info, &resources, None, &shader_names);
+        assert_eq!(states.depth_stencil.depth_compare_op, vk::CompareOp::LESS);
+
+        let info = PipelineInfo::default()
+            .depth(DepthOptions::enabled().compare_op(vk::CompareOp::GREATER));
+        let states = PipelineStates::new(&info, &resources, None, &shader_names);
+        let create_info = states.create_info(&info);
+        let depth_stencil = unsafe { &*create_info.p_depth_stencil_state };
+        assert_eq!(depth_stencil.depth_compare_op, vk::CompareOp::GREATER);
+        assert_eq!(depth_stencil.depth_test_enable, vk::TRUE);
+        assert_eq!(depth_stencil.depth_write_enable, vk::TRUE);
+    }
+
+    #[test]
+    pub fn depth_defaults() {
+        // Regression: enabled() used to leave depth writes disabled.
+        let expectations = [
+            (DepthOptions::enabled(), vk::TRUE, vk::TRUE),
+            (DepthOptions::test_only(), vk::TRUE, vk::FALSE),
+            (DepthOptions::disabled(), vk::FALSE, vk::FALSE),
+        ];
+        for (depth, test, write) in expectations {
+            let state = depth.build();
+            assert_eq!((state.depth_test_enable, state.depth_write_enable), (test, write));
+        }
+    }
+}
@@ -62,6 +62,14 @@ pub struct PipelineLayout {
 
 make_handle! { PipelineLayout, vk::PipelineLayout, layout }
 
+impl PipelineLayout {
+    /// Returns the descriptor set layouts this layout was created with, in set order.
+    #[inline] pub fn set_layouts(&self) -> &[vk::DescriptorSetLayout] { &self.info.descriptor_sets }
+
+    /// Returns the push constant ranges this layout was created with.
+    #[inline] pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] { &self.info.push_constants }
+}
+
 impl Drop for PipelineLayout {
     fn drop(&mut self) {
         unsafe {
@@ -1,19 +1,36 @@
-use std::{fs, path::PathBuf};
+use std::{fs, io, path::{Path, PathBuf}};
 
 use ash::vk;
 
-use crate::{make_handle, vk::logical_device::LogicalDevice};
+use crate::make_handle;
+
+/// Size of `VkPipelineCacheHeaderVersionOne`.
+const HEADER_SIZE : usize = 16 + vk::UUID_SIZE;
 
 pub struct PipelinePool {
+    device : ash::Device,
     cache : vk::PipelineCache,
 
-    path : PathBuf,
+    path : Option<PathBuf>,
 }
 
 impl PipelinePool {
-    pub fn new(device : ash::Device, path : PathBuf) -> Self {
-        let data = fs::read(path.as_path()).unwrap_or(vec![]);
-        
+    /// Creates a new pipeline pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The logical device.
+    /// * `properties` - Properties of the physical device backing `device`.
+    /// * `path` - An optional file the pool is loaded from and saved to. The identity of the device is inserted in its
+    ///            name (see [`device_cache_path`]), so that devices do not share their caches. If that file was still
+    ///            written for a different device or driver, its contents are discarded.
+    pub fn new(device : ash::Device, properties : &vk::PhysicalDeviceProperties, path : Option<PathBuf>) -> Self {
+        let path = path.map(|path| device_cache_path(&path, properties));
+        let data = path.as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .filter(|data| is_compatible(data, properties))
+            .unwrap_or_default();
+
         let create_info = vk::PipelineCacheCreateInfo::default()
             .initial_data(&data);
 
@@ -22,16 +39,151 @@ impl PipelinePool {
                 .expect("An error occured while creating a pipeline cache")
         };
 
-        Self { cache, path }
+        Self { device, cache, path }
+    }
+
+    /// Writes the contents of this pool to its backing file, if any.
+    ///
+    /// # Description
+    ///
+    /// The pool is saved when the device is destroyed, but this function can be called periodically
+    /// so that the cache survives abnormal terminations.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = unsafe {
+            self.device.get_pipeline_cache_data(self.cache)
+                .map_err(io::Error::other)?
+        };
+
+        fs::write(path, data)
     }
 
-    pub fn save(&self, device : &LogicalDevice) {
+    /// Merges the contents of other pools into this pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The pools to merge into this one. They are left untouched.
+    pub fn merge(&self, sources : &[&PipelinePool]) {
+        let sources = sources.iter()
+            .map(|pool| pool.cache)
+            .collect::<Vec<_>>();
+
         unsafe {
-            let data = device.handle().get_pipeline_cache_data(self.cache).unwrap_or(vec![]);
+            self.device.merge_pipeline_caches(self.cache, &sources)
+                .expect("Merging pipeline caches failed");
+        }
+    }
+}
 
-            _ = fs::write(self.path.as_path(), data);
+impl Drop for PipelinePool {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline_cache(self.cache, None);
         }
     }
 }
 
+/// Returns true if the given pipeline cache data was produced by the device described by `properties`.
+///
+/// # Arguments
+///
+/// * `data` - Pipeline cache data, starting with a `VkPipelineCacheHeaderVersionOne`.
+/// * `properties` - The properties of the device that will consume `data`.
+fn is_compatible(data : &[u8], properties : &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset : usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+/// Returns the path of the pipeline cache of a device, inserting its vendor and device IDs as well as its pipeline cache
+/// UUID before the extension of the given path.
+///
+/// # Arguments
+///
+/// * `path` - The path shared by the caches of all devices.
+/// * `properties` - The properties of the device.
+pub fn device_cache_path(path : &Path, properties : &vk::PhysicalDeviceProperties) -> PathBuf {
+    let uuid = properties.pipeline_cache_uuid.iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    let identity = format!("{:04x}-{:04x}-{}", properties.vendor_id, properties.device_id, uuid);
+
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(identity);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
 make_handle! { PipelinePool, vk::PipelineCache, cache }
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use ash::vk;
+
+    use super::{device_cache_path, is_compatible, HEADER_SIZE};
+
+    #[test]
+    pub fn header_validation() {
+        let properties = vk::PhysicalDeviceProperties {
+            vendor_id : 0x10DE,
+            device_id : 0x2684,
+            pipeline_cache_uuid : [7; vk::UUID_SIZE],
+            ..Default::default()
+        };
+
+        let mut data = vec![];
+        data.extend((HEADER_SIZE as u32).to_le_bytes());
+        data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+        data.extend(0x10DEu32.to_le_bytes());
+        data.extend(0x2684u32.to_le_bytes());
+        data.extend([7; vk::UUID_SIZE]);
+        data.extend([0xAB; 64]);
+        assert!(is_compatible(&data, &properties));
+
+        assert!(!is_compatible(&data[..HEADER_SIZE - 1], &properties));
+        assert!(!is_compatible(&[], &properties));
+
+        let mut other_device = data.clone();
+        other_device[12] ^= 1;
+        assert!(!is_compatible(&other_device, &properties));
+
+        let mut other_driver = data.clone();
+        other_driver[HEADER_SIZE - 1] ^= 1;
+        assert!(!is_compatible(&other_driver, &properties));
+    }
+
+    #[test]
+    pub fn device_cache_paths() {
+        let properties = vk::PhysicalDeviceProperties {
+            vendor_id : 0x10DE,
+            device_id : 0x2684,
+            pipeline_cache_uuid : [0xA5; vk::UUID_SIZE],
+            ..Default::default()
+        };
+        let identity = format!("10de-2684-{}", "a5".repeat(vk::UUID_SIZE));
+
+        assert_eq!(device_cache_path(Path::new("pipelines.dat"), &properties), PathBuf::from(format!("pipelines.{}.dat", identity)));
+        assert_eq!(device_cache_path(Path::new("cache/pipelines"), &properties), PathBuf::from(format!("cache/pipelines.{}", identity)));
+
+        let other_device = vk::PhysicalDeviceProperties { device_id : 0x2685, ..properties };
+        assert_ne!(device_cache_path(Path::new("pipelines.dat"), &properties), device_cache_path(Path::new("pipelines.dat"), &other_device));
+    }
+}
@@ -73,17 +73,16 @@ impl Shader {
         }
     }
 
-    pub fn stage_info<'a>(&self, spec : Option<vk::SpecializationInfo>, name : &'a CStr) -> vk::PipelineShaderStageCreateInfo<'a> {
+    pub fn stage_info<'a>(&self, spec : Option<&'a vk::SpecializationInfo<'a>>, name : &'a CStr) -> vk::PipelineShaderStageCreateInfo<'a> {
         let create_info = vk::PipelineShaderStageCreateInfo::default()
             .name(name)
             .stage(self.flags)
             .module(self.module);
 
-        if spec.is_some() {
-            _ = create_info.specialization_info(&spec.unwrap());
+        match spec {
+            Some(spec) => create_info.specialization_info(spec),
+            None => create_info,
         }
-
-        create_info
     }
 }
 
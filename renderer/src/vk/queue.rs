@@ -108,6 +108,10 @@ impl QueueFamily {
     /// Checks if this queue family supports compute operations.
     #[inline] pub fn is_compute(&self) -> bool { self.properties.queue_flags.contains(vk::QueueFlags::COMPUTE) }
 
+    /// Checks if this queue family supports compute operations but not graphics operations.
+    /// Work submitted to queues of such a family can execute concurrently with graphics work.
+    #[inline] pub fn is_async_compute(&self) -> bool { self.is_compute() && !self.is_graphics() }
+
     /// Checks if this queue family supports transfer operations.
     #[inline] pub fn is_transfer(&self) -> bool { self.properties.queue_flags.contains(vk::QueueFlags::TRANSFER) || self.is_compute() || self.is_graphics() }
 
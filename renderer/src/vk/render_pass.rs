@@ -1,9 +1,12 @@
+use std::sync::Arc;
+
 use ash::vk;
 
 use crate::make_handle;
 use crate::orchestration::rendering::RenderingContext;
 
-use super::framebuffer::Framebuffer;
+use super::framebuffer::{Framebuffer, FramebufferCache};
+use super::renderer::RendererOptions;
 use super::swapchain::{Swapchain, SwapchainImage};
 
 pub struct RenderPass {
@@ -11,6 +14,8 @@ pub struct RenderPass {
     context : RenderingContext,
 
     spec : RenderPassAttachmentSpec,
+    layout : RenderPassLayout,
+    scaled : bool,
 }
 
 impl RenderPass {
@@ -18,6 +23,35 @@ impl RenderPass {
         RenderPassCreateInfo::default()
     }
 
+    /// Returns true if this render pass is compatible with another one.
+    ///
+    /// # Description
+    ///
+    /// Framebuffers and pipelines created for a render pass can be used with any compatible render pass. Render passes
+    /// are compatible if the attachments referenced by their subpasses have the same formats and sample counts, and if
+    /// they are otherwise identical, except for load and store operations and image layouts. This is typically used
+    /// to decide whether pipelines have to be rebuilt after the swapchain was recreated.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The render pass to compare against.
+    pub fn is_compatible_with(&self, other : &RenderPass) -> bool {
+        self.layout.is_compatible_with(&other.layout)
+    }
+
+    /// Returns true if this render pass draws to the scaled images of the swapchain; see
+    /// [`RenderPassCreateInfo::scaled`].
+    #[inline] pub fn is_scaled(&self) -> bool { self.scaled }
+
+    /// Returns the extent of the framebuffers of this render pass, which is also the area it renders to.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `swapchain` - The swapchain this render pass draws to.
+    pub fn extent(&self, swapchain : &Swapchain) -> vk::Extent2D {
+        if self.scaled { swapchain.render_extent } else { swapchain.extent }
+    }
+
     /// Returns a framebuffer that is compatible with this render pass and the given swap chain.
     /// 
     /// # Arguments
@@ -25,6 +59,30 @@ impl RenderPass {
     /// * `swapchain` - The swapchain for which a framebuffer is created
     /// * `image` - An image from the swapchain.
     pub fn create_framebuffer(&self, swapchain : &Swapchain, image : &SwapchainImage) -> Framebuffer {
+        let extent = self.extent(swapchain);
+        Framebuffer::new(&self.context, vk::FramebufferCreateInfo::default()
+            .width(extent.width)
+            .height(extent.height)
+            .render_pass(self.handle)
+            .layers(swapchain.layer_count())
+            .attachments(&self.framebuffer_attachments(image)))
+    }
+
+    /// Returns one framebuffer per image of the given swapchain, compatible with this render pass. Framebuffers are
+    /// taken from the cache if they already exist there.
+    ///
+    /// # Arguments
+    ///
+    /// * `swapchain` - The swapchain for which framebuffers are returned.
+    /// * `cache` - The cache framebuffers are taken from.
+    pub fn framebuffers(&self, swapchain : &Swapchain, cache : &mut FramebufferCache) -> Vec<Arc<Framebuffer>> {
+        swapchain.images.iter()
+            .map(|image| cache.get(&self.context, self.handle, &self.framebuffer_attachments(image), self.extent(swapchain), swapchain.layer_count()))
+            .collect()
+    }
+
+    /// Returns the views of a swapchain image that should be bound to the attachments of this render pass, in order.
+    fn framebuffer_attachments(&self, image : &SwapchainImage) -> Vec<vk::ImageView> {
         let mut attachments = vec![];
 
         // The attachments on this render pass dictates what we pull from the swapchain image
@@ -40,7 +98,10 @@ impl RenderPass {
             Some(depth) => depth.view(),
             None => vk::ImageView::null(),
         };
-        let color = image.present.view();
+        let color = match &image.scaled {
+            Some(scaled) if self.scaled => scaled.view(),
+            _ => image.present.view(),
+        };
 
         if has_resolve {
             attachments.push(resolve);
@@ -50,39 +111,33 @@ impl RenderPass {
             attachments.push(color);
             if has_depth { attachments.push(depth); }
         }
-        
-        Framebuffer::new(&self.context, vk::FramebufferCreateInfo::default()
-            .width(swapchain.extent.width)
-            .height(swapchain.extent.height)
-            .render_pass(self.handle)
-            .layers(swapchain.layer_count())
-            .attachments(&attachments))
-    }
 
-    pub fn find_supported_format(context : &RenderingContext, formats : &[vk::Format], tiling : vk::ImageTiling, flags : vk::FormatFeatureFlags) -> Option<vk::Format> {
-        for &format in formats {
-            let properties = context.device.physical_device.get_format_properties(&context.context, format);
-            if let Some(properties) = properties {
-                let supported = match tiling {
-                    vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(flags),
-                    vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features.contains(flags),
-                    _ => panic!("Unsupported tiling mode")
-                };
+        attachments
+    }
 
-                if supported {
-                    return Some(format);
-                }
-            }
-        }
+    /// Returns an array of clear values for all the attachments of this render pass, suitable for use with
+    /// [`CommandBuffer::begin_render_pass`](crate::vk::command_buffer::CommandBuffer::begin_render_pass).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `options` - The options providing the clear values.
+    pub fn clear_values(&self, options : &RendererOptions) -> Vec<vk::ClearValue> {
+        self.spec.clear_values(options.clear_color_value(), options.clear_depth_stencil_value())
+    }
 
-        None
+    pub fn find_supported_format(context : &RenderingContext, formats : &[vk::Format], tiling : vk::ImageTiling, flags : vk::FormatFeatureFlags) -> Option<vk::Format> {
+        formats.iter()
+            .copied()
+            .find(|&format| context.device.physical_device.supports_format(&context.context, format, tiling, flags))
     }
 
-    pub(in crate) fn new(context : RenderingContext, handle : vk::RenderPass, spec : RenderPassAttachmentSpec) -> RenderPass {
+    pub(in crate) fn new(context : RenderingContext, handle : vk::RenderPass, spec : RenderPassAttachmentSpec, layout : RenderPassLayout, scaled : bool) -> RenderPass {
         Self {
             context : context.clone(),
             handle,
             spec,
+            layout,
+            scaled,
         }
     }
 }
@@ -99,15 +154,81 @@ impl Drop for RenderPass {
 
 pub struct RenderPassAttachmentSpec {
     pub color_images   : Vec<(vk::Format, vk::SampleCountFlags, vk::AttachmentLoadOp, vk::AttachmentStoreOp, vk::ImageLayout, vk::ImageLayout)>,
-    pub depth_images   : Vec<(vk::Format, vk::SampleCountFlags, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
+    // Format, samples, depth operations and stencil operations.
+    pub depth_images   : Vec<(vk::Format, vk::SampleCountFlags, vk::AttachmentLoadOp, vk::AttachmentStoreOp, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
     pub resolve_images : Vec<(vk::Format, vk::ImageLayout)>,
 }
 
+impl RenderPassAttachmentSpec {
+    /// Returns one clear value per attachment, in the order attachments are declared in the render pass:
+    /// color attachments first, then depth attachments, and finally resolve attachments.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `color` - The value color and resolve attachments are cleared to.
+    /// * `depth_stencil` - The value depth attachments are cleared to.
+    pub fn clear_values(&self, color : vk::ClearColorValue, depth_stencil : vk::ClearDepthStencilValue) -> Vec<vk::ClearValue> {
+        let mut clear_values = Vec::with_capacity(self.color_images.len() + self.depth_images.len() + self.resolve_images.len());
+        clear_values.extend(self.color_images.iter().map(|_| vk::ClearValue { color }));
+        clear_values.extend(self.depth_images.iter().map(|_| vk::ClearValue { depth_stencil }));
+        clear_values.extend(self.resolve_images.iter().map(|_| vk::ClearValue { color }));
+        clear_values
+    }
+}
+
+/// The attachments referenced by a subpass, described by their format and sample count.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(in crate) struct SubpassLayout {
+    bind_point : vk::PipelineBindPoint,
+    colors : Vec<(vk::Format, vk::SampleCountFlags)>,
+    inputs : Vec<(vk::Format, vk::SampleCountFlags)>,
+    resolves : Vec<(vk::Format, vk::SampleCountFlags)>,
+    depth : Option<(vk::Format, vk::SampleCountFlags)>,
+}
+
+/// Everything that matters when deciding whether two render passes are compatible.
+pub(in crate) struct RenderPassLayout {
+    subpasses : Vec<SubpassLayout>,
+    dependencies : Vec<vk::SubpassDependency>,
+}
+
+impl RenderPassLayout {
+    /// Implements the render pass compatibility rules of the Vulkan specification. See [`RenderPass::is_compatible_with`].
+    fn is_compatible_with(&self, other : &RenderPassLayout) -> bool {
+        if self.subpasses.len() != other.subpasses.len() || self.dependencies.len() != other.dependencies.len() {
+            return false;
+        }
+
+        // Resolve attachments are ignored if both render passes have a single subpass.
+        let single_subpass = self.subpasses.len() == 1;
+        let subpasses_compatible = self.subpasses.iter().zip(&other.subpasses).all(|(lhs, rhs)| {
+            lhs.bind_point == rhs.bind_point
+                && lhs.colors == rhs.colors
+                && lhs.inputs == rhs.inputs
+                && lhs.depth == rhs.depth
+                && (single_subpass || lhs.resolves == rhs.resolves)
+        });
+
+        let dependencies_identical = self.dependencies.iter().zip(&other.dependencies).all(|(lhs, rhs)| {
+            lhs.src_subpass == rhs.src_subpass
+                && lhs.dst_subpass == rhs.dst_subpass
+                && lhs.src_stage_mask == rhs.src_stage_mask
+                && lhs.dst_stage_mask == rhs.dst_stage_mask
+                && lhs.src_access_mask == rhs.src_access_mask
+                && lhs.dst_access_mask == rhs.dst_access_mask
+                && lhs.dependency_flags == rhs.dependency_flags
+        });
+
+        subpasses_compatible && dependencies_identical
+    }
+}
+
 pub struct RenderPassCreateInfo {
     spec : RenderPassAttachmentSpec,
 
     dependencies : Vec<vk::SubpassDependency>,
     subpasses : Vec<(vk::PipelineBindPoint, Vec<SubpassAttachment>, Option<SubpassAttachment>)>,
+    scaled : bool,
 }
 
 impl RenderPassCreateInfo {
@@ -135,14 +256,32 @@ impl RenderPassCreateInfo {
 
     /// Expresses a dependency between two subpasses.
     /// 
-    /// Arguments
+    /// # Description
+    /// 
+    /// A subpass can depend on itself, which allows a pipeline barrier to be recorded within that subpass. This is
+    /// required when a subpass reads an attachment it also writes to, for example to implement programmable blending
+    /// through an [`input`](SubpassAttachment::input) attachment. Such dependencies are always framebuffer-local:
+    /// fragments only observe the writes made at their own location, through `subpassLoad` in fragment shaders.
+    /// 
+    /// ```glsl
+    /// layout (input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput previous;
+    /// 
+    /// void main() {
+    ///     outColor = blend(subpassLoad(previous), color);
+    /// }
+    /// ```
+    /// 
+    /// Between the draws that write and read the attachment, record a barrier matching the dependency, with
+    /// [`vk::DependencyFlags::BY_REGION`], through [`CommandBuffer::pipeline_barrier`](crate::vk::command_buffer::CommandBuffer::pipeline_barrier).
+    /// 
+    /// # Arguments
     /// 
     /// * `src_subpass` - The subpass that is about to finish.
     /// * `dst_subpass` - The subpass that is about to begin.
-    /// * `src_stage_mask` -
-    /// * `dst_stage_mask` - 
-    /// * `src_access_flags` -
-    /// * `dst_access_flags` - 
+    /// * `src_stage_mask` - The stages of the source subpass that must complete.
+    /// * `dst_stage_mask` - The stages of the destination subpass that wait.
+    /// * `src_access_flags` - The memory accesses of the source subpass that are made available.
+    /// * `dst_access_flags` - The memory accesses of the destination subpass that see them.
     pub fn dependency(mut self,
         src_subpass : u32,
         dst_subpass : u32,
@@ -158,11 +297,12 @@ impl RenderPassCreateInfo {
             .dst_stage_mask(dst_stage_mask)
             .src_access_mask(src_access_flags)
             .dst_access_mask(dst_access_flags)
+            .dependency_flags(dependency_flags(src_subpass, dst_subpass))
         );
         self
     }
 
-    /// Adds a depth attachment.
+    /// Adds a depth attachment. If the format has a stencil aspect, the same operations are used for stencil values.
     /// 
     /// # Arguments
     /// 
@@ -171,13 +311,39 @@ impl RenderPassCreateInfo {
     /// * `load` - The operation to use when this render pass begins.
     /// * `store` - The operation to use when this render pass finishes.
     pub fn depth_attachment(
-        mut self,
+        self,
         format : vk::Format,
         samples : vk::SampleCountFlags,
         load : vk::AttachmentLoadOp,
         store : vk::AttachmentStoreOp
     ) -> Self {
-        self.spec.depth_images.push((format, samples, load, store));
+        self.depth_stencil_attachment(format, samples, (load, store), (load, store))
+    }
+
+    /// Adds a depth/stencil attachment, with separate operations for its depth and stencil aspects. This is referenced
+    /// by subpasses as a [`SubpassAttachment::depth`] attachment.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `format` - The format of this attachment.
+    /// * `samples` - The amount of samples to use.
+    /// * `depth` - The operations to use on depth values when this render pass begins and finishes.
+    /// * `stencil` - The operations to use on stencil values when this render pass begins and finishes.
+    pub fn depth_stencil_attachment(
+        mut self,
+        format : vk::Format,
+        samples : vk::SampleCountFlags,
+        depth : (vk::AttachmentLoadOp, vk::AttachmentStoreOp),
+        stencil : (vk::AttachmentLoadOp, vk::AttachmentStoreOp)
+    ) -> Self {
+        self.spec.depth_images.push((format, samples, depth.0, depth.1, stencil.0, stencil.1));
+        self
+    }
+
+    /// Draws to the scaled images of the swapchain, at [`Swapchain::render_extent`], instead of the swapchain's own
+    /// images. This is set by [`Swapchain::create_render_pass`] when the swapchain is scaled.
+    pub fn scaled(mut self, scaled : bool) -> Self {
+        self.scaled = scaled;
         self
     }
 
@@ -228,6 +394,9 @@ impl RenderPassCreateInfo {
     /// It also takes in a single index as a depth attachment. In this case, the attachment must be referenced as a
     /// [`SubpassAttachment::depth`] attachment.
     /// 
+    /// A color attachment referenced both as a color and an input attachment is used in the `GENERAL` layout by this
+    /// subpass, which must then declare a [`dependency`](Self::dependency) on itself.
+    /// 
     /// # Arguments
     /// 
     /// * `bind_point` - The pipeline type supported by this subpass.
@@ -243,7 +412,19 @@ impl RenderPassCreateInfo {
         self
     }
 
+    /// Creates the render pass.
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if a dependency references a subpass that was not declared.
     pub fn build(self, context : &RenderingContext) -> RenderPass {
+        for dependency in &self.dependencies {
+            for subpass in [dependency.src_subpass, dependency.dst_subpass] {
+                assert!(subpass == vk::SUBPASS_EXTERNAL || (subpass as usize) < self.subpasses.len(),
+                    "Subpass dependency references subpass {subpass}, but only {} subpasses were declared", self.subpasses.len());
+            }
+        }
+
         let mut descs = Vec::<vk::AttachmentDescription>::new();
 
         let mut attachment_index = 0;
@@ -266,12 +447,12 @@ impl RenderPassCreateInfo {
         }
 
         let mut depth_attachment_refs = Vec::<vk::AttachmentReference>::new();
-        for (format, samples, load, store) in &self.spec.depth_images {
+        for (format, samples, load, store, stencil_load, stencil_store) in &self.spec.depth_images {
             descs.push(Self::make_attachment_description(
                 *format,
                 *samples,
-                (vk::AttachmentLoadOp::DONT_CARE, vk::AttachmentStoreOp::DONT_CARE),
                 (*load, *store),
+                (*stencil_load, *stencil_store),
                 vk::ImageLayout::UNDEFINED,
                 vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
             ));
@@ -302,17 +483,28 @@ impl RenderPassCreateInfo {
             attachment_index += 1;
         }
 
+        let describe = |reference : &vk::AttachmentReference| {
+            let description = &descs[reference.attachment as usize];
+            (description.format, description.samples)
+        };
+
         // This exists because the mapped arrays need to exist outside of the loop to satisfy the borrow checker.
         let subpass_data = self.subpasses.into_iter().map(|tuple| {
             let (bind_point, attachments, depth) = tuple;
 
             let mut colors = vec![];
+            let mut inputs = vec![];
             let mut resolves = vec![];
 
             for attachment in &attachments {
                 match attachment {
                     SubpassAttachment::Color(index) => {
-                        colors.push(color_attachment_refs[*index as usize])
+                        let layout = subpass_layout(&attachments, *index, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+                        colors.push(color_attachment_refs[*index as usize].layout(layout))
+                    },
+                    SubpassAttachment::Input(index) => {
+                        let layout = subpass_layout(&attachments, *index, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+                        inputs.push(color_attachment_refs[*index as usize].layout(layout))
                     },
                     SubpassAttachment::Resolve(index) => {
                         resolves.push(resolve_attachment_refs[*index as usize])
@@ -321,29 +513,44 @@ impl RenderPassCreateInfo {
                 };
             }
 
-            (bind_point, colors, resolves, depth)
+            (bind_point, colors, inputs, resolves, depth)
         }).collect::<Vec<_>>();
 
+        let mut layout = RenderPassLayout {
+            subpasses : Vec::with_capacity(subpass_data.len()),
+            dependencies : self.dependencies.clone(),
+        };
+
         let mut subpasses = vec![];
-        for (bind_point, colors, resolve, depth) in &subpass_data {
+        for (bind_point, colors, inputs, resolve, depth) in &subpass_data {
             let mut subpass_description = vk::SubpassDescription::default()
                 .pipeline_bind_point(*bind_point)
-                .color_attachments(colors);
+                .color_attachments(colors)
+                .input_attachments(inputs);
 
             if !resolve.is_empty() {
                 subpass_description = subpass_description.resolve_attachments(resolve);
             }
             
+            let mut depth_layout = None;
             if let Some(depth) = depth {
                 match depth {
                     SubpassAttachment::Depth(index) => {
                         subpass_description = subpass_description.depth_stencil_attachment(&depth_attachment_refs[*index as usize]);
+                        depth_layout = Some(describe(&depth_attachment_refs[*index as usize]));
                     },
                     _ => panic!("Invalid depth attachment"),
                 }
             }
 
             subpasses.push(subpass_description);
+            layout.subpasses.push(SubpassLayout {
+                bind_point : *bind_point,
+                colors : colors.iter().map(describe).collect(),
+                inputs : inputs.iter().map(describe).collect(),
+                resolves : resolve.iter().map(describe).collect(),
+                depth : depth_layout,
+            });
         }
 
         let create_info = vk::RenderPassCreateInfo::default()
@@ -356,7 +563,7 @@ impl RenderPassCreateInfo {
                 .create_render_pass(&create_info, None)
                 .expect("Failed to create a render pass");
             
-            RenderPass::new(context.clone(), handle, self.spec)
+            RenderPass::new(context.clone(), handle, self.spec, layout, self.scaled)
         }
     }
 }
@@ -371,20 +578,149 @@ impl Default for RenderPassCreateInfo {
             },
             
             dependencies: Default::default(),
-            subpasses: Default::default()
+            subpasses: Default::default(),
+            scaled : false,
         }
     }
 }
 
+/// Returns the dependency flags of a subpass dependency. Dependencies of a subpass on itself are framebuffer-local.
+fn dependency_flags(src_subpass : u32, dst_subpass : u32) -> vk::DependencyFlags {
+    if src_subpass == dst_subpass && src_subpass != vk::SUBPASS_EXTERNAL {
+        vk::DependencyFlags::BY_REGION
+    } else {
+        vk::DependencyFlags::empty()
+    }
+}
+
+/// Returns the layout a color attachment is used in by a subpass.
+///
+/// # Arguments
+///
+/// * `attachments` - The attachments of the subpass.
+/// * `index` - The index of the color attachment.
+/// * `layout` - The layout the attachment is used in if it is not read while being written to.
+fn subpass_layout(attachments : &[SubpassAttachment], index : u32, layout : vk::ImageLayout) -> vk::ImageLayout {
+    let is_color = attachments.iter().any(|attachment| matches!(attachment, SubpassAttachment::Color(color) if *color == index));
+    let is_input = attachments.iter().any(|attachment| matches!(attachment, SubpassAttachment::Input(input) if *input == index));
+
+    if is_color && is_input { vk::ImageLayout::GENERAL } else { layout }
+}
+
 #[derive(Copy, Clone)]
 pub enum SubpassAttachment {
     Color(u32),
     Depth(u32),
     Resolve(u32),
+    Input(u32),
 }
 
 impl SubpassAttachment {
     pub fn color(index : u32) -> Self { Self::Color(index) }
     pub fn depth(index : u32) -> Self { Self::Depth(index) }
     pub fn resolve(index : u32) -> Self { Self::Resolve(index) }
-}
\ No newline at end of file
+    /// References a color attachment that is read by fragment shaders through `subpassLoad`.
+    pub fn input(index : u32) -> Self { Self::Input(index) }
+}
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use crate::vk::renderer::RendererOptions;
+
+    use super::{dependency_flags, subpass_layout, RenderPassAttachmentSpec, RenderPassLayout, SubpassAttachment, SubpassLayout};
+
+    fn make_spec() -> RenderPassAttachmentSpec {
+        RenderPassAttachmentSpec {
+            color_images : vec![(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4, vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE, vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)],
+            depth_images : vec![(vk::Format::D32_SFLOAT, vk::SampleCountFlags::TYPE_4, vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE, vk::AttachmentLoadOp::DONT_CARE, vk::AttachmentStoreOp::DONT_CARE)],
+            resolve_images : vec![(vk::Format::B8G8R8A8_SRGB, vk::ImageLayout::PRESENT_SRC_KHR)],
+        }
+    }
+
+    #[test]
+    pub fn clear_values() {
+        let options = RendererOptions::default()
+            .clear_color([0.25, 0.5, 0.75, 1.0])
+            .clear_depth(0.5, 7);
+
+        let spec = make_spec();
+        let clear_values = spec.clear_values(options.clear_color_value(), options.clear_depth_stencil_value());
+        assert_eq!(clear_values.len(), 3);
+
+        unsafe {
+            assert_eq!(clear_values[0].color.float32, [0.25, 0.5, 0.75, 1.0]);
+            assert_eq!(clear_values[1].depth_stencil.depth, 0.5);
+            assert_eq!(clear_values[1].depth_stencil.stencil, 7);
+            assert_eq!(clear_values[2].color.float32, [0.25, 0.5, 0.75, 1.0]);
+        }
+
+        let options = options.clear_color([1.0, 0.0, 0.0, 1.0]);
+        let clear_values = spec.clear_values(options.clear_color_value(), options.clear_depth_stencil_value());
+        unsafe {
+            assert_eq!(clear_values[0].color.float32, [1.0, 0.0, 0.0, 1.0]);
+            assert_eq!(clear_values[2].color.float32, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    fn make_layout(format : vk::Format, samples : vk::SampleCountFlags) -> RenderPassLayout {
+        RenderPassLayout {
+            subpasses : vec![SubpassLayout {
+                bind_point : vk::PipelineBindPoint::GRAPHICS,
+                colors : vec![(format, samples)],
+                inputs : vec![],
+                resolves : vec![(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_1)],
+                depth : Some((vk::Format::D32_SFLOAT, samples)),
+            }],
+            dependencies : vec![],
+        }
+    }
+
+    #[test]
+    pub fn compatible_render_passes() {
+        let layout = make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4);
+        assert!(layout.is_compatible_with(&make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4)));
+
+        // Resolve attachments don't matter for render passes with a single subpass.
+        let mut other = make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4);
+        other.subpasses[0].resolves.clear();
+        assert!(layout.is_compatible_with(&other));
+
+        // But they do as soon as there are several subpasses.
+        let mut layout = layout;
+        layout.subpasses.push(layout.subpasses[0].clone());
+        other.subpasses.push(other.subpasses[0].clone());
+        assert!(!layout.is_compatible_with(&other));
+    }
+
+    #[test]
+    pub fn render_pass_format_change() {
+        let layout = make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4);
+        assert!(!layout.is_compatible_with(&make_layout(vk::Format::R8G8B8A8_UNORM, vk::SampleCountFlags::TYPE_4)));
+    }
+
+    #[test]
+    pub fn render_pass_sample_count_change() {
+        let layout = make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_4);
+        assert!(!layout.is_compatible_with(&make_layout(vk::Format::B8G8R8A8_SRGB, vk::SampleCountFlags::TYPE_1)));
+    }
+
+    #[test]
+    pub fn self_dependencies() {
+        assert_eq!(dependency_flags(1, 1), vk::DependencyFlags::BY_REGION);
+        assert_eq!(dependency_flags(0, 1), vk::DependencyFlags::empty());
+        assert_eq!(dependency_flags(vk::SUBPASS_EXTERNAL, vk::SUBPASS_EXTERNAL), vk::DependencyFlags::empty());
+    }
+
+    #[test]
+    pub fn feedback_layouts() {
+        let attachments = [SubpassAttachment::color(0), SubpassAttachment::color(1), SubpassAttachment::input(0), SubpassAttachment::input(2)];
+
+        // The first color attachment is read while being written to.
+        assert_eq!(subpass_layout(&attachments, 0, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL), vk::ImageLayout::GENERAL);
+        assert_eq!(subpass_layout(&attachments, 0, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL), vk::ImageLayout::GENERAL);
+        assert_eq!(subpass_layout(&attachments, 1, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL), vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(subpass_layout(&attachments, 2, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+}
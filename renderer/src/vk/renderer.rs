@@ -1,7 +1,10 @@
+use std::ffi::CString;
 use std::path::PathBuf;
 
 use ash::vk;
+use gpu_allocator::{AllocationSizes, AllocatorDebugSettings};
 
+use super::context::{DebugCallback, DebugMessengerOptions, DebugVerbosity};
 use super::{queue::QueueFamily, swapchain::SwapchainOptions};
 
 #[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
@@ -17,16 +20,36 @@ impl From<f32> for DynamicState<f32> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+impl From<u32> for DynamicState<u32> {
+    fn from(value: u32) -> Self {
+        DynamicState::Fixed(value)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RendererOptions {
     pub(in crate) line_width : DynamicState<f32>,
     pub(in crate) resolution : [u32; 2],
     pub(in crate) get_queue_count : fn(&QueueFamily) -> u32,
+    pub(in crate) get_queue_priority : fn(u32, &QueueFamily) -> f32,
     pub(in crate) get_pipeline_cache_file : fn() -> PathBuf,
     pub(in crate) depth : bool,
     pub(in crate) stencil : bool,
     pub(in crate) separate_depth_stencil : bool, // NYI
     pub(in crate) clear_color : [f32; 4],
+    pub(in crate) clear_depth : f32,
+    pub(in crate) clear_stencil : u32,
+    pub(in crate) required_features : vk::PhysicalDeviceFeatures,
+    pub(in crate) device_extensions : Vec<CString>,
+    pub(in crate) optional_device_extensions : Vec<CString>,
+    pub(in crate) buffer_device_address : bool,
+    pub(in crate) allocator_debug : AllocatorDebugSettings,
+    pub(in crate) allocator_block_sizes : AllocationSizes,
+    pub(in crate) validation : bool,
+    pub(in crate) debug_messenger : DebugMessengerOptions,
+    pub(in crate) swapchain_image_usage : vk::ImageUsageFlags,
+    pub(in crate) swapchain_image_count : Option<u32>,
+    pub(in crate) render_scale : f32,
     pub multisampling : vk::SampleCountFlags,
 }
 
@@ -43,6 +66,26 @@ impl RendererOptions {
         self
     }
 
+    /// Sets the function returning the priority of each queue created on the device.
+    /// 
+    /// # Description
+    /// 
+    /// The function receives the index of a queue within its family, as well as the family itself, and must return
+    /// a priority between `0.0` and `1.0`. Drivers may give more execution time to queues with a higher priority; for
+    /// example, graphics queues can be given a higher priority than queues used for background transfers.
+    /// By default, all queues have a priority of `1.0`.
+    #[inline] pub fn queue_priority(mut self, getter : fn(u32, &QueueFamily) -> f32) -> Self {
+        self.get_queue_priority = getter;
+        self
+    }
+
+    /// Sets the function returning the file pipeline caches are loaded from and saved to. Defaults to `pipelines.dat`.
+    ///
+    /// # Description
+    ///
+    /// Pipeline caches are only valid for the device and driver that wrote them, so the vendor and device IDs as well
+    /// as the pipeline cache UUID of the physical device are inserted before the extension of the file: each device
+    /// gets its own cache, for example `pipelines.10de-2684-<uuid>.dat`.
     #[inline] pub fn pipeline_cache_file(mut self, getter : fn() -> PathBuf) -> Self {
         self.get_pipeline_cache_file = getter;
         self
@@ -51,7 +94,126 @@ impl RendererOptions {
     value_builder! { depth, bool }
     value_builder! { stencil, bool }
     value_builder! { clear_color, [f32; 4] }
+
+    /// Sets the values used to clear depth/stencil attachments.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `depth` - The value depth attachments are cleared to.
+    /// * `stencil` - The value stencil attachments are cleared to.
+    #[inline] pub fn clear_depth(mut self, depth : f32, stencil : u32) -> Self {
+        self.clear_depth = depth;
+        self.clear_stencil = stencil;
+        self
+    }
     value_builder! { multisampling, samples, multisampling, vk::SampleCountFlags }
+
+    /// Sets the features a physical device must support in order to be selected.
+    /// 
+    /// For example, pipelines with geometry shaders require [`vk::PhysicalDeviceFeatures::geometry_shader`].
+    #[inline] pub fn required_features(mut self, features : vk::PhysicalDeviceFeatures) -> Self {
+        self.required_features = features;
+        self
+    }
+
+    /// Requires a device extension. Physical devices that do not support it are not selected. `VK_KHR_swapchain` is
+    /// always required.
+    #[inline] pub fn device_extension(mut self, extension : CString) -> Self {
+        self.device_extensions.push(extension);
+        self
+    }
+
+    /// Enables a device extension if the selected physical device supports it. Use
+    /// [`RenderingContextImpl::is_device_extension_enabled`](crate::orchestration::rendering::RenderingContextImpl::is_device_extension_enabled)
+    /// to find out if it is. `VK_EXT_memory_budget` is always enabled if it is supported.
+    #[inline] pub fn optional_device_extension(mut self, extension : CString) -> Self {
+        self.optional_device_extensions.push(extension);
+        self
+    }
+
+    /// Enables buffer device addresses, which allow shaders to access buffers through raw pointers.
+    /// 
+    /// # Description
+    /// 
+    /// This enables the `bufferDeviceAddress` feature on the device and makes the allocator back buffers with memory
+    /// that supports it. Buffers must opt into this with [`vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS`] before their
+    /// address can be queried with [`Buffer::device_address`](crate::vk::buffer::Buffer::device_address).
+    #[inline] pub fn buffer_device_address(mut self, enabled : bool) -> Self {
+        self.buffer_device_address = enabled;
+        self
+    }
+
+    /// Sets the debug settings of the memory allocator, such as leak reporting or logging of allocations.
+    #[inline] pub fn allocator_debug(mut self, settings : AllocatorDebugSettings) -> Self {
+        self.allocator_debug = settings;
+        self
+    }
+
+    /// Sets the size of the memory blocks the allocator carves allocations out of.
+    #[inline] pub fn allocator_block_sizes(mut self, sizes : AllocationSizes) -> Self {
+        self.allocator_block_sizes = sizes;
+        self
+    }
+
+    /// Enables the Khronos validation layer. This is enabled by default in debug builds.
+    /// 
+    /// # Description
+    /// 
+    /// The validation layer ships with the Vulkan SDK, not with the Vulkan runtime; if it is not installed, validation
+    /// is silently disabled.
+    #[inline] pub fn validation(mut self, enabled : bool) -> Self {
+        self.validation = enabled;
+        self
+    }
+
+    /// Sets the severities of the validation messages that are reported. By default, only warnings and errors are.
+    #[inline] pub fn log_severity(mut self, severity : vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        self.debug_messenger.severity = severity;
+        self
+    }
+
+    /// Sets how much context accompanies validation messages. See [`DebugVerbosity`].
+    #[inline] pub fn log_verbosity(mut self, verbosity : DebugVerbosity) -> Self {
+        self.debug_messenger.verbosity = verbosity;
+        self
+    }
+
+    /// Sets the function validation messages are sent to, for example to forward them to a logging framework. By
+    /// default, messages are printed to the standard output.
+    #[inline] pub fn log_callback(mut self, callback : DebugCallback) -> Self {
+        self.debug_messenger.callback = callback;
+        self
+    }
+
+    /// Sets the usage flags of the swapchain's images. See [`SwapchainOptions::image_usage`].
+    /// 
+    /// # Description
+    /// 
+    /// The flags must be supported by the surface, or creating the swapchain will panic. Color attachment usage is
+    /// always supported.
+    #[inline] pub fn swapchain_image_usage(mut self, usage : vk::ImageUsageFlags) -> Self {
+        self.swapchain_image_usage = usage;
+        self
+    }
+
+    /// Sets the amount of images of the swapchain. See [`SwapchainOptions::desired_image_count`].
+    #[inline] pub fn swapchain_image_count(mut self, count : u32) -> Self {
+        self.swapchain_image_count = Some(count);
+        self
+    }
+
+    /// Renders scenes at a fraction of the resolution of the window, upscaling them before they are presented. See
+    /// [`SwapchainOptions::render_scale`].
+    /// 
+    /// # Panics
+    /// 
+    /// * Panics if `scale` is not within `(0, 1]`.
+    #[inline] pub fn render_scale(mut self, scale : f32) -> Self {
+        assert!(scale > 0.0 && scale <= 1.0, "The render scale must be within (0, 1], found {}", scale);
+
+        self.render_scale = scale;
+        self
+    }
 }
 
 impl Default for RendererOptions {
@@ -60,16 +222,56 @@ impl Default for RendererOptions {
             line_width: DynamicState::Fixed(1.0f32),
             resolution : [1280, 720],
             get_queue_count : |&_| 1,
+            get_queue_priority : |_, &_| 1.0,
             get_pipeline_cache_file : || "pipelines.dat".into(),
             depth : true,
             stencil : true,
             separate_depth_stencil : false,
             clear_color : [0.0f32, 0.0f32, 0.0f32, 0.0f32],
+            clear_depth : 1.0f32,
+            clear_stencil : 0,
+            required_features : vk::PhysicalDeviceFeatures::default(),
+            device_extensions : vec![ash::khr::swapchain::NAME.to_owned()],
+            // Memory budgets are only used for diagnostics.
+            optional_device_extensions : vec![ash::ext::memory_budget::NAME.to_owned()],
+            buffer_device_address : false,
+            allocator_debug : AllocatorDebugSettings::default(),
+            allocator_block_sizes : AllocationSizes::default(),
+            validation : cfg!(debug_assertions),
+            debug_messenger : DebugMessengerOptions::default(),
+            swapchain_image_usage : vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+            swapchain_image_count : None,
+            render_scale : 1.0,
             multisampling : vk::SampleCountFlags::TYPE_1,
         }
     }
 }
 
+impl RendererOptions {
+    /// Returns the value color attachments are cleared to.
+    pub fn clear_color_value(&self) -> vk::ClearColorValue {
+        vk::ClearColorValue { float32 : self.clear_color }
+    }
+
+    /// Returns the value depth/stencil attachments are cleared to.
+    pub fn clear_depth_stencil_value(&self) -> vk::ClearDepthStencilValue {
+        vk::ClearDepthStencilValue { depth : self.clear_depth, stencil : self.clear_stencil }
+    }
+
+    /// Lowers the sample count used for multisampling if it exceeds what the device supports, printing a warning.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `max_samples` - The highest sample count supported by the device; see
+    ///                   [`PhysicalDevice::max_msaa_samples`](crate::vk::physical_device::PhysicalDevice::max_msaa_samples).
+    pub(in crate) fn clamp_multisampling(&mut self, max_samples : vk::SampleCountFlags) {
+        if self.multisampling.as_raw() > max_samples.as_raw() {
+            println!("Multisampling with {:?} is not supported by this device; using {:?} instead.", self.multisampling, max_samples);
+            self.multisampling = max_samples;
+        }
+    }
+}
+
 impl SwapchainOptions for RendererOptions {
     fn select_surface_format(&self, format : &vk::SurfaceFormatKHR) -> bool {
         format.format == vk::Format::B8G8R8A8_SRGB && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
@@ -78,7 +280,34 @@ impl SwapchainOptions for RendererOptions {
     fn width(&self) -> u32 { self.resolution[0] }
     fn height(&self) -> u32 { self.resolution[1] }
 
+    fn desired_image_count(&self) -> Option<u32> { self.swapchain_image_count }
+    fn image_usage(&self) -> vk::ImageUsageFlags { self.swapchain_image_usage }
+
     fn depth(&self) -> bool { self.depth }
     fn stencil(&self) -> bool { self.stencil }
     fn multisampling(&self) -> vk::SampleCountFlags { self.multisampling }
+    fn render_scale(&self) -> f32 { self.render_scale }
+}
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::RendererOptions;
+
+    #[test]
+    pub fn multisampling_clamping() {
+        // A device that supports at most two samples per pixel.
+        let mut options = RendererOptions::default().multisampling(vk::SampleCountFlags::TYPE_4);
+        options.clamp_multisampling(vk::SampleCountFlags::TYPE_2);
+        assert_eq!(options.multisampling, vk::SampleCountFlags::TYPE_2);
+
+        let mut options = RendererOptions::default().multisampling(vk::SampleCountFlags::TYPE_2);
+        options.clamp_multisampling(vk::SampleCountFlags::TYPE_2);
+        assert_eq!(options.multisampling, vk::SampleCountFlags::TYPE_2);
+
+        let mut options = RendererOptions::default();
+        options.clamp_multisampling(vk::SampleCountFlags::TYPE_2);
+        assert_eq!(options.multisampling, vk::SampleCountFlags::TYPE_1);
+    }
 }
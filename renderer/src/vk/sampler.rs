@@ -2,13 +2,15 @@ use ash::vk;
 
 use crate::{make_handle, orchestration::rendering::RenderingContext};
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct SamplerCreateInfo {
     address_mode : [vk::SamplerAddressMode; 3],
     anisotropy : bool,
+    max_anisotropy : Option<f32>,
     filter : [vk::Filter; 2],
     mipmap_mode : vk::SamplerMipmapMode,
     lod : [f32; 2],
+    lod_bias : f32,
 }
 
 impl SamplerCreateInfo {
@@ -17,8 +19,31 @@ impl SamplerCreateInfo {
         self
     }
 
+    /// Uses the same address mode along every axis.
+    pub fn address_mode_all(self, mode : vk::SamplerAddressMode) -> Self {
+        self.address_mode(mode, mode, mode)
+    }
+
+    /// Wraps texture coordinates along every axis, as tiled textures expect.
+    pub fn repeat(self) -> Self {
+        self.address_mode_all(vk::SamplerAddressMode::REPEAT)
+    }
+
     value_builder! { anisotropy, bool }
 
+    /// Enables anisotropic filtering, with at most the given amount of samples. This is clamped to the maximum
+    /// supported by the device when the sampler is built.
+    ///
+    /// If anisotropic filtering is enabled without calling this function, the maximum supported by the device is used.
+    ///
+    /// Anisotropic filtering requires [`vk::PhysicalDeviceFeatures::sampler_anisotropy`]; see
+    /// [`RendererOptions::required_features`](crate::vk::renderer::RendererOptions::required_features).
+    pub fn max_anisotropy(mut self, max_anisotropy : f32) -> Self {
+        self.anisotropy = true;
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
     pub fn filter(mut self, min : vk::Filter, mag : vk::Filter) -> Self {
         self.filter = [min, mag];
         self
@@ -31,16 +56,71 @@ impl SamplerCreateInfo {
         self
     }
 
+    /// Clamps the computed level of detail from below, preventing the most detailed mip levels from being sampled.
+    pub fn min_lod(mut self, min_lod : f32) -> Self {
+        self.lod[0] = min_lod;
+        self
+    }
+
+    /// Clamps the computed level of detail from above. Use [`vk::LOD_CLAMP_NONE`] to sample every mip level.
+    pub fn max_lod(mut self, max_lod : f32) -> Self {
+        self.lod[1] = max_lod;
+        self
+    }
+
+    /// Offsets the computed level of detail before it is clamped. Negative values sharpen textures by sampling more
+    /// detailed mip levels, while positive values soften them. This is clamped to the maximum supported by the device
+    /// when the sampler is built.
+    pub fn lod_bias(mut self, lod_bias : f32) -> Self {
+        self.lod_bias = lod_bias;
+        self
+    }
+
+    /// Filters linearly within and between mip levels, sampling every mip level of the texture.
+    pub fn trilinear(self) -> Self {
+        self.filter(vk::Filter::LINEAR, vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .lod(0.0, vk::LOD_CLAMP_NONE)
+    }
+
+    /// Returns the create info of the sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The limits of the device.
+    fn create_info(&self, limits : &vk::PhysicalDeviceLimits) -> vk::SamplerCreateInfo<'static> {
+        let anisotropy_limit = limits.max_sampler_anisotropy;
+        let max_anisotropy = self.max_anisotropy.map_or(anisotropy_limit, |max| max.clamp(1.0, anisotropy_limit));
+        let lod_bias = self.lod_bias.clamp(-limits.max_sampler_lod_bias, limits.max_sampler_lod_bias);
+
+        vk::SamplerCreateInfo::default()
+            .address_mode_u(self.address_mode[0])
+            .address_mode_v(self.address_mode[1])
+            .address_mode_w(self.address_mode[2])
+            .anisotropy_enable(self.anisotropy)
+            .max_anisotropy(if self.anisotropy { max_anisotropy } else { 1.0 })
+            .mag_filter(self.filter[1])
+            .min_filter(self.filter[0])
+            .mipmap_mode(self.mipmap_mode)
+            .min_lod(self.lod[0])
+            .max_lod(self.lod[1])
+            .mip_lod_bias(lod_bias)
+    }
+
+    /// Creates the sampler.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if anisotropic filtering is enabled but the device was not created with the `samplerAnisotropy`
+    ///   feature.
+    /// * Panics if the minimum level of detail is greater than the maximum level of detail.
     pub fn build(self, context : &RenderingContext) -> Sampler {
+        assert!(!self.anisotropy || context.device.features.sampler_anisotropy != 0,
+            "Anisotropic filtering requires the samplerAnisotropy feature; see RendererOptions::required_features");
+        assert!(self.lod[0] <= self.lod[1], "Minimum level of detail {} exceeds the maximum level of detail {}", self.lod[0], self.lod[1]);
+
         unsafe {
-            let create_info = vk::SamplerCreateInfo::default()
-                .address_mode_u(self.address_mode[0])
-                .address_mode_v(self.address_mode[1])
-                .address_mode_w(self.address_mode[2])
-                .anisotropy_enable(self.anisotropy)
-                .mag_filter(self.filter[1])
-                .min_filter(self.filter[0])
-                .mipmap_mode(self.mipmap_mode);
+            let create_info = self.create_info(&context.device.physical_device.properties().limits);
 
             let handle = context.device.handle()
                 .create_sampler(&create_info, None)
@@ -71,3 +151,60 @@ impl Drop for Sampler {
 }
 
 make_handle! { Sampler, vk::Sampler }
+
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::Sampler;
+
+    fn make_limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits::default()
+            .max_sampler_anisotropy(16.0)
+            .max_sampler_lod_bias(4.0)
+    }
+
+    #[test]
+    pub fn geometry_sampler() {
+        let info = Sampler::builder()
+            .repeat()
+            .trilinear()
+            .max_anisotropy(32.0)
+            .create_info(&make_limits());
+
+        assert_eq!(info.address_mode_u, vk::SamplerAddressMode::REPEAT);
+        assert_eq!(info.address_mode_w, vk::SamplerAddressMode::REPEAT);
+        assert_eq!((info.min_filter, info.mag_filter), (vk::Filter::LINEAR, vk::Filter::LINEAR));
+        assert_eq!(info.mipmap_mode, vk::SamplerMipmapMode::LINEAR);
+        assert_eq!((info.min_lod, info.max_lod), (0.0, vk::LOD_CLAMP_NONE));
+        assert_eq!((info.anisotropy_enable, info.max_anisotropy), (vk::TRUE, 16.0));
+    }
+
+    #[test]
+    pub fn anisotropy() {
+        let info = Sampler::builder().create_info(&make_limits());
+        assert_eq!((info.anisotropy_enable, info.max_anisotropy), (vk::FALSE, 1.0));
+
+        // Without an explicit maximum, the limit of the device is used.
+        let info = Sampler::builder().anisotropy(true).create_info(&make_limits());
+        assert_eq!(info.max_anisotropy, 16.0);
+
+        let info = Sampler::builder().max_anisotropy(4.0).create_info(&make_limits());
+        assert_eq!(info.max_anisotropy, 4.0);
+    }
+
+    #[test]
+    pub fn level_of_detail() {
+        let info = Sampler::builder()
+            .trilinear()
+            .min_lod(1.0)
+            .max_lod(3.0)
+            .lod_bias(-0.5)
+            .create_info(&make_limits());
+        assert_eq!((info.min_lod, info.max_lod, info.mip_lod_bias), (1.0, 3.0, -0.5));
+
+        // The bias is clamped to the limit of the device.
+        let info = Sampler::builder().lod_bias(8.0).create_info(&make_limits());
+        assert_eq!(info.mip_lod_bias, 4.0);
+    }
+}
@@ -33,6 +33,7 @@ impl SemaphorePool {
                     .expect("Failed to allocate a new semaphore");
 
                 self.handles.push(semaphore.clone());
+                self.active_count = self.handles.len();
                 semaphore
             }
         }
@@ -43,6 +44,9 @@ impl SemaphorePool {
     }
 
     /// Signals to this pool that all semaphores are free to use.
+    /// 
+    /// This must only be called once the GPU is done with every semaphore requested since the last reset, typically
+    /// after waiting on the fence of the frame that used them.
     pub fn reset(&mut self) {
         self.active_count = 0;
     }
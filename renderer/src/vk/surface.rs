@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ash::prelude::VkResult;
+use ash::vk;
+use egui_winit::winit;
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+
+use crate::traits::handle::Handle;
+use crate::vk::context::Context;
+use crate::vk::physical_device::PhysicalDevice;
+use crate::vk::queue::QueueFamily;
+
+/// A presentation surface, tied to a window.
+///
+/// # Description
+///
+/// The capabilities, formats and presentation modes of the surface are queried once per physical device, and cached
+/// until [`Surface::invalidate`] is called. They can change during the lifetime of the window (for example when it is
+/// resized, moved to another monitor, rotated, when its scale factor changes, or when the compositor reconfigures it),
+/// so the cache must be invalidated before recreating a swapchain; the orchestrator does so whenever it recreates one.
+pub struct Surface {
+    loader : ash::khr::surface::Instance,
+    handle : vk::SurfaceKHR,
+    cache : Mutex<SurfaceCache>,
+}
+
+impl Surface {
+    /// Creates a new surface for the given window.
+    ///
+    /// # Returns
+    ///
+    /// The error returned by the driver if the surface could not be created, for example because the windowing system
+    /// in use is not supported. [`vk::Result::ERROR_INITIALIZATION_FAILED`] is returned if the window or display
+    /// handles of the window can't be retrieved.
+    pub(in crate) fn try_new(context : &Context, window : &winit::window::Window) -> VkResult<Self> {
+        let (Ok(display_handle), Ok(window_handle)) = (window.display_handle(), window.window_handle()) else {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED);
+        };
+
+        unsafe {
+            let loader = ash::khr::surface::Instance::new(&context.entry, &context.instance);
+            let handle = ash_window::create_surface(&context.entry, &context.instance,
+                display_handle.as_raw(),
+                window_handle.as_raw(),
+                None
+            )?;
+
+            Ok(Self { loader, handle, cache : Mutex::default() })
+        }
+    }
+
+    /// Returns the capabilities of this surface for the given device, as of the last call to [`Surface::invalidate`].
+    pub fn capabilities(&self, device : &PhysicalDevice) -> vk::SurfaceCapabilitiesKHR {
+        self.cache.lock().unwrap().capabilities(device.handle(), || unsafe {
+            self.loader.get_physical_device_surface_capabilities(device.handle(), self.handle)
+                .expect("Failed to retrieve surface capabilities")
+        })
+    }
+
+    /// Returns the formats supported by this surface for the given device, as of the last call to
+    /// [`Surface::invalidate`].
+    pub fn formats(&self, device : &PhysicalDevice) -> Vec<vk::SurfaceFormatKHR> {
+        self.cache.lock().unwrap().formats(device.handle(), || unsafe {
+            self.loader.get_physical_device_surface_formats(device.handle(), self.handle)
+                .expect("Failed to retrieve surface formats")
+        })
+    }
+
+    /// Returns the presentation modes supported by this surface for the given device, as of the last call to
+    /// [`Surface::invalidate`].
+    pub fn present_modes(&self, device : &PhysicalDevice) -> Vec<vk::PresentModeKHR> {
+        self.cache.lock().unwrap().present_modes(device.handle(), || unsafe {
+            self.loader.get_physical_device_surface_present_modes(device.handle(), self.handle)
+                .expect("Failed to retrieve surface present modes")
+        })
+    }
+
+    /// Discards the cached properties of this surface, so that they are queried again from the driver.
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Returns true if the given queue family of a device can present to this surface.
+    pub fn supports_queue(&self, device : &PhysicalDevice, queue : &QueueFamily) -> bool {
+        unsafe {
+            self.loader.get_physical_device_surface_support(device.handle(), queue.index(), self.handle)
+                .expect("Failed to retrieve surface support")
+        }
+    }
+
+    /// Destroys this surface. Any swapchain created for it must have been destroyed beforehand.
+    pub(in crate) fn destroy(self) {
+        unsafe {
+            self.loader.destroy_surface(self.handle, None);
+        }
+    }
+}
+
+impl Handle<vk::SurfaceKHR> for Surface {
+    fn handle(&self) -> vk::SurfaceKHR { self.handle }
+}
+
+/// The properties of a surface for a single physical device. Each property is only queried when first needed.
+#[derive(Default)]
+struct SurfaceProperties {
+    capabilities : Option<vk::SurfaceCapabilitiesKHR>,
+    formats : Option<Vec<vk::SurfaceFormatKHR>>,
+    present_modes : Option<Vec<vk::PresentModeKHR>>,
+}
+
+/// Caches the properties of a surface for every physical device they were queried for.
+#[derive(Default)]
+struct SurfaceCache {
+    devices : HashMap<vk::PhysicalDevice, SurfaceProperties>,
+}
+
+impl SurfaceCache {
+    /// Returns the cached capabilities of the surface for the given device, calling `query` if there are none.
+    fn capabilities(&mut self, device : vk::PhysicalDevice, query : impl FnOnce() -> vk::SurfaceCapabilitiesKHR) -> vk::SurfaceCapabilitiesKHR {
+        *self.devices.entry(device).or_default().capabilities.get_or_insert_with(query)
+    }
+
+    /// Returns the cached formats of the surface for the given device, calling `query` if there are none.
+    fn formats(&mut self, device : vk::PhysicalDevice, query : impl FnOnce() -> Vec<vk::SurfaceFormatKHR>) -> Vec<vk::SurfaceFormatKHR> {
+        self.devices.entry(device).or_default().formats.get_or_insert_with(query).clone()
+    }
+
+    /// Returns the cached presentation modes of the surface for the given device, calling `query` if there are none.
+    fn present_modes(&mut self, device : vk::PhysicalDevice, query : impl FnOnce() -> Vec<vk::PresentModeKHR>) -> Vec<vk::PresentModeKHR> {
+        self.devices.entry(device).or_default().present_modes.get_or_insert_with(query).clone()
+    }
+
+    fn clear(&mut self) {
+        self.devices.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use ash::vk::{self, Handle};
+
+    use super::SurfaceCache;
+
+    #[test]
+    pub fn cached_properties() {
+        let queries = Cell::new(0);
+        let query = || {
+            queries.set(queries.get() + 1);
+            vec![vk::PresentModeKHR::FIFO]
+        };
+
+        let mut cache = SurfaceCache::default();
+        let (first, second) = (vk::PhysicalDevice::from_raw(1), vk::PhysicalDevice::from_raw(2));
+        assert_eq!(cache.present_modes(first, query), vec![vk::PresentModeKHR::FIFO]);
+        assert_eq!(cache.present_modes(first, query), vec![vk::PresentModeKHR::FIFO]);
+        assert_eq!(queries.get(), 1);
+
+        // Each device has its own properties.
+        cache.present_modes(second, query);
+        assert_eq!(queries.get(), 2);
+
+        let capabilities = cache.capabilities(first, || vk::SurfaceCapabilitiesKHR::default().min_image_count(2));
+        assert_eq!(capabilities.min_image_count, 2);
+        let capabilities = cache.capabilities(first, || unreachable!());
+        assert_eq!(capabilities.min_image_count, 2);
+
+        // Invalidation discards every property of every device.
+        cache.clear();
+        cache.present_modes(first, query);
+        cache.present_modes(second, query);
+        assert_eq!(queries.get(), 4);
+        let capabilities = cache.capabilities(first, || vk::SurfaceCapabilitiesKHR::default().min_image_count(3));
+        assert_eq!(capabilities.min_image_count, 3);
+    }
+}
@@ -60,16 +60,46 @@ pub trait SwapchainOptions {
         return Range { start : 0, end : 1 }
     }
 
+    /// Returns the amount of images the swapchain should have, or `None` to use one more than the minimum required by
+    /// the surface.
+    /// 
+    /// # Description
+    /// 
+    /// The value is clamped to the amount of images supported by the surface. Fewer images reduce latency and
+    /// memory usage, but the application is more likely to stall waiting for an image to be available; more images
+    /// (for example, the minimum plus two for triple buffering with [`vk::PresentModeKHR::MAILBOX`]) let the
+    /// application render ahead at the cost of memory and added latency.
+    fn desired_image_count(&self) -> Option<u32> { None }
+
+    /// Returns the usage flags of the swapchain's images.
+    /// By default, images can only be used as color and input attachments; add [`vk::ImageUsageFlags::TRANSFER_SRC`]
+    /// to read them back, or [`vk::ImageUsageFlags::SAMPLED`] to sample them in a post-processing pass.
+    fn image_usage(&self) -> vk::ImageUsageFlags {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT
+    }
+
     fn depth(&self) -> bool;
     fn stencil(&self) -> bool;
 
     fn multisampling(&self) -> vk::SampleCountFlags { vk::SampleCountFlags::TYPE_1 }
+
+    /// Returns the fraction of the resolution of the swapchain's images scenes are rendered at.
+    /// 
+    /// # Description
+    /// 
+    /// Below 1, render passes created with [`Swapchain::create_render_pass`] draw to offscreen images sized after
+    /// [`Swapchain::render_extent`], which the orchestrator upscales into the swapchain's images with a linear filter
+    /// before renderers that draw at the native resolution, such as the interface, record their commands. The
+    /// swapchain's images are then created with [`vk::ImageUsageFlags::TRANSFER_DST`].
+    fn render_scale(&self) -> f32 { 1.0 }
 }
 
 pub struct SwapchainImage {
     pub present : Image,
     pub depth : Option<Image>,
     pub resolve : Option<Image>,
+    /// The image scenes are rendered to if the swapchain is scaled; see [`SwapchainOptions::render_scale`].
+    pub scaled : Option<Image>,
 }
 
 pub struct Swapchain {
@@ -77,9 +107,12 @@ pub struct Swapchain {
     handle : vk::SwapchainKHR,
     pub loader : ash::khr::swapchain::Device,
     pub surface_format : vk::SurfaceFormatKHR,
+    pub present_mode : vk::PresentModeKHR,
     
     // Images
     pub extent : vk::Extent2D,
+    /// The extent scenes are rendered at; see [`SwapchainOptions::render_scale`].
+    pub render_extent : vk::Extent2D,
     pub images : Vec<SwapchainImage>,
     pub sample_count : vk::SampleCountFlags,
     layer_count : u32,
@@ -98,23 +131,36 @@ impl Drop for Swapchain {
 }
 
 impl Swapchain {
+    /// Creates a new swapchain.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `context` - The rendering context.
+    /// * `window` - The window whose surface this swapchain presents to.
+    /// * `options` - Options driving the creation of the swapchain.
+    /// * `queue_families` - Queue families that will access the images of this swapchain.
     pub fn new<T : SwapchainOptions>(
         context : &RenderingContext,
+        window : &Window,
         options : &T,
         queue_families : Vec<QueueFamily>,
     ) -> Swapchain {
-        let surface_format = Self::select_format(options, context.window.get_surface_formats(&context.device.physical_device));
-        let surface_capabilities = context.window.get_surface_capabilities(&context.device.physical_device);
+        let surface_format = Self::select_format(options, window.get_surface_formats(&context.device.physical_device));
+        let surface_capabilities = window.get_surface_capabilities(&context.device.physical_device);
         let extent = Self::get_extent(surface_capabilities, options);
 
-        let image_count = surface_capabilities.min_image_count + 1;
-        let image_count = if surface_capabilities.max_image_count != 0 {
-            image_count.min(surface_capabilities.max_image_count)
+        let image_count = image_count(&surface_capabilities, options.desired_image_count());
+
+        let render_extent = scaled_extent(extent, options.render_scale());
+        let image_usage = if render_extent != extent {
+            // Scaled scenes are blitted into the images of the swapchain.
+            options.image_usage() | vk::ImageUsageFlags::TRANSFER_DST
         } else {
-            image_count
+            options.image_usage()
         };
+        validate_image_usage(image_usage, surface_capabilities.supported_usage_flags);
 
-        let present_modes = context.window.get_present_modes(&context.device.physical_device);
+        let present_mode = options.select_present_mode(window.get_present_modes(&context.device.physical_device));
 
         let mut queue_family_indices = queue_families.iter().map(QueueFamily::index).collect::<Vec<_>>();
         queue_family_indices.dedup();
@@ -125,7 +171,7 @@ impl Swapchain {
         };
 
         let create_info = vk::SwapchainCreateInfoKHR::default()
-            .surface(context.window.surface())
+            .surface(window.surface())
             .min_image_count(image_count)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
@@ -133,7 +179,7 @@ impl Swapchain {
             // Number of views in a multiview/stereo surface. For non-stereoscopic-3D applications, this value is 1.
             .image_array_layers(1)
             // A bitmask of VkImageUsageFlagBits describing the intended usage of the (acquired) swapchain images.
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(sharing_mode)
             .queue_family_indices(&queue_family_indices)
             .pre_transform(if surface_capabilities.supported_transforms.contains(vk::SurfaceTransformFlagsKHR::IDENTITY) {
@@ -146,7 +192,7 @@ impl Swapchain {
             .composite_alpha(options.composite_alpha())
             // Presentation mode the swapchain will use. A swapchain’s present mode determines how incoming present
             // requests will be processed and queued internally.
-            .present_mode(options.select_present_mode(present_modes))
+            .present_mode(present_mode)
             // Specifies whether the Vulkan implementation is allowed to discard rendering operations that affect
             // regions of the surface that are not visible.
             .clipped(true);
@@ -166,13 +212,17 @@ impl Swapchain {
 
         let mut images = vec![];
         for (i, present) in present_images.into_iter().enumerate() {
-            let depth = Self::make_depth_image(context, sharing_mode, extent, format!("Swapchain/Depth[{}]", i), options);
-            let resolve = Self::make_resolve_image(context, surface_format, sharing_mode, extent, format!("Swapchain/Resolve[{}]", i), options);
+            // Depth and multisampled images are only used by scenes, which are rendered at the scaled extent.
+            let depth = Self::make_depth_image(context, sharing_mode, render_extent, format!("Swapchain/Depth[{}]", i), options);
+            let resolve = Self::make_resolve_image(context, surface_format, sharing_mode, render_extent, format!("Swapchain/Resolve[{}]", i), options);
+            let scaled = (render_extent != extent)
+                .then(|| Self::make_scaled_image(context, surface_format, sharing_mode, render_extent, format!("Swapchain/Scaled[{}]", i)));
 
             images.push(SwapchainImage {
                 present,
                 depth,
-                resolve
+                resolve,
+                scaled,
             })
         }
 
@@ -180,7 +230,9 @@ impl Swapchain {
             handle,
             loader,
             surface_format,
+            present_mode,
             extent,
+            render_extent,
             images,
             sample_count : options.multisampling(),
             layer_count : options.layers().len() as _,
@@ -268,6 +320,33 @@ impl Swapchain {
     }
 
 
+    /// Creates an offscreen image scenes are rendered to when the swapchain is scaled.
+    fn make_scaled_image(
+        context : &RenderingContext,
+        surface_format : vk::SurfaceFormatKHR,
+        sharing_mode : vk::SharingMode,
+        extent : vk::Extent2D,
+        name : String,
+    ) -> Image {
+        ImageCreateInfo::default()
+            .aspect(vk::ImageAspectFlags::COLOR)
+            .name(name)
+            .image_type(vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D)
+            .format(surface_format.format)
+            .levels(0, 1)
+            .layers(0, 1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(sharing_mode)
+            .extent(vk::Extent3D {
+                width : extent.width,
+                height : extent.height,
+                depth : 1
+            })
+            .build(context)
+    }
+
     fn select_format<T : SwapchainOptions>(options : &T, formats : Vec<vk::SurfaceFormatKHR>) -> vk::SurfaceFormatKHR {
         for format in &formats {
             if options.select_surface_format(format) {
@@ -293,6 +372,17 @@ impl Swapchain {
 
     pub fn color_format(&self) -> vk::Format { self.images[0].present.format() }
 
+    /// Returns a render pass that draws a scene to the images of this swapchain: a multisampled color attachment, a
+    /// depth attachment, and the attachment the color is resolved to.
+    /// 
+    /// # Description
+    /// 
+    /// If the swapchain is scaled, the color is resolved to an offscreen image that the orchestrator upscales into
+    /// the swapchain's image afterwards, so `is_presenting` is ignored; see [`SwapchainOptions::render_scale`].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `is_presenting` - Whether the render pass leaves the swapchain's image ready for presentation.
     pub fn create_render_pass(&self, is_presenting : bool) -> RenderPassCreateInfo {
         // TODO: Fix this for cases where multisampling is not active
 
@@ -311,7 +401,9 @@ impl Swapchain {
             None => unreachable!()
         };
 
-        let final_format = if is_presenting {
+        let final_format = if self.is_scaled() {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else if is_presenting {
             vk::ImageLayout::PRESENT_SRC_KHR
         } else {
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
@@ -321,8 +413,12 @@ impl Swapchain {
             .color_attachment(color_format, self.sample_count, vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE, vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
             .depth_attachment(depth_format, self.sample_count, vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE)
             .resolve_attachment(resolve_format, final_format)
+            .scaled(self.is_scaled())
     }
 
+    /// Returns true if scenes are rendered at a lower resolution than the swapchain's images.
+    pub fn is_scaled(&self) -> bool { self.render_extent != self.extent }
+
     /// Acquires the next image. Returns the image index, and wether the swapchain is suboptimal for the surface.
     pub(in crate) fn acquire_image(&self, semaphore : vk::Semaphore, fence : vk::Fence, timeout : u64) -> VkResult<(u32, bool)> {
         unsafe {
@@ -334,6 +430,114 @@ impl Swapchain {
     pub fn color_space(&self) -> vk::ColorSpaceKHR { self.surface_format.color_space}
     pub fn layer_count(&self) -> u32 { self.layer_count }
     pub fn image_count(&self) -> usize { self.images.len() }
+
+    /// Returns the views of every image owned by this swapchain, including depth and resolve images.
+    pub fn views(&self) -> Vec<vk::ImageView> {
+        self.images.iter()
+            .flat_map(|image| [Some(&image.present), image.depth.as_ref(), image.resolve.as_ref(), image.scaled.as_ref()])
+            .flatten()
+            .map(|image| image.view())
+            .collect()
+    }
+}
+
+make_handle! { Swapchain, vk::SwapchainKHR }
+
+/// Returns the amount of images to create a swapchain with.
+/// 
+/// # Arguments
+/// 
+/// * `capabilities` - The capabilities of the surface.
+/// * `desired` - The amount of images requested, if any. If `None`, one more than the minimum is used.
+fn image_count(capabilities : &vk::SurfaceCapabilitiesKHR, desired : Option<u32>) -> u32 {
+    let image_count = desired.unwrap_or(capabilities.min_image_count + 1)
+        .max(capabilities.min_image_count);
+
+    // A maximum of zero means there is no limit.
+    if capabilities.max_image_count != 0 {
+        image_count.min(capabilities.max_image_count)
+    } else {
+        image_count
+    }
+}
+
+/// Returns the extent scenes are rendered at, given the extent of the swapchain and the render scale.
+/// 
+/// # Arguments
+/// 
+/// * `extent` - The extent of the swapchain's images.
+/// * `scale` - The render scale; see [`SwapchainOptions::render_scale`].
+fn scaled_extent(extent : vk::Extent2D, scale : f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width : ((extent.width as f32 * scale).round() as u32).clamp(1, extent.width.max(1)),
+        height : ((extent.height as f32 * scale).round() as u32).clamp(1, extent.height.max(1)),
+    }
+}
+
+/// Ensures the surface supports all the usages requested for the images of a swapchain.
+/// 
+/// # Arguments
+/// 
+/// * `requested` - The usage flags requested for the images of the swapchain.
+/// * `supported` - The usage flags supported by the surface.
+/// 
+/// # Panics
+/// 
+/// * Panics if `requested` contains flags that are not in `supported`.
+fn validate_image_usage(requested : vk::ImageUsageFlags, supported : vk::ImageUsageFlags) {
+    assert!(supported.contains(requested),
+        "The surface does not support swapchain images with usage {:?} (supported usages: {:?})", requested & !supported, supported);
 }
 
-make_handle! { Swapchain, vk::SwapchainKHR }
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use ash::vk;
+
+    use super::{image_count, scaled_extent, validate_image_usage};
+
+    #[test]
+    pub fn image_count_clamping() {
+        let unlimited = vk::SurfaceCapabilitiesKHR::default()
+            .min_image_count(2)
+            .max_image_count(0);
+
+        assert_eq!(image_count(&unlimited, None), 3);
+        assert_eq!(image_count(&unlimited, Some(1)), 2);
+        assert_eq!(image_count(&unlimited, Some(2)), 2);
+        assert_eq!(image_count(&unlimited, Some(8)), 8);
+
+        let limited = unlimited.max_image_count(3);
+        assert_eq!(image_count(&limited, None), 3);
+        assert_eq!(image_count(&limited, Some(4)), 3);
+
+        let minimal = unlimited.max_image_count(2);
+        assert_eq!(image_count(&minimal, None), 2);
+    }
+
+    #[test]
+    pub fn supported_image_usage() {
+        let supported = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::SAMPLED;
+
+        validate_image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT, supported);
+        validate_image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC, supported);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn unsupported_image_usage() {
+        validate_image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED);
+    }
+
+    #[test]
+    pub fn scaled_extents() {
+        let extent = vk::Extent2D { width : 1920, height : 1080 };
+
+        assert_eq!(scaled_extent(extent, 1.0), extent);
+        assert_eq!(scaled_extent(extent, 0.5), vk::Extent2D { width : 960, height : 540 });
+        assert_eq!(scaled_extent(extent, 0.75), vk::Extent2D { width : 1440, height : 810 });
+
+        // Scaled images are never empty.
+        assert_eq!(scaled_extent(vk::Extent2D { width : 3, height : 1 }, 0.1), vk::Extent2D { width : 1, height : 1 });
+    }
+}
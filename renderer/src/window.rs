@@ -1,13 +1,26 @@
+use ash::prelude::VkResult;
 use ash::vk;
-use egui_winit::winit::{self, event_loop::EventLoop, window::WindowBuilder};
-use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle};
+use egui_winit::winit::{self, event_loop::EventLoopWindowTarget, monitor::VideoMode, window::{Fullscreen, WindowBuilder}};
+use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
 
-use crate::{application::ApplicationOptions, traits::handle::Handle, vk::{context::Context, physical_device::PhysicalDevice, queue::QueueFamily}};
+use crate::{application::ApplicationOptions, traits::handle::Handle, vk::{context::Context, physical_device::PhysicalDevice, queue::QueueFamily, surface::Surface}};
 
 pub struct Window {
     handle : winit::window::Window,
 
-    surface : Option<(ash::khr::surface::Instance, vk::SurfaceKHR)>,
+    surface : Option<Surface>,
+}
+
+/// Describes how a [`Window`] occupies the screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// The window is decorated and does not cover the entire monitor.
+    Windowed,
+    /// The window covers the monitor it currently is on, without changing the monitor's video mode.
+    Borderless,
+    /// The window takes exclusive control of a monitor and switches it to the given video mode.
+    /// See [`Window::best_video_mode`] to select a default video mode.
+    Exclusive(VideoMode),
 }
 
 impl HasDisplayHandle for Window {
@@ -17,79 +30,104 @@ impl HasDisplayHandle for Window {
 }
 
 impl Window {
+    /// Creates the primary window of an application.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the minimum resolution of the window is larger than its resolution.
     pub fn new(
         options : &ApplicationOptions,
-        event_loop : &EventLoop<()>
+        event_loop : &EventLoopWindowTarget<()>
     ) -> Self {
+        let resolution = options.renderer_options.resolution;
+
+        let mut builder = WindowBuilder::default()
+            .with_title(options.title.clone())
+            .with_inner_size(winit::dpi::LogicalSize::new(resolution[0], resolution[1]))
+            .with_maximized(options.maximized)
+            .with_resizable(options.resizable)
+            .with_decorations(options.decorations);
+
+        #[cfg(target_os = "windows")]
+        {
+            use winit::platform::windows::WindowBuilderExtWindows;
+            builder = builder.with_drag_and_drop(options.drag_and_drop);
+        }
+
+        if let Some(min_resolution) = options.min_resolution {
+            assert!(fits_within(min_resolution, resolution),
+                "The minimum resolution of the window ({:?}) is larger than its resolution ({:?})", min_resolution, resolution);
+
+            builder = builder.with_min_inner_size(winit::dpi::LogicalSize::new(min_resolution[0], min_resolution[1]));
+        }
+
+        Self::from_builder(builder, event_loop)
+    }
+
+    /// Creates a new window from a [`WindowBuilder`]. This is typically used for secondary windows;
+    /// see [`RendererOrchestrator::add_viewport`](crate::orchestration::rendering::RendererOrchestrator::add_viewport).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `builder` - Describes the window to create.
+    /// * `event_loop` - The event loop that will receive the window's events.
+    pub fn from_builder(builder : WindowBuilder, event_loop : &EventLoopWindowTarget<()>) -> Self {
         Self {
-            handle : WindowBuilder::default()
-                .with_title(options.title.clone())
-                .with_inner_size(winit::dpi::LogicalSize::new(options.renderer_options.resolution[0], options.renderer_options.resolution[1]))
-                .build(event_loop)
+            handle : builder.build(event_loop)
                 .expect("Window creation failed"),
             surface : None
         }
     }
 
-    pub(in crate) fn create_surface(&mut self, context : &Context) {
-        unsafe {
-            let surface_loader = ash::khr::surface::Instance::new(&context.entry, &context.instance);
-            self.surface = match (self.handle.display_handle(), self.handle.window_handle()) {
-                (Ok(display_handle), Ok(window_handle)) => {
-                    let surface = ash_window::create_surface(&context.entry, &context.instance,
-                        display_handle.as_raw(),
-                        window_handle.as_raw(),
-                        None
-                    ).expect("Surface creation failed");
-
-                    Some((surface_loader, surface))
-                },
-                _ => None,
-            };
+    /// Creates the presentation surface of this window.
+    ///
+    /// # Returns
+    ///
+    /// The error returned by the driver if the surface could not be created.
+    pub(in crate) fn create_surface(&mut self, context : &Context) -> VkResult<()> {
+        self.surface = Some(Surface::try_new(context, &self.handle)?);
+        Ok(())
+    }
+
+    /// Destroys the surface of this window. Any swapchain created for that surface must have been destroyed beforehand.
+    pub(in crate) fn destroy_surface(&mut self) {
+        if let Some(surface) = self.surface.take() {
+            surface.destroy();
         }
     }
 
+    /// Returns the presentation surface of this window, if it was created.
+    pub fn get_surface(&self) -> Option<&Surface> {
+        self.surface.as_ref()
+    }
+
     pub fn get_surface_formats(&self, device : &PhysicalDevice) -> Vec<vk::SurfaceFormatKHR> {
-        unsafe {
-            if let Some(loader) = &self.surface {
-                loader.0.get_physical_device_surface_formats(device.handle(), loader.1)
-                    .expect("Failed to retrieve surface formats")
-            } else {
-                vec![]
-            }
-        }
+        self.surface.as_ref()
+            .map(|surface| surface.formats(device))
+            .unwrap_or_default()
     }
 
     pub fn get_surface_capabilities(&self, device : &PhysicalDevice) -> vk::SurfaceCapabilitiesKHR {
-        unsafe {
-            if let Some(loader) = &self.surface {
-                loader.0.get_physical_device_surface_capabilities(device.handle(), loader.1)
-                    .expect("Failed to retrieve surface capabilities")
-            } else {
-                vk::SurfaceCapabilitiesKHR::default()
-            }
-        }
+        self.surface.as_ref()
+            .map(|surface| surface.capabilities(device))
+            .unwrap_or_default()
     }
 
     pub fn get_surface_support(&self, device : &PhysicalDevice, queue : &QueueFamily) -> bool {
-        unsafe {
-            if let Some(loader) = &self.surface {
-                loader.0.get_physical_device_surface_support(device.handle(), queue.index(), loader.1)
-                    .expect("Failed to retrieve surface support")
-            } else {
-                false
-            }
-        }
+        self.surface.as_ref()
+            .is_some_and(|surface| surface.supports_queue(device, queue))
     }
 
     pub fn get_present_modes(&self, device : &PhysicalDevice) -> Vec<vk::PresentModeKHR> {
-        unsafe {
-            if let Some(loader) = &self.surface {
-                loader.0.get_physical_device_surface_present_modes(device.handle(), loader.1)
-                    .expect("Failed to retrieve surface present modes")
-            } else {
-                vec![]
-            }
+        self.surface.as_ref()
+            .map(|surface| surface.present_modes(device))
+            .unwrap_or_default()
+    }
+
+    /// Discards the cached properties of the surface of this window; see [`Surface::invalidate`].
+    pub fn invalidate_surface(&self) {
+        if let Some(surface) = &self.surface {
+            surface.invalidate();
         }
     }
 
@@ -109,7 +147,7 @@ impl Window {
 
     pub fn surface(&self) -> vk::SurfaceKHR {
         match &self.surface {
-            Some(surface) => surface.1,
+            Some(surface) => surface.handle(),
             None => vk::SurfaceKHR::null()
         }
     }
@@ -131,4 +169,69 @@ impl Window {
         let size = self.handle.inner_size();
         size.width == 0 && size.height == 0
     }
-}
\ No newline at end of file
+
+    /// Returns the current fullscreen mode of this window.
+    pub fn fullscreen_mode(&self) -> FullscreenMode {
+        match self.handle.fullscreen() {
+            Some(Fullscreen::Borderless(_)) => FullscreenMode::Borderless,
+            Some(Fullscreen::Exclusive(mode)) => FullscreenMode::Exclusive(mode),
+            None => FullscreenMode::Windowed,
+        }
+    }
+
+    /// Changes the fullscreen mode of this window.
+    /// 
+    /// # Description
+    /// 
+    /// Changing the fullscreen mode resizes the window; the swapchain is recreated once the corresponding
+    /// [`WindowEvent::Resized`](winit::event::WindowEvent::Resized) event is received.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `mode` - The fullscreen mode to use.
+    pub fn set_fullscreen(&self, mode : FullscreenMode) {
+        self.handle.set_fullscreen(match mode {
+            FullscreenMode::Windowed => None,
+            FullscreenMode::Borderless => Some(Fullscreen::Borderless(self.handle.current_monitor())),
+            FullscreenMode::Exclusive(mode) => Some(Fullscreen::Exclusive(mode)),
+        });
+    }
+
+    /// Toggles between windowed and borderless fullscreen modes. If the window is in exclusive fullscreen mode,
+    /// it goes back to windowed mode.
+    pub fn toggle_fullscreen(&self) {
+        match self.fullscreen_mode() {
+            FullscreenMode::Windowed => self.set_fullscreen(FullscreenMode::Borderless),
+            _ => self.set_fullscreen(FullscreenMode::Windowed),
+        }
+    }
+
+    /// Returns the video mode with the highest resolution, bit depth and refresh rate (in that order) supported by
+    /// the monitor this window is currently on, or [`None`] if that monitor can't be determined.
+    pub fn best_video_mode(&self) -> Option<VideoMode> {
+        self.handle.current_monitor()?
+            .video_modes()
+            .max_by_key(|mode| {
+                let size = mode.size();
+                (size.width * size.height, mode.bit_depth(), mode.refresh_rate_millihertz())
+            })
+    }
+}
+
+/// Returns true if a resolution is no larger than another one, in both dimensions.
+fn fits_within(resolution : [u32; 2], bounds : [u32; 2]) -> bool {
+    resolution[0] <= bounds[0] && resolution[1] <= bounds[1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::fits_within;
+
+    #[test]
+    pub fn min_resolution() {
+        assert!(fits_within([800, 600], [1280, 720]));
+        assert!(fits_within([1280, 720], [1280, 720]));
+        assert!(!fits_within([1920, 600], [1280, 720]));
+        assert!(!fits_within([800, 1080], [1280, 720]));
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{ensure, Result};
+use bytes::Buf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("Truncated BLTE header")]
+    TruncatedHeader,
+    #[error("Not a BLTE stream")]
+    NotBLTE,
+    #[error("Invalid BLTE chunk table flags: found {0:#x}, expected 0xF")]
+    InvalidFlags(u8),
+    #[error("Truncated BLTE chunk {0}")]
+    TruncatedChunk(usize),
+    #[error("Checksum mismatch in BLTE chunk {0}")]
+    InvalidHash(usize),
+    #[error("Unsupported BLTE chunk mode '{0}'")]
+    UnsupportedMode(char),
+    #[error("Decompressing BLTE chunk {0} failed: {1}")]
+    Decompression(usize, String),
+    #[error("Decoded size of BLTE chunk {0} mismatch: found {1}, expected {2}")]
+    SizeMismatch(usize, usize, usize),
+}
+
+/// Decodes a BLTE stream, which is how every file is stored in CASC archives.
+///
+/// # Description
+///
+/// A BLTE stream is a sequence of chunks, each of which can be stored raw (`N`), compressed with zlib (`Z`), or be a
+/// BLTE stream itself (`F`). Encrypted chunks (`E`) are not supported.
+///
+/// # Arguments
+///
+/// * `data` - The encoded stream, starting with the `BLTE` magic.
+pub fn decode(data : &[u8]) -> Result<Vec<u8>> {
+    let mut cursor = data;
+    ensure!(cursor.remaining() >= 8, ErrorCode::TruncatedHeader);
+    ensure!(&cursor[0..4] == b"BLTE", ErrorCode::NotBLTE);
+    cursor.advance(4);
+
+    let header_size : usize = cursor.get_u32().try_into()?;
+    if header_size == 0 {
+        // A single chunk spans the rest of the stream.
+        return decode_chunk(0, cursor, None);
+    }
+
+    ensure!(header_size >= 12 && data.len() >= header_size, ErrorCode::TruncatedHeader);
+    let flags = cursor.get_u8();
+    ensure!(flags == 0x0F, ErrorCode::InvalidFlags(flags));
+
+    let chunk_count : usize = cursor.get_uint(3).try_into()?;
+    ensure!(header_size >= 12 + chunk_count * 24, ErrorCode::TruncatedHeader);
+
+    let mut chunks = &data[header_size..];
+    let mut decoded = Vec::new();
+    for index in 0..chunk_count {
+        let encoded_size : usize = cursor.get_u32().try_into()?;
+        let decoded_size : usize = cursor.get_u32().try_into()?;
+        let hash = cursor.get_u128();
+
+        ensure!(chunks.remaining() >= encoded_size, ErrorCode::TruncatedChunk(index));
+        let chunk = &chunks[..encoded_size];
+        ensure!(u128::from_be_bytes(md5::compute(chunk).0) == hash, ErrorCode::InvalidHash(index));
+
+        decoded.extend(decode_chunk(index, chunk, Some(decoded_size))?);
+        chunks.advance(encoded_size);
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes a single chunk of a BLTE stream.
+///
+/// # Arguments
+///
+/// * `index` - The index of the chunk in the stream, used to report errors.
+/// * `chunk` - The chunk, starting with its mode.
+/// * `decoded_size` - The size of the decoded chunk, if known.
+fn decode_chunk(index : usize, chunk : &[u8], decoded_size : Option<usize>) -> Result<Vec<u8>> {
+    ensure!(!chunk.is_empty(), ErrorCode::TruncatedChunk(index));
+
+    let payload = &chunk[1..];
+    let decoded = match chunk[0] {
+        b'N' => payload.to_vec(),
+        b'Z' => miniz_oxide::inflate::decompress_to_vec_zlib(payload)
+            .map_err(|err| ErrorCode::Decompression(index, format!("{:?}", err)))?,
+        b'F' => decode(payload)?,
+        mode => return Err(ErrorCode::UnsupportedMode(mode as char).into()),
+    };
+
+    if let Some(decoded_size) = decoded_size {
+        ensure!(decoded.len() == decoded_size, ErrorCode::SizeMismatch(index, decoded.len(), decoded_size));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, ErrorCode};
+
+    /// Builds a BLTE stream with a chunk table from already encoded chunks.
+    fn make_blte(chunks : &[(Vec<u8>, usize)]) -> Vec<u8> {
+        let header_size = 12 + chunks.len() * 24;
+
+        let mut data = b"BLTE".to_vec();
+        data.extend((header_size as u32).to_be_bytes());
+        data.push(0x0F);
+        data.extend(&(chunks.len() as u32).to_be_bytes()[1..]);
+        for (chunk, decoded_size) in chunks {
+            data.extend((chunk.len() as u32).to_be_bytes());
+            data.extend((*decoded_size as u32).to_be_bytes());
+            data.extend(md5::compute(chunk).0);
+        }
+        for (chunk, _) in chunks {
+            data.extend(chunk);
+        }
+        data
+    }
+
+    #[test]
+    pub fn single_chunk() {
+        let mut data = b"BLTE".to_vec();
+        data.extend(0u32.to_be_bytes());
+        data.push(b'N');
+        data.extend(b"Hello");
+
+        assert_eq!(decode(&data).unwrap(), b"Hello");
+    }
+
+    #[test]
+    pub fn chunk_table() {
+        let mut raw = vec![b'N'];
+        raw.extend(b"Hello, ");
+
+        let mut compressed = vec![b'Z'];
+        compressed.extend(miniz_oxide::deflate::compress_to_vec_zlib(b"world!", 6));
+
+        let data = make_blte(&[(raw, 7), (compressed, 6)]);
+        assert_eq!(decode(&data).unwrap(), b"Hello, world!");
+    }
+
+    #[test]
+    pub fn corrupt_chunk() {
+        let mut data = make_blte(&[(b"NHello".to_vec(), 5)]);
+        *data.last_mut().unwrap() = b'!';
+
+        let error = decode(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::InvalidHash(0))));
+    }
+
+    #[test]
+    pub fn encrypted_chunk() {
+        let data = make_blte(&[(b"E\x00".to_vec(), 1)]);
+
+        let error = decode(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::UnsupportedMode('E'))));
+    }
+}
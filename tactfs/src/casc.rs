@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bytes::Buf;
+use thiserror::Error;
+
+use crate::blte;
+use crate::encoding::{Encoding, LoadFlags};
+use crate::psv::PSV;
+use crate::root::Root;
+use crate::types::{ContentKey, EncodingKey, FileDataID};
+
+/// The amount of bytes of encoding keys stored in local indices.
+const INDEX_KEY_SIZE : usize = 9;
+
+/// The size of the header preceding each file in the local data archives.
+const ARCHIVE_HEADER_SIZE : u32 = 30;
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("No .build.info file found at {0}")]
+    MissingBuildInfo(PathBuf),
+    #[error("Product {0} is not installed")]
+    UnknownProduct(String),
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Build configuration does not declare '{0}'")]
+    MissingConfigEntry(&'static str),
+    #[error("No index files found in {0}")]
+    MissingIndices(PathBuf),
+    #[error("Truncated index header")]
+    TruncatedIndexHeader,
+    #[error("Unsupported index version: found {0}, expected 7")]
+    InvalidIndexVersion(u16),
+    #[error("Unsupported index layout: {0}")]
+    UnsupportedIndexLayout(&'static str),
+    #[error("Truncated index entries: expected {0} bytes, found {1}")]
+    TruncatedIndexEntries(usize, usize),
+    #[error("Encoding key {0} is not present in local storage")]
+    UnknownEncodingKey(EncodingKey),
+    #[error("Content key {0} is not present in the encoding table")]
+    UnknownContentKey(ContentKey),
+    #[error("Invalid archive entry for encoding key {0}")]
+    InvalidArchiveEntry(EncodingKey),
+    #[error("File {0} is not present in the root table")]
+    UnknownFileDataID(FileDataID),
+}
+
+/// The location of a file in the local data archives.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct IndexEntry {
+    archive : u32,
+    offset : u64,
+    size : u32,
+}
+
+/// A local CASC storage, as found in a game installation directory.
+///
+/// # Description
+///
+/// Files stored in CASC are identified by the hash of their content (a [`ContentKey`]). The encoding table maps these
+/// to the hash of their encoded BLTE stream (an [`EncodingKey`]), and local indices map encoding keys to a location in
+/// the data archives found in `Data/data`. Game assets are usually referenced by a [`FileDataID`], which the root
+/// table maps to a content key.
+///
+/// Only files present on disk can be read; files that would have to be streamed from a CDN are reported as missing.
+pub struct Storage {
+    data_path : PathBuf,
+    indices : HashMap<[u8; INDEX_KEY_SIZE], IndexEntry>,
+    encoding : Encoding,
+    root : Root,
+}
+
+impl Storage {
+    /// Opens the local storage of a game installation.
+    ///
+    /// # Arguments
+    ///
+    /// * `install_path` - The installation directory, which contains `.build.info`.
+    /// * `product` - The product to open, as found in the `Product` column of `.build.info` (for example, `wow`).
+    pub fn open(install_path : &Path, product : &str) -> Result<Storage> {
+        let build_info_path = install_path.join(".build.info");
+        let build_info = PSV::from_file(&build_info_path)
+            .map_err(|_| ErrorCode::MissingBuildInfo(build_info_path))?;
+
+        let mut build_key = None;
+        build_info.for_each_record(|record| {
+            if build_key.is_none() && record.read("Product").try_raw().ok() == Some(product) {
+                build_key = record.read("Build Key").try_raw().ok().map(str::to_owned);
+            }
+        });
+        let build_key = build_key.ok_or_else(|| ErrorCode::UnknownProduct(product.to_owned()))?;
+
+        let data_path = install_path.join("Data");
+        let build_config = read_config(&data_path, &build_key)?;
+
+        let encoding_keys = build_config.get("encoding").ok_or(ErrorCode::MissingConfigEntry("encoding"))?;
+        ensure!(encoding_keys.len() == 2, ErrorCode::MissingConfigEntry("encoding"));
+        let encoding_key = EncodingKey::new(&decode_hex(&encoding_keys[1])?);
+
+        let root_key = build_config.get("root").and_then(|keys| keys.first()).ok_or(ErrorCode::MissingConfigEntry("root"))?;
+        let root_key = ContentKey::new(&decode_hex(root_key)?);
+
+        let mut storage = Storage {
+            indices : read_indices(&data_path.join("data"))?,
+            data_path,
+            encoding : Encoding::default(),
+            root : Root::default(),
+        };
+
+        let encoding = storage.read_file_by_encoding_key(&encoding_key).context("Reading encoding table")?;
+        storage.encoding = Encoding::new(&encoding, LoadFlags::Content.into())?;
+
+        let root = storage.read_file_by_content_key(&root_key).context("Reading root table")?;
+        storage.root = Root::new(&root)?;
+        Ok(storage)
+    }
+
+    /// Returns the root table, which lists every file of the build.
+    #[inline] pub fn root(&self) -> &Root { &self.root }
+
+    /// Returns the content key of a file given its ID.
+    pub fn resolve(&self, id : FileDataID) -> Option<&ContentKey> {
+        self.root.resolve(id)
+    }
+
+    /// Reads and decodes a file given its ID.
+    pub fn read_file_by_id(&self, id : FileDataID) -> Result<Vec<u8>> {
+        let key = self.resolve(id).ok_or(ErrorCode::UnknownFileDataID(id))?;
+        self.read_file_by_content_key(key)
+    }
+
+    /// Reads and decodes a file given its encoding key.
+    pub fn read_file_by_encoding_key(&self, key : &EncodingKey) -> Result<Vec<u8>> {
+        let entry = self.find(key).ok_or_else(|| ErrorCode::UnknownEncodingKey(key.clone()))?;
+        ensure!(entry.size > ARCHIVE_HEADER_SIZE, ErrorCode::InvalidArchiveEntry(key.clone()));
+
+        let archive_path = self.data_path.join("data").join(format!("data.{:03}", entry.archive));
+        let mut archive = File::open(&archive_path)
+            .with_context(|| format!("Opening {}", archive_path.display()))?;
+
+        let mut data = vec![0; (entry.size - ARCHIVE_HEADER_SIZE) as usize];
+        archive.seek(SeekFrom::Start(entry.offset + ARCHIVE_HEADER_SIZE as u64))?;
+        archive.read_exact(&mut data)
+            .with_context(|| format!("Reading {} from {}", key, archive_path.display()))?;
+
+        blte::decode(&data).with_context(|| format!("Decoding {}", key))
+    }
+
+    /// Reads and decodes a file given its content key.
+    pub fn read_file_by_content_key(&self, key : &ContentKey) -> Result<Vec<u8>> {
+        let encoding_keys = self.encoding.encoding_keys(key)
+            .ok_or_else(|| ErrorCode::UnknownContentKey(key.clone()))?;
+
+        // A file can be encoded in several ways; use the first one available locally.
+        let encoding_key = encoding_keys.iter()
+            .find(|key| self.contains(key))
+            .ok_or_else(|| ErrorCode::UnknownContentKey(key.clone()))?;
+
+        self.read_file_by_encoding_key(encoding_key)
+    }
+
+    /// Returns true if the file with the given encoding key is available in local storage.
+    pub fn contains(&self, key : &EncodingKey) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns the location of a file in the local data archives. Indices only store the first bytes of each key.
+    fn find(&self, key : &EncodingKey) -> Option<&IndexEntry> {
+        let index_key : [u8; INDEX_KEY_SIZE] = key.as_bytes().get(..INDEX_KEY_SIZE)?.try_into().ok()?;
+        self.indices.get(&index_key)
+    }
+}
+
+/// Reads a configuration file from `Data/config`.
+///
+/// # Returns
+///
+/// The values of each key, split on whitespace.
+fn read_config(data_path : &Path, key : &str) -> Result<HashMap<String, Vec<String>>> {
+    ensure!(key.len() >= 4 && key.is_ascii(), ErrorCode::InvalidKey(key.to_owned()));
+
+    let path = data_path.join("config").join(&key[0..2]).join(&key[2..4]).join(key);
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Reading {}", path.display()))?;
+
+    Ok(parse_config(&contents))
+}
+
+/// Parses a configuration file, made of lines formatted as `key = value value...`.
+fn parse_config(contents : &str) -> HashMap<String, Vec<String>> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, values)| (key.trim().to_owned(), values.split_whitespace().map(str::to_owned).collect()))
+        .collect()
+}
+
+/// Decodes a hexadecimal string.
+fn decode_hex(value : &str) -> Result<Vec<u8>> {
+    ensure!(value.len().is_multiple_of(2) && value.is_ascii(), ErrorCode::InvalidKey(value.to_owned()));
+
+    (0..value.len()).step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..index + 2], 16).map_err(|_| ErrorCode::InvalidKey(value.to_owned()).into()))
+        .collect()
+}
+
+/// Reads the most recent version of every index file in the given directory.
+fn read_indices(path : &Path) -> Result<HashMap<[u8; INDEX_KEY_SIZE], IndexEntry>> {
+    // Index files are named after their bucket and version, as two and eight hexadecimal digits respectively.
+    let mut buckets = HashMap::<u8, (u32, PathBuf)>::new();
+    let entries = fs::read_dir(path).map_err(|_| ErrorCode::MissingIndices(path.to_owned()))?;
+    for entry in entries {
+        let entry_path = entry?.path();
+        let Some(name) = entry_path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(".idx").filter(|stem| stem.len() == 10) else {
+            continue;
+        };
+        let (Ok(bucket), Ok(version)) = (u8::from_str_radix(&stem[0..2], 16), u32::from_str_radix(&stem[2..], 16)) else {
+            continue;
+        };
+
+        match buckets.get(&bucket) {
+            Some((latest, _)) if *latest >= version => { },
+            _ => { buckets.insert(bucket, (version, entry_path)); },
+        }
+    }
+    ensure!(!buckets.is_empty(), ErrorCode::MissingIndices(path.to_owned()));
+
+    let mut indices = HashMap::new();
+    for (_, index_path) in buckets.values() {
+        let data = fs::read(index_path).with_context(|| format!("Reading {}", index_path.display()))?;
+        let entries = parse_index(&data).with_context(|| format!("Parsing {}", index_path.display()))?;
+        indices.extend(entries);
+    }
+    Ok(indices)
+}
+
+/// Parses a local index file (version 7).
+fn parse_index(data : &[u8]) -> Result<Vec<([u8; INDEX_KEY_SIZE], IndexEntry)>> {
+    let mut cursor = data;
+    ensure!(cursor.remaining() >= 8 + 16, ErrorCode::TruncatedIndexHeader);
+
+    let header_size : usize = cursor.get_u32_le().try_into()?;
+    cursor.advance(4); // Header hash
+    ensure!(header_size >= 16 && cursor.remaining() >= header_size, ErrorCode::TruncatedIndexHeader);
+
+    let version = cursor.get_u16_le();
+    ensure!(version == 7, ErrorCode::InvalidIndexVersion(version));
+    cursor.advance(1 + 1); // Bucket, extra bytes
+    let size_bytes : usize = cursor.get_u8().into();
+    let offset_bytes : usize = cursor.get_u8().into();
+    let key_size : usize = cursor.get_u8().into();
+    let offset_bits = cursor.get_u8();
+
+    ensure!(size_bytes == 4, ErrorCode::UnsupportedIndexLayout("sizes must be 4 bytes wide"));
+    ensure!(offset_bytes == 5, ErrorCode::UnsupportedIndexLayout("offsets must be 5 bytes wide"));
+    ensure!(key_size == INDEX_KEY_SIZE, ErrorCode::UnsupportedIndexLayout("keys must be 9 bytes wide"));
+    ensure!(offset_bits < 40, ErrorCode::UnsupportedIndexLayout("offsets must leave room for an archive index"));
+
+    // Entries start on the next 16 bytes boundary after the header.
+    let entries_start = (8 + header_size + 15) & !15;
+    ensure!(data.len() >= entries_start + 8, ErrorCode::TruncatedIndexHeader);
+    let mut cursor = &data[entries_start..];
+
+    let entries_size : usize = cursor.get_u32_le().try_into()?;
+    cursor.advance(4); // Entries hash
+    ensure!(cursor.remaining() >= entries_size, ErrorCode::TruncatedIndexEntries(entries_size, cursor.remaining()));
+
+    let entry_size = key_size + offset_bytes + size_bytes;
+    let offset_mask = (1u64 << offset_bits) - 1;
+
+    let entries = cursor[..entries_size].chunks_exact(entry_size)
+        .map(|mut entry| {
+            let mut key = [0; INDEX_KEY_SIZE];
+            entry.copy_to_slice(&mut key);
+
+            let location = entry.get_uint(offset_bytes);
+            let size = entry.get_u32_le();
+
+            (key, IndexEntry {
+                archive : (location >> offset_bits) as u32,
+                offset : location & offset_mask,
+                size,
+            })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_hex, parse_config, parse_index, ErrorCode, IndexEntry};
+
+    fn make_index(entries : &[([u8; 9], u32, u64, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(16u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(7u16.to_le_bytes());
+        data.extend([0x03, 0, 4, 5, 9, 30]);
+        data.extend(0x4000_0000u64.to_le_bytes());
+        data.resize(32, 0);
+
+        data.extend((entries.len() as u32 * 18).to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        for (key, archive, offset, size) in entries {
+            data.extend(key);
+            data.extend(&(((*archive as u64) << 30) | offset).to_be_bytes()[3..]);
+            data.extend(size.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    pub fn index_entries() {
+        let key = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0x42];
+        let data = make_index(&[(key, 3, 0x1234_5678, 512), ([0; 9], 0, 0, 31)]);
+
+        let entries = parse_index(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (key, IndexEntry { archive : 3, offset : 0x1234_5678, size : 512 }));
+        assert_eq!(entries[1].1, IndexEntry { archive : 0, offset : 0, size : 31 });
+    }
+
+    #[test]
+    pub fn corrupt_index() {
+        let mut data = make_index(&[([0; 9], 0, 0, 31)]);
+        data[8] = 6;
+        let error = parse_index(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::InvalidIndexVersion(6))));
+
+        let data = make_index(&[([0; 9], 0, 0, 31)]);
+        let error = parse_index(&data[..data.len() - 1]).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::TruncatedIndexEntries(18, 17))));
+    }
+
+    #[test]
+    pub fn build_config() {
+        let config = parse_config("# Build Configuration\n\nroot = 0123\nencoding = 4567 89ab\nbuild-name = WOW-54901patch4.4.0_ClassicRetail\n");
+
+        assert_eq!(config["root"], ["0123"]);
+        assert_eq!(config["encoding"], ["4567", "89ab"]);
+        assert_eq!(decode_hex(&config["encoding"][1]).unwrap(), [0x89, 0xAB]);
+        assert!(decode_hex("xyz").is_err());
+    }
+}
@@ -1,17 +1,15 @@
 use std::collections::HashMap;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{ensure, Result};
 use bytes::Buf;
 use enumflags2::{bitflags, BitFlags};
 use thiserror::Error;
 
 use super::types::{ContentKey, EncodingKey};
 
+#[derive(Default)]
 pub(crate) struct Encoding {
-    especs : Vec<String>,
     content_map : HashMap<ContentKey, (Vec<EncodingKey>, u64)>,
-    encoding_map : HashMap<u128, (usize, u64)>,
-    espec : String
 }
 
 #[bitflags]
@@ -19,8 +17,6 @@ pub(crate) struct Encoding {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum LoadFlags {
     Content = 0x01,
-    Encoding = 0x02,
-    EncodingSpec = 0x04,
 }
 
 #[derive(Error, Debug)]
@@ -42,8 +38,8 @@ pub enum ErrorCode {
 impl Encoding {
     pub(crate) fn new(data : &[u8], flags : BitFlags<LoadFlags>) -> Result<Encoding> {
         let mut cursor = data;
-        ensure!(cursor.remaining() >= 16, ErrorCode::TruncatedHeader);
-        ensure!(&cursor[0..2] == b"NE", ErrorCode::NotEncoding);
+        ensure!(cursor.remaining() >= 22, ErrorCode::TruncatedHeader);
+        ensure!(&cursor[0..2] == b"EN", ErrorCode::NotEncoding);
         ensure!(cursor[2] == 1, ErrorCode::InvalidVersion(cursor[2]));
         cursor.advance(2 + 1);
 
@@ -51,25 +47,20 @@ impl Encoding {
         let ekey_size : usize = cursor.get_u8().into();
 
         let cpage_size : usize = usize::from(cursor.get_u16()) * 1024;
-        let epage_size : usize = usize::from(cursor.get_u16()) * 1024;
+        // Encoding pages, which map encoding keys to encoding specs, are not read.
+        cursor.advance(2);
 
         let ccount : usize = cursor.get_u32().try_into()?;
-        let ecount : usize = cursor.get_u32().try_into()?;
+        cursor.advance(4);
 
         ensure!(cursor[0] == 0, ErrorCode::Unexpected(2 + 3 + 2 + 2 + 4 + 4, cursor[0], 0));
+        cursor.advance(1);
 
         let espec_size = cursor.get_u32().try_into()?;
         ensure!(cursor.remaining() >= espec_size, ErrorCode::TruncatedEspec(espec_size, cursor.remaining()));
 
-        let especs = {
-            if flags.contains(LoadFlags::EncodingSpec) {
-                cursor[0..espec_size].split(|byte| *byte == 0)
-                    .map(|bytes| String::from_utf8(bytes.to_vec()).context("Parsing encoding spec"))
-                    .collect::<Result<Vec<String>>>()?
-            } else {
-                vec![]
-            }
-        }; cursor.advance(espec_size);
+        // Encoding specs describe how files were encoded, which decoding BLTE streams does not need.
+        cursor.advance(espec_size);
 
         ensure!(cursor.remaining() >= ccount * 32);
         
@@ -103,10 +94,11 @@ impl Encoding {
                     ensure!(!first || first_key == content_key); // First key mismatch in content
                     first = false;
 
-                    ensure!(page.remaining() >= key_count * 16_usize);
+                    ensure!(page.remaining() >= key_count * ekey_size);
 
                     let mut encoding_keys = Vec::<EncodingKey>::with_capacity(key_count);
                     page.chunk().chunks(ekey_size)
+                        .take(key_count)
                         .map(&EncodingKey::new)
                         .for_each(|k| encoding_keys.push(k));
 
@@ -120,11 +112,56 @@ impl Encoding {
             cursor.advance(ccount * (ckey_size + 0x10 + cpage_size));
         }
 
-        Ok(Encoding {
-            especs,
-            content_map,
-            encoding_map : HashMap::new(),
-            espec : "".to_string()
-        })
+        Ok(Encoding { content_map })
+    }
+
+    /// Returns the encoding keys of a file given its content key. Only available if the table was loaded with
+    /// [`LoadFlags::Content`].
+    pub(crate) fn encoding_keys(&self, key : &ContentKey) -> Option<&[EncodingKey]> {
+        self.content_map.get(key).map(|(keys, _)| &keys[..])
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::{ContentKey, EncodingKey};
+
+    use super::{Encoding, LoadFlags};
+
+    #[test]
+    pub fn content_pages() {
+        // A single content page of 1 KiB, holding two files; the second one has two encodings.
+        let mut page = Vec::new();
+        page.extend([1, 0, 0, 0, 0, 100]);
+        page.extend([0x11; 16]);
+        page.extend([0xA1; 16]);
+        page.extend([2, 0, 0, 0, 1, 0]);
+        page.extend([0x22; 16]);
+        page.extend([0xB1; 16]);
+        page.extend([0xB2; 16]);
+        page.resize(1024, 0);
+
+        let mut data = b"EN".to_vec();
+        data.extend([1, 16, 16]);
+        data.extend(1u16.to_be_bytes()); // Content page size, in KiB
+        data.extend(1u16.to_be_bytes()); // Encoding page size, in KiB
+        data.extend(1u32.to_be_bytes()); // Content page count
+        data.extend(0u32.to_be_bytes()); // Encoding page count
+        data.push(0);
+        data.extend(2u32.to_be_bytes());
+        data.extend(b"z\0");
+        data.extend([0x11; 16]);
+        data.extend(md5::compute(&page).0);
+        data.extend(&page);
+
+        let encoding = Encoding::new(&data, LoadFlags::Content.into()).unwrap();
+        assert_eq!(encoding.encoding_keys(&ContentKey::new(&[0x11; 16])), Some(&[EncodingKey::new(&[0xA1; 16])][..]));
+        assert_eq!(encoding.encoding_keys(&ContentKey::new(&[0x22; 16])),
+            Some(&[EncodingKey::new(&[0xB1; 16]), EncodingKey::new(&[0xB2; 16])][..]));
+        assert_eq!(encoding.encoding_keys(&ContentKey::new(&[0x33; 16])), None);
+
+        // Corrupting the page invalidates its checksum.
+        *data.last_mut().unwrap() ^= 0xFF;
+        assert!(Encoding::new(&data, LoadFlags::Content.into()).is_err());
+    }
+}
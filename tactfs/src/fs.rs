@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+// Placeholder for the installation described by `.build.info`; none of its fields are read yet.
+#[allow(dead_code)]
 pub struct FileSystem {
     path : PathBuf,
     branch : String,
@@ -18,14 +20,14 @@ pub struct FileSystem {
     product : String,
 }
 impl FileSystem {
-    pub fn open(path : PathBuf) {
+    pub fn open(_path : PathBuf) {
         
     }
 }
 
 pub struct FileSystemProvider;
 impl FileSystemProvider {
-    pub fn enumerate(root : PathBuf) {
+    pub fn enumerate(_root : PathBuf) {
 
     }
 }
\ No newline at end of file
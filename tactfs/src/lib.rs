@@ -1,4 +1,7 @@
+pub mod blte;
+pub mod casc;
 pub mod encoding;
 pub mod fs;
 pub mod psv;
+pub mod root;
 pub mod types;
\ No newline at end of file
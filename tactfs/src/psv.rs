@@ -92,7 +92,7 @@ impl Value {
     }
 
     pub fn bool(&self) -> Result<bool, Error> {
-        return self.dec().map(|v| v == 1)
+        self.dec().map(|v| v == 1)
     }
 }
 
@@ -159,7 +159,7 @@ pub struct Record<'a>(&'a PSV, usize);
 impl Record<'_> {
     pub fn index(&self) -> usize { self.1 }
 
-    pub fn read(&self, column : &'static str) -> OptionalValue {
+    pub fn read(&self, column : &'static str) -> OptionalValue<'_> {
         let column_index = self.0.columns.iter().position(|(column_name, _)| {
             *column_name == column
         });
@@ -251,18 +251,18 @@ impl PSV {
     }
 
     pub fn for_each_record<F>(&self, callback : F) where F : FnMut(Record) {
-        (0..self.record_count()).map(|i| Record(&self, i)).for_each(callback)
+        (0..self.record_count()).map(|i| Record(self, i)).for_each(callback)
     }
 
     pub fn record_count(&self) -> usize {
         self.values.len() / self.record_size
     }
 
-    pub fn record(&self, index : usize) -> Option<Record> {
+    pub fn record(&self, index : usize) -> Option<Record<'_>> {
         if index * self.record_size >= self.values.len() {
             None
         } else {
-            Some(Record(&self, index))
+            Some(Record(self, index))
         }
     }
 }
@@ -292,7 +292,7 @@ mod test {
 
             assert_eq!(branch, "eu");
             assert_eq!(product, "wow_classic");
-            assert_eq!(active, true);
+            assert!(active);
             assert_eq!(cdn_hosts, vec![ "blzddist1-a.akamaihd.net", "level3.blizzard.com", "eu.cdn.blizzard.com" ]);
             assert_eq!(build_key, vec![ 0x8A, 0xED, 0xE3, 0xC9, 0x2D, 0x9C, 0x28, 0xD8, 0x94, 0xCB, 0xC9, 0x78, 0xC5, 0xB7, 0xC2, 0x42 ]);
         });
@@ -1,3 +1,197 @@
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+use bytes::Buf;
+use thiserror::Error;
+
+use super::types::{ContentKey, FileDataID};
+
+/// The magic of root files since 8.2, `MFST` read as a little-endian integer.
+const MFST_MAGIC : u32 = 0x4D46_5354;
+
+/// Blocks with this content flag have no name hashes, if the root file allows it.
+const NO_NAME_HASH : u32 = 0x1000_0000;
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("Truncated root header")]
+    TruncatedHeader,
+    #[error("Invalid root version: found {0}, expected 1")]
+    InvalidVersion(u32),
+    #[error("Truncated root block at offset {0}")]
+    TruncatedBlock(usize),
+}
+
+/// A version of a file referenced by the root table. The same file can be present several times, for different
+/// locales or platforms.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RootEntry {
+    pub content_key : ContentKey,
+    pub locale_flags : u32,
+    pub content_flags : u32,
+}
+
+/// The root table, which maps file data IDs to the content keys of the files.
+#[derive(Default)]
 pub struct Root {
-    // God bless
-}
\ No newline at end of file
+    entries : HashMap<FileDataID, Vec<RootEntry>>,
+}
+
+impl Root {
+    /// Parses a root table, in either the legacy format or the `MFST` format.
+    pub fn new(data : &[u8]) -> Result<Root> {
+        let mut cursor = data;
+        ensure!(cursor.remaining() >= 4, ErrorCode::TruncatedHeader);
+
+        // (version, whether blocks without name hashes are allowed)
+        let (version, allow_unnamed) = if cursor.chunk()[0..4] == MFST_MAGIC.to_le_bytes() {
+            cursor.advance(4);
+            ensure!(cursor.remaining() >= 8, ErrorCode::TruncatedHeader);
+
+            let first = cursor.get_u32_le();
+            let second = cursor.get_u32_le();
+            if first == 24 {
+                // Since 10.1.7, the header declares its size and version before the file counts.
+                ensure!(second == 1, ErrorCode::InvalidVersion(second));
+                ensure!(cursor.remaining() >= 12, ErrorCode::TruncatedHeader);
+
+                let total_files = cursor.get_u32_le();
+                let named_files = cursor.get_u32_le();
+                cursor.advance(4);
+                (2, total_files != named_files)
+            } else {
+                (1, first != second)
+            }
+        } else {
+            (0, false)
+        };
+
+        let mut entries = HashMap::<FileDataID, Vec<RootEntry>>::new();
+        while cursor.has_remaining() {
+            let offset = data.len() - cursor.remaining();
+            ensure!(cursor.remaining() >= if version == 2 { 17 } else { 12 }, ErrorCode::TruncatedBlock(offset));
+
+            let record_count : usize = cursor.get_u32_le().try_into()?;
+            let (content_flags, locale_flags) = if version == 2 {
+                let locale_flags = cursor.get_u32_le();
+                let content_flags = cursor.get_u32_le() | cursor.get_u32_le() | (u32::from(cursor.get_u8()) << 17);
+                (content_flags, locale_flags)
+            } else {
+                (cursor.get_u32_le(), cursor.get_u32_le())
+            };
+
+            let has_name_hashes = !allow_unnamed || content_flags & NO_NAME_HASH == 0;
+            let record_size = 4 + 16 + if has_name_hashes { 8 } else { 0 };
+            ensure!(cursor.remaining() >= record_count * record_size, ErrorCode::TruncatedBlock(offset));
+
+            // File data IDs are stored as deltas from the previous ID plus one.
+            let mut file_data_ids = Vec::with_capacity(record_count);
+            let mut next_id = 0u32;
+            for _ in 0..record_count {
+                let id = next_id.wrapping_add_signed(cursor.get_i32_le());
+                file_data_ids.push(FileDataID(id));
+                next_id = id.wrapping_add(1);
+            }
+
+            for id in file_data_ids {
+                let content_key = ContentKey::from(&mut cursor, 16, true);
+                // Legacy root files interleave content keys and name hashes.
+                if version == 0 {
+                    cursor.advance(8);
+                }
+
+                entries.entry(id).or_default().push(RootEntry { content_key, locale_flags, content_flags });
+            }
+
+            if version != 0 && has_name_hashes {
+                cursor.advance(record_count * 8);
+            }
+        }
+
+        Ok(Root { entries })
+    }
+
+    /// Returns the content key of a file, or `None` if the file is unknown. If several versions of the file exist,
+    /// the first one is returned.
+    pub fn resolve(&self, id : FileDataID) -> Option<&ContentKey> {
+        self.entries(id).first().map(|entry| &entry.content_key)
+    }
+
+    /// Returns every version of a file.
+    pub fn entries(&self, id : FileDataID) -> &[RootEntry] {
+        self.entries.get(&id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns the IDs of all files in this table, in no particular order.
+    pub fn file_data_ids(&self) -> impl Iterator<Item = FileDataID> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Returns the amount of files in this table.
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::{ContentKey, FileDataID};
+
+    use super::{Root, MFST_MAGIC, NO_NAME_HASH};
+
+    /// Builds a root block in the `MFST` format, version 1.
+    fn make_block(ids : &[i32], content_flags : u32, locale_flags : u32, name_hashes : bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((ids.len() as u32).to_le_bytes());
+        data.extend(content_flags.to_le_bytes());
+        data.extend(locale_flags.to_le_bytes());
+        ids.iter().for_each(|delta| data.extend(delta.to_le_bytes()));
+        (0..ids.len()).for_each(|index| data.extend([index as u8 + 1; 16]));
+        if name_hashes {
+            (0..ids.len()).for_each(|index| data.extend((index as u64).to_le_bytes()));
+        }
+        data
+    }
+
+    #[test]
+    pub fn mfst_root() {
+        let mut data = Vec::new();
+        data.extend(MFST_MAGIC.to_le_bytes());
+        data.extend(5u32.to_le_bytes()); // Total files
+        data.extend(2u32.to_le_bytes()); // Named files
+        data.extend(make_block(&[10, 0, 5], NO_NAME_HASH, 0x2, false));
+        data.extend(make_block(&[10, 7], 0, 0x4, true));
+
+        let root = Root::new(&data).unwrap();
+        assert_eq!(root.len(), 4);
+        assert_eq!(root.resolve(FileDataID(10)), Some(&ContentKey::new(&[1; 16])));
+        assert_eq!(root.resolve(FileDataID(11)), Some(&ContentKey::new(&[2; 16])));
+        assert_eq!(root.resolve(FileDataID(17)), Some(&ContentKey::new(&[3; 16])));
+        assert_eq!(root.resolve(FileDataID(18)), Some(&ContentKey::new(&[2; 16])));
+        assert_eq!(root.resolve(FileDataID(12)), None);
+
+        // File 10 is present in both blocks, with different locales.
+        let entries = root.entries(FileDataID(10));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].locale_flags, 0x4);
+    }
+
+    #[test]
+    pub fn legacy_root() {
+        let mut data = Vec::new();
+        data.extend(2u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0x2u32.to_le_bytes());
+        data.extend(3i32.to_le_bytes());
+        data.extend(1i32.to_le_bytes());
+        for index in 0..2u8 {
+            data.extend([index + 1; 16]);
+            data.extend(0u64.to_le_bytes());
+        }
+
+        let root = Root::new(&data).unwrap();
+        assert_eq!(root.resolve(FileDataID(3)), Some(&ContentKey::new(&[1; 16])));
+        assert_eq!(root.resolve(FileDataID(5)), Some(&ContentKey::new(&[2; 16])));
+        assert!(Root::new(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -2,17 +2,9 @@ use std::fmt::{Debug, Display, Formatter};
 
 use bytes::Buf;
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-pub(crate) struct ArchiveKey(pub(crate) u128);
-
-impl Display for ArchiveKey {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:032x}", self.0)
-    }
-}
-
+/// The hash of the contents of a file.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub(crate) struct ContentKey {
+pub struct ContentKey {
     buffer : Vec<u8>
 }
 
@@ -30,16 +22,21 @@ impl ContentKey {
     }
 
     pub fn len(&self) -> usize { self.buffer.len() }
+
+    pub fn is_empty(&self) -> bool { self.buffer.is_empty() }
+
+    pub fn as_bytes(&self) -> &[u8] { &self.buffer }
 }
 
 impl Display for ContentKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.buffer.fmt(f)
+        self.buffer.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
     }
 }
 
+/// The hash of a file once encoded as a BLTE stream.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub(crate) struct EncodingKey {
+pub struct EncodingKey {
     buffer : Vec<u8>
 }
 
@@ -57,17 +54,22 @@ impl EncodingKey {
     }
 
     pub fn len(&self) -> usize { self.buffer.len() }
+
+    pub fn is_empty(&self) -> bool { self.buffer.is_empty() }
+
+    pub fn as_bytes(&self) -> &[u8] { &self.buffer }
 }
 
 impl Display for EncodingKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.buffer.fmt(f)
+        self.buffer.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
     }
 }
 
 
-#[derive(Clone, Copy, Eq, Hash, PartialEq)]
-pub(crate) struct FileDataID(pub(crate) u32);
+/// The numeric identifier of a file, as used by the game to reference assets.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FileDataID(pub u32);
 
 impl Display for FileDataID {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -1,10 +1,45 @@
-use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub enum Event {
+use crate::rendering::model::Mesh;
 
+/// Requests sent between the interface, the renderers and the application.
+pub enum Event {
+    /// Replaces the model displayed in the viewport.
+    LoadModel(Arc<Mesh>),
+    /// Selects the game installation in the given directory, typically because it was dropped onto the window.
+    SelectInstallation(PathBuf),
 }
 
+/// A queue of events, shared by the interface, the renderers and the application.
+///
+/// Renderers are created by the orchestrator, which can't hand them a channel, so there is a single global queue.
+#[derive(Default)]
 pub struct Publisher {
-    
-}
\ No newline at end of file
+    events : Mutex<Vec<Event>>,
+}
+
+impl Publisher {
+    /// Returns the queue of events of the editor.
+    pub fn global() -> &'static Publisher {
+        static PUBLISHER : OnceLock<Publisher> = OnceLock::new();
+        PUBLISHER.get_or_init(Publisher::default)
+    }
+
+    /// Queues an event, to be processed by the renderers before the next frame is drawn.
+    pub fn publish(&self, event : Event) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    /// Removes the queued events accepted by a filter, in the order they were published. Other events remain queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Returns true for events handled by the caller.
+    pub fn drain(&self, filter : impl Fn(&Event) -> bool) -> Vec<Event> {
+        let mut events = self.events.lock().unwrap();
+        let (drained, remaining) = std::mem::take(&mut *events).into_iter().partition(filter);
+        *events = remaining;
+        drained
+    }
+}
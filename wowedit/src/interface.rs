@@ -1,8 +1,15 @@
 use std::{fs::FileType, path::{Path, PathBuf}};
+use std::sync::Arc;
 
-use egui::{collapsing_header::CollapsingState, Color32, Context, FontFamily, FontId, Label, Margin, RichText, TextEdit, Ui, Widget};
+use egui::{collapsing_header::CollapsingState, Color32, ColorImage, Context, FontFamily, FontId, Label, Margin, RichText, TextEdit, TextureHandle, TextureOptions, Ui, Widget};
 use egui_extras::{Column, TableBuilder};
+use tactfs::casc::Storage;
 use tactfs::psv::{Record, PSV};
+use tactfs::types::FileDataID;
+
+use crate::events::{Event, Publisher};
+use crate::rendering::blp;
+use crate::rendering::model::Mesh;
 
 #[derive(Default)]
 pub struct InterfaceState {    
@@ -11,6 +18,16 @@ pub struct InterfaceState {
 
     installation_path : String,
     psv_selection : Option<(String, String, String, String, String)>, // Row selected in .build.info
+    storage : Option<Storage>,
+    storage_error : Option<String>,
+
+    explorer_filter : String,
+    explorer_files : Vec<FileDataID>, // Files matching the filter, sorted by ID
+    explorer_selection : Option<(FileDataID, Result<Vec<u8>, String>)>, // Last file opened, or why it couldn't be read
+    explorer_preview : Option<Result<TextureHandle, String>>, // Preview of the last file opened, if it is a texture
+
+    model_id : String,
+    model_status : Option<Result<String, String>>, // Summary of the last model loaded, or why it couldn't be loaded
 
     active_tab : Tab,
 }
@@ -64,6 +81,13 @@ macro_rules! include_license {
 
 impl InterfaceState {
     pub fn render(&mut self, ctx : &Context) {
+        for event in Publisher::global().drain(|event| matches!(event, Event::SelectInstallation(_))) {
+            if let Event::SelectInstallation(path) = event {
+                self.installation_path = path.display().to_string();
+                self.active_tab = Tab::Home;
+            }
+        }
+
         egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
             ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
                 if ui.button("Profiler").clicked() {
@@ -177,6 +201,10 @@ impl InterfaceState {
                 .margin(Margin::symmetric(6.0, 8.0))
                 .ui(ui);
 
+            if let Some(error) = &self.storage_error {
+                ui.label(RichText::new(error).color(Color32::from_rgb(200, 0, 0)));
+            }
+
             let build_info = PSV::from_file(&Path::new(&self.installation_path).join(".build.info"));
             match build_info {
                 Ok(build_info) => {
@@ -219,6 +247,7 @@ impl InterfaceState {
                                         row.col(|ui| {
                                             if ui.button("Open").clicked() {
                                                 self.psv_selection = Some((version.to_string(), branch.to_string(), build_key.to_string(), cdn_key.to_string(), product.to_string()));
+                                                self.open_storage(product);
                                             }
                                         });
                                     });
@@ -235,6 +264,37 @@ impl InterfaceState {
         });
     }
 
+    /// Opens the local storage of the given product, found in the installation directory.
+    fn open_storage(&mut self, product : &str) {
+        match Storage::open(Path::new(&self.installation_path), product) {
+            Ok(storage) => {
+                self.storage = Some(storage);
+                self.storage_error = None;
+            },
+            Err(error) => {
+                self.storage = None;
+                self.storage_error = Some(format!("Could not open {}: {:#}", product, error));
+            },
+        }
+
+        self.explorer_selection = None;
+        self.explorer_preview = None;
+        self.filter_explorer();
+    }
+
+    /// Updates the list of files displayed in the file explorer.
+    fn filter_explorer(&mut self) {
+        let filter = self.explorer_filter.trim();
+
+        self.explorer_files = match &self.storage {
+            Some(storage) => storage.root().file_data_ids()
+                .filter(|id| id.to_string().starts_with(filter))
+                .collect(),
+            None => vec![],
+        };
+        self.explorer_files.sort_unstable();
+    }
+
     fn render_database(&mut self, ctx : &Context, ui : &mut Ui) {
 
     }
@@ -244,11 +304,119 @@ impl InterfaceState {
     }
 
     fn render_model(&mut self, ctx : &Context, ui : &mut Ui) {
+        let Some(storage) = &self.storage else {
+            ui.label("Open a game installation from the Home tab to load its models.");
+            return;
+        };
+
+        ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
+            ui.label(RichText::new("Models")
+                .size(18.0));
+
+            ui.label("File data ID of the model");
+            egui::TextEdit::singleline(&mut self.model_id)
+                .margin(Margin::symmetric(6.0, 8.0))
+                .ui(ui);
+
+            if ui.button("Load").clicked() {
+                self.model_status = Some(match self.model_id.trim().parse::<u32>() {
+                    Ok(id) => Mesh::load(storage, FileDataID(id))
+                        .map(|mesh| {
+                            let summary = format!("Model {}: {} vertices, {} triangles, {} submeshes",
+                                id, mesh.vertices.len(), mesh.indices.len() / 3, mesh.submeshes.len());
+                            Publisher::global().publish(Event::LoadModel(Arc::new(mesh)));
+                            summary
+                        })
+                        .map_err(|error| format!("Could not load model {}: {:#}", id, error)),
+                    Err(_) => Err(format!("'{}' is not a file data ID", self.model_id.trim())),
+                });
+            }
 
+            match &self.model_status {
+                Some(Ok(summary)) => { ui.label(summary); },
+                Some(Err(error)) => { ui.label(RichText::new(error).color(Color32::from_rgb(200, 0, 0))); },
+                None => { },
+            }
+        });
     }
 
     fn render_explorer(&mut self, ctx : &Context, ui : &mut Ui) {
+        if self.storage.is_none() {
+            ui.label("Open a game installation from the Home tab to browse its files.");
+            return;
+        }
+
+        ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
+            ui.label(RichText::new("File explorer")
+                .size(18.0));
+
+            ui.label("Filter files by ID");
+            let filter = egui::TextEdit::singleline(&mut self.explorer_filter)
+                .margin(Margin::symmetric(6.0, 8.0))
+                .ui(ui);
+            if filter.changed() {
+                self.filter_explorer();
+            }
 
+            match &self.explorer_selection {
+                Some((id, Ok(contents))) => { ui.label(format!("File {}: {} bytes", id, contents.len())); },
+                Some((id, Err(error))) => { ui.label(RichText::new(format!("Could not read file {}: {}", id, error)).color(Color32::from_rgb(200, 0, 0))); },
+                None => { },
+            }
+
+            match &self.explorer_preview {
+                Some(Ok(texture)) => { egui::Image::new(texture).max_size(egui::vec2(256.0, 256.0)).ui(ui); },
+                Some(Err(error)) => { ui.label(RichText::new(format!("Could not decode texture: {}", error)).color(Color32::from_rgb(200, 0, 0))); },
+                None => { },
+            }
+
+            let Some(storage) = &self.storage else {
+                return;
+            };
+
+            let mut opened = None;
+            TableBuilder::new(ui)
+                .striped(true)
+                .resizable(false)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto()) // File data ID
+                .column(Column::remainder()) // Content key
+                .column(Column::auto()) // Interaction button
+                .min_scrolled_height(0.0)
+                .header(20.0, |mut header| {
+                    header.col(|ui| { ui.strong("File data ID"); });
+                    header.col(|ui| { ui.strong("Content key"); });
+                    header.col(|_| { });
+                })
+                .body(|body| {
+                    body.rows(18.0, self.explorer_files.len(), |mut row| {
+                        let id = self.explorer_files[row.index()];
+                        let content_key = storage.resolve(id).map(|key| key.to_string()).unwrap_or_default();
+
+                        row.col(|ui| { Label::new(id.to_string()).selectable(false).ui(ui); });
+                        row.col(|ui| { Label::new(content_key).selectable(false).ui(ui); });
+                        row.col(|ui| {
+                            if ui.button("Open").clicked() {
+                                opened = Some(id);
+                            }
+                        });
+                    });
+                });
+
+            if let Some(id) = opened {
+                let contents = storage.read_file_by_id(id).map_err(|error| format!("{:#}", error));
+                self.explorer_preview = match &contents {
+                    Ok(contents) if contents.starts_with(b"BLP") => Some(blp::decode(contents)
+                        .map(|texture| {
+                            let image = ColorImage::from_rgba_unmultiplied([texture.width as usize, texture.height as usize], &texture.pixels);
+                            ctx.load_texture(format!("explorer-{}", id), image, TextureOptions::LINEAR)
+                        })
+                        .map_err(|error| format!("{:#}", error))),
+                    _ => None,
+                };
+                self.explorer_selection = Some((id, contents));
+            }
+        });
     }
 
     fn render_settings(&mut self, ctx : &Context, ui : &mut Ui) {
@@ -1,20 +1,20 @@
-use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[allow(dead_code)]
 
 use egui::Context;
-use egui::{FontData, FontDefinitions, FontFamily, FontId};
 use interface::InterfaceState;
-use renderer::application::{Application, ApplicationOptions, RendererError};
+use events::{Event, Publisher};
+use renderer::application::{Application, ApplicationOptions, FileDropEvent, RendererError};
 use renderer::gui::context::{Interface, InterfaceOptions};
 use renderer::orchestration::rendering::Orchestrator;
 use renderer::vk::renderer::{DynamicState, RendererOptions};
 
 use ash::vk;
 use rendering::geometry::GeometryRenderer;
-use theming::aesthetix::Aesthetix;
-use winit::event::WindowEvent;
+use theming::themes::StandardDark;
+use winit::event::{ElementState, WindowEvent};
+use winit::keyboard::{Key, NamedKey};
 
 mod events;
 mod interface;
@@ -31,30 +31,21 @@ fn setup(app : &mut Application) -> ApplicationData {
 fn prepare() -> ApplicationOptions {
     ApplicationOptions::default()
         .title("Send help")
-        .device_extension(ash::khr::swapchain::NAME.to_owned())
         .renderer(RendererOptions::default()
             .line_width(DynamicState::Fixed(1.0f32))
             .resolution([1280, 720])
             .multisampling(vk::SampleCountFlags::TYPE_4)
+            // Model textures are filtered anisotropically.
+            .required_features(vk::PhysicalDeviceFeatures::default()
+                .sampler_anisotropy(true))
         )
         .orchestrator(|context| {
             Orchestrator::new(context)
                 .add_renderer(|ctx, swapchain| Box::new(GeometryRenderer::supplier(swapchain, ctx, false)), None, None)
                 .add_renderer(|ctx, swapchain| {
-                    let _theme = theming::themes::StandardDark{};
-                    let style = egui::Style::default(); // _theme.custom_style();
-
-                    let mut fonts = FontDefinitions::default();
-                    load_fonts(&mut fonts, &None, "./assets/fonts");
-
-                    for (k, v) in &fonts.families {
-                        println!("Loaded {:?} {:?}", k, v);
-                    }
-
-                    let options = InterfaceOptions {
-                        style,
-                        fonts
-                    };
+                    let options = InterfaceOptions::default()
+                        .load_fonts_from("./assets/fonts")
+                        .theme(&StandardDark);
 
                     Box::new(Interface::supplier(swapchain, ctx, true, render_interface, options))
                 }, None, None)
@@ -66,7 +57,25 @@ pub fn render(app: &mut Application, data: &mut ApplicationData) -> Result<(), R
 }
 
 pub fn window_event(app: &mut Application, data : &mut ApplicationData, event: &WindowEvent) {
-    _ = app.orchestrator.handle_event(&event);
+    // Events consumed by the interface (such as typing in a text field) are not handled by the editor.
+    if app.orchestrator.handle_event(&event) {
+        return;
+    }
+
+    if let WindowEvent::KeyboardInput { event, .. } = event {
+        if event.state == ElementState::Pressed && !event.repeat && event.logical_key == Key::Named(NamedKey::F11) {
+            app.orchestrator.context.window.toggle_fullscreen();
+        }
+    }
+}
+
+pub fn file_drop(app: &mut Application, data : &mut ApplicationData, event : FileDropEvent) {
+    // Game installations are directories; files dropped onto the window are ignored.
+    if let FileDropEvent::Dropped(path) = event {
+        if path.is_dir() {
+            Publisher::global().publish(Event::SelectInstallation(path));
+        }
+    }
 }
 
 fn main() {
@@ -74,69 +83,10 @@ fn main() {
         .prepare(prepare)
         .render(render)
         .window_event(window_event)
+        .file_drop(file_drop)
         .run();
 }
 
 #[inline] fn render_interface(ctx : &Context, state : &mut InterfaceState) {
     state.render(ctx);
 }
-
-fn load_fonts<P>(def : &mut FontDefinitions, mut family : &Option<FontFamily>, dir : P) where P : AsRef<Path> {
-    if let Ok(entries) = std::fs::read_dir(&dir) {
-        for sub_path in entries {
-            if let Ok(sub_path) = sub_path {
-                let file_type = sub_path.file_type();
-                if let Ok(file_type) = file_type {
-                    let absolute_path = AsRef::<Path>::as_ref(&dir).join(sub_path.file_name());
-                    if file_type.is_file() {
-                        let file_data = std::fs::read(&absolute_path);
-                        if file_data.is_err() {
-                            println!("An error occured while loading '{:?}': {}", absolute_path, file_data.err().unwrap());
-                            continue;
-                        }
-
-                        let font_name = absolute_path.file_stem()
-                            .unwrap()
-                            .to_str()
-                            .unwrap();
-
-                        let index = font_name.find('-').map(|i| i + 1).unwrap_or_default();
-                        let font_name = &font_name[index..];
-
-                        if let Some(family) = &family {
-                            match family {
-                                FontFamily::Name(_) => unreachable!(),
-                                value => {
-                                    def.font_data.insert(
-                                        font_name.to_owned(),
-                                        FontData::from_owned(file_data.unwrap())
-                                    );
-
-                                    def.families.entry(value.clone())
-                                        .and_modify(move |value| value.push(font_name.to_owned()))
-                                        .or_insert(vec![font_name.to_owned()]);
-
-                                    def.families.insert(FontFamily::Name(font_name.into()), vec![font_name.to_owned()]);
-
-                                    println!("Loaded {:?} as {}", font_name, value);
-                                },
-                            }
-                        } else {
-                            println!("Tried to load font {:?} but this font should be in a subdirectory named 'proportional' or 'monospace'", font_name);
-                        }
-
-                    } else {
-                        if sub_path.file_name() == "proportional" {
-                            family = &Some(FontFamily::Proportional);
-                        } else if sub_path.file_name() == "monospace" {
-                            family = &Some(FontFamily::Monospace);
-                        }
-
-                        load_fonts(def, family, absolute_path);    
-                    }
-
-                }
-            }
-        }
-    }
-}
\ No newline at end of file
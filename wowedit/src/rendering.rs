@@ -1,3 +1,7 @@
+pub mod blp;
+pub mod camera;
 pub mod geometry;
+pub mod m2;
+pub mod model;
 pub mod terrain;
-pub mod world;
\ No newline at end of file
+pub mod world;
@@ -0,0 +1,367 @@
+use anyhow::{ensure, Result};
+use ash::vk;
+use bytes::Buf;
+use thiserror::Error;
+
+/// The size of the header of BLP2 files, including the palette.
+const BLP2_HEADER_SIZE : usize = 0x94 + 256 * 4;
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("Not a BLP file")]
+    NotBLP,
+    #[error("Truncated BLP header")]
+    TruncatedHeader,
+    #[error("Unsupported BLP encoding: {0}")]
+    Unsupported(&'static str),
+    #[error("Unsupported alpha depth: {0}")]
+    UnsupportedAlphaDepth(u8),
+    #[error("Invalid texture dimensions: {0}x{1}")]
+    InvalidDimensions(u32, u32),
+    #[error("Truncated mip level: expected {0} bytes, found {1}")]
+    TruncatedMip(usize, usize),
+}
+
+/// A texture decoded from a BLP file.
+#[derive(Debug)]
+pub struct Texture {
+    pub width : u32,
+    pub height : u32,
+    /// The pixels of the first mip level, as tightly packed RGBA8 values (see [`Texture::format`]).
+    pub pixels : Vec<u8>,
+}
+
+impl Texture {
+    /// Returns the format of the pixels, to use when creating an image for this texture.
+    #[inline] pub fn format(&self) -> vk::Format { vk::Format::R8G8B8A8_UNORM }
+
+    /// Returns the extent of an image that can hold the pixels of this texture.
+    #[inline] pub fn extent(&self) -> vk::Extent3D {
+        vk::Extent3D::default()
+            .width(self.width)
+            .height(self.height)
+            .depth(1)
+    }
+}
+
+/// The way pixels of a BLP file are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// Indices into a palette of 256 colors, followed by an alpha channel of the given depth.
+    Palettized(u8),
+    DXT1,
+    DXT3,
+    DXT5,
+    BGRA,
+}
+
+/// Decodes the first mip level of a BLP1 or BLP2 file to RGBA8.
+///
+/// # Description
+///
+/// Palettized textures, DXT1, DXT3 and DXT5 compressed textures, and uncompressed BGRA textures are supported. JPEG
+/// compressed textures are not.
+pub fn decode(data : &[u8]) -> Result<Texture> {
+    let mut cursor = data;
+    ensure!(cursor.remaining() >= 4, ErrorCode::NotBLP);
+
+    let (encoding, width, height, palette_offset) = match &cursor[0..4] {
+        b"BLP1" => {
+            ensure!(cursor.remaining() >= 0x9C + 256 * 4, ErrorCode::TruncatedHeader);
+            cursor.advance(4);
+
+            let compression = cursor.get_u32_le();
+            let alpha_depth = cursor.get_u32_le();
+            let width = cursor.get_u32_le();
+            let height = cursor.get_u32_le();
+            ensure!(compression == 1, ErrorCode::Unsupported("JPEG compression"));
+
+            (Encoding::Palettized(alpha_depth as u8), width, height, 0x9C)
+        },
+        b"BLP2" => {
+            ensure!(cursor.remaining() >= BLP2_HEADER_SIZE, ErrorCode::TruncatedHeader);
+            cursor.advance(4);
+
+            let color_type = cursor.get_u32_le();
+            ensure!(color_type == 1, ErrorCode::Unsupported("JPEG compression"));
+
+            let compression = cursor.get_u8();
+            let alpha_depth = cursor.get_u8();
+            let alpha_type = cursor.get_u8();
+            cursor.advance(1); // Has mips
+            let width = cursor.get_u32_le();
+            let height = cursor.get_u32_le();
+
+            let encoding = match (compression, alpha_type) {
+                (1, _) => Encoding::Palettized(alpha_depth),
+                (2, 0) => Encoding::DXT1,
+                (2, 1) => Encoding::DXT3,
+                (2, 7) => Encoding::DXT5,
+                (3, _) => Encoding::BGRA,
+                _ => return Err(ErrorCode::Unsupported("unknown compression").into()),
+            };
+
+            (encoding, width, height, 0x94)
+        },
+        _ => return Err(ErrorCode::NotBLP.into()),
+    };
+
+    ensure!(width != 0 && height != 0 && width <= 8192 && height <= 8192, ErrorCode::InvalidDimensions(width, height));
+
+    // Mip offsets and sizes immediately precede the palette.
+    let mut mips = &data[palette_offset - 128..palette_offset];
+    let offset = mips.get_u32_le() as usize;
+    mips.advance(15 * 4);
+    let size = mips.get_u32_le() as usize;
+
+    let expected_size = mip_size(encoding, width, height)?;
+    ensure!(size >= expected_size && data.len() >= offset + expected_size,
+        ErrorCode::TruncatedMip(expected_size, data.len().saturating_sub(offset).min(size)));
+    let mip = &data[offset..offset + expected_size];
+
+    let pixels = match encoding {
+        Encoding::Palettized(alpha_depth) => decode_palettized(mip, &data[palette_offset..palette_offset + 256 * 4], alpha_depth, width, height),
+        Encoding::BGRA => mip.chunks_exact(4).flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]]).collect(),
+        Encoding::DXT1 | Encoding::DXT3 | Encoding::DXT5 => decode_dxt(mip, encoding, width, height),
+    };
+
+    Ok(Texture { width, height, pixels })
+}
+
+/// Returns the size of the first mip level of a texture, in bytes.
+fn mip_size(encoding : Encoding, width : u32, height : u32) -> Result<usize> {
+    let pixels = width as usize * height as usize;
+    let blocks = width.div_ceil(4) as usize * height.div_ceil(4) as usize;
+
+    Ok(match encoding {
+        Encoding::Palettized(alpha_depth) => {
+            ensure!(matches!(alpha_depth, 0 | 1 | 4 | 8), ErrorCode::UnsupportedAlphaDepth(alpha_depth));
+            pixels + (pixels * alpha_depth as usize).div_ceil(8)
+        },
+        Encoding::DXT1 => blocks * 8,
+        Encoding::DXT3 | Encoding::DXT5 => blocks * 16,
+        Encoding::BGRA => pixels * 4,
+    })
+}
+
+fn decode_palettized(mip : &[u8], palette : &[u8], alpha_depth : u8, width : u32, height : u32) -> Vec<u8> {
+    let pixel_count = width as usize * height as usize;
+    let (indices, alpha) = mip.split_at(pixel_count);
+
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    for (i, index) in indices.iter().enumerate() {
+        let color = &palette[*index as usize * 4..][..4];
+        let alpha = match alpha_depth {
+            1 => if (alpha[i / 8] >> (i % 8)) & 1 != 0 { 0xFF } else { 0x00 },
+            4 => ((alpha[i / 2] >> ((i % 2) * 4)) & 0x0F) * 0x11,
+            8 => alpha[i],
+            _ => 0xFF,
+        };
+
+        pixels.extend([color[2], color[1], color[0], alpha]);
+    }
+    pixels
+}
+
+fn decode_dxt(mip : &[u8], encoding : Encoding, width : u32, height : u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let block_size = if encoding == Encoding::DXT1 { 8 } else { 16 };
+    let blocks_per_row = width.div_ceil(4);
+
+    let mut pixels = vec![0; width * height * 4];
+    for (block_index, block) in mip.chunks_exact(block_size).enumerate() {
+        let (alpha_block, color_block) = block.split_at(block_size - 8);
+        let colors = decode_color_block(color_block, encoding == Encoding::DXT1);
+        let alphas = match encoding {
+            Encoding::DXT3 => decode_explicit_alpha(alpha_block),
+            Encoding::DXT5 => decode_interpolated_alpha(alpha_block),
+            _ => [0xFF; 16],
+        };
+
+        let (block_x, block_y) = ((block_index % blocks_per_row) * 4, (block_index / blocks_per_row) * 4);
+        for texel in 0..16 {
+            let (x, y) = (block_x + texel % 4, block_y + texel / 4);
+            if x >= width || y >= height {
+                continue;
+            }
+
+            let mut color = colors[texel];
+            if encoding != Encoding::DXT1 {
+                color[3] = alphas[texel];
+            }
+
+            let offset = (y * width + x) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+/// Decodes the 16 texels of a DXT color block.
+///
+/// # Arguments
+///
+/// * `block` - The 8 bytes of the block.
+/// * `allow_transparency` - Whether blocks with `color0 <= color1` encode a transparent color, as in DXT1.
+fn decode_color_block(block : &[u8], allow_transparency : bool) -> [[u8; 4]; 16] {
+    let mut block = block;
+    let color0 = block.get_u16_le();
+    let color1 = block.get_u16_le();
+    let indices = block.get_u32_le();
+
+    let [r0, g0, b0] = rgb565(color0);
+    let [r1, g1, b1] = rgb565(color1);
+    let mix = |a : u8, b : u8, wa : u32, wb : u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+    let palette = if color0 > color1 || !allow_transparency {
+        [
+            [r0, g0, b0, 0xFF],
+            [r1, g1, b1, 0xFF],
+            [mix(r0, r1, 2, 1), mix(g0, g1, 2, 1), mix(b0, b1, 2, 1), 0xFF],
+            [mix(r0, r1, 1, 2), mix(g0, g1, 1, 2), mix(b0, b1, 1, 2), 0xFF],
+        ]
+    } else {
+        [
+            [r0, g0, b0, 0xFF],
+            [r1, g1, b1, 0xFF],
+            [mix(r0, r1, 1, 1), mix(g0, g1, 1, 1), mix(b0, b1, 1, 1), 0xFF],
+            [0, 0, 0, 0],
+        ]
+    };
+
+    std::array::from_fn(|texel| palette[((indices >> (texel * 2)) & 0b11) as usize])
+}
+
+/// Expands a 5:6:5 color to 8 bits per channel.
+fn rgb565(color : u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1F) as u8;
+    let g = ((color >> 5) & 0x3F) as u8;
+    let b = (color & 0x1F) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+/// Decodes the alpha block of a DXT3 block, which stores 4 bits per texel.
+fn decode_explicit_alpha(block : &[u8]) -> [u8; 16] {
+    std::array::from_fn(|texel| ((block[texel / 2] >> ((texel % 2) * 4)) & 0x0F) * 0x11)
+}
+
+/// Decodes the alpha block of a DXT5 block, which interpolates between two values with 3 bits per texel.
+fn decode_interpolated_alpha(block : &[u8]) -> [u8; 16] {
+    let (alpha0, alpha1) = (block[0] as u32, block[1] as u32);
+    let indices = block[2..8].iter().rev().fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+
+    std::array::from_fn(|texel| {
+        let index = ((indices >> (texel * 3)) & 0b111) as u32;
+        let alpha = match index {
+            0 => alpha0,
+            1 => alpha1,
+            _ if alpha0 > alpha1 => ((8 - index) * alpha0 + (index - 1) * alpha1) / 7,
+            6 => 0,
+            7 => 0xFF,
+            _ => ((6 - index) * alpha0 + (index - 1) * alpha1) / 5,
+        };
+        alpha as u8
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, ErrorCode, BLP2_HEADER_SIZE};
+
+    /// Builds a BLP2 file with a single mip level.
+    fn make_blp2(compression : u8, alpha_depth : u8, alpha_type : u8, width : u32, height : u32, palette : &[u32], mip : &[u8]) -> Vec<u8> {
+        let mut data = b"BLP2".to_vec();
+        data.extend(1u32.to_le_bytes());
+        data.extend([compression, alpha_depth, alpha_type, 0]);
+        data.extend(width.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        data.extend((BLP2_HEADER_SIZE as u32).to_le_bytes());
+        data.extend([0; 15 * 4]);
+        data.extend((mip.len() as u32).to_le_bytes());
+        data.extend([0; 15 * 4]);
+        for index in 0..256 {
+            data.extend(palette.get(index).copied().unwrap_or_default().to_le_bytes());
+        }
+        data.extend(mip);
+        data
+    }
+
+    fn pixel(pixels : &[u8], width : u32, x : u32, y : u32) -> [u8; 4] {
+        let offset = ((y * width + x) * 4) as usize;
+        pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    pub fn dxt1() {
+        // Red and blue endpoints; rows use indices 0, 1, 2 and 3 respectively.
+        let mut mip = Vec::new();
+        mip.extend(0xF800u16.to_le_bytes());
+        mip.extend(0x001Fu16.to_le_bytes());
+        mip.extend([0b00_00_00_00, 0b01_01_01_01, 0b10_10_10_10, 0b11_11_11_11]);
+
+        let texture = decode(&make_blp2(2, 0, 0, 4, 4, &[], &mip)).unwrap();
+        assert_eq!((texture.width, texture.height), (4, 4));
+        assert_eq!(texture.pixels.len(), 4 * 4 * 4);
+        assert_eq!(pixel(&texture.pixels, 4, 0, 0), [0xFF, 0, 0, 0xFF]);
+        assert_eq!(pixel(&texture.pixels, 4, 3, 1), [0, 0, 0xFF, 0xFF]);
+        assert_eq!(pixel(&texture.pixels, 4, 1, 2), [0xAA, 0, 0x55, 0xFF]);
+        assert_eq!(pixel(&texture.pixels, 4, 2, 3), [0x55, 0, 0xAA, 0xFF]);
+
+        // With color0 <= color1, the last index is transparent.
+        let mut mip = mip;
+        mip.swap(0, 2);
+        mip.swap(1, 3);
+        let texture = decode(&make_blp2(2, 1, 0, 4, 4, &[], &mip)).unwrap();
+        assert_eq!(pixel(&texture.pixels, 4, 0, 3), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    pub fn dxt1_fixture() {
+        // An 8x8 BLP2 file with a full mip chain, whose four blocks are opaque white, opaque red to green, blue to
+        // cyan with a transparent row, and opaque black.
+        let texture = decode(include_bytes!("../../tests/dxt1.blp")).unwrap();
+        assert_eq!((texture.width, texture.height), (8, 8));
+        assert_eq!(texture.pixels.len(), 8 * 8 * 4);
+
+        let rows = [
+            [[0xFF, 0xFF, 0xFF, 0xFF], [0xFF, 0, 0, 0xFF]],
+            [[0xFF, 0xFF, 0xFF, 0xFF], [0, 0xFF, 0, 0xFF]],
+            [[0xFF, 0xFF, 0xFF, 0xFF], [0xAA, 0x55, 0, 0xFF]],
+            [[0xFF, 0xFF, 0xFF, 0xFF], [0x55, 0xAA, 0, 0xFF]],
+            [[0, 0, 0xFF, 0xFF], [0, 0, 0, 0xFF]],
+            [[0, 0x82, 0xFF, 0xFF], [0, 0, 0, 0xFF]],
+            [[0, 0x41, 0xFF, 0xFF], [0, 0, 0, 0xFF]],
+            [[0, 0, 0, 0], [0, 0, 0, 0xFF]],
+        ];
+        for (y, [left, right]) in rows.into_iter().enumerate() {
+            for x in 0..8 {
+                let expected = if x < 4 { left } else { right };
+                assert_eq!(pixel(&texture.pixels, 8, x, y as u32), expected, "Unexpected texel at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    pub fn palettized() {
+        // Two colors, with an 8-bit alpha channel following the indices.
+        let palette = [0xFFFF_0000, 0x0000_FF00];
+        let mip = [0, 1, 1, 0, 0x10, 0x20, 0x30, 0x40];
+
+        let texture = decode(&make_blp2(1, 8, 0, 2, 2, &palette, &mip)).unwrap();
+        assert_eq!(pixel(&texture.pixels, 2, 0, 0), [0xFF, 0, 0, 0x10]);
+        assert_eq!(pixel(&texture.pixels, 2, 1, 0), [0, 0xFF, 0, 0x20]);
+        assert_eq!(pixel(&texture.pixels, 2, 1, 1), [0xFF, 0, 0, 0x40]);
+    }
+
+    #[test]
+    pub fn unsupported_textures() {
+        let mut data = make_blp2(2, 0, 0, 4, 4, &[], &[0; 8]);
+        data[4] = 0;
+        let error = decode(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::Unsupported(_))));
+
+        let data = make_blp2(2, 0, 0, 8, 8, &[], &[0; 8]);
+        let error = decode(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::TruncatedMip(32, 8))));
+    }
+}
@@ -0,0 +1,325 @@
+use std::f32::consts::FRAC_PI_2;
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// A column-major 4x4 matrix, laid out the way GLSL expects a `mat4`.
+pub type Mat4 = [[f32; 4]; 4];
+pub type Vec3 = [f32; 3];
+
+/// How the camera reacts to user input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Dragging the mouse rotates the camera around a target; scrolling moves it closer or further.
+    Orbit,
+    /// Dragging the mouse rotates the camera in place; `W`, `A`, `S` and `D` move it.
+    FirstPerson,
+}
+
+/// A perspective camera, controlled with the mouse and keyboard.
+pub struct Camera {
+    pub mode : CameraMode,
+
+    /// The point the camera orbits around in [`CameraMode::Orbit`].
+    pub target : Vec3,
+    /// The distance between the camera and its target in [`CameraMode::Orbit`].
+    pub distance : f32,
+    /// The position of the camera in [`CameraMode::FirstPerson`].
+    pub position : Vec3,
+    /// Rotation around the vertical axis, in radians.
+    pub yaw : f32,
+    /// Rotation around the horizontal axis, in radians. Positive values look down.
+    pub pitch : f32,
+
+    /// The vertical field of view, in radians.
+    pub fov_y : f32,
+    pub near : f32,
+    pub far : f32,
+
+    /// Radians of rotation per pixel the mouse is dragged by.
+    pub sensitivity : f32,
+    /// Distance travelled per key press in [`CameraMode::FirstPerson`].
+    pub speed : f32,
+
+    dragging : bool,
+    cursor : Option<(f64, f64)>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            mode : CameraMode::Orbit,
+            target : [0.0; 3],
+            distance : 3.0,
+            position : [0.0, 0.0, 3.0],
+            yaw : 0.0,
+            pitch : 0.0,
+            fov_y : 60.0f32.to_radians(),
+            near : 0.1,
+            far : 1000.0,
+            sensitivity : 0.005,
+            speed : 0.1,
+            dragging : false,
+            cursor : None,
+        }
+    }
+}
+
+impl Camera {
+    /// Returns the position of the camera.
+    pub fn eye(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Orbit => add(self.target, scale(self.forward(), -self.distance)),
+            CameraMode::FirstPerson => self.position,
+        }
+    }
+
+    /// Returns the direction the camera looks towards.
+    pub fn forward(&self) -> Vec3 {
+        [
+            -self.pitch.cos() * self.yaw.sin(),
+            -self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        ]
+    }
+
+    /// Returns the matrix transforming world coordinates to view coordinates.
+    pub fn view(&self) -> Mat4 {
+        let eye = self.eye();
+        look_at(eye, add(eye, self.forward()), [0.0, 1.0, 0.0])
+    }
+
+    /// Returns the matrix transforming view coordinates to clip coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `aspect_ratio` - The width of the viewport divided by its height.
+    pub fn projection(&self, aspect_ratio : f32) -> Mat4 {
+        perspective(self.fov_y, aspect_ratio, self.near, self.far)
+    }
+
+    /// Returns the matrix transforming world coordinates to clip coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `aspect_ratio` - The width of the viewport divided by its height.
+    pub fn view_projection(&self, aspect_ratio : f32) -> Mat4 {
+        multiply(&self.projection(aspect_ratio), &self.view())
+    }
+
+    /// Updates the camera from a window event.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the event was used to control the camera.
+    pub fn handle_event(&mut self, event : &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput { state, button : MouseButton::Left, .. } => {
+                self.dragging = *state == ElementState::Pressed;
+                true
+            },
+            WindowEvent::CursorLeft { .. } => {
+                self.dragging = false;
+                self.cursor = None;
+                false
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let previous = self.cursor.replace((position.x, position.y));
+                match previous {
+                    Some((x, y)) if self.dragging => {
+                        self.rotate((position.x - x) as f32, (position.y - y) as f32);
+                        true
+                    },
+                    _ => false,
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } if self.mode == CameraMode::Orbit => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+
+                self.distance = (self.distance * 0.9f32.powf(lines)).max(self.near);
+                true
+            },
+            WindowEvent::KeyboardInput { event, .. } if self.mode == CameraMode::FirstPerson && event.state == ElementState::Pressed => {
+                let forward = self.forward();
+                let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+
+                let direction = match event.physical_key {
+                    PhysicalKey::Code(KeyCode::KeyW) => forward,
+                    PhysicalKey::Code(KeyCode::KeyS) => scale(forward, -1.0),
+                    PhysicalKey::Code(KeyCode::KeyD) => right,
+                    PhysicalKey::Code(KeyCode::KeyA) => scale(right, -1.0),
+                    _ => return false,
+                };
+
+                self.position = add(self.position, scale(direction, self.speed));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn rotate(&mut self, dx : f32, dy : f32) {
+        // Stop just short of looking straight up or down, where the view matrix degenerates.
+        const MAX_PITCH : f32 = FRAC_PI_2 - 0.01;
+
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = (self.pitch + dy * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+}
+
+/// Returns a right-handed view matrix.
+///
+/// # Arguments
+///
+/// * `eye` - The position of the camera.
+/// * `target` - The point the camera looks at.
+/// * `up` - The direction of the vertical axis.
+pub fn look_at(eye : Vec3, target : Vec3, up : Vec3) -> Mat4 {
+    let f = normalize(sub(target, eye));
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+
+    [
+        [s[0], u[0], -f[0], 0.0],
+        [s[1], u[1], -f[1], 0.0],
+        [s[2], u[2], -f[2], 0.0],
+        [-dot(s, eye), -dot(u, eye), dot(f, eye), 1.0],
+    ]
+}
+
+/// Returns a right-handed perspective projection matrix for Vulkan, where depth goes from zero at the near plane to
+/// one at the far plane and the vertical axis of clip space points down.
+///
+/// # Arguments
+///
+/// * `fov_y` - The vertical field of view, in radians.
+/// * `aspect_ratio` - The width of the viewport divided by its height.
+/// * `near` - The distance to the near plane.
+/// * `far` - The distance to the far plane.
+pub fn perspective(fov_y : f32, aspect_ratio : f32, near : f32, far : f32) -> Mat4 {
+    let focal_length = 1.0 / (fov_y / 2.0).tan();
+
+    [
+        [focal_length / aspect_ratio, 0.0, 0.0, 0.0],
+        [0.0, -focal_length, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+/// Returns the product of two matrices, `lhs * rhs`.
+pub fn multiply(lhs : &Mat4, rhs : &Mat4) -> Mat4 {
+    let mut product = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            product[column][row] = (0..4).map(|k| lhs[k][row] * rhs[column][k]).sum();
+        }
+    }
+    product
+}
+
+fn add(lhs : Vec3, rhs : Vec3) -> Vec3 { [lhs[0] + rhs[0], lhs[1] + rhs[1], lhs[2] + rhs[2]] }
+fn sub(lhs : Vec3, rhs : Vec3) -> Vec3 { [lhs[0] - rhs[0], lhs[1] - rhs[1], lhs[2] - rhs[2]] }
+fn scale(vector : Vec3, factor : f32) -> Vec3 { [vector[0] * factor, vector[1] * factor, vector[2] * factor] }
+fn dot(lhs : Vec3, rhs : Vec3) -> f32 { lhs[0] * rhs[0] + lhs[1] * rhs[1] + lhs[2] * rhs[2] }
+
+fn cross(lhs : Vec3, rhs : Vec3) -> Vec3 {
+    [
+        lhs[1] * rhs[2] - lhs[2] * rhs[1],
+        lhs[2] * rhs[0] - lhs[0] * rhs[2],
+        lhs[0] * rhs[1] - lhs[1] * rhs[0],
+    ]
+}
+
+fn normalize(vector : Vec3) -> Vec3 {
+    scale(vector, 1.0 / dot(vector, vector).sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{look_at, multiply, perspective, Mat4};
+
+    fn assert_matrix_eq(actual : Mat4, expected : Mat4) {
+        for (actual_column, expected_column) in actual.iter().zip(&expected) {
+            for (actual, expected) in actual_column.iter().zip(expected_column) {
+                assert!((actual - expected).abs() < 1e-5, "Expected {:?}, found {:?}", expected, actual);
+            }
+        }
+    }
+
+    /// Transforms a point by a matrix, returning its homogeneous coordinates.
+    fn transform(matrix : &Mat4, point : [f32; 3]) -> [f32; 4] {
+        std::array::from_fn(|row| (0..3).map(|k| matrix[k][row] * point[k]).sum::<f32>() + matrix[3][row])
+    }
+
+    #[test]
+    pub fn look_at_matrices() {
+        // Looking down -Z from +Z only translates the scene.
+        assert_matrix_eq(look_at([0.0, 0.0, 5.0], [0.0; 3], [0.0, 1.0, 0.0]), [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, -5.0, 1.0],
+        ]);
+
+        // Looking down -X from +X rotates the world X axis onto the view Z axis.
+        let view = look_at([3.0, 0.0, 0.0], [0.0; 3], [0.0, 1.0, 0.0]);
+        assert_matrix_eq(view, [
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, -3.0, 1.0],
+        ]);
+        assert_eq!(transform(&view, [0.0; 3]), [0.0, 0.0, -3.0, 1.0]);
+    }
+
+    #[test]
+    pub fn perspective_matrices() {
+        let projection = perspective(std::f32::consts::FRAC_PI_2, 2.0, 1.0, 11.0);
+        assert_matrix_eq(projection, [
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, -1.0, 0.0, 0.0],
+            [0.0, 0.0, -1.1, -1.0],
+            [0.0, 0.0, -1.1, 0.0],
+        ]);
+
+        // Depth is zero on the near plane and one on the far plane.
+        let [_, _, z, w] = transform(&projection, [0.0, 0.0, -1.0]);
+        assert!((z / w).abs() < 1e-5);
+        let [_, _, z, w] = transform(&projection, [0.0, 0.0, -11.0]);
+        assert!((z / w - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    pub fn matrix_products() {
+        let translation = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 2.0, 3.0, 1.0],
+        ];
+        let scaling = [
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        // The right-hand side is applied first.
+        assert_matrix_eq(multiply(&translation, &scaling), [
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0],
+            [1.0, 2.0, 3.0, 1.0],
+        ]);
+        assert_matrix_eq(multiply(&scaling, &translation), [
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0],
+            [2.0, 4.0, 6.0, 1.0],
+        ]);
+    }
+}
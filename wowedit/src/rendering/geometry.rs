@@ -1,26 +1,47 @@
 use std::mem::{offset_of, size_of};
+use std::sync::Arc;
 
 use ash::vk;
+use egui_winit::EventResponse;
 use puffin::profile_scope;
-use renderer::{orchestration::rendering::{Renderer, RenderingContext}, traits::handle::Handle, vk::{buffer::{Buffer, DynamicBufferBuilder, DynamicInitializer}, command_pool::CommandPool, frame_data::FrameData, framebuffer::Framebuffer, pipeline::{layout::{PipelineLayout, PipelineLayoutInfo}, DepthOptions, Pipeline, PipelineInfo, Vertex}, render_pass::{RenderPass, SubpassAttachment}, swapchain::Swapchain}};
+use renderer::{orchestration::rendering::{Renderer, RenderingContext}, traits::handle::Handle, vk::{buffer::{Buffer, DynamicBufferBuilder, DynamicInitializer, UniformBuffer}, command_pool::CommandPool, descriptor::{layout::DescriptorSetLayout, set::DescriptorSetInfo}, frame_data::FrameData, framebuffer::{Framebuffer, FramebufferCache}, pipeline::{layout::{PipelineLayout, PipelineLayoutInfo}, DepthOptions, Pipeline, PipelineInfo, Vertex}, render_pass::{RenderPass, SubpassAttachment}, swapchain::Swapchain}};
+use winit::event::WindowEvent;
+
+use crate::events::{Event, Publisher};
+use crate::rendering::camera::{Camera, Mat4};
+use crate::rendering::model::{Mesh, ModelRenderer};
 
 #[derive(Copy, Clone)]
 struct TerrainVertex {
-    pos : [f32; 2],
+    pos : [f32; 3],
     color : [f32; 3],
 }
 
+/// Per-instance data of the test mesh.
+#[derive(Copy, Clone)]
+struct InstanceData {
+    offset : [f32; 3],
+}
+
+/// The uniform block read by the vertex shader.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct CameraUniform {
+    view_projection : Mat4,
+}
+
 impl Vertex for TerrainVertex {
     fn bindings() -> Vec<(u32, vk::VertexInputRate)> {
         vec![
-            (size_of::<Self>() as u32, vk::VertexInputRate::VERTEX)
+            (size_of::<Self>() as u32, vk::VertexInputRate::VERTEX),
+            (size_of::<InstanceData>() as u32, vk::VertexInputRate::INSTANCE),
         ]
     }
 
     fn format_offset() -> Vec<vk::VertexInputAttributeDescription> {
         vec![
             vk::VertexInputAttributeDescription::default()
-                .format(vk::Format::R32G32_SFLOAT)
+                .format(vk::Format::R32G32B32_SFLOAT)
                 .binding(0)
                 .location(0)
                 .offset(offset_of!(TerrainVertex, pos) as u32),
@@ -29,70 +50,113 @@ impl Vertex for TerrainVertex {
                 .binding(0)
                 .location(1)
                 .offset(offset_of!(TerrainVertex, color) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .binding(1)
+                .location(2)
+                .offset(offset_of!(InstanceData, offset) as u32),
         ]
     }
 }
 
 impl Renderer for GeometryRenderer {
-    fn create_framebuffers(&self, swapchain : &Swapchain) -> Vec<Framebuffer> {
-        let mut framebuffers = vec![];
-        for image in &swapchain.images {
-            framebuffers.push(self.render_pass.create_framebuffer(swapchain, image));
-        }
-        framebuffers
+    fn create_framebuffers(&self, swapchain : &Swapchain, cache : &mut FramebufferCache) -> Vec<Arc<Framebuffer>> {
+        self.render_pass.framebuffers(swapchain, cache)
     }
 
     fn record_commands(&mut self, swapchain : &Swapchain, framebuffer : &Framebuffer, frame : &FrameData) {
         profile_scope!("Geometry command recording");
 
+        // The scene is rendered at the scaled resolution of the swapchain, if any.
+        let extent = self.render_pass.extent(swapchain);
         let viewport = vk::Viewport::default()
             .x(0.0f32)
             .y(0.0f32)
             .min_depth(0.0f32)
             .max_depth(1.0f32)
-            .width(swapchain.extent.width as _)
-            .height(swapchain.extent.height as _);
+            .width(extent.width as _)
+            .height(extent.height as _);
 
         let scissors = vk::Rect2D::default()
             .offset(vk::Offset2D { x: 0, y: 0 })
-            .extent(swapchain.extent);
+            .extent(extent);
+
+        let aspect_ratio = extent.width as f32 / extent.height as f32;
+        self.camera_uniform.update(frame.index, &CameraUniform {
+            view_projection : self.camera.view_projection(aspect_ratio),
+        });
 
         frame.cmd.begin_render_pass(&self.render_pass, framebuffer, vk::Rect2D {
             offset : vk::Offset2D { x: 0, y : 0 },
-            extent : swapchain.extent
-        }, &[
-            vk::ClearValue {
-                color : vk::ClearColorValue {
-                    float32: [0.0; 4],
-                },
-            },
-            vk::ClearValue {
-                depth_stencil : vk::ClearDepthStencilValue {
-                    depth : 1.0f32,
-                    stencil : 0,
-                }
-            }
-        ], vk::SubpassContents::INLINE);
-        frame.cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, &self.pipeline);
+            extent
+        }, &self.clear_values, vk::SubpassContents::INLINE);
         frame.cmd.set_viewport(0, &[viewport]);
         frame.cmd.set_scissors(0, &[scissors]);
-        frame.cmd.bind_vertex_buffers(0, &[(&self.buffer, 0)]);
-        frame.cmd.draw(self.buffer.element_count(), 1, 0, 0);
+        // The test mesh is only drawn until a model is loaded.
+        if self.model.is_loaded() {
+            self.model.draw(&frame.cmd, self.descriptor_sets[frame.index]);
+        } else {
+            frame.cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, &self.pipeline);
+            frame.cmd.bind_descriptor_sets(vk::PipelineBindPoint::GRAPHICS, &self.pipeline, 0, &[self.descriptor_sets[frame.index]], &[]);
+            frame.cmd.draw_instanced(&self.buffer, &self.instances);
+        }
         frame.cmd.end_render_pass();
     }
 
     fn marker_data<'a>(&self) -> (&'a str, [f32; 4]) {
         ("Geometry renderer", [0.0; 4])
     }
+
+    fn handle_event(&mut self, event : &WindowEvent) -> EventResponse {
+        let consumed = self.camera.handle_event(event);
+        EventResponse { repaint : consumed, consumed }
+    }
+
+    fn update(&mut self) {
+        for event in Publisher::global().drain(|event| matches!(event, Event::LoadModel(_))) {
+            if let Event::LoadModel(mesh) = event {
+                self.load_model(&mesh);
+            }
+        }
+    }
+
+    fn on_swapchain_recreated(&mut self, swapchain : &Swapchain) {
+        // There is one copy of the camera uniform per frame in flight.
+        if self.camera_uniform.frames() != swapchain.image_count() {
+            // SAFETY: The device is idle while the swapchain is recreated, so no frame uses the previous sets anymore.
+            for set in self.descriptor_sets.drain(..) {
+                unsafe {
+                    self.descriptor_set_layout.free(set);
+                }
+            }
+
+            self.camera_uniform = UniformBuffer::new(&self.context, "Camera", swapchain.image_count());
+            self.descriptor_sets = Self::create_descriptor_sets(&mut self.descriptor_set_layout, &self.camera_uniform);
+        }
+    }
+
+    fn is_scaled(&self) -> bool { self.render_pass.is_scaled() }
+
+    fn is_enabled(&self) -> bool { self.enabled }
+
+    fn set_enabled(&mut self, enabled : bool) { self.enabled = enabled; }
 }
 
 pub struct GeometryRenderer {
+    context : RenderingContext,
     buffer : Buffer,
+    instances : Buffer,
     transfer_pool : CommandPool,
-    // descriptor_set_layout : DescriptorSetLayout,
+    camera : Camera,
+    camera_uniform : UniformBuffer<CameraUniform>,
+    descriptor_sets : Vec<vk::DescriptorSet>,
+    descriptor_set_layout : DescriptorSetLayout,
     pipeline_layout : PipelineLayout,
     pipeline : Pipeline,
+    model : ModelRenderer,
     render_pass : RenderPass,
+    clear_values : Vec<vk::ClearValue>,
+    enabled : bool,
 }
 
 impl GeometryRenderer {
@@ -101,14 +165,14 @@ impl GeometryRenderer {
             .dependency(
                 vk::SUBPASS_EXTERNAL,
                 0,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
                 vk::AccessFlags::empty(),
-                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
             ).subpass(vk::PipelineBindPoint::GRAPHICS, &[
                 SubpassAttachment::color(0),
                 SubpassAttachment::resolve(0)
-            ], None)
+            ], Some(SubpassAttachment::depth(0)))
             .build(context);
 
         Self::initialize(swapchain, context, render_pass)
@@ -121,31 +185,26 @@ impl GeometryRenderer {
         let buffer = DynamicBufferBuilder::dynamic()
             .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
             .gpu_only()
-            .build(&context, &transfer_pool, &[
-                TerrainVertex {
-                    pos : [ 0.0f32, -0.5f32 ],
-                    color : [ 1.0f32, 0.0f32, 0.0f32 ]
-                },
-                TerrainVertex {
-                    pos : [ 0.5f32, 0.5f32 ],
-                    color : [ 0.0f32, 1.0f32, 0.0f32 ]
-                },
-                TerrainVertex {
-                    pos : [ -0.5f32, 0.5f32 ],
-                    color : [ 0.0f32, 0.0f32, 1.0f32 ]
-                }
-            ]);
+            .build(&context, &transfer_pool, &test_cube());
 
-        // let descriptor_set_layout = DescriptorSetLayout::builder()
-        //     .build(&context.device);
+        let instances = DynamicBufferBuilder::dynamic()
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .gpu_only()
+            .build(&context, &transfer_pool, &test_instances());
+
+        let mut descriptor_set_layout = DescriptorSetLayout::builder()
+            .uniform_buffer(0, vk::ShaderStageFlags::VERTEX, 1)
+            .build(&context);
+        let camera_uniform = UniformBuffer::new(&context, "Camera", swapchain.image_count());
+        let descriptor_sets = Self::create_descriptor_sets(&mut descriptor_set_layout, &camera_uniform);
 
         let pipeline_layout = PipelineLayoutInfo::default()
-        //     .layout(&descriptor_set_layout)
+            .layout(&descriptor_set_layout)
             .build(&context);
 
         let pipeline = PipelineInfo::default()
             .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .layout(pipeline_layout.handle())
+            .layout(&pipeline_layout)
             .depth(DepthOptions::enabled())
             .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default()
                 .blend_enable(false)
@@ -157,7 +216,8 @@ impl GeometryRenderer {
                 .alpha_blend_op(vk::BlendOp::ADD)
                 .color_write_mask(vk::ColorComponentFlags::RGBA))
             .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE)
+            // The projection flips the vertical axis, which preserves the winding of counter-clockwise faces.
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
             .render_pass(render_pass.handle(), 0)
             .samples(context.options.multisampling)
             .pool()
@@ -166,13 +226,82 @@ impl GeometryRenderer {
             .add_shader("./assets/triangle.frag".into(), vk::ShaderStageFlags::FRAGMENT)
             .build(&context);
 
+        let model = ModelRenderer::new(&context, &render_pass, 0, &descriptor_set_layout);
+
+        let mut camera = Camera::default();
+        // Far enough to see the whole grid of test instances.
+        camera.distance = 25.0;
+
+        let clear_values = render_pass.clear_values(&context.options);
+
         Self {
+            context : context.clone(),
             buffer,
+            instances,
             transfer_pool,
-            // descriptor_set_layout,
+            camera,
+            camera_uniform,
+            descriptor_sets,
+            descriptor_set_layout,
             pipeline_layout,
             pipeline,
-            render_pass
+            model,
+            render_pass,
+            clear_values,
+            enabled : true,
         }
     }
+
+    /// Replaces the model displayed, and moves the camera so that the whole model is visible.
+    fn load_model(&mut self, mesh : &Mesh) {
+        self.model.load(mesh);
+
+        let (min, max) = mesh.bounds();
+        let radius = (0..3).map(|axis| (max[axis] - min[axis]) / 2.0).fold(0.0f32, f32::max);
+        self.camera.target = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        self.camera.distance = (radius * 3.0).max(self.camera.near);
+    }
+
+    /// Creates one descriptor set per frame in flight, each pointing at the copy of the camera uniform of that frame.
+    fn create_descriptor_sets(layout : &mut DescriptorSetLayout, camera_uniform : &UniformBuffer<CameraUniform>) -> Vec<vk::DescriptorSet> {
+        (0..camera_uniform.frames())
+            .map(|frame| layout.request(DescriptorSetInfo::default()
+                .uniform_buffers(0, vec![camera_uniform.descriptor_info(frame)])))
+            .collect()
+    }
+}
+
+/// Returns the vertices of a cube centered on the origin, with a different color on each face. Faces are wound
+/// counter-clockwise when seen from outside the cube.
+fn test_cube() -> Vec<TerrainVertex> {
+    // The corners of each face, counter-clockwise when seen from outside the cube.
+    const FACES : [([[f32; 3]; 4], [f32; 3]); 6] = [
+        ([[ 1.0, -1.0,  1.0], [ 1.0, -1.0, -1.0], [ 1.0,  1.0, -1.0], [ 1.0,  1.0,  1.0]], [1.0, 0.0, 0.0]), // +X
+        ([[-1.0, -1.0, -1.0], [-1.0, -1.0,  1.0], [-1.0,  1.0,  1.0], [-1.0,  1.0, -1.0]], [0.0, 1.0, 1.0]), // -X
+        ([[-1.0,  1.0,  1.0], [ 1.0,  1.0,  1.0], [ 1.0,  1.0, -1.0], [-1.0,  1.0, -1.0]], [0.0, 1.0, 0.0]), // +Y
+        ([[-1.0, -1.0, -1.0], [ 1.0, -1.0, -1.0], [ 1.0, -1.0,  1.0], [-1.0, -1.0,  1.0]], [1.0, 0.0, 1.0]), // -Y
+        ([[-1.0, -1.0,  1.0], [ 1.0, -1.0,  1.0], [ 1.0,  1.0,  1.0], [-1.0,  1.0,  1.0]], [0.0, 0.0, 1.0]), // +Z
+        ([[ 1.0, -1.0, -1.0], [-1.0, -1.0, -1.0], [-1.0,  1.0, -1.0], [ 1.0,  1.0, -1.0]], [1.0, 1.0, 0.0]), // -Z
+    ];
+
+    FACES.iter()
+        .flat_map(|(corners, color)| [0, 1, 2, 0, 2, 3].map(|corner| TerrainVertex {
+            pos : corners[corner].map(|coordinate| coordinate * 0.5),
+            color : *color,
+        }))
+        .collect()
+}
+
+/// Returns the instances of the test mesh: a 10x10x10 grid of cubes, centered on the origin.
+fn test_instances() -> Vec<InstanceData> {
+    const SIZE : usize = 10;
+    const SPACING : f32 = 1.5;
+
+    let center = (SIZE - 1) as f32 * SPACING / 2.0;
+    (0..SIZE * SIZE * SIZE)
+        .map(|index| InstanceData {
+            offset : [index % SIZE, (index / SIZE) % SIZE, index / (SIZE * SIZE)]
+                .map(|coordinate| coordinate as f32 * SPACING - center),
+        })
+        .collect()
 }
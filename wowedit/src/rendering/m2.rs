@@ -0,0 +1,360 @@
+use anyhow::{ensure, Result};
+use bytes::Buf;
+use tactfs::types::FileDataID;
+use thiserror::Error;
+
+/// The first version of the format that stores skin profiles in separate files.
+const MIN_VERSION : u32 = 264;
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("Not an M2 file")]
+    NotM2,
+    #[error("Not a skin file")]
+    NotSkin,
+    #[error("Unsupported M2 version: found {0}, expected at least 264")]
+    UnsupportedVersion(u32),
+    #[error("Truncated header")]
+    TruncatedHeader,
+    #[error("Truncated chunk at offset {0}")]
+    TruncatedChunk(usize),
+    #[error("The array declared at offset {0:#x} ({1} elements) exceeds the file")]
+    TruncatedArray(usize, usize),
+}
+
+/// A vertex of an M2 model. Bone weights and the second set of texture coordinates are not read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct M2Vertex {
+    pub position : [f32; 3],
+    pub normal : [f32; 3],
+    pub tex_coords : [f32; 2],
+}
+
+/// A texture used by an M2 model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum M2Texture {
+    /// A texture stored in the game files.
+    File(FileDataID),
+    /// A texture referenced by its path, in models that predate file data IDs.
+    Named(String),
+    /// A texture that depends on the unit displaying the model, such as a skin or a cape. The value is the type of
+    /// the texture.
+    Replaceable(u32),
+}
+
+/// The parts of an M2 model needed to display its static geometry.
+#[derive(Debug)]
+pub struct M2 {
+    pub vertices : Vec<M2Vertex>,
+    pub textures : Vec<M2Texture>,
+    /// Maps the texture combos referenced by batches to indices in [`M2::textures`].
+    pub texture_combos : Vec<u16>,
+    /// The skin profiles of the model, from the most to the least detailed. Only chunked files reference them by ID.
+    pub skin_files : Vec<FileDataID>,
+}
+
+impl M2 {
+    /// Parses an M2 file, either chunked (`MD21`) or not (`MD20`).
+    pub fn parse(data : &[u8]) -> Result<M2> {
+        ensure!(data.len() >= 8, ErrorCode::NotM2);
+
+        let (header, skin_files, texture_files) = if &data[0..4] == b"MD21" {
+            let mut header = None;
+            let mut skin_files = vec![];
+            let mut texture_files = vec![];
+
+            let mut cursor = data;
+            while cursor.has_remaining() {
+                let offset = data.len() - cursor.remaining();
+                ensure!(cursor.remaining() >= 8, ErrorCode::TruncatedChunk(offset));
+
+                let mut magic = [0; 4];
+                cursor.copy_to_slice(&mut magic);
+                let size : usize = cursor.get_u32_le().try_into()?;
+                ensure!(cursor.remaining() >= size, ErrorCode::TruncatedChunk(offset));
+
+                let chunk = &cursor[..size];
+                match &magic {
+                    b"MD21" => header = Some(chunk),
+                    b"SFID" => skin_files = read_ids(chunk),
+                    b"TXID" => texture_files = read_ids(chunk),
+                    _ => { },
+                }
+                cursor.advance(size);
+            }
+
+            (header.ok_or(ErrorCode::NotM2)?, skin_files, texture_files)
+        } else {
+            (data, vec![], vec![])
+        };
+
+        ensure!(header.len() >= 0x88 && &header[0..4] == b"MD20", ErrorCode::NotM2);
+        let version = (&header[4..]).get_u32_le();
+        ensure!(version >= MIN_VERSION, ErrorCode::UnsupportedVersion(version));
+
+        let vertices = array(header, 0x3C, 48)?
+            .map(|mut vertex| {
+                let position = [vertex.get_f32_le(), vertex.get_f32_le(), vertex.get_f32_le()];
+                vertex.advance(8); // Bone weights and indices
+                let normal = [vertex.get_f32_le(), vertex.get_f32_le(), vertex.get_f32_le()];
+                let tex_coords = [vertex.get_f32_le(), vertex.get_f32_le()];
+
+                M2Vertex { position, normal, tex_coords }
+            })
+            .collect();
+
+        let textures_offset = (&header[0x54..]).get_u32_le() as usize;
+        let textures = array(header, 0x50, 16)?
+            .enumerate()
+            .map(|(index, mut texture)| {
+                let kind = texture.get_u32_le();
+                if kind != 0 {
+                    return Ok(M2Texture::Replaceable(kind));
+                }
+
+                match texture_files.get(index) {
+                    Some(&id) if id != FileDataID(0) => Ok(M2Texture::File(id)),
+                    _ => {
+                        // The path is stored in an array of characters, right after the type and flags of the texture.
+                        let name = array(header, textures_offset + index * 16 + 8, 1)?.flatten().copied().collect::<Vec<_>>();
+                        Ok(M2Texture::Named(String::from_utf8_lossy(&name).trim_end_matches('\0').to_owned()))
+                    },
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        let texture_combos = array(header, 0x80, 2)?
+            .map(|mut combo| combo.get_u16_le())
+            .collect();
+
+        // Chunked files may also list the skins of lower levels of detail after the skin profiles.
+        let skin_profile_count = (&header[0x44..]).get_u32_le() as usize;
+        let skin_files = skin_files.into_iter().take(skin_profile_count).collect();
+
+        Ok(M2 { vertices, textures, texture_combos, skin_files })
+    }
+}
+
+/// A range of vertices and triangles of a skin profile, which is drawn by one or more batches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SkinSection {
+    pub vertex_start : u32,
+    pub vertex_count : u32,
+    /// The offset of the first index of this section in [`Skin::indices`].
+    pub index_start : u32,
+    pub index_count : u32,
+}
+
+/// Describes how a skin section is drawn, also known as a texture unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Batch {
+    /// The index of the section drawn, in [`Skin::sections`].
+    pub section : u16,
+    /// Zero for the base layer of a material, greater than zero for layers blended on top of it.
+    pub material_layer : u16,
+    pub texture_count : u16,
+    /// The index of the first texture used, in [`M2::texture_combos`].
+    pub texture_combo : u16,
+}
+
+/// A skin profile of an M2 model, which describes the triangles of the model at a given level of detail.
+#[derive(Debug)]
+pub struct Skin {
+    /// Indices of the vertices used by this profile, in [`M2::vertices`].
+    pub vertices : Vec<u16>,
+    /// Triangles, as indices in [`Skin::vertices`].
+    pub indices : Vec<u16>,
+    pub sections : Vec<SkinSection>,
+    pub batches : Vec<Batch>,
+}
+
+impl Skin {
+    /// Parses a skin file.
+    pub fn parse(data : &[u8]) -> Result<Skin> {
+        ensure!(data.len() >= 0x2C && &data[0..4] == b"SKIN", ErrorCode::NotSkin);
+
+        let vertices = array(data, 0x04, 2)?
+            .map(|mut index| index.get_u16_le())
+            .collect();
+
+        let indices = array(data, 0x0C, 2)?
+            .map(|mut index| index.get_u16_le())
+            .collect();
+
+        let sections = array(data, 0x1C, 48)?
+            .map(|mut section| {
+                section.advance(2); // Section ID
+                // Sections of skins with more than 65535 indices store the upper bits of their offsets separately.
+                let level = u32::from(section.get_u16_le()) << 16;
+                let vertex_start = u32::from(section.get_u16_le()) + level;
+                let vertex_count = u32::from(section.get_u16_le());
+                let index_start = u32::from(section.get_u16_le()) + level;
+                let index_count = u32::from(section.get_u16_le());
+
+                SkinSection { vertex_start, vertex_count, index_start, index_count }
+            })
+            .collect();
+
+        let batches = array(data, 0x24, 24)?
+            .map(|mut batch| {
+                batch.advance(4); // Flags, priority plane, shader ID
+                let section = batch.get_u16_le();
+                batch.advance(6); // Geoset index, color index, material index
+                let material_layer = batch.get_u16_le();
+                let texture_count = batch.get_u16_le();
+                let texture_combo = batch.get_u16_le();
+
+                Batch { section, material_layer, texture_count, texture_combo }
+            })
+            .collect();
+
+        Ok(Skin { vertices, indices, sections, batches })
+    }
+}
+
+/// Returns the elements of an array, declared by its element count and the offset of its first element.
+///
+/// # Arguments
+///
+/// * `data` - The data offsets are relative to.
+/// * `declaration` - The offset of the declaration of the array.
+/// * `element_size` - The size of a single element of the array, in bytes.
+fn array(data : &[u8], declaration : usize, element_size : usize) -> Result<std::slice::ChunksExact<'_, u8>> {
+    ensure!(data.len() >= declaration + 8, ErrorCode::TruncatedHeader);
+
+    let mut cursor = &data[declaration..];
+    let count = cursor.get_u32_le() as usize;
+    let offset = cursor.get_u32_le() as usize;
+
+    let end = count.checked_mul(element_size).and_then(|size| size.checked_add(offset));
+    ensure!(end.is_some_and(|end| end <= data.len()), ErrorCode::TruncatedArray(declaration, count));
+
+    Ok(data[offset..offset + count * element_size].chunks_exact(element_size))
+}
+
+fn read_ids(mut chunk : &[u8]) -> Vec<FileDataID> {
+    let mut ids = Vec::with_capacity(chunk.len() / 4);
+    while chunk.remaining() >= 4 {
+        ids.push(FileDataID(chunk.get_u32_le()));
+    }
+    ids
+}
+
+#[cfg(test)]
+pub(in crate) mod test {
+    use tactfs::types::FileDataID;
+
+    use super::{ErrorCode, M2Texture, Skin, M2};
+
+    /// Appends an array to a file, and writes its declaration at the given offset.
+    fn push_array(data : &mut Vec<u8>, declaration : usize, count : usize, elements : &[u8]) {
+        let offset = data.len() as u32;
+        data[declaration..declaration + 4].copy_from_slice(&(count as u32).to_le_bytes());
+        data[declaration + 4..declaration + 8].copy_from_slice(&offset.to_le_bytes());
+        data.extend(elements);
+    }
+
+    /// Builds a chunked M2 file with a single triangle, a hardcoded texture, a replaceable one and one referenced by
+    /// its path.
+    pub fn make_m2() -> Vec<u8> {
+        let mut header = b"MD20".to_vec();
+        header.extend(274u32.to_le_bytes());
+        header.resize(0x130, 0);
+        header[0x44..0x48].copy_from_slice(&1u32.to_le_bytes());
+
+        let mut vertices = vec![];
+        for (index, position) in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].iter().enumerate() {
+            position.iter().for_each(|coordinate| vertices.extend(coordinate.to_le_bytes()));
+            vertices.extend([0; 8]);
+            [0.0f32, 0.0, 1.0].iter().for_each(|coordinate| vertices.extend(coordinate.to_le_bytes()));
+            [index as f32, 0.5].iter().for_each(|coordinate| vertices.extend(coordinate.to_le_bytes()));
+            vertices.extend([0; 8]);
+        }
+        push_array(&mut header, 0x3C, 3, &vertices);
+
+        let mut textures = vec![];
+        textures.extend(0u32.to_le_bytes());
+        textures.extend([0; 12]);
+        textures.extend(1u32.to_le_bytes());
+        textures.extend([0; 12]);
+        textures.extend([0; 16]);
+        let textures_offset = header.len();
+        push_array(&mut header, 0x50, 3, &textures);
+        push_array(&mut header, textures_offset + 2 * 16 + 8, 19, b"textures\\named.blp\0");
+        push_array(&mut header, 0x80, 2, &[1, 0, 0, 0]);
+
+        let mut data = b"MD21".to_vec();
+        data.extend((header.len() as u32).to_le_bytes());
+        data.extend(header);
+        for (magic, ids) in [(b"SFID", [100u32, 101]), (b"TXID", [200, 0])] {
+            data.extend(magic);
+            data.extend(8u32.to_le_bytes());
+            ids.iter().for_each(|id| data.extend(id.to_le_bytes()));
+        }
+        data
+    }
+
+    /// Builds a skin file drawing the triangle of [`make_m2`], with a base layer and a second layer.
+    pub fn make_skin() -> Vec<u8> {
+        let mut data = b"SKIN".to_vec();
+        data.resize(0x30, 0);
+
+        push_array(&mut data, 0x04, 3, &[0, 0, 1, 0, 2, 0]);
+        push_array(&mut data, 0x0C, 3, &[0, 0, 2, 0, 1, 0]);
+
+        let mut section = vec![0; 48];
+        section[6..8].copy_from_slice(&3u16.to_le_bytes()); // Vertex count
+        section[10..12].copy_from_slice(&3u16.to_le_bytes()); // Index count
+        push_array(&mut data, 0x1C, 1, &section);
+
+        let mut batches = vec![0; 48];
+        batches[14..16].copy_from_slice(&1u16.to_le_bytes()); // Texture count
+        batches[24 + 12..24 + 14].copy_from_slice(&1u16.to_le_bytes()); // Material layer
+        batches[24 + 14..24 + 16].copy_from_slice(&1u16.to_le_bytes());
+        batches[24 + 16..24 + 18].copy_from_slice(&1u16.to_le_bytes()); // Texture combo
+        push_array(&mut data, 0x24, 2, &batches);
+        data
+    }
+
+    #[test]
+    pub fn chunked_model() {
+        let model = M2::parse(&make_m2()).unwrap();
+
+        assert_eq!(model.vertices.len(), 3);
+        assert_eq!(model.vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(model.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(model.vertices[2].tex_coords, [2.0, 0.5]);
+        assert_eq!(model.textures, vec![
+            M2Texture::File(FileDataID(200)),
+            M2Texture::Replaceable(1),
+            M2Texture::Named("textures\\named.blp".to_owned())
+        ]);
+        assert_eq!(model.texture_combos, vec![1, 0]);
+        assert_eq!(model.skin_files, vec![FileDataID(100)]);
+    }
+
+    #[test]
+    pub fn skin() {
+        let skin = Skin::parse(&make_skin()).unwrap();
+
+        assert_eq!(skin.vertices, vec![0, 1, 2]);
+        assert_eq!(skin.indices, vec![0, 2, 1]);
+        assert_eq!(skin.sections[0].index_count, 3);
+        assert_eq!(skin.batches.len(), 2);
+        assert_eq!(skin.batches[1].material_layer, 1);
+        assert_eq!(skin.batches[1].texture_combo, 1);
+    }
+
+    #[test]
+    pub fn truncated_model() {
+        let mut data = make_m2();
+        // Make the vertex array overflow the header.
+        data[8 + 0x3C] = 0xFF;
+
+        let error = M2::parse(&data).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::TruncatedArray(0x3C, 0xFF))));
+
+        let error = M2::parse(b"MD20\x00\x01\x00\x00").unwrap_err();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::NotM2)));
+    }
+}
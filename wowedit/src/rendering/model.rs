@@ -0,0 +1,432 @@
+use std::mem::{offset_of, size_of};
+
+use anyhow::{ensure, Result};
+use ash::vk;
+use renderer::orchestration::rendering::RenderingContext;
+use renderer::traits::handle::Handle;
+use renderer::vk::buffer::{Buffer, DynamicBufferBuilder, DynamicInitializer};
+use renderer::vk::command_buffer::CommandBuffer;
+use renderer::vk::command_pool::CommandPool;
+use renderer::vk::descriptor::layout::DescriptorSetLayout;
+use renderer::vk::descriptor::set::DescriptorSetInfo;
+use renderer::vk::fence_pool::FencePool;
+use renderer::vk::image::{Image, ImageCreateInfo};
+use renderer::vk::pipeline::layout::{PipelineLayout, PipelineLayoutInfo};
+use renderer::vk::pipeline::{DepthOptions, Pipeline, PipelineInfo, Vertex};
+use renderer::vk::queue::QueueAffinity;
+use renderer::vk::render_pass::RenderPass;
+use renderer::vk::sampler::Sampler;
+use tactfs::casc::Storage;
+use tactfs::types::FileDataID;
+use thiserror::Error;
+
+use crate::rendering::blp::{self, Texture};
+use crate::rendering::m2::{M2Texture, Skin, M2};
+
+#[derive(Error, Debug)]
+pub enum ErrorCode {
+    #[error("The model has no skin profile")]
+    NoSkin,
+    #[error("The skin profile has no triangles")]
+    Empty,
+    #[error("The skin profile references vertex {0}, which does not exist")]
+    InvalidVertex(u16),
+    #[error("Batch {0} references a section that does not exist or exceeds the skin profile")]
+    InvalidSection(usize),
+}
+
+/// A vertex of a [`Mesh`], as read by `model.vert`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct ModelVertex {
+    pub position : [f32; 3],
+    pub normal : [f32; 3],
+    pub tex_coords : [f32; 2],
+}
+
+impl Vertex for ModelVertex {
+    fn bindings() -> Vec<(u32, vk::VertexInputRate)> {
+        vec![(size_of::<Self>() as u32, vk::VertexInputRate::VERTEX)]
+    }
+
+    fn format_offset() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription::default()
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .binding(0)
+                .location(0)
+                .offset(offset_of!(ModelVertex, position) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .binding(0)
+                .location(1)
+                .offset(offset_of!(ModelVertex, normal) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .format(vk::Format::R32G32_SFLOAT)
+                .binding(0)
+                .location(2)
+                .offset(offset_of!(ModelVertex, tex_coords) as u32),
+        ]
+    }
+}
+
+/// A range of triangles of a [`Mesh`] drawn with a single texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub first_index : u32,
+    pub index_count : u32,
+    /// The index of the diffuse texture of this submesh in [`Mesh::textures`], if any.
+    pub texture : Option<usize>,
+}
+
+/// The static geometry of a model, ready to be uploaded to the GPU.
+pub struct Mesh {
+    pub vertices : Vec<ModelVertex>,
+    pub indices : Vec<u16>,
+    pub submeshes : Vec<Submesh>,
+    /// The textures of the model. Textures that could not be loaded, or that depend on the unit displaying the model,
+    /// are [`None`].
+    pub textures : Vec<Option<Texture>>,
+}
+
+impl Mesh {
+    /// Builds a mesh from an M2 model and one of its skin profiles. Textures are not loaded.
+    ///
+    /// # Description
+    ///
+    /// Models are Z-up, while the editor is Y-up; coordinates are converted accordingly.
+    pub fn new(model : &M2, skin : &Skin) -> Result<Mesh> {
+        ensure!(!skin.indices.is_empty(), ErrorCode::Empty);
+
+        let vertices = skin.vertices.iter()
+            .map(|&index| {
+                let vertex = model.vertices.get(usize::from(index)).ok_or(ErrorCode::InvalidVertex(index))?;
+                Ok(ModelVertex {
+                    position : to_y_up(vertex.position),
+                    normal : to_y_up(vertex.normal),
+                    tex_coords : vertex.tex_coords,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(&index) = skin.indices.iter().find(|&&index| usize::from(index) >= vertices.len()) {
+            return Err(ErrorCode::InvalidVertex(index).into());
+        }
+
+        // Only the base layer of each material is drawn; additional layers are blended on top of it.
+        let submeshes = skin.batches.iter()
+            .enumerate()
+            .filter(|(_, batch)| batch.material_layer == 0)
+            .map(|(index, batch)| {
+                let section = skin.sections.get(usize::from(batch.section))
+                    .filter(|section| (section.index_start + section.index_count) as usize <= skin.indices.len())
+                    .ok_or(ErrorCode::InvalidSection(index))?;
+
+                let texture = if batch.texture_count > 0 {
+                    model.texture_combos.get(usize::from(batch.texture_combo))
+                        .map(|&texture| usize::from(texture))
+                        .filter(|&texture| texture < model.textures.len())
+                } else {
+                    None
+                };
+
+                Ok(Submesh { first_index : section.index_start, index_count : section.index_count, texture })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Mesh {
+            vertices,
+            indices : skin.indices.clone(),
+            submeshes,
+            textures : model.textures.iter().map(|_| None).collect(),
+        })
+    }
+
+    /// Loads a model and its textures from the game files, using its most detailed skin profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage of the game installation.
+    /// * `id` - The file data ID of the model.
+    pub fn load(storage : &Storage, id : FileDataID) -> Result<Mesh> {
+        let model = M2::parse(&storage.read_file_by_id(id)?)?;
+        let skin_file = *model.skin_files.first().ok_or(ErrorCode::NoSkin)?;
+        let skin = Skin::parse(&storage.read_file_by_id(skin_file)?)?;
+
+        let mut mesh = Mesh::new(&model, &skin)?;
+        // A missing texture should not prevent the geometry from being displayed; untextured submeshes are white.
+        mesh.textures = model.textures.iter()
+            .map(|texture| match texture {
+                M2Texture::File(id) => storage.read_file_by_id(*id).and_then(|data| blp::decode(&data)).ok(),
+                _ => None,
+            })
+            .collect();
+
+        Ok(mesh)
+    }
+
+    /// Returns the corners of the axis-aligned box containing every vertex of this mesh.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.vertices.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), vertex| {
+            (
+                [0, 1, 2].map(|axis| min[axis].min(vertex.position[axis])),
+                [0, 1, 2].map(|axis| max[axis].max(vertex.position[axis])),
+            )
+        })
+    }
+}
+
+/// Converts a position or direction from the Z-up coordinate system of models to the Y-up coordinate system of the
+/// editor.
+fn to_y_up(vector : [f32; 3]) -> [f32; 3] {
+    [vector[0], vector[2], -vector[1]]
+}
+
+/// The GPU resources of a [`Mesh`].
+struct GpuMesh {
+    vertices : Buffer,
+    indices : Buffer,
+    submeshes : Vec<Submesh>,
+    /// The textures of the mesh, followed by a white texture used by untextured submeshes.
+    textures : Vec<Image>,
+    /// One descriptor set per texture.
+    texture_sets : Vec<vk::DescriptorSet>,
+}
+
+/// Draws a [`Mesh`] in a subpass owned by another renderer, which provides the camera uniform.
+pub struct ModelRenderer {
+    context : RenderingContext,
+    upload_pool : CommandPool,
+    fence_pool : FencePool,
+    sampler : Sampler,
+    texture_set_layout : DescriptorSetLayout,
+    pipeline_layout : PipelineLayout,
+    pipeline : Pipeline,
+    mesh : Option<GpuMesh>,
+}
+
+impl ModelRenderer {
+    /// Creates a new model renderer.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The rendering context.
+    /// * `render_pass` - The render pass models are drawn in.
+    /// * `subpass` - The index of the subpass models are drawn in.
+    /// * `camera_layout` - The layout of the descriptor set holding the camera uniform, bound as set 0.
+    pub fn new(context : &RenderingContext, render_pass : &RenderPass, subpass : u32, camera_layout : &DescriptorSetLayout) -> Self {
+        // Textures are sampled on the graphics queue, so they are uploaded there as well.
+        let upload_pool = CommandPool::builder(&context.graphics_queue)
+            .build(context);
+
+        // Terrain and model textures tile, and are often seen at grazing angles.
+        let sampler = Sampler::builder()
+            .repeat()
+            .trilinear()
+            .max_anisotropy(16.0)
+            .build(context);
+
+        let texture_set_layout = DescriptorSetLayout::builder()
+            .binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, 1)
+            .build(context);
+
+        let pipeline_layout = PipelineLayoutInfo::default()
+            .layout(camera_layout)
+            .layout(&texture_set_layout)
+            .build(context);
+
+        let pipeline = PipelineInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .layout(&pipeline_layout)
+            .depth(DepthOptions::enabled())
+            .color_blend_attachment(vk::PipelineColorBlendAttachmentState::default()
+                .blend_enable(false)
+                .color_write_mask(vk::ColorComponentFlags::RGBA))
+            // Materials can be two-sided, and the winding of triangles is not consistent across models.
+            .cull_mode(vk::CullModeFlags::NONE)
+            .render_pass(render_pass.handle(), subpass)
+            .samples(context.options.multisampling)
+            .pool()
+            .vertex::<ModelVertex>()
+            .add_shader("./assets/model.vert".into(), vk::ShaderStageFlags::VERTEX)
+            .add_shader("./assets/model.frag".into(), vk::ShaderStageFlags::FRAGMENT)
+            .build(context);
+
+        Self {
+            context : context.clone(),
+            upload_pool,
+            fence_pool : FencePool::new(context),
+            sampler,
+            texture_set_layout,
+            pipeline_layout,
+            pipeline,
+            mesh : None,
+        }
+    }
+
+    /// Returns true if a mesh was loaded.
+    #[inline] pub fn is_loaded(&self) -> bool { self.mesh.is_some() }
+
+    /// Uploads a mesh to the GPU, replacing the one currently drawn.
+    pub fn load(&mut self, mesh : &Mesh) {
+        // The previous mesh may still be in use by frames in flight.
+        self.context.device.wait_idle();
+        self.mesh = None;
+        self.texture_set_layout.reset();
+
+        let vertices = DynamicBufferBuilder::dynamic()
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .gpu_only()
+            .build(&self.context, &self.upload_pool, &mesh.vertices);
+
+        let indices = DynamicBufferBuilder::dynamic()
+            .usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .index(vk::IndexType::UINT16)
+            .gpu_only()
+            .build(&self.context, &self.upload_pool, &mesh.indices);
+
+        let white = Texture { width : 1, height : 1, pixels : vec![0xFF; 4] };
+        let textures = self.upload_textures(mesh.textures.iter()
+            .map(|texture| texture.as_ref().unwrap_or(&white))
+            .chain([&white]));
+
+        let texture_sets = textures.iter()
+            .map(|image| self.texture_set_layout.request(DescriptorSetInfo::default()
+                .images(0, vec![
+                    vk::DescriptorImageInfo::default()
+                        .image_layout(image.layout())
+                        .sampler(self.sampler.handle())
+                        .image_view(image.view())
+                ])))
+            .collect();
+
+        self.mesh = Some(GpuMesh {
+            vertices,
+            indices,
+            submeshes : mesh.submeshes.clone(),
+            textures,
+            texture_sets,
+        });
+    }
+
+    /// Uploads textures to the GPU, waiting for the upload to complete.
+    fn upload_textures<'a>(&mut self, textures : impl Iterator<Item = &'a Texture>) -> Vec<Image> {
+        let queue = self.context.device.get_queue(QueueAffinity::Graphics, self.upload_pool.family())
+            .expect("Failed to recover the graphics queue");
+
+        let cmd = CommandBuffer::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .pool(&self.upload_pool)
+            .build_one(&self.context);
+        cmd.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        cmd.begin_label("Model texture upload", [0.0; 4]);
+
+        // Staging buffers must outlive the upload.
+        let mut staging_buffers = vec![];
+        let mut images = vec![];
+        for texture in textures {
+            let staging_buffer = DynamicBufferBuilder::dynamic()
+                .cpu_to_gpu()
+                .linear(true)
+                .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+                .build(&self.context, &self.upload_pool, &texture.pixels);
+
+            let mut image = ImageCreateInfo::default()
+                .color()
+                .layers(0, 1)
+                .levels(0, 1)
+                .image_type(vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .name("Model texture".to_owned())
+                .extent(texture.extent())
+                .format(texture.format())
+                .build(&self.context);
+
+            image.upload_from_buffer(&cmd, &staging_buffer, Some(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            staging_buffers.push(staging_buffer);
+            images.push(image);
+        }
+
+        cmd.end_label();
+        cmd.end();
+
+        let fence = self.fence_pool.acquire();
+        self.context.device.submit(queue, &[&cmd], &[], &[], fence);
+        self.context.device.wait_for_fence(fence);
+        self.fence_pool.release(fence);
+
+        // Models are loaded rarely, and the upload may have allocated a lot of command memory.
+        self.upload_pool.free_command_buffers(vec![cmd.handle()]);
+        self.upload_pool.trim();
+
+        images
+    }
+
+    /// Records the commands drawing the mesh, if one was loaded. The render pass must have been started.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The command buffer on which the commands will be recorded.
+    /// * `camera_set` - The descriptor set holding the camera uniform for the current frame.
+    pub fn draw(&self, cmd : &CommandBuffer, camera_set : vk::DescriptorSet) {
+        let Some(mesh) = &self.mesh else {
+            return;
+        };
+
+        cmd.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, &self.pipeline);
+        cmd.bind_descriptor_sets(vk::PipelineBindPoint::GRAPHICS, &self.pipeline, 0, &[camera_set], &[]);
+        cmd.bind_vertex_buffers(0, &[(&mesh.vertices, 0)]);
+        cmd.bind_index_buffer(&mesh.indices, 0);
+
+        for submesh in &mesh.submeshes {
+            // The white texture comes last.
+            let texture = submesh.texture.unwrap_or(mesh.textures.len() - 1);
+            cmd.bind_descriptor_sets(vk::PipelineBindPoint::GRAPHICS, &self.pipeline, 1, &[mesh.texture_sets[texture]], &[]);
+            cmd.draw_indexed(submesh.index_count, 1, submesh.first_index, 0, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rendering::m2::test::{make_m2, make_skin};
+    use crate::rendering::m2::{Skin, M2};
+
+    use super::{ErrorCode, Mesh, Submesh};
+
+    #[test]
+    pub fn mesh_from_model() {
+        let model = M2::parse(&make_m2()).unwrap();
+        let skin = Skin::parse(&make_skin()).unwrap();
+        let mesh = Mesh::new(&model, &skin).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 3);
+        // Z-up positions and normals become Y-up.
+        assert_eq!(mesh.vertices[2].position, [0.0, 0.0, -1.0]);
+        assert_eq!(mesh.vertices[2].normal, [0.0, 1.0, 0.0]);
+        assert_eq!(mesh.indices, vec![0, 2, 1]);
+        assert_eq!(mesh.textures.len(), 3);
+        assert_eq!(mesh.bounds(), ([0.0, 0.0, -1.0], [1.0, 0.0, 0.0]));
+
+        // The second batch is an additional material layer, and is not drawn on its own.
+        assert_eq!(mesh.submeshes, vec![Submesh { first_index : 0, index_count : 3, texture : Some(1) }]);
+    }
+
+    #[test]
+    pub fn invalid_skin() {
+        let model = M2::parse(&make_m2()).unwrap();
+        let mut skin = Skin::parse(&make_skin()).unwrap();
+        skin.vertices[1] = 3;
+
+        let error = Mesh::new(&model, &skin).err().unwrap();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::InvalidVertex(3))));
+
+        skin.vertices[1] = 1;
+        skin.sections[0].index_count = 6;
+        let error = Mesh::new(&model, &skin).err().unwrap();
+        assert!(matches!(error.downcast_ref::<ErrorCode>(), Some(ErrorCode::InvalidSection(0))));
+    }
+}
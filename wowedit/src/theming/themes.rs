@@ -1,3 +1,5 @@
+use renderer::gui::theme::Theme;
+
 use super::aesthetix::Aesthetix;
 
 /// A Standard dark theme, with rounded buttons, and ample margin. Adapted from the Gnome dark
@@ -76,4 +78,10 @@ impl Aesthetix for StandardDark {
     fn rounding_visuals(&self) -> f32 {
         6.0
     }
-}
\ No newline at end of file
+}
+
+impl Theme for StandardDark {
+    fn style(&self) -> egui::Style {
+        self.custom_style()
+    }
+}